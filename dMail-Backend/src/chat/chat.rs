@@ -59,6 +59,7 @@ pub async fn send_admin_message_to_group_chat(chat_id: ChatID, text: String) {
             chat_id,
             serialized_content: serde_json::to_string(&text).unwrap(),
             timestamp: Utc::now().timestamp_millis() as u64,
+            deliver_at: None,
         },
     )
     .await;
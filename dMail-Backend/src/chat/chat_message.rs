@@ -1,8 +1,12 @@
 use serde::{Deserialize, Serialize};
 
+use std::collections::HashMap;
+
 use crate::{
-    config::datatype::{ChatID, MessageID, Timestamp, UserID},
-    user::ChatMessageType,
+    config::datatype::{
+        ChatID, MessageID, ReactionCounts, SerializedChatMessage, Timestamp, UserID,
+    },
+    user::{ChatMessageType, MentionTextType, ReplyTextType},
 };
 
 /** `ChatMessage` 聊天消息数据类型
@@ -16,4 +20,104 @@ pub struct ChatMessage {
     pub sender_id: UserID,
     pub serialized_content: String,
     pub timestamp: Timestamp,
+    /// 最后一次编辑的时间，未编辑过的消息没有该字段
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edited_at: Option<Timestamp>,
+    /// 转发消息的来源，不是转发的消息没有该字段
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forwarded_from: Option<ForwardedFrom>,
+    /// 每种表情回应的人数，只在读取消息时附带，不随消息存储
+    #[serde(default, skip_serializing_if = "ReactionCounts::is_empty")]
+    pub reactions: ReactionCounts,
+    /// 回复该消息的消息数量，只在读取消息时附带，不随消息存储
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub reply_count: u32,
+}
+
+impl ChatMessage {
+    /** `get_forward_content` 转发时写入的消息类型与内容，不可转发的消息返回None
+     * 回复与提及只转发其中的文本，不再关联原聊天中的消息与用户
+     */
+    pub fn get_forward_content(&self) -> Option<(ChatMessageType, String)> {
+        match self.r#type {
+            ChatMessageType::Text
+            | ChatMessageType::Image
+            | ChatMessageType::File
+            | ChatMessageType::Voice => {
+                return Some((self.r#type.clone(), self.serialized_content.clone()))
+            }
+            ChatMessageType::ReplyText => {
+                let content =
+                    serde_json::from_str::<ReplyTextType>(&self.serialized_content).ok()?;
+                return Some((ChatMessageType::Text, content.text));
+            }
+            ChatMessageType::MentionText => {
+                let content =
+                    serde_json::from_str::<MentionTextType>(&self.serialized_content).ok()?;
+                return Some((ChatMessageType::Text, content.text));
+            }
+            ChatMessageType::Transfer | ChatMessageType::Revoked | ChatMessageType::Encrypted => {
+                return None
+            }
+        }
+    }
+
+    /** `get_forwarded_from` 转发该消息时记录的来源，转发过来的消息沿用其最初的来源
+     */
+    pub fn get_forwarded_from(&self) -> ForwardedFrom {
+        return self.forwarded_from.clone().unwrap_or(ForwardedFrom {
+            chat_id: self.chat_id,
+            in_chat_id: self.in_chat_id,
+            sender_id: self.sender_id,
+            timestamp: self.timestamp,
+        });
+    }
+}
+
+/** `ForwardedFrom` 转发消息的来源，多次转发时保留最初的来源
+ */
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ForwardedFrom {
+    pub chat_id: ChatID,
+    pub in_chat_id: MessageID,
+    pub sender_id: UserID,
+    pub timestamp: Timestamp,
+}
+
+/** `get_forwarded_field` 写入消息时附加在末尾的转发来源字段，不是转发的消息为空
+ */
+pub fn get_forwarded_field(forwarded_from: Option<&ForwardedFrom>) -> String {
+    return match forwarded_from {
+        Some(forwarded_from) => format!(
+            r#", "forwardedFrom":{}"#,
+            serde_json::to_string(forwarded_from).unwrap()
+        ),
+        None => String::new(),
+    };
+}
+
+fn is_zero(num: &u32) -> bool {
+    return *num == 0;
+}
+
+/** `attach_counts` 为有表情回应或回复的消息附带回应人数与回复数量，其余消息保持不变
+ */
+pub fn attach_counts(
+    serialized_msg: SerializedChatMessage,
+    reactions: &HashMap<MessageID, ReactionCounts>,
+    reply_counts: &HashMap<MessageID, u32>,
+) -> SerializedChatMessage {
+    let mut msg = match serde_json::from_str::<ChatMessage>(&serialized_msg) {
+        Ok(msg) => msg,
+        Err(_) => return serialized_msg,
+    };
+    let counts = reactions.get(&msg.in_chat_id);
+    let reply_count = reply_counts.get(&msg.in_chat_id);
+    if counts.is_none() && reply_count.is_none() {
+        return serialized_msg;
+    }
+    msg.reactions = counts.cloned().unwrap_or_default();
+    msg.reply_count = reply_count.cloned().unwrap_or(0);
+    return serde_json::to_string(&msg).unwrap();
 }
@@ -4,6 +4,8 @@
 
 mod chat;
 mod chat_message;
+mod search;
 
 pub use chat::*;
 pub use chat_message::*;
+pub use search::*;
@@ -0,0 +1,203 @@
+/*!
+ * 消息搜索
+ * 消息文本按字切分为词项写入倒排索引，搜索时先用关键词的词项求交集得到候选消息，再按子串精确匹配
+ * 文本与关键词在切分前都转为小写，搜索不区分大小写
+*/
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use super::ChatMessage;
+use crate::config::datatype::{ChatID, MessageID, SerializedChatMessage};
+use crate::user::{ChatMessageType, MentionTextType, ReplyTextType};
+
+impl ChatMessage {
+    /** `get_search_text` 消息中可被搜索的文本，已转为小写
+     * 只有Text, ReplyText与MentionText可被搜索，其余类型以及无法解析的内容返回None
+     */
+    pub fn get_search_text(&self) -> Option<String> {
+        let text = match self.r#type {
+            ChatMessageType::Text => get_plain_text(&self.serialized_content),
+            ChatMessageType::ReplyText => {
+                match serde_json::from_str::<ReplyTextType>(&self.serialized_content) {
+                    Ok(content) => content.text,
+                    Err(_) => get_plain_text(&self.serialized_content),
+                }
+            }
+            ChatMessageType::MentionText => {
+                serde_json::from_str::<MentionTextType>(&self.serialized_content)
+                    .ok()?
+                    .text
+            }
+            _ => return None,
+        };
+        return Some(text.to_lowercase());
+    }
+}
+
+/// 系统消息等会将文本再序列化一次，能解析为字符串时取解析后的文本
+fn get_plain_text(content: &str) -> String {
+    return serde_json::from_str::<String>(content).unwrap_or_else(|_| content.to_string());
+}
+
+/** `get_search_tokens` 文本在倒排索引中的全部词项：每段连续非空白文本中的单字与相邻两字
+ */
+pub fn get_search_tokens(text: &str) -> BTreeSet<String> {
+    let mut tokens = BTreeSet::new();
+    for word in text.to_lowercase().split_whitespace() {
+        let chars: Vec<char> = word.chars().collect();
+        for (i, c) in chars.iter().enumerate() {
+            tokens.insert(c.to_string());
+            if let Some(next) = chars.get(i + 1) {
+                tokens.insert([*c, *next].iter().collect());
+            }
+        }
+    }
+    return tokens;
+}
+
+/** `get_keyword_tokens` 搜索关键词需要命中的词项
+ * 多字的片段只取相邻两字即可覆盖其中的单字，单字的片段取其本身
+ */
+pub fn get_keyword_tokens(keyword: &str) -> Vec<String> {
+    let mut tokens = BTreeSet::new();
+    for word in keyword.to_lowercase().split_whitespace() {
+        let chars: Vec<char> = word.chars().collect();
+        if chars.len() == 1 {
+            tokens.insert(word.to_string());
+        }
+        for pair in chars.windows(2) {
+            tokens.insert(pair.iter().collect());
+        }
+    }
+    return tokens.into_iter().collect();
+}
+
+/** `get_message_search_tokens` 序列化消息在倒排索引中的词项，消息不可被搜索时为空
+ */
+pub fn get_message_search_tokens(serialized_msg: &str) -> BTreeSet<String> {
+    return serde_json::from_str::<ChatMessage>(serialized_msg)
+        .ok()
+        .and_then(|msg| msg.get_search_text())
+        .map_or_else(BTreeSet::new, |text| get_search_tokens(&text));
+}
+
+/** `match_keyword` 消息文本包含关键词时传出解析后的消息，已撤回或无法解析的消息不会匹配
+ */
+pub fn match_keyword(serialized_msg: &str, keyword: &str) -> Option<ChatMessage> {
+    let msg = serde_json::from_str::<ChatMessage>(serialized_msg).ok()?;
+    if msg.get_search_text()?.contains(keyword) {
+        return Some(msg);
+    }
+    return None;
+}
+
+/** `MAX_SEARCH_CANDIDATES` 每页最多检查的候选消息数量
+ * 候选消息中匹配的不足一页时也会返回，客户端从`next_cursor`继续，单次搜索不会读出整个聊天记录
+ */
+pub const MAX_SEARCH_CANDIDATES: usize = 256;
+
+/** `SearchCursor` 搜索结果的翻页位置
+ * 结果按chat_id从小到大排列，同一聊天中按in_chat_id从新到旧排列，下一页从该消息之后继续
+ */
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchCursor {
+    pub chat_id: ChatID,
+    pub in_chat_id: MessageID,
+}
+
+/** `SearchPage` 一页搜索结果，`next_cursor`为None时已没有更多结果
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchPage {
+    pub messages: Vec<SerializedChatMessage>,
+    pub next_cursor: Option<SearchCursor>,
+}
+
+/** `get_search_ranges` 需要搜索的聊天与各自in_chat_id的上界（不含），按chat_id从小到大排列
+ * 跳过cursor之前的聊天，cursor所在的聊天从cursor之前的消息开始
+ */
+pub fn get_search_ranges(
+    chat_ids: &[ChatID],
+    cursor: Option<SearchCursor>,
+) -> Vec<(ChatID, MessageID)> {
+    let chat_ids: BTreeSet<ChatID> = chat_ids.iter().copied().collect();
+    return chat_ids
+        .into_iter()
+        .filter_map(|chat_id| match cursor {
+            Some(cursor) if chat_id < cursor.chat_id => None,
+            Some(cursor) if chat_id == cursor.chat_id => Some((chat_id, cursor.in_chat_id)),
+            _ => Some((chat_id, MessageID::MAX)),
+        })
+        .collect();
+}
+
+/** `SearchCollector` 逐条检查候选消息，收集一页搜索结果
+ */
+pub struct SearchCollector<'a> {
+    keyword: &'a str,
+    limit: usize,
+    remaining: usize,
+    messages: Vec<SerializedChatMessage>,
+    last_checked: Option<SearchCursor>,
+}
+
+impl<'a> SearchCollector<'a> {
+    pub fn new(keyword: &'a str, limit: u8) -> SearchCollector<'a> {
+        return SearchCollector {
+            keyword,
+            limit: limit as usize,
+            remaining: MAX_SEARCH_CANDIDATES,
+            messages: vec![],
+            last_checked: None,
+        };
+    }
+
+    /** `is_done` 已经取满一页，或已检查了本页允许的全部候选消息
+     */
+    pub fn is_done(&self) -> bool {
+        return self.messages.len() >= self.limit || self.remaining == 0;
+    }
+
+    /** `remaining_candidates` 本页还可以检查的候选消息数量
+     */
+    pub fn remaining_candidates(&self) -> usize {
+        return self.remaining;
+    }
+
+    /** `check` 检查一条候选消息，消息已被撤回或清理时为None
+     */
+    pub fn check(
+        &mut self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        serialized_msg: Option<SerializedChatMessage>,
+    ) {
+        self.remaining = self.remaining.saturating_sub(1);
+        self.last_checked = Some(SearchCursor {
+            chat_id,
+            in_chat_id,
+        });
+        if let Some(serialized_msg) = serialized_msg {
+            if match_keyword(&serialized_msg, self.keyword).is_some() {
+                self.messages.push(serialized_msg);
+            }
+        }
+    }
+
+    /** `finish` 结束本页，全部候选消息都已检查完时没有下一页
+     */
+    pub fn finish(self) -> SearchPage {
+        let next_cursor = if self.is_done() {
+            self.last_checked
+        } else {
+            None
+        };
+        return SearchPage {
+            messages: self.messages,
+            next_cursor,
+        };
+    }
+}
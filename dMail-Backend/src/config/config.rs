@@ -1,16 +1,23 @@
 /*! 配置文件解析 */
 
 use std::{
+    collections::HashMap,
     fs::File,
     io::{Read, Write},
     path::PathBuf,
     process::exit,
 };
 
+use ed25519_dalek::SigningKey;
 use once_cell::sync::{Lazy, OnceCell};
+use rand::rngs::OsRng;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
+use super::datatype::ChatID;
+use crate::user::user_session::protocol::PROTOCOL_VERSION;
+use crate::utils::base64;
+
 static CONFIG: OnceCell<Config> = OnceCell::new();
 
 /** `PWD_PATTERN` 从配置文件中生成的密码正则匹配式
@@ -31,6 +38,11 @@ pub struct Config {
     pub user: UserConfig,
     pub database: DatabaseConfig,
     pub s3_oss: S3Config,
+    /// 缺省时不清理任何消息
+    #[serde(default)]
+    pub retention: RetentionConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
 }
 
 /** `TlsConfig` tls有关的配置信息的数据类型
@@ -50,8 +62,57 @@ pub struct ProtocolConfig {
     pub max_messages_num_in_one_chat_when_pulling: u8,
     /// 当用户使用get_messages_in_chat接口时，单次能发送的最大消息数量
     pub max_messages_num_in_one_chat_when_getting: u8,
+    /// 当用户搜索消息时，单次能发送的最大消息数量，缺省为30
+    #[serde(default = "default_max_messages_num_when_searching")]
+    pub max_messages_num_when_searching: u8,
+    /// 单次转发的最大消息数量，缺省为100
+    #[serde(default = "default_max_messages_num_when_forwarding")]
+    pub max_messages_num_when_forwarding: u8,
     /// 当群聊人数超过这一阈值时，会发送到server_worker进行信息发送
     pub worker_send_messages_member_num_threshold: u8,
+    /// 群聊已读位置的推送间隔（毫秒），间隔内的变化合并为一次推送，缺省为1000
+    #[serde(default = "default_read_receipt_push_interval")]
+    pub read_receipt_push_interval: u32,
+    /// 同一聊天中正在输入状态的最短转发间隔（毫秒），缺省为2000
+    #[serde(default = "default_typing_throttle_interval")]
+    pub typing_throttle_interval: u32,
+    /// 正在输入状态的过期时间（毫秒），超时未刷新时自动转发停止输入，缺省为5000
+    #[serde(default = "default_typing_expire_time")]
+    pub typing_expire_time: u32,
+    /// 单次查询在线状态的最大用户数量，缺省为100
+    #[serde(default = "default_max_users_num_when_getting_presence")]
+    pub max_users_num_when_getting_presence: u16,
+    /// 允许连接使用的最低协议版本，缺省为2，使用固定nonce的旧版客户端需将其设为1才能连接
+    #[serde(default = "default_min_protocol_version")]
+    pub min_protocol_version: u32,
+}
+
+fn default_max_messages_num_when_searching() -> u8 {
+    return 30;
+}
+
+fn default_max_messages_num_when_forwarding() -> u8 {
+    return 100;
+}
+
+fn default_min_protocol_version() -> u32 {
+    return PROTOCOL_VERSION;
+}
+
+fn default_read_receipt_push_interval() -> u32 {
+    return 1000;
+}
+
+fn default_typing_throttle_interval() -> u32 {
+    return 2000;
+}
+
+fn default_typing_expire_time() -> u32 {
+    return 5000;
+}
+
+fn default_max_users_num_when_getting_presence() -> u16 {
+    return 100;
 }
 
 /** `SafetyConfig` 安全性保证的配置信息的数据类型
@@ -60,6 +121,34 @@ pub struct ProtocolConfig {
 pub struct SafetyConfig {
     pub max_msg_length: u16,
     pub max_notice_length: u16,
+    /// 表情回应的最大长度（字节），缺省为32
+    #[serde(default = "default_max_reaction_length")]
+    pub max_reaction_length: u16,
+    /// 每个聊天中置顶消息的最大数量，缺省为50
+    #[serde(default = "default_max_pinned_messages_num")]
+    pub max_pinned_messages_num: u16,
+    /// 是否允许使用RSA公钥交换对称秘钥的握手，缺省为true
+    #[serde(default = "default_enable_handshake")]
+    pub enable_rsa_handshake: bool,
+    /// 是否允许使用X25519密钥交换的握手，缺省为true
+    #[serde(default = "default_enable_handshake")]
+    pub enable_x25519_handshake: bool,
+    /// 服务端签名X25519临时公钥所用的Ed25519私钥（base64），启用X25519握手时必须配置
+    /// 对应的公钥不在握手中发送，需预先分发给客户端，客户端用其校验服务端的签名
+    #[serde(default)]
+    pub handshake_signing_key: Option<String>,
+}
+
+fn default_max_reaction_length() -> u16 {
+    return 32;
+}
+
+fn default_max_pinned_messages_num() -> u16 {
+    return 50;
+}
+
+fn default_enable_handshake() -> bool {
+    return true;
 }
 
 /** `EmailConfig` 邮件配置信息的数据类型
@@ -82,17 +171,63 @@ pub struct EmailConfig {
  */
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserConfig {
+    /// 刷新令牌的有效期（秒）
     pub token_expire_time: u32,
+    /// 访问令牌的有效期（秒），缺省为3600
+    #[serde(default = "default_access_token_expire_time")]
+    pub access_token_expire_time: u32,
     pub max_user_name_length: u32,
     pub heart_beat_time: u32,
     pub password_check: String,
     pub sender_revoke_expire: u32,
+    /// 发送者可以编辑消息的时限（秒），缺省为900
+    #[serde(default = "default_sender_edit_expire")]
+    pub sender_edit_expire: u32,
+    /// 每个用户同时等待发送的定时消息的最大数量，缺省为100
+    #[serde(default = "default_max_scheduled_messages_num")]
+    pub max_scheduled_messages_num: u16,
+    /// 检查定时消息是否到期的间隔（秒），缺省为1
+    #[serde(default = "default_schedule_check_interval")]
+    pub schedule_check_interval: u32,
+    /// 检查阅后即焚的消息是否过期的间隔（秒），缺省为5
+    #[serde(default = "default_disappearing_check_interval")]
+    pub disappearing_check_interval: u32,
+    /// 每位用户同时在线的最大设备数量，缺省为5
+    #[serde(default = "default_max_devices_num")]
+    pub max_devices_num: u8,
+}
+
+fn default_access_token_expire_time() -> u32 {
+    return 3600;
+}
+
+fn default_sender_edit_expire() -> u32 {
+    return 900;
+}
+
+fn default_max_scheduled_messages_num() -> u16 {
+    return 100;
+}
+
+fn default_schedule_check_interval() -> u32 {
+    return 1;
+}
+
+fn default_disappearing_check_interval() -> u32 {
+    return 5;
+}
+
+fn default_max_devices_num() -> u8 {
+    return 5;
 }
 
 /** `DatabaseConfig` 数据库的配置信息的数据类型
  */
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DatabaseConfig {
+    /// 使用的存储后端，缺省时为Redis
+    #[serde(default)]
+    pub backend: DatabaseBackendType,
     pub address: String,
     pub pool_max_open: usize,
     pub pool_max_idle: usize,
@@ -100,6 +235,116 @@ pub struct DatabaseConfig {
     pub pool_expire: usize,
 }
 
+/** `DatabaseBackendType` 可选用的存储后端类型
+ */
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DatabaseBackendType {
+    #[default]
+    Redis,
+    /// 进程内存储，重启后数据丢失，用于测试与开发
+    Memory,
+    /// PostgreSQL存储，启动时自动执行迁移脚本
+    Postgres,
+}
+
+/** `RetentionConfig` 消息保留策略的配置信息的数据类型
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RetentionConfig {
+    /// 后台清理任务的执行间隔，单位为秒
+    pub prune_interval: u32,
+    /// 所有聊天默认使用的保留策略
+    pub global: RetentionPolicy,
+    /// 单独为某些群聊设置的保留策略，优先于全局策略
+    pub groups: HashMap<ChatID, RetentionPolicy>,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        return RetentionConfig {
+            prune_interval: 3600,
+            global: RetentionPolicy::default(),
+            groups: HashMap::new(),
+        };
+    }
+}
+
+/** `RetentionPolicy` 单个聊天的消息保留策略，字段为空时不做对应的限制
+ */
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// 消息最长保留的时间，单位为秒
+    pub max_age: Option<u64>,
+    /// 最多保留的消息数量
+    pub max_count: Option<u64>,
+}
+
+impl RetentionPolicy {
+    pub fn is_unlimited(&self) -> bool {
+        return self.max_age.is_none() && self.max_count.is_none();
+    }
+}
+
+/** `RateLimitConfig` 请求速率限制的配置信息的数据类型
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    pub enable: bool,
+    /// 同一IP的所有连接发来的消息
+    pub per_ip: RateLimitRule,
+    /// 同一用户的所有设备发来的消息，只对已登录的连接生效
+    pub per_user: RateLimitRule,
+    /// 单独限制的消息，键为消息的command，已登录时按用户计，否则按IP计
+    pub per_command: HashMap<String, RateLimitRule>,
+    /// 同一IP申请邮箱验证码
+    pub email_code: RateLimitRule,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        return RateLimitConfig {
+            enable: true,
+            per_ip: RateLimitRule {
+                capacity: 500,
+                refill_per_sec: 100.0,
+            },
+            per_user: RateLimitRule {
+                capacity: 200,
+                refill_per_sec: 50.0,
+            },
+            per_command: HashMap::from([
+                (
+                    "SendMessage".to_string(),
+                    RateLimitRule {
+                        capacity: 50,
+                        refill_per_sec: 10.0,
+                    },
+                ),
+                (
+                    "SendRequest".to_string(),
+                    RateLimitRule {
+                        capacity: 20,
+                        refill_per_sec: 1.0,
+                    },
+                ),
+            ]),
+            email_code: RateLimitRule {
+                capacity: 5,
+                refill_per_sec: 0.1,
+            },
+        };
+    }
+}
+
+/** `RateLimitRule` 令牌桶的参数，桶满时允许连续发送`capacity`次请求
+ */
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RateLimitRule {
+    pub capacity: u32,
+    /// 每秒补充的令牌数量
+    pub refill_per_sec: f64,
+}
+
 /** `S3Config` oss配置信息的数据类型
  */
 #[derive(Debug, Serialize, Deserialize)]
@@ -143,6 +388,22 @@ impl Config {
         }
     }
 
+    /** `init_for_test` 读取配置文件生成CONFIG，数据库固定使用内存后端，测试不依赖外部的数据库服务
+     * 未配置握手签名私钥时使用固定的私钥
+     */
+    #[cfg(test)]
+    pub fn init_for_test() {
+        let mut config = Self::try_read_from_file().unwrap_or_else(|_| Config::default());
+        config.database.backend = DatabaseBackendType::Memory;
+        if config.safety.handshake_signing_key.is_none() {
+            config.safety.handshake_signing_key = Some(base64::encode([7; 32]));
+        }
+        // 测试中仍有不协商版本的旧版客户端
+        config.protocol.min_protocol_version =
+            crate::user::user_session::protocol::LEGACY_PROTOCOL_VERSION;
+        CONFIG.get_or_init(|| config);
+    }
+
     /** `get` 调用已初始化的CONFIG
      */
     pub fn get() -> &'static Config {
@@ -162,11 +423,25 @@ impl Config {
             safety: SafetyConfig {
                 max_msg_length: 500,
                 max_notice_length: 500,
+                max_reaction_length: 32,
+                max_pinned_messages_num: 50,
+                enable_rsa_handshake: true,
+                enable_x25519_handshake: true,
+                handshake_signing_key: Some(base64::encode(
+                    SigningKey::generate(&mut OsRng).to_bytes(),
+                )),
             },
             protocol: ProtocolConfig {
                 max_messages_num_in_one_chat_when_pulling: 4,
                 max_messages_num_in_one_chat_when_getting: 30,
+                max_messages_num_when_searching: 30,
+                max_messages_num_when_forwarding: 100,
                 worker_send_messages_member_num_threshold: 5,
+                read_receipt_push_interval: 1000,
+                typing_throttle_interval: 2000,
+                typing_expire_time: 5000,
+                max_users_num_when_getting_presence: 100,
+                min_protocol_version: PROTOCOL_VERSION,
             },
             email: EmailConfig {
                 enable: true,
@@ -185,9 +460,16 @@ impl Config {
                 heart_beat_time: 5,
                 password_check: "^[a-fA-F0-9]{64}$".to_string(),
                 sender_revoke_expire: 180,
+                sender_edit_expire: 900,
+                max_scheduled_messages_num: 100,
+                schedule_check_interval: 1,
+                disappearing_check_interval: 5,
+                max_devices_num: 5,
                 token_expire_time: 604800,
+                access_token_expire_time: 3600,
             },
             database: DatabaseConfig {
+                backend: DatabaseBackendType::Redis,
                 address: "redis://127.0.0.1:6379/".to_string(),
                 pool_max_open: 16,
                 pool_max_idle: 8,
@@ -205,6 +487,8 @@ impl Config {
                 presign_put_image_expire: 120,
                 presign_get_expire: 3600 * 24 * 7,
             },
+            retention: RetentionConfig::default(),
+            rate_limit: RateLimitConfig::default(),
         }
     }
 
@@ -1,5 +1,7 @@
 /*! 数据类型定义 */
-use aes_gcm::Aes128Gcm;
+use std::collections::BTreeMap;
+
+use crate::utils::aes::SessionCipher;
 
 /** `ClientID` 客户端消息ID
 */
@@ -31,7 +33,11 @@ pub type UserReqId = u32;
 
 /** `SymCipher` 对称秘钥
 */
-pub type SymCipher = Aes128Gcm;
+pub type SymCipher = SessionCipher;
+
+/** `ProtocolVersion` 连接使用的加密协议版本
+*/
+pub type ProtocolVersion = u32;
 
 /** `EmailCodeValue` 邮箱验证码
 */
@@ -72,3 +78,15 @@ pub type SerializedGroupNotice = String;
 /** `NoticeID` 群公告ID
 */
 pub type NoticeID = u32;
+
+/** `ScheduleID` 定时消息ID
+*/
+pub type ScheduleID = u64;
+
+/** `DeviceID` 用户登录设备ID
+*/
+pub type DeviceID = String;
+
+/** `ReactionCounts` 一条消息中每种表情回应的人数
+*/
+pub type ReactionCounts = BTreeMap<String, u32>;
@@ -0,0 +1,570 @@
+/*!
+ * 存储后端的统一接口，不同的数据库后端（Redis等）分别实现这些特征。
+*/
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use once_cell::sync::OnceCell;
+
+use crate::chat::*;
+use crate::config::datatype::*;
+use crate::database::DatabaseError;
+use crate::oss::{ObjectUploadRequest, PresignUrl};
+use crate::user::*;
+
+/** `UserStorage` 用户、好友关系、用户通知与令牌相关的存储接口
+*/
+#[async_trait]
+pub trait UserStorage {
+    async fn user_register(
+        &self,
+        data: UserRegisterData,
+    ) -> Result<UserRegisterResponse, DatabaseError>;
+
+    async fn user_login_with_password(
+        &self,
+        data: &UserLoginData,
+    ) -> Result<UserLoginResponse, DatabaseError>;
+
+    async fn user_login_with_token(
+        &self,
+        data: &UserLoginData,
+    ) -> Result<UserLoginResponse, DatabaseError>;
+
+    async fn get_user_id_by_email(&self, email: &str) -> Result<Option<UserID>, DatabaseError>;
+
+    async fn get_user_chat_list(
+        &self,
+        user_id: UserID,
+    ) -> Result<Vec<(ChatID, MessageID)>, DatabaseError>;
+
+    async fn get_user_info(&self, user_id: UserID) -> GetUserInfoResponse;
+
+    async fn get_user_email(&self, user_id: UserID) -> Result<String, DatabaseError>;
+
+    async fn make_two_users_be_friends(
+        &self,
+        user_one_id: UserID,
+        user_two_id: UserID,
+    ) -> Result<ChatID, DatabaseError>;
+
+    async fn check_make_friend_error(
+        &self,
+        user_one_id: UserID,
+        user_two_id: UserID,
+    ) -> Result<(), MakeFriendError>;
+
+    async fn set_user_setting(&self, user_id: UserID, user_setting: String) -> SetSettingResponse;
+
+    async fn get_user_setting(&self, user_id: UserID) -> Result<Option<String>, DatabaseError>;
+
+    async fn update_user_name(&self, user_id: UserID, new_name: String) -> UserUpdateResponse;
+
+    async fn update_user_avater(&self, user_id: UserID, new_hash: String) -> UserUpdateResponse;
+
+    async fn update_user_password(
+        &self,
+        user_id: UserID,
+        new_password: String,
+    ) -> UserUpdateResponse;
+
+    async fn unfriend(&self, user_id: UserID, friend_id: UserID) -> UserUnfriendResponse;
+
+    async fn set_user_already_read(
+        &self,
+        user_id: UserID,
+        data: UserSetAlreadyReadData,
+    ) -> SetAlreadyReadResponse;
+
+    async fn check_user_in_chat(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<bool, DatabaseError>;
+
+    async fn write_user_notice(
+        &self,
+        user_id: UserID,
+        timestamp: Timestamp,
+        serialized: &SerializedUserNotice,
+    ) -> Result<(), DatabaseError>;
+
+    async fn get_user_notice(
+        &self,
+        user_id: UserID,
+        start_timestamp: Timestamp,
+    ) -> Result<Vec<SerializedUserNotice>, DatabaseError>;
+
+    async fn get_chat_id_by_friends(
+        &self,
+        user_id1: UserID,
+        user_id2: UserID,
+    ) -> Result<Option<ChatID>, DatabaseError>;
+
+    /// 未找到时返回`Err(true)`，数据库错误时返回`Err(false)`
+    async fn get_user_id(&self, name: &str) -> Result<Vec<UserID>, bool>;
+
+    async fn user_log_off(&self, user_id: UserID) -> (UserLogOffResponse, Vec<(UserID, ChatID)>);
+
+    async fn apply_for_token(
+        &self,
+        user_id: UserID,
+        device_id: &DeviceID,
+    ) -> UserApplyForTokenResponse;
+
+    /// 刷新令牌有效时替换为新的一对令牌，同一刷新令牌只能换取一次
+    async fn refresh_token(
+        &self,
+        data: &UserRefreshTokenData,
+    ) -> Result<UserRefreshTokenResponse, DatabaseError>;
+
+    /// 返回设备上是否有令牌
+    async fn revoke_token(
+        &self,
+        user_id: UserID,
+        device_id: &DeviceID,
+    ) -> Result<bool, DatabaseError>;
+
+    async fn revoke_all_tokens(&self, user_id: UserID) -> Result<(), DatabaseError>;
+
+    async fn check_user_exist(&self, user_id: UserID) -> Result<(), DatabaseError>;
+
+    async fn set_user_last_seen(
+        &self,
+        user_id: UserID,
+        timestamp: Timestamp,
+    ) -> Result<(), DatabaseError>;
+
+    async fn get_user_last_seen(&self, user_id: UserID)
+        -> Result<Option<Timestamp>, DatabaseError>;
+
+    async fn set_presence_hidden(&self, user_id: UserID, hidden: bool)
+        -> Result<(), DatabaseError>;
+
+    async fn get_presence_hidden(&self, user_id: UserID) -> Result<bool, DatabaseError>;
+
+    async fn set_device_keys(
+        &self,
+        user_id: UserID,
+        device_id: &DeviceID,
+        serialized: &str,
+    ) -> Result<(), DatabaseError>;
+
+    async fn get_device_keys(
+        &self,
+        user_id: UserID,
+        device_id: &DeviceID,
+    ) -> Result<Option<String>, DatabaseError>;
+
+    async fn get_user_device_keys(&self, user_id: UserID) -> Result<Vec<String>, DatabaseError>;
+}
+
+/** `ChatStorage` 聊天、消息、群管理与群公告相关的存储接口
+*/
+#[async_trait]
+pub trait ChatStorage {
+    async fn create_group_chat(
+        &self,
+        creator_id: UserID,
+        data: UserCreateGroupChatData,
+    ) -> Result<ChatID, DatabaseError>;
+
+    async fn add_user_to_group_chat(
+        &self,
+        chat_id: ChatID,
+        user_id: UserID,
+    ) -> Result<(), DatabaseError>;
+
+    async fn write_message_to_chat(
+        &self,
+        r#type: &str,
+        serialized_content: String,
+        chat_id: ChatID,
+        sender_id: UserID,
+        forwarded_from: Option<&ForwardedFrom>,
+        reply_to: Option<MessageID>,
+    ) -> Result<(SerializedChatMessage, MessageID, Timestamp), DatabaseError>;
+
+    async fn check_user_can_send_in_chat(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<ChatType, UserSendMessageResponseState>;
+
+    async fn get_chat_user_list(&self, chat_id: ChatID) -> Result<ChatMembers, DatabaseError>;
+
+    async fn get_private_chat_user_list(
+        &self,
+        chat_id: ChatID,
+    ) -> Result<Option<(UserID, UserID)>, DatabaseError>;
+
+    async fn get_messages_in_chat(
+        &self,
+        chat_id: ChatID,
+        start_msg_id: MessageID,
+        end_msg_id: Option<MessageID>,
+    ) -> Result<Vec<SerializedChatMessage>, DatabaseError>;
+
+    async fn get_chats_last_messages(
+        &self,
+        chats: &[(ChatID, MessageID)],
+        max_messages_per_chat: u8,
+    ) -> Result<Vec<SerializedChatMessage>, DatabaseError>;
+
+    async fn get_chat_info(
+        &self,
+        chat_id: ChatID,
+    ) -> Result<Option<SerializedChatInfo>, DatabaseError>;
+
+    async fn revoke_message(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        sender_id: UserID,
+        timestamp: Timestamp,
+    ) -> Result<(), DatabaseError>;
+
+    async fn edit_message(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        old_msg: &str,
+        new_msg: &str,
+    ) -> Result<bool, DatabaseError>;
+
+    async fn get_edit_history(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+    ) -> Result<Vec<SerializedChatMessage>, DatabaseError>;
+
+    async fn add_reaction(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        user_id: UserID,
+        emoji: &str,
+    ) -> Result<bool, DatabaseError>;
+
+    async fn remove_reaction(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        user_id: UserID,
+        emoji: &str,
+    ) -> Result<bool, DatabaseError>;
+
+    async fn get_reaction_counts(
+        &self,
+        chat_id: ChatID,
+        start_msg_id: MessageID,
+        end_msg_id: Option<MessageID>,
+    ) -> Result<HashMap<MessageID, ReactionCounts>, DatabaseError>;
+
+    async fn get_thread_replies(
+        &self,
+        chat_id: ChatID,
+        root_id: MessageID,
+    ) -> Result<Vec<SerializedChatMessage>, DatabaseError>;
+
+    async fn get_reply_counts(
+        &self,
+        chat_id: ChatID,
+        start_msg_id: MessageID,
+        end_msg_id: Option<MessageID>,
+    ) -> Result<HashMap<MessageID, u32>, DatabaseError>;
+
+    async fn pin_message(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+    ) -> Result<bool, DatabaseError>;
+
+    async fn unpin_message(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+    ) -> Result<bool, DatabaseError>;
+
+    async fn get_pinned_messages(
+        &self,
+        chat_id: ChatID,
+    ) -> Result<Vec<SerializedChatMessage>, DatabaseError>;
+
+    async fn get_chat_ids(&self) -> Result<Vec<ChatID>, DatabaseError>;
+
+    async fn prune_messages(
+        &self,
+        chat_id: ChatID,
+        min_timestamp: Option<Timestamp>,
+        max_count: Option<MessageID>,
+    ) -> Result<MessageID, DatabaseError>;
+
+    async fn get_pruned_id(&self, chat_id: ChatID) -> Result<MessageID, DatabaseError>;
+
+    async fn set_disappearing_ttl(
+        &self,
+        chat_id: ChatID,
+        ttl: Option<u32>,
+    ) -> Result<(), DatabaseError>;
+
+    async fn get_disappearing_ttl(&self, chat_id: ChatID) -> Result<Option<u32>, DatabaseError>;
+
+    async fn get_disappearing_chats(&self) -> Result<Vec<(ChatID, u32)>, DatabaseError>;
+
+    async fn get_expired_id(&self, chat_id: ChatID) -> Result<MessageID, DatabaseError>;
+
+    async fn set_expired_id(
+        &self,
+        chat_id: ChatID,
+        expired_id: MessageID,
+    ) -> Result<(), DatabaseError>;
+
+    async fn search_messages(
+        &self,
+        chat_ids: &[ChatID],
+        keyword: &str,
+        cursor: Option<SearchCursor>,
+        limit: u8,
+    ) -> Result<SearchPage, DatabaseError>;
+
+    async fn add_scheduled_message(
+        &self,
+        sender_id: UserID,
+        deliver_at: Timestamp,
+        message: &UserSendMessageData,
+    ) -> Result<ScheduleID, DatabaseError>;
+
+    async fn get_scheduled_messages(
+        &self,
+        user_id: UserID,
+    ) -> Result<Vec<ScheduledMessage>, DatabaseError>;
+
+    async fn cancel_scheduled_message(
+        &self,
+        user_id: UserID,
+        schedule_id: ScheduleID,
+    ) -> Result<bool, DatabaseError>;
+
+    async fn get_due_scheduled_messages(
+        &self,
+        now: Timestamp,
+    ) -> Result<Vec<ScheduledMessage>, DatabaseError>;
+
+    async fn remove_scheduled_message(
+        &self,
+        schedule_id: ScheduleID,
+    ) -> Result<bool, DatabaseError>;
+
+    async fn check_group_invitation_error(
+        &self,
+        sender_id: UserID,
+        receiver_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), GroupInvitationError>;
+
+    async fn check_invited_join_group_error(
+        &self,
+        sender_id: UserID,
+        receiver_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), InvitedJoinGroupError>;
+
+    async fn quit_group_chat(&self, user_id: UserID, chat_id: ChatID) -> UserQuitGroupChatResponse;
+
+    async fn set_as_admin(&self, user_id: UserID, chat_id: ChatID) -> UserSetGroupAdminResponse;
+
+    async fn check_join_group_error(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), JoinGroupError>;
+
+    async fn get_chat_admins_list(
+        &self,
+        chat_id: ChatID,
+    ) -> Result<UserRequestHandler, DatabaseError>;
+
+    async fn get_chat_owner(&self, chat_id: ChatID) -> Result<UserID, DatabaseError>;
+
+    async fn check_user_is_owner(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<bool, DatabaseError>;
+
+    async fn check_user_is_admin(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<bool, DatabaseError>;
+
+    async fn owner_transfer(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> UserGroupOwnerTransferResponse;
+
+    async fn add_group_notice(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+        client_id: ClientID,
+        notice: String,
+    ) -> UserSendGroupNoticeResponse;
+
+    async fn pull_group_notice(
+        &self,
+        chat_id: ChatID,
+        last_notice_id: NoticeID,
+    ) -> UserPullGroupNoticeResponse;
+
+    async fn update_group_info(
+        &self,
+        chat_id: ChatID,
+        data: UserUpdateGroupContent,
+    ) -> UserUpdateGroupInfoResponse;
+
+    async fn unset_admin(&self, user_id: UserID, chat_id: ChatID) -> UserUnsetGroupAdminResponse;
+
+    async fn check_is_group_chat(&self, chat_id: ChatID) -> Result<bool, DatabaseError>;
+
+    async fn get_user_read_in_group(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+    ) -> UserGetUserReadInGroupResponse;
+
+    async fn get_user_read_in_private(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> UserGetUserReadInPrivateResponse;
+}
+
+/** `RequestStorage` 用户请求（好友申请、加群申请、群邀请）相关的存储接口
+*/
+#[async_trait]
+pub trait RequestStorage {
+    async fn get_user_requests(
+        &self,
+        user_id: UserID,
+        start_req_id: UserReqId,
+    ) -> Result<Vec<SerializedRequest>, DatabaseError>;
+
+    async fn write_user_request(
+        &self,
+        sender_id: UserID,
+        data: UserSendRequestData,
+        handler: &UserRequestHandler,
+    ) -> Result<(SerializedRequest, UserRequestInfo), DatabaseError>;
+
+    async fn store_user_request(
+        &self,
+        user_id: UserID,
+        req_id: UserReqId,
+    ) -> Result<(), DatabaseError>;
+
+    async fn get_user_request(
+        &self,
+        req_id: UserReqId,
+    ) -> Result<Option<UserRequset>, DatabaseError>;
+
+    async fn set_user_request_state(
+        &self,
+        req_id: UserReqId,
+        state: UserRequestState,
+    ) -> Result<(), UserSolveRequestState>;
+
+    async fn write_friend_request_send(
+        &self,
+        user_one_id: UserID,
+        user_two_id: UserID,
+    ) -> Result<(), DatabaseError>;
+
+    async fn delete_friend_request_send(
+        &self,
+        user_one_id: UserID,
+        user_two_id: UserID,
+    ) -> Result<(), DatabaseError>;
+
+    async fn write_join_group_request_send(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), DatabaseError>;
+
+    async fn delete_join_group_request_send(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), DatabaseError>;
+
+    async fn write_invite_request_send(
+        &self,
+        inviter_id: UserID,
+        receiver_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), DatabaseError>;
+
+    async fn delete_invite_request_send(
+        &self,
+        inviter_id: UserID,
+        receiver_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), DatabaseError>;
+}
+
+/** `FileStorage` 文件上传请求与文件公开url相关的存储接口
+*/
+#[async_trait]
+pub trait FileStorage {
+    async fn get_file_public_url(
+        &self,
+        hash: &str,
+    ) -> Result<Option<(PresignUrl, SerializedFilePubUrl)>, DatabaseError>;
+
+    async fn write_file_public_url(
+        &self,
+        hash: &str,
+        pub_url: &PresignUrl,
+    ) -> Result<(), DatabaseError>;
+
+    async fn write_upload_request(
+        &self,
+        req: ObjectUploadRequest,
+    ) -> Result<UploadId, DatabaseError>;
+
+    async fn get_upload_request(
+        &self,
+        upload_id: UploadId,
+    ) -> Result<Option<ObjectUploadRequest>, DatabaseError>;
+
+    async fn get_file_url(&self, hash: &str) -> Result<SerializedFilePubUrl, DatabaseError>;
+}
+
+/** `StorageBackend` 一个完整的存储后端，需要实现所有的存储接口
+*/
+#[async_trait]
+pub trait StorageBackend:
+    UserStorage + ChatStorage + RequestStorage + FileStorage + Send + Sync
+{
+    /// 建立与数据库的连接，在服务器启动时调用一次
+    async fn connect(&self) -> Result<(), DatabaseError>;
+
+    /// 清空数据库中的所有数据
+    async fn flush(&self) -> Result<(), DatabaseError>;
+}
+
+static BACKEND: OnceCell<Box<dyn StorageBackend>> = OnceCell::new();
+
+/// 设置全局使用的存储后端，重复设置时保留第一次设置的后端
+pub(super) fn set_backend(backend: Box<dyn StorageBackend>) {
+    BACKEND.set(backend).ok();
+}
+
+/// 获得全局使用的存储后端
+pub(super) fn backend() -> &'static dyn StorageBackend {
+    return BACKEND.get().expect("数据库未连接").as_ref();
+}
@@ -1,21 +1,22 @@
 use crate::chat::*;
 use crate::config::datatype::*;
+use crate::database::DatabaseError;
 
 use crate::user::*;
 
-use super::redis;
+use super::backend::backend;
 
 /// 创建一个群聊，传入创建者ID，传出群聊ID
 pub async fn create_group_chat(
     creator_id: UserID,
     data: UserCreateGroupChatData,
-) -> Result<ChatID, ()> {
-    return redis::create_group_chat(creator_id, data).await;
+) -> Result<ChatID, DatabaseError> {
+    return backend().create_group_chat(creator_id, data).await;
 }
 
 /// 向一个群聊中添加用户
-pub async fn add_user_to_chat(chat_id: ChatID, user_id: UserID) -> Result<(), ()> {
-    return redis::add_user_to_group_chat(chat_id, user_id).await;
+pub async fn add_user_to_chat(chat_id: ChatID, user_id: UserID) -> Result<(), DatabaseError> {
+    return backend().add_user_to_group_chat(chat_id, user_id).await;
 }
 
 /// 向消息列表写入一条Message
@@ -25,8 +26,52 @@ pub async fn write_message_to_chat(
     serialized_content: String,
     chat_id: ChatID,
     user_id: UserID,
-) -> Result<(SerializedChatMessage, MessageID, Timestamp), ()> {
-    return redis::write_message_to_chat(r#type, serialized_content, chat_id, user_id).await;
+) -> Result<(SerializedChatMessage, MessageID, Timestamp), DatabaseError> {
+    return backend()
+        .write_message_to_chat(r#type, serialized_content, chat_id, user_id, None, None)
+        .await;
+}
+
+/// 向消息列表写入一条回复root_id的Message，同时将其记为root_id的回复并增加root_id的回复数量
+/// 成功时，返回序列化后的ChatMessage 与 In chat ID
+pub async fn reply_message_to_chat(
+    r#type: &str,
+    serialized_content: String,
+    chat_id: ChatID,
+    user_id: UserID,
+    root_id: MessageID,
+) -> Result<(SerializedChatMessage, MessageID, Timestamp), DatabaseError> {
+    return backend()
+        .write_message_to_chat(
+            r#type,
+            serialized_content,
+            chat_id,
+            user_id,
+            None,
+            Some(root_id),
+        )
+        .await;
+}
+
+/// 向消息列表写入一条转发的Message，消息中附带其来源
+/// 成功时，返回序列化后的ChatMessage 与 In chat ID
+pub async fn forward_message_to_chat(
+    r#type: &str,
+    serialized_content: String,
+    chat_id: ChatID,
+    user_id: UserID,
+    forwarded_from: &ForwardedFrom,
+) -> Result<(SerializedChatMessage, MessageID, Timestamp), DatabaseError> {
+    return backend()
+        .write_message_to_chat(
+            r#type,
+            serialized_content,
+            chat_id,
+            user_id,
+            Some(forwarded_from),
+            None,
+        )
+        .await;
 }
 
 /// 检查用户是否能在Chat中发表信息
@@ -35,34 +80,68 @@ pub async fn check_user_can_send_in_chat(
     user_id: UserID,
     chat_id: ChatID,
 ) -> Result<ChatType, UserSendMessageResponseState> {
-    return redis::check_user_can_send_in_chat(user_id, chat_id).await;
+    return backend()
+        .check_user_can_send_in_chat(user_id, chat_id)
+        .await;
 }
 
 /// 获得一个Chat中所有的用户
-pub async fn get_chat_user_list(chat_id: ChatID) -> Result<ChatMembers, ()> {
-    return redis::get_chat_user_list(chat_id).await;
+pub async fn get_chat_user_list(chat_id: ChatID) -> Result<ChatMembers, DatabaseError> {
+    return backend().get_chat_user_list(chat_id).await;
 }
 
-/// 获得一个Chat中从start_msg_id到end_msg_id的所有消息
+/// 获得一个Chat中从start_msg_id到end_msg_id的所有消息，有表情回应或回复的消息附带回应人数与回复数量
 pub async fn get_messages_in_chat(
     chat_id: ChatID,
     start_msg_id: MessageID,
     end_msg_id: Option<MessageID>,
-) -> Result<Vec<SerializedChatMessage>, ()> {
-    return redis::get_messages_in_chat(chat_id, start_msg_id, end_msg_id).await;
+) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+    let msgs = backend()
+        .get_messages_in_chat(chat_id, start_msg_id, end_msg_id)
+        .await?;
+    if msgs.is_empty() {
+        return Ok(msgs);
+    }
+
+    let reactions = backend()
+        .get_reaction_counts(chat_id, start_msg_id, end_msg_id)
+        .await?;
+    let reply_counts = backend()
+        .get_reply_counts(chat_id, start_msg_id, end_msg_id)
+        .await?;
+    if reactions.is_empty() && reply_counts.is_empty() {
+        return Ok(msgs);
+    }
+    return Ok(msgs
+        .into_iter()
+        .map(|msg| attach_counts(msg, &reactions, &reply_counts))
+        .collect());
+}
+
+/// 获得一条消息存储的原样，不附带表情回应与回复数量
+pub async fn get_message(
+    chat_id: ChatID,
+    in_chat_id: MessageID,
+) -> Result<Option<SerializedChatMessage>, DatabaseError> {
+    let mut msgs = backend()
+        .get_messages_in_chat(chat_id, in_chat_id, Some(in_chat_id))
+        .await?;
+    return Ok(msgs.pop());
 }
 
 /// 获取一个Chat最后一条消息
 pub async fn get_chats_last_messages(
-    chats: &Vec<(ChatID, MessageID)>,
+    chats: &[(ChatID, MessageID)],
     max_messages_per_chat: u8,
-) -> Result<Vec<SerializedChatMessage>, ()> {
-    return redis::get_chats_last_messages(chats, max_messages_per_chat).await;
+) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+    return backend()
+        .get_chats_last_messages(chats, max_messages_per_chat)
+        .await;
 }
 
 /// 获得一个Chat的基本信息
-pub async fn get_chat_info(chat_id: ChatID) -> Result<Option<SerializedChatInfo>, ()> {
-    return redis::get_chat_info(chat_id).await;
+pub async fn get_chat_info(chat_id: ChatID) -> Result<Option<SerializedChatInfo>, DatabaseError> {
+    return backend().get_chat_info(chat_id).await;
 }
 
 /// 撤回消息
@@ -71,8 +150,182 @@ pub async fn revoke_message(
     in_chat_id: MessageID,
     sender_id: UserID,
     timestamp: Timestamp,
-) -> Result<(), ()> {
-    return redis::revoke_message(chat_id, in_chat_id, sender_id, timestamp).await;
+) -> Result<(), DatabaseError> {
+    return backend()
+        .revoke_message(chat_id, in_chat_id, sender_id, timestamp)
+        .await;
+}
+
+/// 将一条消息替换为编辑后的版本，原消息写入编辑历史
+/// 消息已不是old_msg（被撤回、清理或再次编辑）时不做修改，传出false
+pub async fn edit_message(
+    chat_id: ChatID,
+    in_chat_id: MessageID,
+    old_msg: &str,
+    new_msg: &str,
+) -> Result<bool, DatabaseError> {
+    return backend()
+        .edit_message(chat_id, in_chat_id, old_msg, new_msg)
+        .await;
+}
+
+/// 获得一条消息被编辑前的各个版本，按编辑的先后排列
+pub async fn get_edit_history(
+    chat_id: ChatID,
+    in_chat_id: MessageID,
+) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+    return backend().get_edit_history(chat_id, in_chat_id).await;
+}
+
+/// 用户对一条消息添加表情回应，已添加过时传出false
+pub async fn add_reaction(
+    chat_id: ChatID,
+    in_chat_id: MessageID,
+    user_id: UserID,
+    emoji: &str,
+) -> Result<bool, DatabaseError> {
+    return backend()
+        .add_reaction(chat_id, in_chat_id, user_id, emoji)
+        .await;
+}
+
+/// 用户取消对一条消息的表情回应，未添加过时传出false
+pub async fn remove_reaction(
+    chat_id: ChatID,
+    in_chat_id: MessageID,
+    user_id: UserID,
+    emoji: &str,
+) -> Result<bool, DatabaseError> {
+    return backend()
+        .remove_reaction(chat_id, in_chat_id, user_id, emoji)
+        .await;
+}
+
+/// 获得回复root_id的全部消息，按in_chat_id排列，已被清理的回复不包含在内
+pub async fn get_thread_replies(
+    chat_id: ChatID,
+    root_id: MessageID,
+) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+    return backend().get_thread_replies(chat_id, root_id).await;
+}
+
+/// 置顶一条消息，消息已被置顶时传出false
+pub async fn pin_message(chat_id: ChatID, in_chat_id: MessageID) -> Result<bool, DatabaseError> {
+    return backend().pin_message(chat_id, in_chat_id).await;
+}
+
+/// 取消置顶一条消息，消息未被置顶时传出false
+pub async fn unpin_message(chat_id: ChatID, in_chat_id: MessageID) -> Result<bool, DatabaseError> {
+    return backend().unpin_message(chat_id, in_chat_id).await;
+}
+
+/// 获得一个Chat中的全部置顶消息，按in_chat_id排列
+pub async fn get_pinned_messages(
+    chat_id: ChatID,
+) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+    return backend().get_pinned_messages(chat_id).await;
+}
+
+/// 保存一条定时消息，传出定时消息ID
+pub async fn add_scheduled_message(
+    sender_id: UserID,
+    deliver_at: Timestamp,
+    message: &UserSendMessageData,
+) -> Result<ScheduleID, DatabaseError> {
+    return backend()
+        .add_scheduled_message(sender_id, deliver_at, message)
+        .await;
+}
+
+/// 获得一个用户尚未发送的定时消息，按投递时间排列
+pub async fn get_scheduled_messages(
+    user_id: UserID,
+) -> Result<Vec<ScheduledMessage>, DatabaseError> {
+    return backend().get_scheduled_messages(user_id).await;
+}
+
+/// 取消一个用户的定时消息，定时消息不存在或不属于该用户时传出false
+pub async fn cancel_scheduled_message(
+    user_id: UserID,
+    schedule_id: ScheduleID,
+) -> Result<bool, DatabaseError> {
+    return backend()
+        .cancel_scheduled_message(user_id, schedule_id)
+        .await;
+}
+
+/// 获得所有投递时间不晚于now的定时消息，按投递时间排列
+/// 定时消息不会被删除，发送完成后需调用`remove_scheduled_message`
+pub async fn get_due_scheduled_messages(
+    now: Timestamp,
+) -> Result<Vec<ScheduledMessage>, DatabaseError> {
+    return backend().get_due_scheduled_messages(now).await;
+}
+
+/// 删除一条已经发送的定时消息，定时消息已被取消时传出false
+pub async fn remove_scheduled_message(schedule_id: ScheduleID) -> Result<bool, DatabaseError> {
+    return backend().remove_scheduled_message(schedule_id).await;
+}
+
+/// 获得所有聊天（私聊与群聊）的ID
+pub async fn get_chat_ids() -> Result<Vec<ChatID>, DatabaseError> {
+    return backend().get_chat_ids().await;
+}
+
+/// 按保留策略清理一个Chat中最早的消息，早于min_timestamp或超出最近max_count条的消息都会被清理，传出清理后的pruned_id
+pub async fn prune_messages(
+    chat_id: ChatID,
+    min_timestamp: Option<Timestamp>,
+    max_count: Option<MessageID>,
+) -> Result<MessageID, DatabaseError> {
+    return backend()
+        .prune_messages(chat_id, min_timestamp, max_count)
+        .await;
+}
+
+/// 获得一个Chat已被清理的消息范围，in_chat_id不超过pruned_id的消息都已不存在
+pub async fn get_pruned_id(chat_id: ChatID) -> Result<MessageID, DatabaseError> {
+    return backend().get_pruned_id(chat_id).await;
+}
+
+/// 设置聊天中消息的存活时间（秒），为None时关闭阅后即焚
+/// 从关闭变为开启时，之前的消息不会过期
+pub async fn set_disappearing_ttl(chat_id: ChatID, ttl: Option<u32>) -> Result<(), DatabaseError> {
+    return backend().set_disappearing_ttl(chat_id, ttl).await;
+}
+
+/// 获得聊天中消息的存活时间（秒），未开启阅后即焚时为None
+pub async fn get_disappearing_ttl(chat_id: ChatID) -> Result<Option<u32>, DatabaseError> {
+    return backend().get_disappearing_ttl(chat_id).await;
+}
+
+/// 获得所有开启了阅后即焚的聊天及其消息的存活时间
+pub async fn get_disappearing_chats() -> Result<Vec<(ChatID, u32)>, DatabaseError> {
+    return backend().get_disappearing_chats().await;
+}
+
+/// in_chat_id不超过该值的消息已完成过期检查
+pub async fn get_expired_id(chat_id: ChatID) -> Result<MessageID, DatabaseError> {
+    return backend().get_expired_id(chat_id).await;
+}
+
+/// 记录已完成过期检查的最后一条消息
+pub async fn set_expired_id(chat_id: ChatID, expired_id: MessageID) -> Result<(), DatabaseError> {
+    return backend().set_expired_id(chat_id, expired_id).await;
+}
+
+/// 在若干Chat中搜索文本包含关键词的消息，关键词需已转为小写，不包含已撤回的消息
+/// 结果按chat_id从小到大、同一聊天中从新到旧排列，从cursor之后最多传出limit条
+/// 每页检查的候选消息有上限，匹配的消息不足limit条时仍可能有下一页
+pub async fn search_messages(
+    chat_ids: &[ChatID],
+    keyword: &str,
+    cursor: Option<SearchCursor>,
+    limit: u8,
+) -> Result<SearchPage, DatabaseError> {
+    return backend()
+        .search_messages(chat_ids, keyword, cursor, limit)
+        .await;
 }
 
 /// 检查进群邀请是否出错
@@ -81,7 +334,9 @@ pub async fn check_group_invitation_error(
     receiver_id: UserID,
     chat_id: ChatID,
 ) -> Result<(), GroupInvitationError> {
-    return redis::check_group_invitation_error(sender_id, receiver_id, chat_id).await;
+    return backend()
+        .check_group_invitation_error(sender_id, receiver_id, chat_id)
+        .await;
 }
 /// 检查受邀进群是否出错
 pub async fn check_invited_join_group_error(
@@ -89,16 +344,18 @@ pub async fn check_invited_join_group_error(
     receiver_id: UserID,
     chat_id: ChatID,
 ) -> Result<(), InvitedJoinGroupError> {
-    return redis::check_invited_join_group_error(sender_id, receiver_id, chat_id).await;
+    return backend()
+        .check_invited_join_group_error(sender_id, receiver_id, chat_id)
+        .await;
 }
 /// 退群
 pub async fn quit_group_chat(user_id: UserID, chat_id: ChatID) -> UserQuitGroupChatResponse {
-    return redis::quit_group_chat(user_id, chat_id).await;
+    return backend().quit_group_chat(user_id, chat_id).await;
 }
 
 /// 设为管理员
 pub async fn set_as_admin(user_id: UserID, chat_id: ChatID) -> UserSetGroupAdminResponse {
-    return redis::set_as_admin(user_id, chat_id).await;
+    return backend().set_as_admin(user_id, chat_id).await;
 }
 
 /// 检查进群申请是否出错
@@ -106,32 +363,32 @@ pub async fn check_join_group_error(
     user_id: UserID,
     chat_id: ChatID,
 ) -> Result<(), JoinGroupError> {
-    return redis::check_join_group_error(user_id, chat_id).await;
+    return backend().check_join_group_error(user_id, chat_id).await;
 }
 
 /// 获得一个群聊的所有管理员
-pub async fn get_chat_admins_list(chat_id: ChatID) -> Result<UserRequestHandler, ()> {
-    return redis::get_chat_admins_list(chat_id).await;
+pub async fn get_chat_admins_list(chat_id: ChatID) -> Result<UserRequestHandler, DatabaseError> {
+    return backend().get_chat_admins_list(chat_id).await;
 }
 
 /// 获得一个群聊的群主
-pub async fn get_chat_owner(chat_id: ChatID) -> Result<UserID, ()> {
-    return redis::get_chat_owner(chat_id).await;
+pub async fn get_chat_owner(chat_id: ChatID) -> Result<UserID, DatabaseError> {
+    return backend().get_chat_owner(chat_id).await;
 }
 
 /// 检查一个用户是否是某群群主
-pub async fn check_user_is_owner(user_id: UserID, chat_id: ChatID) -> Result<bool, ()> {
-    return redis::check_user_is_owner(user_id, chat_id).await;
+pub async fn check_user_is_owner(user_id: UserID, chat_id: ChatID) -> Result<bool, DatabaseError> {
+    return backend().check_user_is_owner(user_id, chat_id).await;
 }
 
 /// 检查一个用户是否是某群管理员
-pub async fn check_user_is_admin(user_id: UserID, chat_id: ChatID) -> Result<bool, ()> {
-    return redis::check_user_is_admin(user_id, chat_id).await;
+pub async fn check_user_is_admin(user_id: UserID, chat_id: ChatID) -> Result<bool, DatabaseError> {
+    return backend().check_user_is_admin(user_id, chat_id).await;
 }
 
 /// 群主转移
 pub async fn owner_transfer(user_id: UserID, chat_id: ChatID) -> UserGroupOwnerTransferResponse {
-    return redis::owner_transfer(user_id, chat_id).await;
+    return backend().owner_transfer(user_id, chat_id).await;
 }
 
 /// 添加群公告
@@ -141,7 +398,9 @@ pub async fn add_group_notice(
     client_id: ClientID,
     notice: String,
 ) -> UserSendGroupNoticeResponse {
-    return redis::add_group_notice(user_id, chat_id, client_id, notice).await;
+    return backend()
+        .add_group_notice(user_id, chat_id, client_id, notice)
+        .await;
 }
 
 /// 获取群公告
@@ -149,7 +408,7 @@ pub async fn pull_group_notice(
     chat_id: ChatID,
     last_notice_id: NoticeID,
 ) -> UserPullGroupNoticeResponse {
-    return redis::pull_group_notice(chat_id, last_notice_id).await;
+    return backend().pull_group_notice(chat_id, last_notice_id).await;
 }
 
 /// 更新群聊基本信息
@@ -157,17 +416,17 @@ pub async fn update_group_info(
     chat_id: ChatID,
     data: UserUpdateGroupContent,
 ) -> UserUpdateGroupInfoResponse {
-    return redis::update_group_info(chat_id, data).await;
+    return backend().update_group_info(chat_id, data).await;
 }
 
 /// 取消管理员权限
 pub async fn unset_admin(user_id: UserID, chat_id: ChatID) -> UserUnsetGroupAdminResponse {
-    return redis::unset_admin(user_id, chat_id).await;
+    return backend().unset_admin(user_id, chat_id).await;
 }
 
 /// 检查某聊天是否是群聊
-pub async fn check_is_group(chat_id: ChatID) -> Result<bool, ()> {
-    return redis::check_is_group_chat(chat_id).await;
+pub async fn check_is_group(chat_id: ChatID) -> Result<bool, DatabaseError> {
+    return backend().check_is_group_chat(chat_id).await;
 }
 
 /// 获取群聊中已读某条消息的用户列表
@@ -175,7 +434,7 @@ pub async fn get_user_read_in_group(
     chat_id: ChatID,
     in_chat_id: MessageID,
 ) -> UserGetUserReadInGroupResponse {
-    return redis::get_user_read_in_group(chat_id, in_chat_id).await;
+    return backend().get_user_read_in_group(chat_id, in_chat_id).await;
 }
 
 /// 获取私聊中用户已经读到的消息id
@@ -183,10 +442,12 @@ pub async fn get_user_read_in_private(
     user_id: UserID,
     chat_id: ChatID,
 ) -> UserGetUserReadInPrivateResponse {
-    return redis::get_user_read_in_private(user_id, chat_id).await;
+    return backend().get_user_read_in_private(user_id, chat_id).await;
 }
 
 /// 获取私聊中的用户列表
-pub async fn get_private_chat_user_list(chat_id: ChatID) -> Result<Option<(UserID, UserID)>, ()> {
-    return redis::get_private_chat_user_list(chat_id).await;
+pub async fn get_private_chat_user_list(
+    chat_id: ChatID,
+) -> Result<Option<(UserID, UserID)>, DatabaseError> {
+    return backend().get_private_chat_user_list(chat_id).await;
 }
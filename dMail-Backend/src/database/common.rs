@@ -1,8 +1,22 @@
-use super::redis;
+use crate::config::{config::DatabaseBackendType, Config};
+use crate::database::DatabaseError;
 
-/// 连接数据库
+use super::backend::{backend, set_backend};
+use super::memory::MemoryBackend;
+use super::postgre::PostgreBackend;
+use super::redis::RedisBackend;
+
+/// 连接数据库，根据配置选择存储后端
 pub async fn connect_database() {
-    redis::connect_database()
-        .await
-        .expect("Redis 数据库连接失败");
+    match Config::get().database.backend {
+        DatabaseBackendType::Redis => set_backend(Box::new(RedisBackend)),
+        DatabaseBackendType::Memory => set_backend(Box::<MemoryBackend>::default()),
+        DatabaseBackendType::Postgres => set_backend(Box::new(PostgreBackend)),
+    }
+    backend().connect().await.expect("数据库连接失败");
+}
+
+/// 清空数据库，主要用于测试
+pub async fn flush_database() -> Result<(), DatabaseError> {
+    return backend().flush().await;
 }
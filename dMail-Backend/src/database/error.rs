@@ -0,0 +1,51 @@
+/*!
+ * 数据库层的错误类型
+*/
+
+use std::fmt;
+
+/** `DatabaseError` 数据库操作失败的原因
+ * 只用于服务端日志，返回给客户端时仍统一为各响应中的`DatabaseError`或`ServerError`
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatabaseError {
+    /// 从连接池获取连接超时
+    PoolTimeout,
+    /// 连接失败或命令执行失败
+    Backend(String),
+    /// 存储的数据无法反序列化
+    Deserialize(String),
+    /// 需要的键或记录不存在
+    MissingKey(String),
+    /// 写入的数据与已有数据冲突
+    Conflict(String),
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatabaseError::PoolTimeout => write!(f, "获取数据库连接超时"),
+            DatabaseError::Backend(msg) => write!(f, "数据库执行失败: {}", msg),
+            DatabaseError::Deserialize(msg) => write!(f, "数据反序列化失败: {}", msg),
+            DatabaseError::MissingKey(key) => write!(f, "数据不存在: {}", key),
+            DatabaseError::Conflict(msg) => write!(f, "数据冲突: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+impl From<serde_json::Error> for DatabaseError {
+    fn from(err: serde_json::Error) -> Self {
+        return DatabaseError::Deserialize(err.to_string());
+    }
+}
+
+impl<E: fmt::Display> From<mobc::Error<E>> for DatabaseError {
+    fn from(err: mobc::Error<E>) -> Self {
+        match err {
+            mobc::Error::Timeout => return DatabaseError::PoolTimeout,
+            err => return DatabaseError::Backend(err.to_string()),
+        }
+    }
+}
@@ -1,39 +1,37 @@
+use crate::database::DatabaseError;
 use crate::{
     config::datatype::{SerializedFilePubUrl, UploadId},
     oss::{ObjectUploadRequest, PresignUrl},
 };
 
-use super::redis;
+use super::backend::backend;
 
 /// 获取文件的公共url
 pub async fn get_file_public_url(
-    hash: &String,
-) -> Result<Option<(PresignUrl, SerializedFilePubUrl)>, ()> {
-    return redis::get_file_public_url(hash).await;
+    hash: &str,
+) -> Result<Option<(PresignUrl, SerializedFilePubUrl)>, DatabaseError> {
+    return backend().get_file_public_url(hash).await;
 }
 
 /// 写入文件上传请求，获取upload_id
-pub async fn write_upload_request(req: ObjectUploadRequest) -> Result<UploadId, ()> {
-    return redis::write_upload_request(req).await;
+pub async fn write_upload_request(req: ObjectUploadRequest) -> Result<UploadId, DatabaseError> {
+    return backend().write_upload_request(req).await;
 }
 
 /// 根据upload_id获取文件上传请求
-pub async fn get_upload_request(upload_id: UploadId) -> Result<Option<ObjectUploadRequest>, ()> {
-    return redis::get_upload_request(upload_id).await;
+pub async fn get_upload_request(
+    upload_id: UploadId,
+) -> Result<Option<ObjectUploadRequest>, DatabaseError> {
+    return backend().get_upload_request(upload_id).await;
 }
 
 /// 写入文件的公开url
-pub async fn write_file_public_url(hash: &String, pub_url: &PresignUrl) -> Result<(), ()> {
-    return redis::write_file_public_url(hash, pub_url).await;
+pub async fn write_file_public_url(hash: &str, pub_url: &PresignUrl) -> Result<(), DatabaseError> {
+    return backend().write_file_public_url(hash, pub_url).await;
 }
 
 /// 获取文件的公开url
-pub async fn get_file_url(hash: &String) -> Result<String, ()> {
-    match redis::get_file_url(hash).await {
-        Ok(presign_url) => match serde_json::from_str::<PresignUrl>(&presign_url) {
-            Ok(presign_url) => Ok(presign_url.url),
-            Err(_) => Err(()),
-        },
-        Err(_) => Err(()),
-    }
+pub async fn get_file_url(hash: &str) -> Result<String, DatabaseError> {
+    let presign_url = backend().get_file_url(hash).await?;
+    return Ok(serde_json::from_str::<PresignUrl>(&presign_url)?.url);
 }
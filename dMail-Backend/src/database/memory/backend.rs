@@ -0,0 +1,806 @@
+/*!
+ * 内存存储后端，将存储接口转发至`MemoryDatabase`
+*/
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use super::database::MemoryDatabase;
+use super::MemoryBackend;
+use crate::chat::*;
+use crate::config::datatype::*;
+use crate::database::backend::*;
+use crate::database::DatabaseError;
+use crate::oss::{ObjectUploadRequest, PresignUrl};
+use crate::user::*;
+use crate::utils::password::{self, PasswordCheck};
+
+impl MemoryBackend {
+    /// Argon2的计算耗时较长，校验与哈希时不持有数据库的锁
+    async fn login_with_password(
+        &self,
+        data: &UserLoginData,
+    ) -> Result<UserLoginResponse, DatabaseError> {
+        let (user_id, stored) = match self.db().get_user_password_by_email(&data.email)? {
+            Some(found) => found,
+            None => return Ok(UserLoginResponse::UserNotFound),
+        };
+
+        let input = data.password.as_ref().unwrap();
+        match password::spawn_check_password(&stored, input).await {
+            PasswordCheck::Match => {}
+            PasswordCheck::MatchLegacy => {
+                // 旧版明文密码替换为哈希，失败时下次登录再替换
+                if let Ok(hash) = password::spawn_hash_password(input).await {
+                    self.db().replace_legacy_password(user_id, &stored, hash);
+                }
+            }
+            PasswordCheck::Mismatch => return Ok(UserLoginResponse::PasswordError),
+        }
+
+        return Ok(UserLoginResponse::Success { user_id });
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MemoryBackend {
+    async fn connect(&self) -> Result<(), DatabaseError> {
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), DatabaseError> {
+        *self.db() = MemoryDatabase::default();
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserStorage for MemoryBackend {
+    async fn user_register(
+        &self,
+        data: UserRegisterData,
+    ) -> Result<UserRegisterResponse, DatabaseError> {
+        self.db().user_register(data)
+    }
+
+    async fn user_login_with_password(
+        &self,
+        data: &UserLoginData,
+    ) -> Result<UserLoginResponse, DatabaseError> {
+        self.login_with_password(data).await
+    }
+
+    async fn user_login_with_token(
+        &self,
+        data: &UserLoginData,
+    ) -> Result<UserLoginResponse, DatabaseError> {
+        self.db().user_login_with_token(data)
+    }
+
+    async fn get_user_id_by_email(&self, email: &str) -> Result<Option<UserID>, DatabaseError> {
+        self.db().get_user_id_by_email(email)
+    }
+
+    async fn get_user_chat_list(
+        &self,
+        user_id: UserID,
+    ) -> Result<Vec<(ChatID, MessageID)>, DatabaseError> {
+        self.db().get_user_chat_list(user_id)
+    }
+
+    async fn get_user_info(&self, user_id: UserID) -> GetUserInfoResponse {
+        self.db().get_user_info(user_id)
+    }
+
+    async fn get_user_email(&self, user_id: UserID) -> Result<String, DatabaseError> {
+        self.db().get_user_email(user_id)
+    }
+
+    async fn make_two_users_be_friends(
+        &self,
+        user_one_id: UserID,
+        user_two_id: UserID,
+    ) -> Result<ChatID, DatabaseError> {
+        self.db()
+            .make_two_users_be_friends(user_one_id, user_two_id)
+    }
+
+    async fn check_make_friend_error(
+        &self,
+        user_one_id: UserID,
+        user_two_id: UserID,
+    ) -> Result<(), MakeFriendError> {
+        self.db().check_make_friend_error(user_one_id, user_two_id)
+    }
+
+    async fn set_user_setting(&self, user_id: UserID, user_setting: String) -> SetSettingResponse {
+        self.db().set_user_setting(user_id, user_setting)
+    }
+
+    async fn get_user_setting(&self, user_id: UserID) -> Result<Option<String>, DatabaseError> {
+        self.db().get_user_setting(user_id)
+    }
+
+    async fn update_user_name(&self, user_id: UserID, new_name: String) -> UserUpdateResponse {
+        self.db().update_user_name(user_id, new_name)
+    }
+
+    async fn update_user_avater(&self, user_id: UserID, new_hash: String) -> UserUpdateResponse {
+        self.db().update_user_avater(user_id, new_hash)
+    }
+
+    async fn update_user_password(
+        &self,
+        user_id: UserID,
+        new_password: String,
+    ) -> UserUpdateResponse {
+        self.db().update_user_password(user_id, new_password)
+    }
+
+    async fn unfriend(&self, user_id: UserID, friend_id: UserID) -> UserUnfriendResponse {
+        self.db().unfriend(user_id, friend_id)
+    }
+
+    async fn set_user_already_read(
+        &self,
+        user_id: UserID,
+        data: UserSetAlreadyReadData,
+    ) -> SetAlreadyReadResponse {
+        self.db().set_user_already_read(user_id, data)
+    }
+
+    async fn check_user_in_chat(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<bool, DatabaseError> {
+        self.db().check_user_in_chat(user_id, chat_id)
+    }
+
+    async fn write_user_notice(
+        &self,
+        user_id: UserID,
+        timestamp: Timestamp,
+        serialized: &SerializedUserNotice,
+    ) -> Result<(), DatabaseError> {
+        self.db().write_user_notice(user_id, timestamp, serialized)
+    }
+
+    async fn get_user_notice(
+        &self,
+        user_id: UserID,
+        start_timestamp: Timestamp,
+    ) -> Result<Vec<SerializedUserNotice>, DatabaseError> {
+        self.db().get_user_notice(user_id, start_timestamp)
+    }
+
+    async fn get_chat_id_by_friends(
+        &self,
+        user_id1: UserID,
+        user_id2: UserID,
+    ) -> Result<Option<ChatID>, DatabaseError> {
+        self.db().get_chat_id_by_friends(user_id1, user_id2)
+    }
+
+    async fn get_user_id(&self, name: &str) -> Result<Vec<UserID>, bool> {
+        self.db().get_user_id(name)
+    }
+
+    async fn user_log_off(&self, user_id: UserID) -> (UserLogOffResponse, Vec<(UserID, ChatID)>) {
+        self.db().user_log_off(user_id)
+    }
+
+    async fn apply_for_token(
+        &self,
+        user_id: UserID,
+        device_id: &DeviceID,
+    ) -> UserApplyForTokenResponse {
+        self.db().apply_for_token(user_id, device_id)
+    }
+
+    async fn refresh_token(
+        &self,
+        data: &UserRefreshTokenData,
+    ) -> Result<UserRefreshTokenResponse, DatabaseError> {
+        self.db().refresh_token(data)
+    }
+
+    async fn revoke_token(
+        &self,
+        user_id: UserID,
+        device_id: &DeviceID,
+    ) -> Result<bool, DatabaseError> {
+        self.db().revoke_token(user_id, device_id)
+    }
+
+    async fn revoke_all_tokens(&self, user_id: UserID) -> Result<(), DatabaseError> {
+        self.db().revoke_all_tokens(user_id)
+    }
+
+    async fn check_user_exist(&self, user_id: UserID) -> Result<(), DatabaseError> {
+        self.db().check_user_exist(user_id)
+    }
+
+    async fn set_user_last_seen(
+        &self,
+        user_id: UserID,
+        timestamp: Timestamp,
+    ) -> Result<(), DatabaseError> {
+        self.db().set_user_last_seen(user_id, timestamp)
+    }
+
+    async fn get_user_last_seen(
+        &self,
+        user_id: UserID,
+    ) -> Result<Option<Timestamp>, DatabaseError> {
+        self.db().get_user_last_seen(user_id)
+    }
+
+    async fn set_presence_hidden(
+        &self,
+        user_id: UserID,
+        hidden: bool,
+    ) -> Result<(), DatabaseError> {
+        self.db().set_presence_hidden(user_id, hidden)
+    }
+
+    async fn get_presence_hidden(&self, user_id: UserID) -> Result<bool, DatabaseError> {
+        self.db().get_presence_hidden(user_id)
+    }
+
+    async fn set_device_keys(
+        &self,
+        user_id: UserID,
+        device_id: &DeviceID,
+        serialized: &str,
+    ) -> Result<(), DatabaseError> {
+        self.db().set_device_keys(user_id, device_id, serialized)
+    }
+
+    async fn get_device_keys(
+        &self,
+        user_id: UserID,
+        device_id: &DeviceID,
+    ) -> Result<Option<String>, DatabaseError> {
+        self.db().get_device_keys(user_id, device_id)
+    }
+
+    async fn get_user_device_keys(&self, user_id: UserID) -> Result<Vec<String>, DatabaseError> {
+        self.db().get_user_device_keys(user_id)
+    }
+}
+
+#[async_trait]
+impl ChatStorage for MemoryBackend {
+    async fn create_group_chat(
+        &self,
+        creator_id: UserID,
+        data: UserCreateGroupChatData,
+    ) -> Result<ChatID, DatabaseError> {
+        self.db().create_group_chat(creator_id, data)
+    }
+
+    async fn add_user_to_group_chat(
+        &self,
+        chat_id: ChatID,
+        user_id: UserID,
+    ) -> Result<(), DatabaseError> {
+        self.db().add_user_to_group_chat(chat_id, user_id)
+    }
+
+    async fn write_message_to_chat(
+        &self,
+        r#type: &str,
+        serialized_content: String,
+        chat_id: ChatID,
+        sender_id: UserID,
+        forwarded_from: Option<&ForwardedFrom>,
+        reply_to: Option<MessageID>,
+    ) -> Result<(SerializedChatMessage, MessageID, Timestamp), DatabaseError> {
+        self.db().write_message_to_chat(
+            r#type,
+            serialized_content,
+            chat_id,
+            sender_id,
+            forwarded_from,
+            reply_to,
+        )
+    }
+
+    async fn check_user_can_send_in_chat(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<ChatType, UserSendMessageResponseState> {
+        self.db().check_user_can_send_in_chat(user_id, chat_id)
+    }
+
+    async fn get_chat_user_list(&self, chat_id: ChatID) -> Result<ChatMembers, DatabaseError> {
+        self.db().get_chat_user_list(chat_id)
+    }
+
+    async fn get_private_chat_user_list(
+        &self,
+        chat_id: ChatID,
+    ) -> Result<Option<(UserID, UserID)>, DatabaseError> {
+        self.db().get_private_chat_user_list(chat_id)
+    }
+
+    async fn get_messages_in_chat(
+        &self,
+        chat_id: ChatID,
+        start_msg_id: MessageID,
+        end_msg_id: Option<MessageID>,
+    ) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+        self.db()
+            .get_messages_in_chat(chat_id, start_msg_id, end_msg_id)
+    }
+
+    async fn get_chats_last_messages(
+        &self,
+        chats: &[(ChatID, MessageID)],
+        max_messages_per_chat: u8,
+    ) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+        self.db()
+            .get_chats_last_messages(chats, max_messages_per_chat)
+    }
+
+    async fn get_chat_info(
+        &self,
+        chat_id: ChatID,
+    ) -> Result<Option<SerializedChatInfo>, DatabaseError> {
+        self.db().get_chat_info(chat_id)
+    }
+
+    async fn revoke_message(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        sender_id: UserID,
+        timestamp: Timestamp,
+    ) -> Result<(), DatabaseError> {
+        self.db()
+            .revoke_message(chat_id, in_chat_id, sender_id, timestamp)
+    }
+
+    async fn edit_message(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        old_msg: &str,
+        new_msg: &str,
+    ) -> Result<bool, DatabaseError> {
+        self.db()
+            .edit_message(chat_id, in_chat_id, old_msg, new_msg)
+    }
+
+    async fn get_edit_history(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+    ) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+        self.db().get_edit_history(chat_id, in_chat_id)
+    }
+
+    async fn add_reaction(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        user_id: UserID,
+        emoji: &str,
+    ) -> Result<bool, DatabaseError> {
+        self.db().add_reaction(chat_id, in_chat_id, user_id, emoji)
+    }
+
+    async fn remove_reaction(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        user_id: UserID,
+        emoji: &str,
+    ) -> Result<bool, DatabaseError> {
+        self.db()
+            .remove_reaction(chat_id, in_chat_id, user_id, emoji)
+    }
+
+    async fn get_reaction_counts(
+        &self,
+        chat_id: ChatID,
+        start_msg_id: MessageID,
+        end_msg_id: Option<MessageID>,
+    ) -> Result<HashMap<MessageID, ReactionCounts>, DatabaseError> {
+        self.db()
+            .get_reaction_counts(chat_id, start_msg_id, end_msg_id)
+    }
+
+    async fn get_thread_replies(
+        &self,
+        chat_id: ChatID,
+        root_id: MessageID,
+    ) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+        self.db().get_thread_replies(chat_id, root_id)
+    }
+
+    async fn get_reply_counts(
+        &self,
+        chat_id: ChatID,
+        start_msg_id: MessageID,
+        end_msg_id: Option<MessageID>,
+    ) -> Result<HashMap<MessageID, u32>, DatabaseError> {
+        self.db()
+            .get_reply_counts(chat_id, start_msg_id, end_msg_id)
+    }
+
+    async fn pin_message(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+    ) -> Result<bool, DatabaseError> {
+        self.db().pin_message(chat_id, in_chat_id)
+    }
+
+    async fn unpin_message(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+    ) -> Result<bool, DatabaseError> {
+        self.db().unpin_message(chat_id, in_chat_id)
+    }
+
+    async fn get_pinned_messages(
+        &self,
+        chat_id: ChatID,
+    ) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+        self.db().get_pinned_messages(chat_id)
+    }
+
+    async fn get_chat_ids(&self) -> Result<Vec<ChatID>, DatabaseError> {
+        self.db().get_chat_ids()
+    }
+
+    async fn prune_messages(
+        &self,
+        chat_id: ChatID,
+        min_timestamp: Option<Timestamp>,
+        max_count: Option<MessageID>,
+    ) -> Result<MessageID, DatabaseError> {
+        self.db().prune_messages(chat_id, min_timestamp, max_count)
+    }
+
+    async fn get_pruned_id(&self, chat_id: ChatID) -> Result<MessageID, DatabaseError> {
+        self.db().get_pruned_id(chat_id)
+    }
+
+    async fn set_disappearing_ttl(
+        &self,
+        chat_id: ChatID,
+        ttl: Option<u32>,
+    ) -> Result<(), DatabaseError> {
+        self.db().set_disappearing_ttl(chat_id, ttl)
+    }
+
+    async fn get_disappearing_ttl(&self, chat_id: ChatID) -> Result<Option<u32>, DatabaseError> {
+        self.db().get_disappearing_ttl(chat_id)
+    }
+
+    async fn get_disappearing_chats(&self) -> Result<Vec<(ChatID, u32)>, DatabaseError> {
+        self.db().get_disappearing_chats()
+    }
+
+    async fn get_expired_id(&self, chat_id: ChatID) -> Result<MessageID, DatabaseError> {
+        self.db().get_expired_id(chat_id)
+    }
+
+    async fn set_expired_id(
+        &self,
+        chat_id: ChatID,
+        expired_id: MessageID,
+    ) -> Result<(), DatabaseError> {
+        self.db().set_expired_id(chat_id, expired_id)
+    }
+
+    async fn search_messages(
+        &self,
+        chat_ids: &[ChatID],
+        keyword: &str,
+        cursor: Option<SearchCursor>,
+        limit: u8,
+    ) -> Result<SearchPage, DatabaseError> {
+        self.db().search_messages(chat_ids, keyword, cursor, limit)
+    }
+
+    async fn add_scheduled_message(
+        &self,
+        sender_id: UserID,
+        deliver_at: Timestamp,
+        message: &UserSendMessageData,
+    ) -> Result<ScheduleID, DatabaseError> {
+        self.db()
+            .add_scheduled_message(sender_id, deliver_at, message)
+    }
+
+    async fn get_scheduled_messages(
+        &self,
+        user_id: UserID,
+    ) -> Result<Vec<ScheduledMessage>, DatabaseError> {
+        self.db().get_scheduled_messages(user_id)
+    }
+
+    async fn cancel_scheduled_message(
+        &self,
+        user_id: UserID,
+        schedule_id: ScheduleID,
+    ) -> Result<bool, DatabaseError> {
+        self.db().cancel_scheduled_message(user_id, schedule_id)
+    }
+
+    async fn get_due_scheduled_messages(
+        &self,
+        now: Timestamp,
+    ) -> Result<Vec<ScheduledMessage>, DatabaseError> {
+        self.db().get_due_scheduled_messages(now)
+    }
+
+    async fn remove_scheduled_message(
+        &self,
+        schedule_id: ScheduleID,
+    ) -> Result<bool, DatabaseError> {
+        self.db().remove_scheduled_message(schedule_id)
+    }
+
+    async fn check_group_invitation_error(
+        &self,
+        sender_id: UserID,
+        receiver_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), GroupInvitationError> {
+        self.db()
+            .check_group_invitation_error(sender_id, receiver_id, chat_id)
+    }
+
+    async fn check_invited_join_group_error(
+        &self,
+        sender_id: UserID,
+        receiver_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), InvitedJoinGroupError> {
+        self.db()
+            .check_invited_join_group_error(sender_id, receiver_id, chat_id)
+    }
+
+    async fn quit_group_chat(&self, user_id: UserID, chat_id: ChatID) -> UserQuitGroupChatResponse {
+        self.db().quit_group_chat(user_id, chat_id)
+    }
+
+    async fn set_as_admin(&self, user_id: UserID, chat_id: ChatID) -> UserSetGroupAdminResponse {
+        self.db().set_as_admin(user_id, chat_id)
+    }
+
+    async fn check_join_group_error(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), JoinGroupError> {
+        self.db().check_join_group_error(user_id, chat_id)
+    }
+
+    async fn get_chat_admins_list(
+        &self,
+        chat_id: ChatID,
+    ) -> Result<UserRequestHandler, DatabaseError> {
+        self.db().get_chat_admins_list(chat_id)
+    }
+
+    async fn get_chat_owner(&self, chat_id: ChatID) -> Result<UserID, DatabaseError> {
+        self.db().get_chat_owner(chat_id)
+    }
+
+    async fn check_user_is_owner(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<bool, DatabaseError> {
+        self.db().check_user_is_owner(user_id, chat_id)
+    }
+
+    async fn check_user_is_admin(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<bool, DatabaseError> {
+        self.db().check_user_is_admin(user_id, chat_id)
+    }
+
+    async fn owner_transfer(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> UserGroupOwnerTransferResponse {
+        self.db().owner_transfer(user_id, chat_id)
+    }
+
+    async fn add_group_notice(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+        client_id: ClientID,
+        notice: String,
+    ) -> UserSendGroupNoticeResponse {
+        self.db()
+            .add_group_notice(user_id, chat_id, client_id, notice)
+    }
+
+    async fn pull_group_notice(
+        &self,
+        chat_id: ChatID,
+        last_notice_id: NoticeID,
+    ) -> UserPullGroupNoticeResponse {
+        self.db().pull_group_notice(chat_id, last_notice_id)
+    }
+
+    async fn update_group_info(
+        &self,
+        chat_id: ChatID,
+        data: UserUpdateGroupContent,
+    ) -> UserUpdateGroupInfoResponse {
+        self.db().update_group_info(chat_id, data)
+    }
+
+    async fn unset_admin(&self, user_id: UserID, chat_id: ChatID) -> UserUnsetGroupAdminResponse {
+        self.db().unset_admin(user_id, chat_id)
+    }
+
+    async fn check_is_group_chat(&self, chat_id: ChatID) -> Result<bool, DatabaseError> {
+        self.db().check_is_group_chat(chat_id)
+    }
+
+    async fn get_user_read_in_group(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+    ) -> UserGetUserReadInGroupResponse {
+        self.db().get_user_read_in_group(chat_id, in_chat_id)
+    }
+
+    async fn get_user_read_in_private(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> UserGetUserReadInPrivateResponse {
+        self.db().get_user_read_in_private(user_id, chat_id)
+    }
+}
+
+#[async_trait]
+impl RequestStorage for MemoryBackend {
+    async fn get_user_requests(
+        &self,
+        user_id: UserID,
+        start_req_id: UserReqId,
+    ) -> Result<Vec<SerializedRequest>, DatabaseError> {
+        self.db().get_user_requests(user_id, start_req_id)
+    }
+
+    async fn write_user_request(
+        &self,
+        sender_id: UserID,
+        data: UserSendRequestData,
+        handler: &UserRequestHandler,
+    ) -> Result<(SerializedRequest, UserRequestInfo), DatabaseError> {
+        self.db().write_user_request(sender_id, data, handler)
+    }
+
+    async fn store_user_request(
+        &self,
+        user_id: UserID,
+        req_id: UserReqId,
+    ) -> Result<(), DatabaseError> {
+        self.db().store_user_request(user_id, req_id)
+    }
+
+    async fn get_user_request(
+        &self,
+        req_id: UserReqId,
+    ) -> Result<Option<UserRequset>, DatabaseError> {
+        self.db().get_user_request(req_id)
+    }
+
+    async fn set_user_request_state(
+        &self,
+        req_id: UserReqId,
+        state: UserRequestState,
+    ) -> Result<(), UserSolveRequestState> {
+        self.db().set_user_request_state(req_id, state)
+    }
+
+    async fn write_friend_request_send(
+        &self,
+        user_one_id: UserID,
+        user_two_id: UserID,
+    ) -> Result<(), DatabaseError> {
+        self.db()
+            .write_friend_request_send(user_one_id, user_two_id)
+    }
+
+    async fn delete_friend_request_send(
+        &self,
+        user_one_id: UserID,
+        user_two_id: UserID,
+    ) -> Result<(), DatabaseError> {
+        self.db()
+            .delete_friend_request_send(user_one_id, user_two_id)
+    }
+
+    async fn write_join_group_request_send(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), DatabaseError> {
+        self.db().write_join_group_request_send(user_id, chat_id)
+    }
+
+    async fn delete_join_group_request_send(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), DatabaseError> {
+        self.db().delete_join_group_request_send(user_id, chat_id)
+    }
+
+    async fn write_invite_request_send(
+        &self,
+        inviter_id: UserID,
+        receiver_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), DatabaseError> {
+        self.db()
+            .write_invite_request_send(inviter_id, receiver_id, chat_id)
+    }
+
+    async fn delete_invite_request_send(
+        &self,
+        inviter_id: UserID,
+        receiver_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), DatabaseError> {
+        self.db()
+            .delete_invite_request_send(inviter_id, receiver_id, chat_id)
+    }
+}
+
+#[async_trait]
+impl FileStorage for MemoryBackend {
+    async fn get_file_public_url(
+        &self,
+        hash: &str,
+    ) -> Result<Option<(PresignUrl, SerializedFilePubUrl)>, DatabaseError> {
+        self.db().get_file_public_url(hash)
+    }
+
+    async fn write_file_public_url(
+        &self,
+        hash: &str,
+        pub_url: &PresignUrl,
+    ) -> Result<(), DatabaseError> {
+        self.db().write_file_public_url(hash, pub_url)
+    }
+
+    async fn write_upload_request(
+        &self,
+        req: ObjectUploadRequest,
+    ) -> Result<UploadId, DatabaseError> {
+        self.db().write_upload_request(req)
+    }
+
+    async fn get_upload_request(
+        &self,
+        upload_id: UploadId,
+    ) -> Result<Option<ObjectUploadRequest>, DatabaseError> {
+        self.db().get_upload_request(upload_id)
+    }
+
+    async fn get_file_url(&self, hash: &str) -> Result<SerializedFilePubUrl, DatabaseError> {
+        self.db().get_file_url(hash)
+    }
+}
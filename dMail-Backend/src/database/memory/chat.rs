@@ -0,0 +1,951 @@
+use std::collections::HashMap;
+
+use super::database::*;
+use crate::chat::{
+    get_forwarded_field, get_keyword_tokens, get_message_search_tokens, get_search_ranges,
+    ChatInfo, ChatMembers, ChatMessage, ChatType, ForwardedFrom, SearchCollector, SearchCursor,
+    SearchPage,
+};
+use crate::config::datatype::*;
+use crate::database::DatabaseError;
+use crate::user::*;
+
+impl MemoryDatabase {
+    pub fn create_group_chat(
+        &mut self,
+        creator: UserID,
+        data: UserCreateGroupChatData,
+    ) -> Result<ChatID, DatabaseError> {
+        self.last_chat_id += 1;
+        let chat_id = self.last_chat_id;
+        let serialized_chat_info = format!(
+            r#"{{"id":{},"name":{},"avaterHash":{}}}"#,
+            chat_id,
+            serde_json::to_string::<String>(&data.name).unwrap(),
+            serde_json::to_string::<String>(&data.avater_hash).unwrap(),
+        );
+
+        let chat = self.chat_mut(chat_id);
+        chat.info = Some(serialized_chat_info);
+        chat.owner = Some(creator);
+        chat.admins.insert(creator);
+        chat.users.insert(creator);
+        self.user_mut(creator).chats.insert(chat_id, 0);
+
+        return Ok(chat_id);
+    }
+
+    pub fn add_user_to_group_chat(
+        &mut self,
+        chat_id: ChatID,
+        user_id: UserID,
+    ) -> Result<(), DatabaseError> {
+        self.chat_mut(chat_id).users.insert(user_id);
+        self.user_mut(user_id).chats.insert(chat_id, 0);
+        return Ok(());
+    }
+
+    pub fn write_message_to_chat(
+        &mut self,
+        r#type: &str,
+        serialized_content: String,
+        chat_id: ChatID,
+        sender_id: UserID,
+        forwarded_from: Option<&ForwardedFrom>,
+        reply_to: Option<MessageID>,
+    ) -> Result<(SerializedChatMessage, MessageID, Timestamp), DatabaseError> {
+        let timestamp = chrono::Utc::now().timestamp_millis() as Timestamp;
+
+        let chat = self.chat_mut(chat_id);
+        let in_chat_id = chat.last_id.unwrap_or(0) + 1;
+        chat.last_id = Some(in_chat_id);
+
+        let serialized_msg = format!(
+            r#"{{"type":{}, "inChatId":{}, "chatId":{}, "senderId":{}, "serializedContent":{}, "timestamp":{}{}}}"#,
+            r#type,
+            in_chat_id,
+            chat_id,
+            sender_id,
+            serialized_content,
+            timestamp,
+            get_forwarded_field(forwarded_from)
+        );
+
+        chat.msgs.add(serialized_msg.clone(), in_chat_id);
+        add_to_search_index(chat, in_chat_id, &serialized_msg);
+        if let Some(root_id) = reply_to {
+            chat.threads.entry(root_id).or_default().push(in_chat_id);
+        }
+
+        return Ok((serialized_msg, in_chat_id, timestamp));
+    }
+
+    pub fn get_chat_user_list(&self, chat_id: ChatID) -> Result<ChatMembers, DatabaseError> {
+        let chat = self
+            .chat(chat_id)
+            .ok_or_else(|| DatabaseError::MissingKey(format!("chat:{}", chat_id)))?;
+
+        if chat.owner.is_some() {
+            return Ok(ChatMembers::Group(chat.users.iter().copied().collect()));
+        } else {
+            return chat
+                .pair
+                .map(ChatMembers::Private)
+                .ok_or_else(|| DatabaseError::MissingKey(format!("chat:{}:users", chat_id)));
+        }
+    }
+
+    pub fn get_private_chat_user_list(
+        &self,
+        chat_id: ChatID,
+    ) -> Result<Option<(UserID, UserID)>, DatabaseError> {
+        let chat = self
+            .chat(chat_id)
+            .ok_or_else(|| DatabaseError::MissingKey(format!("chat:{}", chat_id)))?;
+
+        if chat.owner.is_some() {
+            return Ok(None);
+        } else {
+            return chat
+                .pair
+                .map(Some)
+                .ok_or_else(|| DatabaseError::MissingKey(format!("chat:{}:users", chat_id)));
+        }
+    }
+
+    pub fn check_user_can_send_in_chat(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<ChatType, UserSendMessageResponseState> {
+        let chat = match self.chat(chat_id) {
+            Some(chat) if chat.info.is_some() => chat,
+            _ => return Err(UserSendMessageResponseState::ChatNotFound),
+        };
+
+        if chat.owner.is_some() {
+            if !chat.users.contains(&user_id) && user_id != 0 {
+                return Err(UserSendMessageResponseState::UserNotInChat);
+            }
+            return Ok(ChatType::Group(chat.users.len() as UserID));
+        } else {
+            let pair = match chat.pair {
+                Some(pair) => pair,
+                None => return Err(UserSendMessageResponseState::UserNotInChat),
+            };
+            if user_id == pair.0 || user_id == pair.1 || user_id == 0 {
+                return Ok(ChatType::Private(pair));
+            } else {
+                return Err(UserSendMessageResponseState::UserNotInChat);
+            }
+        }
+    }
+
+    pub fn get_chats_last_messages(
+        &self,
+        chats_id: &[(ChatID, MessageID)],
+        max_messages_per_chat: u8,
+    ) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+        let mut ret: Vec<SerializedChatMessage> = Vec::new();
+
+        for (chat_id, _) in chats_id {
+            if let Some(chat) = self.chat(*chat_id) {
+                let end_msg_id = chat.last_id.unwrap_or(0);
+                let mut msgs = chat.msgs.range_by_score(
+                    std::cmp::max(
+                        1,
+                        end_msg_id.saturating_sub(max_messages_per_chat as MessageID),
+                    ),
+                    end_msg_id,
+                );
+                ret.append(&mut msgs);
+            }
+        }
+
+        return Ok(ret);
+    }
+
+    pub fn get_messages_in_chat(
+        &self,
+        chat_id: ChatID,
+        start_msg_id: MessageID,
+        end_msg_id_opt: Option<MessageID>,
+    ) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+        let chat = match self.chat(chat_id) {
+            Some(chat) => chat,
+            None => return Ok(vec![]),
+        };
+
+        let end_msg_id = match end_msg_id_opt {
+            Some(id) => id,
+            None => chat.last_id.unwrap_or(0),
+        };
+
+        return Ok(chat
+            .msgs
+            .range_by_score(std::cmp::max(1, start_msg_id), end_msg_id));
+    }
+
+    pub fn get_chat_info(
+        &self,
+        chat_id: ChatID,
+    ) -> Result<Option<SerializedChatInfo>, DatabaseError> {
+        return Ok(self.chat(chat_id).and_then(|chat| chat.info.clone()));
+    }
+
+    pub fn revoke_message(
+        &mut self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        sender_id: UserID,
+        timestamp: Timestamp,
+    ) -> Result<(), DatabaseError> {
+        let serialized_msg = format!(
+            r#"{{"type":{}, "inChatId":{}, "chatId":{}, "senderId":{}, "serializedContent":"\"\"", "timestamp":{}}}"#,
+            ChatMessageType::Revoked.get_str(),
+            in_chat_id,
+            chat_id,
+            sender_id,
+            timestamp,
+        );
+
+        let chat = self.chat_mut(chat_id);
+        for old_msg in chat.msgs.range_by_score(in_chat_id, in_chat_id) {
+            remove_from_search_index(chat, &old_msg);
+        }
+        chat.msgs.remove_by_score(in_chat_id, in_chat_id);
+        chat.msgs.add(serialized_msg, in_chat_id);
+        chat.edits.remove(&in_chat_id);
+        chat.reactions.remove(&in_chat_id);
+        chat.pinned.remove(&in_chat_id);
+
+        return Ok(());
+    }
+
+    pub fn edit_message(
+        &mut self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        old_msg: &str,
+        new_msg: &str,
+    ) -> Result<bool, DatabaseError> {
+        let chat = match self.chats.get_mut(&chat_id) {
+            Some(chat) => chat,
+            None => return Ok(false),
+        };
+        if chat.msgs.range_by_score(in_chat_id, in_chat_id) != vec![old_msg.to_string()] {
+            return Ok(false);
+        }
+
+        remove_from_search_index(chat, old_msg);
+        chat.msgs.remove_by_score(in_chat_id, in_chat_id);
+        chat.msgs.add(new_msg.to_string(), in_chat_id);
+        add_to_search_index(chat, in_chat_id, new_msg);
+        chat.edits
+            .entry(in_chat_id)
+            .or_default()
+            .push(old_msg.to_string());
+
+        return Ok(true);
+    }
+
+    pub fn get_edit_history(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+    ) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+        return Ok(self
+            .chat(chat_id)
+            .and_then(|chat| chat.edits.get(&in_chat_id).cloned())
+            .unwrap_or_default());
+    }
+
+    pub fn add_reaction(
+        &mut self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        user_id: UserID,
+        emoji: &str,
+    ) -> Result<bool, DatabaseError> {
+        return Ok(self
+            .chat_mut(chat_id)
+            .reactions
+            .entry(in_chat_id)
+            .or_default()
+            .entry(emoji.to_string())
+            .or_default()
+            .insert(user_id));
+    }
+
+    pub fn remove_reaction(
+        &mut self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        user_id: UserID,
+        emoji: &str,
+    ) -> Result<bool, DatabaseError> {
+        let reactions = match self
+            .chats
+            .get_mut(&chat_id)
+            .and_then(|chat| chat.reactions.get_mut(&in_chat_id))
+        {
+            Some(reactions) => reactions,
+            None => return Ok(false),
+        };
+        let removed = match reactions.get_mut(emoji) {
+            Some(users) => users.remove(&user_id),
+            None => false,
+        };
+        reactions.retain(|_, users| !users.is_empty());
+        if reactions.is_empty() {
+            self.chat_mut(chat_id).reactions.remove(&in_chat_id);
+        }
+        return Ok(removed);
+    }
+
+    pub fn get_reaction_counts(
+        &self,
+        chat_id: ChatID,
+        start_msg_id: MessageID,
+        end_msg_id: Option<MessageID>,
+    ) -> Result<HashMap<MessageID, ReactionCounts>, DatabaseError> {
+        let chat = match self.chat(chat_id) {
+            Some(chat) => chat,
+            None => return Ok(HashMap::new()),
+        };
+        let end_msg_id = end_msg_id.unwrap_or(MessageID::MAX);
+        if end_msg_id < start_msg_id {
+            return Ok(HashMap::new());
+        }
+
+        return Ok(chat
+            .reactions
+            .range(start_msg_id..=end_msg_id)
+            .map(|(in_chat_id, reactions)| {
+                let counts = reactions
+                    .iter()
+                    .map(|(emoji, users)| (emoji.clone(), users.len() as u32))
+                    .collect();
+                (*in_chat_id, counts)
+            })
+            .collect());
+    }
+
+    pub fn get_thread_replies(
+        &self,
+        chat_id: ChatID,
+        root_id: MessageID,
+    ) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+        let chat = match self.chat(chat_id) {
+            Some(chat) => chat,
+            None => return Ok(vec![]),
+        };
+        let reply_ids = match chat.threads.get(&root_id) {
+            Some(reply_ids) => reply_ids,
+            None => return Ok(vec![]),
+        };
+
+        return Ok(reply_ids
+            .iter()
+            .flat_map(|reply_id| chat.msgs.range_by_score(*reply_id, *reply_id))
+            .collect());
+    }
+
+    pub fn get_reply_counts(
+        &self,
+        chat_id: ChatID,
+        start_msg_id: MessageID,
+        end_msg_id: Option<MessageID>,
+    ) -> Result<HashMap<MessageID, u32>, DatabaseError> {
+        let chat = match self.chat(chat_id) {
+            Some(chat) => chat,
+            None => return Ok(HashMap::new()),
+        };
+        let end_msg_id = end_msg_id.unwrap_or(MessageID::MAX);
+        if end_msg_id < start_msg_id {
+            return Ok(HashMap::new());
+        }
+
+        return Ok(chat
+            .threads
+            .range(start_msg_id..=end_msg_id)
+            .map(|(root_id, reply_ids)| (*root_id, reply_ids.len() as u32))
+            .collect());
+    }
+
+    pub fn pin_message(
+        &mut self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+    ) -> Result<bool, DatabaseError> {
+        return Ok(self.chat_mut(chat_id).pinned.insert(in_chat_id));
+    }
+
+    pub fn unpin_message(
+        &mut self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+    ) -> Result<bool, DatabaseError> {
+        return Ok(self
+            .chats
+            .get_mut(&chat_id)
+            .is_some_and(|chat| chat.pinned.remove(&in_chat_id)));
+    }
+
+    pub fn get_pinned_messages(
+        &self,
+        chat_id: ChatID,
+    ) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+        let chat = match self.chat(chat_id) {
+            Some(chat) => chat,
+            None => return Ok(vec![]),
+        };
+
+        return Ok(chat
+            .pinned
+            .iter()
+            .flat_map(|in_chat_id| chat.msgs.range_by_score(*in_chat_id, *in_chat_id))
+            .collect());
+    }
+
+    pub fn get_chat_ids(&self) -> Result<Vec<ChatID>, DatabaseError> {
+        let mut chat_ids: Vec<ChatID> = self.chats.keys().cloned().collect();
+        chat_ids.sort();
+        return Ok(chat_ids);
+    }
+
+    pub fn prune_messages(
+        &mut self,
+        chat_id: ChatID,
+        min_timestamp: Option<Timestamp>,
+        max_count: Option<MessageID>,
+    ) -> Result<MessageID, DatabaseError> {
+        let chat = match self.chats.get_mut(&chat_id) {
+            Some(chat) => chat,
+            None => return Ok(0),
+        };
+        let last_id = chat.last_id.unwrap_or(0);
+
+        let mut pruned_id = chat.pruned_id;
+        if let Some(max_count) = max_count {
+            pruned_id = std::cmp::max(pruned_id, last_id.saturating_sub(max_count));
+        }
+        if let Some(min_timestamp) = min_timestamp {
+            for serialized_msg in chat.msgs.range_by_score(pruned_id + 1, last_id) {
+                let msg = serde_json::from_str::<ChatMessage>(&serialized_msg)?;
+                if msg.timestamp >= min_timestamp {
+                    break;
+                }
+                pruned_id = msg.in_chat_id;
+            }
+        }
+
+        if pruned_id > chat.pruned_id {
+            for serialized_msg in chat.msgs.range_by_score(chat.pruned_id + 1, pruned_id) {
+                remove_from_search_index(chat, &serialized_msg);
+            }
+            chat.msgs.remove_by_score(0, pruned_id);
+            chat.edits.retain(|in_chat_id, _| *in_chat_id > pruned_id);
+            chat.reactions = chat.reactions.split_off(&(pruned_id + 1));
+            chat.threads = chat.threads.split_off(&(pruned_id + 1));
+            chat.pinned = chat.pinned.split_off(&(pruned_id + 1));
+            chat.pruned_id = pruned_id;
+        }
+        return Ok(chat.pruned_id);
+    }
+
+    pub fn get_pruned_id(&self, chat_id: ChatID) -> Result<MessageID, DatabaseError> {
+        return Ok(self.chat(chat_id).map_or(0, |chat| chat.pruned_id));
+    }
+
+    pub fn set_disappearing_ttl(
+        &mut self,
+        chat_id: ChatID,
+        ttl: Option<u32>,
+    ) -> Result<(), DatabaseError> {
+        let chat = self.chat_mut(chat_id);
+        if chat.disappearing_ttl.is_none() && ttl.is_some() {
+            chat.expired_id = chat.last_id.unwrap_or(0);
+        }
+        chat.disappearing_ttl = ttl;
+        return Ok(());
+    }
+
+    pub fn get_disappearing_ttl(&self, chat_id: ChatID) -> Result<Option<u32>, DatabaseError> {
+        return Ok(self.chat(chat_id).and_then(|chat| chat.disappearing_ttl));
+    }
+
+    pub fn get_disappearing_chats(&self) -> Result<Vec<(ChatID, u32)>, DatabaseError> {
+        let mut chats: Vec<(ChatID, u32)> = self
+            .chats
+            .iter()
+            .filter_map(|(chat_id, chat)| chat.disappearing_ttl.map(|ttl| (*chat_id, ttl)))
+            .collect();
+        chats.sort();
+        return Ok(chats);
+    }
+
+    pub fn get_expired_id(&self, chat_id: ChatID) -> Result<MessageID, DatabaseError> {
+        return Ok(self.chat(chat_id).map_or(0, |chat| chat.expired_id));
+    }
+
+    pub fn set_expired_id(
+        &mut self,
+        chat_id: ChatID,
+        expired_id: MessageID,
+    ) -> Result<(), DatabaseError> {
+        self.chat_mut(chat_id).expired_id = expired_id;
+        return Ok(());
+    }
+
+    pub fn search_messages(
+        &self,
+        chat_ids: &[ChatID],
+        keyword: &str,
+        cursor: Option<SearchCursor>,
+        limit: u8,
+    ) -> Result<SearchPage, DatabaseError> {
+        let tokens = get_keyword_tokens(keyword);
+        let mut collector = SearchCollector::new(keyword, limit);
+        if tokens.is_empty() {
+            return Ok(collector.finish());
+        }
+
+        for (chat_id, upper) in get_search_ranges(chat_ids, cursor) {
+            let chat = match self.chat(chat_id) {
+                Some(chat) => chat,
+                None => continue,
+            };
+            // 候选消息取自消息数最少的词项，其余词项由子串匹配保证
+            let ids = tokens
+                .iter()
+                .map(|token| chat.search_index.get(token))
+                .min_by_key(|ids| ids.map(|ids| ids.len()).unwrap_or(0));
+            let ids = match ids {
+                Some(Some(ids)) => ids,
+                _ => continue,
+            };
+            for in_chat_id in ids.range(..upper).rev() {
+                if collector.is_done() {
+                    break;
+                }
+                let serialized_msg = chat
+                    .msgs
+                    .range_by_score(*in_chat_id, *in_chat_id)
+                    .into_iter()
+                    .next();
+                collector.check(chat_id, *in_chat_id, serialized_msg);
+            }
+            if collector.is_done() {
+                break;
+            }
+        }
+
+        return Ok(collector.finish());
+    }
+
+    pub fn add_scheduled_message(
+        &mut self,
+        sender_id: UserID,
+        deliver_at: Timestamp,
+        message: &UserSendMessageData,
+    ) -> Result<ScheduleID, DatabaseError> {
+        self.last_schedule_id += 1;
+        let schedule_id = self.last_schedule_id;
+        self.scheduled.insert(
+            schedule_id,
+            ScheduledMessage {
+                schedule_id,
+                sender_id,
+                deliver_at,
+                message: message.clone(),
+            },
+        );
+        return Ok(schedule_id);
+    }
+
+    pub fn get_scheduled_messages(
+        &self,
+        user_id: UserID,
+    ) -> Result<Vec<ScheduledMessage>, DatabaseError> {
+        let mut messages: Vec<ScheduledMessage> = self
+            .scheduled
+            .values()
+            .filter(|scheduled| scheduled.sender_id == user_id)
+            .cloned()
+            .collect();
+        messages.sort_by_key(|scheduled| (scheduled.deliver_at, scheduled.schedule_id));
+        return Ok(messages);
+    }
+
+    pub fn cancel_scheduled_message(
+        &mut self,
+        user_id: UserID,
+        schedule_id: ScheduleID,
+    ) -> Result<bool, DatabaseError> {
+        match self.scheduled.get(&schedule_id) {
+            Some(scheduled) if scheduled.sender_id == user_id => {
+                self.scheduled.remove(&schedule_id);
+                return Ok(true);
+            }
+            _ => return Ok(false),
+        }
+    }
+
+    pub fn get_due_scheduled_messages(
+        &self,
+        now: Timestamp,
+    ) -> Result<Vec<ScheduledMessage>, DatabaseError> {
+        let mut messages: Vec<ScheduledMessage> = self
+            .scheduled
+            .values()
+            .filter(|scheduled| scheduled.deliver_at <= now)
+            .cloned()
+            .collect();
+        messages.sort_by_key(|scheduled| (scheduled.deliver_at, scheduled.schedule_id));
+        return Ok(messages);
+    }
+
+    pub fn remove_scheduled_message(
+        &mut self,
+        schedule_id: ScheduleID,
+    ) -> Result<bool, DatabaseError> {
+        return Ok(self.scheduled.remove(&schedule_id).is_some());
+    }
+
+    pub fn check_group_invitation_error(
+        &self,
+        sender_id: UserID,
+        receiver_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), GroupInvitationError> {
+        if sender_id > self.last_user_id || receiver_id > self.last_user_id {
+            return Err(GroupInvitationError::UserNotFound);
+        }
+
+        if self.check_user_exist(sender_id).is_err() || self.check_user_exist(receiver_id).is_err()
+        {
+            return Err(GroupInvitationError::UserNotFound);
+        }
+
+        if !self.check_is_group_chat(chat_id).unwrap() {
+            return Err(GroupInvitationError::NotGroupChat);
+        }
+
+        let already_in_group = self.check_user_in_chat(receiver_id, chat_id).unwrap();
+        let req_exist = self
+            .invitations
+            .contains(&(sender_id, receiver_id, chat_id));
+        if already_in_group {
+            return Err(GroupInvitationError::AlreadyInGroup);
+        } else if req_exist {
+            return Err(GroupInvitationError::RequestExist);
+        } else {
+            return Ok(());
+        }
+    }
+
+    pub fn check_invited_join_group_error(
+        &self,
+        sender_id: UserID,
+        receiver_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), InvitedJoinGroupError> {
+        if sender_id > self.last_user_id || receiver_id > self.last_user_id {
+            return Err(InvitedJoinGroupError::UserNotFound);
+        }
+
+        if self.check_user_exist(sender_id).is_err() || self.check_user_exist(receiver_id).is_err()
+        {
+            return Err(InvitedJoinGroupError::UserNotFound);
+        }
+
+        if !self.check_is_group_chat(chat_id).unwrap() {
+            return Err(InvitedJoinGroupError::NotGroupChat);
+        }
+        if self.check_user_in_chat(receiver_id, chat_id).unwrap() {
+            return Err(InvitedJoinGroupError::AlreadyInGroup);
+        } else {
+            return Ok(());
+        }
+    }
+
+    pub fn quit_group_chat(
+        &mut self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> UserQuitGroupChatResponse {
+        if !self.chat_mut(chat_id).users.remove(&user_id) {
+            return UserQuitGroupChatResponse::UserNotInChat;
+        }
+        if self.user_mut(user_id).chats.remove(&chat_id).is_none() {
+            return UserQuitGroupChatResponse::UserNotInChat;
+        }
+        self.chat_mut(chat_id).admins.remove(&user_id);
+
+        return UserQuitGroupChatResponse::Success { chat_id };
+    }
+
+    pub fn set_as_admin(&mut self, user_id: UserID, chat_id: ChatID) -> UserSetGroupAdminResponse {
+        if self.chat_mut(chat_id).admins.insert(user_id) {
+            return UserSetGroupAdminResponse::Success { chat_id, user_id };
+        } else {
+            return UserSetGroupAdminResponse::DatabaseError;
+        }
+    }
+
+    pub fn check_join_group_error(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), JoinGroupError> {
+        if self.check_user_exist(user_id).is_err() {
+            return Err(JoinGroupError::UserNotFound);
+        }
+        if !self.check_is_group_chat(chat_id).unwrap() {
+            return Err(JoinGroupError::NotGroupChat);
+        }
+        let already_in_group = self.check_user_in_chat(user_id, chat_id).unwrap();
+        let req_exist = self
+            .user(user_id)
+            .is_some_and(|user| user.pre_join.contains(&chat_id));
+        if already_in_group {
+            return Err(JoinGroupError::AlreadyInGroup);
+        } else if req_exist {
+            return Err(JoinGroupError::RequestExisted);
+        } else {
+            return Ok(());
+        }
+    }
+
+    pub fn get_chat_admins_list(
+        &self,
+        chat_id: ChatID,
+    ) -> Result<UserRequestHandler, DatabaseError> {
+        match self.chat(chat_id) {
+            Some(chat) if chat.owner.is_some() => {
+                return Ok(UserRequestHandler::Group(
+                    chat.admins.iter().copied().collect(),
+                ))
+            }
+            _ => return Err(DatabaseError::MissingKey(format!("chat:{}:owner", chat_id))),
+        }
+    }
+
+    pub fn get_chat_owner(&self, chat_id: ChatID) -> Result<UserID, DatabaseError> {
+        return self
+            .chat(chat_id)
+            .and_then(|chat| chat.owner)
+            .ok_or_else(|| DatabaseError::MissingKey(format!("chat:{}:owner", chat_id)));
+    }
+
+    pub fn check_user_is_owner(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<bool, DatabaseError> {
+        let owner = self.get_chat_owner(chat_id)?;
+        return Ok(owner == user_id);
+    }
+
+    pub fn check_user_is_admin(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<bool, DatabaseError> {
+        return Ok(self
+            .chat(chat_id)
+            .is_some_and(|chat| chat.admins.contains(&user_id)));
+    }
+
+    pub fn owner_transfer(
+        &mut self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> UserGroupOwnerTransferResponse {
+        let chat = self.chat_mut(chat_id);
+        chat.owner = Some(user_id);
+        chat.admins.insert(user_id);
+        return UserGroupOwnerTransferResponse::Success { chat_id, user_id };
+    }
+
+    pub fn add_group_notice(
+        &mut self,
+        user_id: UserID,
+        chat_id: ChatID,
+        client_id: ClientID,
+        notice: String,
+    ) -> UserSendGroupNoticeResponse {
+        let timestamp = chrono::Utc::now().timestamp_millis() as Timestamp;
+        let chat = self.chat_mut(chat_id);
+        chat.last_notice_id += 1;
+        let notice_id = chat.last_notice_id;
+
+        let serialized_notice = format!(
+            r#"{{"chatId":{}, "noticeId":{}, "senderId":{}, "content":{}, "timestamp":{}}}"#,
+            chat_id,
+            notice_id,
+            user_id,
+            serde_json::to_string::<String>(&notice).unwrap(),
+            timestamp
+        );
+
+        if !chat.notices.add(serialized_notice, notice_id as u64) {
+            return UserSendGroupNoticeResponse::DatabaseError;
+        }
+        return UserSendGroupNoticeResponse::Success {
+            chat_id,
+            client_id,
+            notice_id,
+            timestamp,
+        };
+    }
+
+    pub fn pull_group_notice(
+        &self,
+        chat_id: ChatID,
+        last_notice_id: NoticeID,
+    ) -> UserPullGroupNoticeResponse {
+        let group_notice = match self.chat(chat_id) {
+            Some(chat) => {
+                let end_id = chat.notices.card() as isize;
+                chat.notices.range(last_notice_id as isize, end_id - 1)
+            }
+            None => vec![],
+        };
+
+        return UserPullGroupNoticeResponse::Success {
+            group_notice,
+            chat_id,
+        };
+    }
+
+    pub fn update_group_info(
+        &mut self,
+        chat_id: ChatID,
+        data: UserUpdateGroupContent,
+    ) -> UserUpdateGroupInfoResponse {
+        let old_info = match self.get_chat_info(chat_id).unwrap() {
+            Some(info) => info,
+            None => return UserUpdateGroupInfoResponse::DatabaseError,
+        };
+        let old_info = match serde_json::from_str::<ChatInfo>(&old_info) {
+            Ok(info) => info,
+            Err(_) => return UserUpdateGroupInfoResponse::DatabaseError,
+        };
+
+        let new_info = match data {
+            UserUpdateGroupContent::GroupName { new_name } => ChatInfo {
+                name: new_name,
+                ..old_info
+            },
+            UserUpdateGroupContent::Avater { new_avater } => ChatInfo {
+                avater_hash: new_avater,
+                ..old_info
+            },
+        };
+
+        self.chat_mut(chat_id).info = Some(serde_json::to_string::<ChatInfo>(&new_info).unwrap());
+        return UserUpdateGroupInfoResponse::Success;
+    }
+
+    pub fn unset_admin(&mut self, user_id: UserID, chat_id: ChatID) -> UserUnsetGroupAdminResponse {
+        if self.chat_mut(chat_id).admins.remove(&user_id) {
+            return UserUnsetGroupAdminResponse::Success { chat_id, user_id };
+        } else {
+            return UserUnsetGroupAdminResponse::DatabaseError;
+        }
+    }
+
+    pub fn check_is_group_chat(&self, chat_id: ChatID) -> Result<bool, DatabaseError> {
+        return Ok(self.chat(chat_id).is_some_and(|chat| chat.owner.is_some()));
+    }
+
+    pub fn get_user_read_in_group(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+    ) -> UserGetUserReadInGroupResponse {
+        match self.get_chat_user_list(chat_id) {
+            Ok(ChatMembers::Group(user_ids)) => {
+                let mut reads = vec![];
+                for user_id in user_ids {
+                    match self.user(user_id).and_then(|user| user.chats.get(&chat_id)) {
+                        Some(msg_id) => {
+                            if *msg_id >= in_chat_id {
+                                reads.push(user_id);
+                            }
+                        }
+                        None => return UserGetUserReadInGroupResponse::DatabaseError,
+                    }
+                }
+                return UserGetUserReadInGroupResponse::Success {
+                    user_ids: reads,
+                    chat_id,
+                    in_chat_id,
+                };
+            }
+            Ok(ChatMembers::Private(_)) => return UserGetUserReadInGroupResponse::NotGroupChat,
+            Err(_) => return UserGetUserReadInGroupResponse::DatabaseError,
+        }
+    }
+
+    pub fn get_user_read_in_private(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> UserGetUserReadInPrivateResponse {
+        let users = match self.get_private_chat_user_list(chat_id) {
+            Ok(Some(users)) => users,
+            _ => return UserGetUserReadInPrivateResponse::DatabaseError,
+        };
+
+        let friend_id;
+        if user_id == users.0 {
+            friend_id = users.1;
+        } else if user_id == users.1 {
+            friend_id = users.0;
+        } else {
+            return UserGetUserReadInPrivateResponse::UserNotInChat;
+        }
+
+        match self
+            .user(friend_id)
+            .and_then(|user| user.chats.get(&chat_id))
+        {
+            Some(msg_id) => {
+                return UserGetUserReadInPrivateResponse::Success {
+                    chat_id,
+                    in_chat_id: *msg_id,
+                }
+            }
+            None => return UserGetUserReadInPrivateResponse::DatabaseError,
+        }
+    }
+}
+
+/// 将消息的词项写入倒排索引
+fn add_to_search_index(chat: &mut ChatData, in_chat_id: MessageID, serialized_msg: &str) {
+    for token in get_message_search_tokens(serialized_msg) {
+        chat.search_index
+            .entry(token)
+            .or_default()
+            .insert(in_chat_id);
+    }
+}
+
+/// 将消息的词项移出倒排索引，词项不再对应任何消息时一并删除
+fn remove_from_search_index(chat: &mut ChatData, serialized_msg: &str) {
+    let in_chat_id = match serde_json::from_str::<ChatMessage>(serialized_msg) {
+        Ok(msg) => msg.in_chat_id,
+        Err(_) => return,
+    };
+    for token in get_message_search_tokens(serialized_msg) {
+        if let Some(ids) = chat.search_index.get_mut(&token) {
+            ids.remove(&in_chat_id);
+            if ids.is_empty() {
+                chat.search_index.remove(&token);
+            }
+        }
+    }
+}
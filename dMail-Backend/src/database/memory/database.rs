@@ -1,27 +1,194 @@
-// use chashmap::CHashMap;
-// use once_cell::sync::Lazy;
-// use std::sync::atomic::AtomicU64;
-// use std::sync::Mutex;
-
-// use crate::chat::*;
-// use crate::config::datatype::{ChatID, UserID};
-// pub struct UserBaseData {
-//     pub user_name: String,
-//     pub password: String,
-//     pub email: String,
-// }
-
-// // 不需要长时间读，或许使用Mutex更好一些
-// // TODO : 比较spin、parking_lot等第三方锁实现的性能
-// #[allow(non_upper_case_globals)]
-// pub static user_array: Lazy<Mutex<Vec<UserBaseData>>> = Lazy::new(|| Mutex::new(vec![]));
-// #[allow(non_upper_case_globals)]
-// pub static user_email_hash_map: Lazy<CHashMap<String, UserID>> = Lazy::new(|| CHashMap::new());
-// #[allow(non_upper_case_globals)]
-// pub static chat_array: Lazy<Mutex<Vec<Chat>>> = Lazy::new(|| Mutex::new(vec![]));
-// #[allow(non_upper_case_globals)]
-// pub static chat_message: Lazy<Mutex<Vec<Vec<ChatMessage>>>> = Lazy::new(|| Mutex::new(vec![]));
-// #[allow(non_upper_case_globals)]
-// pub static user_chats: Lazy<Mutex<Vec<Vec<ChatID>>>> = Lazy::new(|| Mutex::new(vec![]));
-// #[allow(non_upper_case_globals)]
-// pub static meesage_current_id: AtomicU64 = AtomicU64::new(0);
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::config::datatype::*;
+use crate::user::ScheduledMessage;
+
+/** `SortedSet` 按分数排序的有序集合，行为与Redis的ZSET一致
+ * 成员按`(分数, 成员)`排序，另以成员为键记录分数，写入与按分数查找均为O(log n)
+ */
+#[derive(Default)]
+pub struct SortedSet {
+    items: BTreeSet<(u64, Arc<str>)>,
+    scores: HashMap<Arc<str>, u64>,
+}
+
+impl SortedSet {
+    /// 写入成员，成员已存在时更新其分数，返回是否为新成员
+    pub fn add(&mut self, member: String, score: u64) -> bool {
+        let member: Arc<str> = Arc::from(member);
+        let old_score = self.scores.insert(member.clone(), score);
+        if let Some(old_score) = old_score {
+            self.items.remove(&(old_score, member.clone()));
+        }
+        self.items.insert((score, member));
+        return old_score.is_none();
+    }
+
+    pub fn card(&self) -> usize {
+        return self.items.len();
+    }
+
+    /// 按排名取出成员，支持负数下标
+    pub fn range(&self, start: isize, stop: isize) -> Vec<String> {
+        let len = self.items.len() as isize;
+        let start = if start < 0 {
+            std::cmp::max(0, len + start)
+        } else {
+            start
+        };
+        let stop = if stop < 0 {
+            len + stop
+        } else {
+            std::cmp::min(stop, len - 1)
+        };
+        if start > stop || start >= len {
+            return vec![];
+        }
+        return self
+            .items
+            .iter()
+            .skip(start as usize)
+            .take((stop - start + 1) as usize)
+            .map(|(_, m)| m.to_string())
+            .collect();
+    }
+
+    /// 取出分数在`[min, max]`之间的成员
+    pub fn range_by_score(&self, min: u64, max: u64) -> Vec<String> {
+        return self
+            .score_range(min, max)
+            .map(|(_, m)| m.to_string())
+            .collect();
+    }
+
+    /// 删除分数在`[min, max]`之间的成员
+    pub fn remove_by_score(&mut self, min: u64, max: u64) {
+        let removed: Vec<(u64, Arc<str>)> = self.score_range(min, max).cloned().collect();
+        for item in removed {
+            self.scores.remove(&item.1);
+            self.items.remove(&item);
+        }
+    }
+
+    fn score_range(&self, min: u64, max: u64) -> impl Iterator<Item = &(u64, Arc<str>)> {
+        let start: Arc<str> = Arc::from("");
+        return self
+            .items
+            .range((min, start)..)
+            .take_while(move |(s, _)| *s <= max);
+    }
+}
+
+/** `UserData` 单个用户在内存中存储的数据
+ */
+#[derive(Default)]
+pub struct UserData {
+    pub info: Option<String>,
+    pub password: Option<String>,
+    pub email: Option<String>,
+    pub exist: Option<bool>,
+    pub setting: Option<String>,
+    /// 各设备上序列化的令牌
+    pub tokens: HashMap<DeviceID, String>,
+    /// 用户所在的聊天与已读到的消息ID
+    pub chats: HashMap<ChatID, MessageID>,
+    pub notices: SortedSet,
+    pub reqs: BTreeSet<UserReqId>,
+    /// 用户已申请加入的群聊
+    pub pre_join: HashSet<ChatID>,
+    /// 用户最后在线的时间
+    pub last_seen: Option<Timestamp>,
+    /// 是否向他人隐藏在线状态
+    pub presence_hidden: bool,
+    /// 各设备上序列化的公钥
+    pub device_keys: HashMap<DeviceID, String>,
+}
+
+/** `ChatData` 单个聊天在内存中存储的数据
+ */
+#[derive(Default)]
+pub struct ChatData {
+    pub info: Option<SerializedChatInfo>,
+    /// 只有群聊拥有群主
+    pub owner: Option<UserID>,
+    /// 私聊的两位用户
+    pub pair: Option<(UserID, UserID)>,
+    pub users: BTreeSet<UserID>,
+    pub admins: BTreeSet<UserID>,
+    pub msgs: SortedSet,
+    pub last_id: Option<MessageID>,
+    /// in_chat_id不超过该值的消息已被保留策略清理
+    pub pruned_id: MessageID,
+    /// 消息搜索的倒排索引，词项到in_chat_id的映射
+    pub search_index: HashMap<String, BTreeSet<MessageID>>,
+    /// 消息被编辑前的各个版本
+    pub edits: HashMap<MessageID, Vec<SerializedChatMessage>>,
+    /// 消息的表情回应，每种表情回应对应添加了它的用户
+    pub reactions: BTreeMap<MessageID, BTreeMap<String, BTreeSet<UserID>>>,
+    /// 回复各条消息的消息，回复数量即列表长度
+    pub threads: BTreeMap<MessageID, Vec<MessageID>>,
+    /// 被置顶的消息
+    pub pinned: BTreeSet<MessageID>,
+    /// 阅后即焚中消息的存活时间（秒）
+    pub disappearing_ttl: Option<u32>,
+    /// in_chat_id不超过该值的消息已完成过期检查
+    pub expired_id: MessageID,
+    pub notices: SortedSet,
+    pub last_notice_id: NoticeID,
+}
+
+/** `MemoryDatabase` 内存数据库的全部数据
+ */
+#[derive(Default)]
+pub struct MemoryDatabase {
+    pub last_user_id: UserID,
+    pub last_chat_id: ChatID,
+    pub last_req_id: UserReqId,
+    pub last_upload_id: UploadId,
+
+    pub email_to_id: HashMap<String, UserID>,
+    pub name_to_id: HashMap<String, Vec<UserID>>,
+    /// 好友对到私聊ID的映射，值为0时表示好友申请已发送
+    pub friend_chats: HashMap<(UserID, UserID), ChatID>,
+    pub invitations: HashSet<(UserID, UserID, ChatID)>,
+
+    pub users: HashMap<UserID, UserData>,
+    pub chats: HashMap<ChatID, ChatData>,
+    pub requests: HashMap<UserReqId, String>,
+    /// 已处理的请求，`true`为同意
+    pub request_states: HashMap<UserReqId, bool>,
+
+    pub file_uploads: HashMap<UploadId, String>,
+    pub file_urls: HashMap<String, SerializedFilePubUrl>,
+
+    pub last_schedule_id: ScheduleID,
+    /// 尚未发送的定时消息
+    pub scheduled: BTreeMap<ScheduleID, ScheduledMessage>,
+}
+
+impl MemoryDatabase {
+    pub fn user(&self, user_id: UserID) -> Option<&UserData> {
+        return self.users.get(&user_id);
+    }
+
+    pub fn user_mut(&mut self, user_id: UserID) -> &mut UserData {
+        return self.users.entry(user_id).or_default();
+    }
+
+    pub fn chat(&self, chat_id: ChatID) -> Option<&ChatData> {
+        return self.chats.get(&chat_id);
+    }
+
+    pub fn chat_mut(&mut self, chat_id: ChatID) -> &mut ChatData {
+        return self.chats.entry(chat_id).or_default();
+    }
+}
+
+pub fn friend_pair(user_id1: UserID, user_id2: UserID) -> (UserID, UserID) {
+    return if user_id1 < user_id2 {
+        (user_id1, user_id2)
+    } else {
+        (user_id2, user_id1)
+    };
+}
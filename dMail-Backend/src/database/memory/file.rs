@@ -0,0 +1,59 @@
+use super::database::*;
+use crate::config::datatype::*;
+use crate::database::DatabaseError;
+use crate::oss::{ObjectUploadRequest, PresignUrl};
+
+impl MemoryDatabase {
+    pub fn get_file_public_url(
+        &self,
+        hash: &str,
+    ) -> Result<Option<(PresignUrl, SerializedFilePubUrl)>, DatabaseError> {
+        return Ok(self.file_urls.get(hash).map(|serialized| {
+            (
+                serde_json::from_str::<PresignUrl>(serialized).unwrap(),
+                serialized.clone(),
+            )
+        }));
+    }
+
+    pub fn write_file_public_url(
+        &mut self,
+        hash: &str,
+        pub_url: &PresignUrl,
+    ) -> Result<(), DatabaseError> {
+        let serialized = serde_json::to_string(pub_url).unwrap();
+        self.file_urls.insert(hash.to_string(), serialized);
+        return Ok(());
+    }
+
+    pub fn write_upload_request(
+        &mut self,
+        req: ObjectUploadRequest,
+    ) -> Result<UploadId, DatabaseError> {
+        self.last_upload_id += 1;
+        let upload_id = self.last_upload_id;
+
+        self.file_uploads
+            .insert(upload_id, serde_json::to_string(&req).unwrap());
+
+        return Ok(upload_id);
+    }
+
+    pub fn get_upload_request(
+        &self,
+        upload_id: UploadId,
+    ) -> Result<Option<ObjectUploadRequest>, DatabaseError> {
+        return Ok(self
+            .file_uploads
+            .get(&upload_id)
+            .map(|serialized| serde_json::from_str(serialized).unwrap()));
+    }
+
+    pub fn get_file_url(&self, hash: &str) -> Result<SerializedFilePubUrl, DatabaseError> {
+        return self
+            .file_urls
+            .get(hash)
+            .cloned()
+            .ok_or_else(|| DatabaseError::MissingKey(format!("file:url:{}", hash)));
+    }
+}
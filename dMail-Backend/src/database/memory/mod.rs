@@ -1,4 +1,28 @@
-// mod database;
-// mod user;
+/*!
+ * 进程内的内存数据库，用于测试与单节点的开发部署，进程退出后数据即丢失。
+*/
 
-// pub use user::*;
+mod backend;
+mod chat;
+mod database;
+mod file;
+mod request;
+mod user;
+
+use std::sync::{Mutex, MutexGuard};
+
+use database::MemoryDatabase;
+
+/** `MemoryBackend` 以进程内存为存储的后端
+ */
+#[derive(Default)]
+pub struct MemoryBackend {
+    data: Mutex<MemoryDatabase>,
+}
+
+impl MemoryBackend {
+    /// 所有操作都在同一把锁内完成，因此每个接口都是原子的
+    fn db(&self) -> MutexGuard<'_, MemoryDatabase> {
+        return self.data.lock().unwrap_or_else(|e| e.into_inner());
+    }
+}
@@ -0,0 +1,181 @@
+use super::database::*;
+use crate::config::datatype::*;
+use crate::database::DatabaseError;
+use crate::user::*;
+
+impl MemoryDatabase {
+    pub fn write_user_request(
+        &mut self,
+        sender_id: UserID,
+        data: UserSendRequestData,
+        handler: &UserRequestHandler,
+    ) -> Result<(SerializedRequest, UserRequestInfo), DatabaseError> {
+        self.last_req_id += 1;
+        let req_id = self.last_req_id;
+
+        let req_info = UserRequestInfo {
+            req_id,
+            sender_id,
+            message: data.message,
+            content: data.content,
+        };
+
+        let serialized_info = serde_json::to_string(&req_info).unwrap();
+
+        self.requests.insert(req_id, serialized_info.clone());
+
+        match handler {
+            UserRequestHandler::One(user_id) => {
+                self.user_mut(*user_id).reqs.insert(req_id);
+            }
+            UserRequestHandler::Group(ids) => {
+                for user_id in ids {
+                    self.user_mut(*user_id).reqs.insert(req_id);
+                }
+            }
+        }
+        self.user_mut(sender_id).reqs.insert(req_id);
+
+        return Ok((
+            format!(r#"{{"info":{},"state":"Unsolved"}}"#, serialized_info),
+            req_info,
+        ));
+    }
+
+    pub fn store_user_request(
+        &mut self,
+        user_id: UserID,
+        req_id: UserReqId,
+    ) -> Result<(), DatabaseError> {
+        self.user_mut(user_id).reqs.insert(req_id);
+        return Ok(());
+    }
+
+    pub fn get_user_request(
+        &self,
+        req_id: UserReqId,
+    ) -> Result<Option<UserRequset>, DatabaseError> {
+        let serialized_info = match self.requests.get(&req_id) {
+            Some(info) => info,
+            None => return Ok(None),
+        };
+
+        let state = match self.request_states.get(&req_id) {
+            Some(true) => UserRequestState::Approved,
+            Some(false) => UserRequestState::Refused,
+            None => UserRequestState::Unsolved,
+        };
+
+        let info: UserRequestInfo = serde_json::from_str(serialized_info)?;
+
+        return Ok(Some(UserRequset { info, state }));
+    }
+
+    pub fn set_user_request_state(
+        &mut self,
+        req_id: UserReqId,
+        state: UserRequestState,
+    ) -> Result<(), UserSolveRequestState> {
+        if self.request_states.contains_key(&req_id) {
+            return Err(UserSolveRequestState::AlreadySolved);
+        };
+
+        let state_bool = match state {
+            UserRequestState::Approved => true,
+            UserRequestState::Unsolved => return Err(UserSolveRequestState::AnswerUnsolved),
+            UserRequestState::Refused => false,
+        };
+
+        self.request_states.insert(req_id, state_bool);
+
+        return Ok(());
+    }
+
+    pub fn get_user_requests(
+        &self,
+        user_id: UserID,
+        start_id: UserReqId,
+    ) -> Result<Vec<SerializedRequest>, DatabaseError> {
+        let user = match self.user(user_id) {
+            Some(user) => user,
+            None => return Ok(vec![]),
+        };
+
+        let mut reqs: Vec<SerializedRequest> = Vec::new();
+
+        for req_id in user.reqs.range(start_id..) {
+            let serialized_info = self
+                .requests
+                .get(req_id)
+                .ok_or_else(|| DatabaseError::MissingKey(format!("req:{}", req_id)))?;
+
+            let serialized_req = match self.request_states.get(req_id) {
+                Some(true) => format!(r#"{{"info":{},"state":"Approved"}}"#, serialized_info),
+                Some(false) => format!(r#"{{"info":{},"state":"Refused"}}"#, serialized_info),
+                None => format!(r#"{{"info":{},"state":"Unsolved"}}"#, serialized_info),
+            };
+
+            reqs.push(serialized_req);
+        }
+
+        return Ok(reqs);
+    }
+
+    pub fn write_friend_request_send(
+        &mut self,
+        user_one_id: UserID,
+        user_two_id: UserID,
+    ) -> Result<(), DatabaseError> {
+        self.friend_chats
+            .insert(friend_pair(user_one_id, user_two_id), 0);
+        return Ok(());
+    }
+
+    pub fn delete_friend_request_send(
+        &mut self,
+        user_one_id: UserID,
+        user_two_id: UserID,
+    ) -> Result<(), DatabaseError> {
+        self.friend_chats
+            .remove(&friend_pair(user_one_id, user_two_id));
+        return Ok(());
+    }
+
+    pub fn write_join_group_request_send(
+        &mut self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), DatabaseError> {
+        self.user_mut(user_id).pre_join.insert(chat_id);
+        return Ok(());
+    }
+
+    pub fn delete_join_group_request_send(
+        &mut self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), DatabaseError> {
+        self.user_mut(user_id).pre_join.remove(&chat_id);
+        return Ok(());
+    }
+
+    pub fn write_invite_request_send(
+        &mut self,
+        inviter_id: UserID,
+        receiver_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), DatabaseError> {
+        self.invitations.insert((inviter_id, receiver_id, chat_id));
+        return Ok(());
+    }
+
+    pub fn delete_invite_request_send(
+        &mut self,
+        inviter_id: UserID,
+        receiver_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), DatabaseError> {
+        self.invitations.remove(&(inviter_id, receiver_id, chat_id));
+        return Ok(());
+    }
+}
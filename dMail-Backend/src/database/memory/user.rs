@@ -1,228 +1,498 @@
-// use super::database::*;
-// use crate::chat::*;
-// use crate::config::datatype::{ChatID, MessageID, UserID};
-// use crate::user::*;
-
-// pub fn user_register(data: UserRegisterData) -> UserRegisterResponse {
-//     if user_email_hash_map.contains_key(&data.email) {
-//         return UserRegisterResponse::EmailRegistered;
-//     }
-
-//     let mut user_array_lock = match user_array.lock() {
-//         Ok(lock) => lock,
-//         Err(_) => return UserRegisterResponse::ServerError,
-//     };
-
-//     user_array_lock.push(UserBaseData {
-//         user_name: data.user_name,
-//         password: data.password,
-//         email: data.email.clone(),
-//     });
-
-//     let user_id = (user_array_lock.len() - 1) as u32;
-//     user_email_hash_map.insert(data.email, user_id);
-//     user_chats.lock().unwrap().push(vec![]);
-
-//     return UserRegisterResponse::Success { user_id: user_id };
-// }
-
-// pub fn get_user_id_by_email(email: &String) -> Option<UserID> {
-//     return user_email_hash_map.get(email).map(|guard| *guard);
-// }
-
-// pub fn user_login(data: &UserLoginData) -> UserLoginResponse {
-//     let user_array_lock = match user_array.lock() {
-//         Ok(lock) => lock,
-//         Err(_) => return UserLoginResponse::ServerError,
-//     };
-
-//     let user_id_opt = get_user_id_by_email(&data.email);
-
-//     if user_id_opt.is_none() {
-//         return UserLoginResponse::UserNotFound;
-//     }
-
-//     let user_id = user_id_opt.unwrap();
-
-//     match user_array_lock.get(user_id as usize) {
-//         Some(user_data) => {
-//             if user_data.password != *data.password.as_ref().unwrap() {
-//                 return UserLoginResponse::PasswordError;
-//             }
-//         }
-//         None => return UserLoginResponse::UserNotFound,
-//     }
-
-//     return UserLoginResponse::Success { user_id: user_id };
-// }
-
-// pub fn check_user_can_send_in_chat(
-//     user_id: UserID,
-//     chat_id: ChatID,
-// ) -> Result<ChatType, UserSendMessageResponseState> {
-//     let chat_array_lock = match chat_array.lock() {
-//         Ok(lock) => lock,
-//         Err(_) => return Err(UserSendMessageResponseState::ServerError),
-//     };
-
-//     let members = match chat_array_lock.get(chat_id as usize) {
-//         Some(chat) => &chat.members,
-//         None => return Err(UserSendMessageResponseState::ChatNotFound),
-//     };
-
-//     match members {
-//         ChatMembers::Private((id1, id2)) => {
-//             if *id1 == user_id || *id2 == user_id {
-//                 return Ok(ChatType::Private((*id1, *id2)));
-//             } else {
-//                 return Err(UserSendMessageResponseState::UserNotInChat);
-//             }
-//         }
-//         ChatMembers::Group(ids) => {
-//             if ids.contains(&user_id) {
-//                 return Ok(ChatType::Group(ids.len() as UserID));
-//             } else {
-//                 return Err(UserSendMessageResponseState::UserNotInChat);
-//             }
-//         }
-//     }
-
-//     // return Ok(ChatType::Private);
-// }
-
-// pub fn write_message_to_chat(
-//     _send_msg_data: UserSendMessageData,
-//     _sender_id: UserID,
-// ) -> Result<(ChatMessage, MessageID), ()> {
-//     todo!()
-//     // let mut chat_msg_array_lock = match chat_message.lock() {
-//     //     Ok(chat_msg) => chat_msg,
-//     //     Err(_) => return Err(()),
-//     // };
-
-//     // let msg = ChatMessage {
-//     //     chat_id: send_msg_data.chat_id,
-//     //     sender_id: sender_id,
-//     //     in_chat_id: chat_msg_array_lock[send_msg_data.chat_id as usize].len() as u64,
-//     //     text: send_msg_data.text,
-//     //     timestamp: send_msg_data.timestamp,
-//     // };
-
-//     // chat_msg_array_lock[msg.chat_id as usize].push(msg.clone());
-
-//     // return Ok((msg, send_msg_data.client_id));
-// }
-
-// pub fn get_chat_user_list(chat_id: ChatID) -> Result<ChatMembers, ()> {
-//     let chat_lock = if let Ok(lock) = chat_array.lock() {
-//         lock
-//     } else {
-//         return Err(());
-//     };
-
-//     match chat_lock.get(chat_id as usize) {
-//         Some(chat) => {
-//             // 这里的克隆是因为在远端数据库接口传回来的不可避免的要再申请内存
-//             // 内存这边其实也就无所谓了（），如果内存单独传引用接口还得改
-//             return Ok(chat.members.clone());
-//         }
-//         None => {
-//             return Err(());
-//         }
-//     }
-// }
-
-// pub fn create_group_chat(creator: Option<UserID>) -> Result<ChatID, ()> {
-//     let mut chat_lock = if let Ok(lock) = chat_array.lock() {
-//         lock
-//     } else {
-//         return Err(());
-//     };
-
-//     let chat_id = chat_lock.len() as ChatID;
-
-//     chat_lock.push(Chat {
-//         id: chat_id,
-//         members: if let Some(user_id) = creator {
-//             ChatMembers::Group(vec![user_id])
-//         } else {
-//             ChatMembers::Group(vec![])
-//         },
-//     });
-
-//     {
-//         let mut chat_msg_array_lock = match chat_message.lock() {
-//             Ok(chat_msg) => chat_msg,
-//             Err(_) => return Err(()),
-//         };
-
-//         chat_msg_array_lock.push(vec![])
-//     }
-
-//     if let Some(user_id) = creator {
-//         let mut user_chats_lock = user_chats.lock().unwrap();
-
-//         let chat_list = user_chats_lock.get_mut(user_id as usize).unwrap();
-
-//         chat_list.push(chat_id);
-//     }
-
-//     return Ok(chat_id);
-// }
-
-// pub fn add_user_to_group_chat(chat_id: ChatID, user_id: UserID) -> Result<(), ()> {
-//     let mut chat_lock = if let Ok(lock) = chat_array.lock() {
-//         lock
-//     } else {
-//         return Err(());
-//     };
-
-//     let chat = match chat_lock.get_mut(chat_id as usize) {
-//         Some(chat) => chat,
-//         None => return Err(()),
-//     };
-
-//     match &mut chat.members {
-//         ChatMembers::Private(_) => return Err(()),
-//         ChatMembers::Group(ids) => {
-//             ids.push(user_id);
-//             let mut user_chats_lock = user_chats.lock().unwrap();
-
-//             let chat_list = user_chats_lock.get_mut(user_id as usize).unwrap();
-//             chat_list.push(chat_id);
-//         }
-//     }
-
-//     return Ok(());
-// }
-
-// // pub fn get_user_chat_list_with_info(
-// //     user_id: UserID,
-// //     start_id: ChatID,
-// // ) -> Result<Vec<ChatInfo>, ()> {
-// //     let mut user_chats_lock = user_chats.lock().unwrap();
-
-// //     let chat_list = match user_chats_lock.get(user_id as usize) {
-// //         Some(list) => list,
-// //         None => return Err(()),
-// //     };
-
-// // }
-
-// pub fn get_user_info(user_id: UserID) -> GetUserInfoResponse {
-//     let users_lock = if let Ok(lock) = user_array.lock() {
-//         lock
-//     } else {
-//         return GetUserInfoResponse::ServerError;
-//     };
-
-//     if let Some(data) = users_lock.get(user_id as usize) {
-//         return GetUserInfoResponse::Success(UserInfo {
-//             user_name: data.email.clone(),
-//             user_id: 0,
-//             avater_hash: todo!(),
-//         });
-//     } else {
-//         return GetUserInfoResponse::UserNotFound;
-//     }
-// }
+use super::database::*;
+use crate::chat::ChatMembers;
+use crate::config::datatype::*;
+use crate::database::DatabaseError;
+use crate::user::*;
+
+// TODO : 切换至u64 Inf
+const TIMESTAMP_INF: Timestamp = 1000000000000000000;
+
+impl MemoryDatabase {
+    pub fn user_register(
+        &mut self,
+        data: UserRegisterData,
+    ) -> Result<UserRegisterResponse, DatabaseError> {
+        if self.email_to_id.contains_key(&data.email) {
+            return Ok(UserRegisterResponse::EmailRegistered);
+        }
+
+        self.last_user_id += 1;
+        let user_id = self.last_user_id;
+
+        let info_serialized = format!(
+            r#"{{"userId":{},"userName":"{}","avaterHash":""}}"#,
+            user_id, data.user_name
+        );
+
+        let user = self.user_mut(user_id);
+        user.info = Some(info_serialized);
+        user.password = Some(data.password);
+        user.exist = Some(true);
+        user.email = Some(data.email.clone());
+        self.email_to_id.insert(data.email, user_id);
+
+        self.add_user_name_id(&data.user_name, user_id);
+        return Ok(UserRegisterResponse::Success { user_id });
+    }
+
+    /// 通过邮箱获得用户ID与存储的密码，用户不存在时传出None
+    pub fn get_user_password_by_email(
+        &self,
+        email: &str,
+    ) -> Result<Option<(UserID, String)>, DatabaseError> {
+        let user_id = match self.email_to_id.get(email) {
+            Some(id) => *id,
+            None => return Ok(None),
+        };
+
+        let password = self
+            .user(user_id)
+            .and_then(|user| user.password.clone())
+            .ok_or_else(|| DatabaseError::MissingKey(format!("user:{}:password", user_id)))?;
+        return Ok(Some((user_id, password)));
+    }
+
+    /// 存储的密码仍为`legacy`时替换为哈希，期间密码已被修改时不替换
+    pub fn replace_legacy_password(&mut self, user_id: UserID, legacy: &str, hash: String) {
+        let user = self.user_mut(user_id);
+        if user.password.as_deref() == Some(legacy) {
+            user.password = Some(hash);
+        }
+    }
+
+    pub fn user_login_with_token(
+        &self,
+        data: &UserLoginData,
+    ) -> Result<UserLoginResponse, DatabaseError> {
+        let user_id = match self.email_to_id.get(&data.email) {
+            Some(id) => *id,
+            None => return Ok(UserLoginResponse::UserNotFound),
+        };
+
+        let token = match data.device_id.as_ref().and_then(|device_id| {
+            self.user(user_id)
+                .and_then(|user| user.tokens.get(device_id))
+        }) {
+            Some(token) => serde_json::from_str::<Token>(token)?,
+            None => return Ok(UserLoginResponse::TokenError),
+        };
+
+        if let Err(res) = token.check_login(data) {
+            return Ok(res);
+        }
+
+        return Ok(UserLoginResponse::Success { user_id });
+    }
+
+    pub fn get_user_id_by_email(&self, email: &str) -> Result<Option<UserID>, DatabaseError> {
+        return Ok(self.email_to_id.get(email).copied());
+    }
+
+    pub fn get_user_chat_list(
+        &self,
+        user_id: UserID,
+    ) -> Result<Vec<(ChatID, MessageID)>, DatabaseError> {
+        return Ok(match self.user(user_id) {
+            Some(user) => user.chats.iter().map(|(k, v)| (*k, *v)).collect(),
+            None => vec![],
+        });
+    }
+
+    pub fn get_user_info(&self, user_id: UserID) -> GetUserInfoResponse {
+        match self.user(user_id).and_then(|user| user.info.as_ref()) {
+            Some(info) => match serde_json::from_str::<UserInfo>(info) {
+                Ok(info) => return GetUserInfoResponse::Success(info),
+                Err(_) => return GetUserInfoResponse::ServerError,
+            },
+            None => return GetUserInfoResponse::UserNotFound,
+        }
+    }
+
+    pub fn get_user_email(&self, user_id: UserID) -> Result<String, DatabaseError> {
+        return self
+            .user(user_id)
+            .and_then(|user| user.email.clone())
+            .ok_or_else(|| DatabaseError::MissingKey(format!("user:{}:email", user_id)));
+    }
+
+    pub fn make_two_users_be_friends(
+        &mut self,
+        user_one_id: UserID,
+        user_two_id: UserID,
+    ) -> Result<ChatID, DatabaseError> {
+        let (id1, id2) = friend_pair(user_one_id, user_two_id);
+
+        self.last_chat_id += 1;
+        let chat_id = self.last_chat_id;
+        let serialized_chat_info = format!(r#"{{"id":{}, "users":[{},{}]}}"#, chat_id, id1, id2);
+
+        self.friend_chats.insert((id1, id2), chat_id);
+        let chat = self.chat_mut(chat_id);
+        chat.info = Some(serialized_chat_info);
+        chat.pair = Some((id1, id2));
+        self.user_mut(id1).chats.insert(chat_id, 0);
+        self.user_mut(id2).chats.insert(chat_id, 0);
+
+        return Ok(chat_id);
+    }
+
+    pub fn check_make_friend_error(
+        &self,
+        user_one_id: UserID,
+        user_two_id: UserID,
+    ) -> Result<(), MakeFriendError> {
+        let (id1, id2) = friend_pair(user_one_id, user_two_id);
+
+        if id1 > self.last_user_id || id2 > self.last_user_id {
+            return Err(MakeFriendError::UserNotFound);
+        }
+
+        if self.check_user_exist(id1).is_err() || self.check_user_exist(id2).is_err() {
+            return Err(MakeFriendError::UserNotFound);
+        }
+
+        if let Some(chat_id) = self.friend_chats.get(&(id1, id2)) {
+            return Err(if *chat_id == 0 {
+                MakeFriendError::RequestExisted
+            } else {
+                MakeFriendError::AlreadyBeFrineds
+            });
+        } else {
+            return Ok(());
+        }
+    }
+
+    pub fn set_user_setting(
+        &mut self,
+        user_id: UserID,
+        user_setting: String,
+    ) -> SetSettingResponse {
+        self.user_mut(user_id).setting = Some(user_setting);
+        return SetSettingResponse::Success;
+    }
+
+    pub fn get_user_setting(&self, user_id: UserID) -> Result<Option<String>, DatabaseError> {
+        return Ok(self.user(user_id).and_then(|user| user.setting.clone()));
+    }
+
+    pub fn update_user_name(&mut self, user_id: UserID, new_name: String) -> UserUpdateResponse {
+        match self.get_user_info(user_id) {
+            GetUserInfoResponse::Success(old_user_info) => {
+                let old_name = old_user_info.user_name.clone();
+                let new_user_info: UserInfo = UserInfo {
+                    user_name: new_name.clone(),
+                    ..old_user_info
+                };
+                self.user_mut(user_id).info =
+                    Some(serde_json::to_string::<UserInfo>(&new_user_info).unwrap());
+                self.add_user_name_id(&new_name, user_id);
+                if self.del_user_name_id(&old_name, user_id).is_err() {
+                    return UserUpdateResponse::DatabaseError;
+                }
+                return UserUpdateResponse::Success;
+            }
+            _ => return UserUpdateResponse::ServerError,
+        }
+    }
+
+    pub fn update_user_avater(&mut self, user_id: UserID, new_hash: String) -> UserUpdateResponse {
+        match self.get_user_info(user_id) {
+            GetUserInfoResponse::Success(old_user_info) => {
+                let new_user_info: UserInfo = UserInfo {
+                    avater_hash: new_hash,
+                    ..old_user_info
+                };
+                self.user_mut(user_id).info =
+                    Some(serde_json::to_string::<UserInfo>(&new_user_info).unwrap());
+                return UserUpdateResponse::Success;
+            }
+            _ => return UserUpdateResponse::ServerError,
+        }
+    }
+
+    pub fn update_user_password(
+        &mut self,
+        user_id: UserID,
+        new_password: String,
+    ) -> UserUpdateResponse {
+        let user = self.user_mut(user_id);
+        user.password = Some(new_password);
+        // 修改密码后撤销全部设备上的令牌
+        user.tokens.clear();
+        return UserUpdateResponse::Success;
+    }
+
+    pub fn get_chat_id_by_friends(
+        &self,
+        user_id1: UserID,
+        user_id2: UserID,
+    ) -> Result<Option<ChatID>, DatabaseError> {
+        return Ok(self
+            .friend_chats
+            .get(&friend_pair(user_id1, user_id2))
+            .copied());
+    }
+
+    pub fn unfriend(&mut self, user_id: UserID, friend_id: UserID) -> UserUnfriendResponse {
+        let pair = friend_pair(user_id, friend_id);
+
+        match self.friend_chats.remove(&pair) {
+            Some(chat_id) => {
+                self.user_mut(user_id).chats.remove(&chat_id);
+                self.user_mut(friend_id).chats.remove(&chat_id);
+                if let Some(chat) = self.chats.get_mut(&chat_id) {
+                    chat.pair = None;
+                }
+                return UserUnfriendResponse::Success { chat_id };
+            }
+            None => return UserUnfriendResponse::NotFriend,
+        }
+    }
+
+    pub fn set_user_already_read(
+        &mut self,
+        user_id: UserID,
+        data: UserSetAlreadyReadData,
+    ) -> SetAlreadyReadResponse {
+        let last_message_id = match self.chat(data.chat_id).and_then(|chat| chat.last_id) {
+            Some(id) => id,
+            None => return SetAlreadyReadResponse::ServerError,
+        };
+        if data.in_chat_id > last_message_id {
+            return SetAlreadyReadResponse::ServerError;
+        }
+        self.user_mut(user_id)
+            .chats
+            .insert(data.chat_id, data.in_chat_id);
+        return SetAlreadyReadResponse::Success;
+    }
+
+    pub fn check_user_in_chat(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<bool, DatabaseError> {
+        return Ok(self
+            .user(user_id)
+            .is_some_and(|user| user.chats.contains_key(&chat_id)));
+    }
+
+    pub fn write_user_notice(
+        &mut self,
+        user_id: UserID,
+        timestamp: Timestamp,
+        serialized: &SerializedUserNotice,
+    ) -> Result<(), DatabaseError> {
+        self.user_mut(user_id)
+            .notices
+            .add(serialized.clone(), timestamp);
+        return Ok(());
+    }
+
+    pub fn get_user_notice(
+        &self,
+        user_id: UserID,
+        start_timestamp: Timestamp,
+    ) -> Result<Vec<SerializedUserNotice>, DatabaseError> {
+        return Ok(match self.user(user_id) {
+            Some(user) => user.notices.range_by_score(start_timestamp, TIMESTAMP_INF),
+            None => vec![],
+        });
+    }
+
+    pub fn get_user_id(&self, name: &str) -> Result<Vec<UserID>, bool> {
+        return self.name_to_id.get(name).cloned().ok_or(true);
+    }
+
+    pub fn add_user_name_id(&mut self, name: &str, id: UserID) {
+        self.name_to_id
+            .entry(name.to_string())
+            .or_default()
+            .push(id);
+    }
+
+    pub fn del_user_name_id(&mut self, name: &str, id: UserID) -> Result<(), DatabaseError> {
+        let ids = self
+            .name_to_id
+            .get_mut(name)
+            .ok_or_else(|| DatabaseError::MissingKey(format!("name:{}", name)))?;
+        ids.retain(|&x| x != id);
+        return Ok(());
+    }
+
+    pub fn user_log_off(&mut self, user_id: UserID) -> (UserLogOffResponse, Vec<(UserID, ChatID)>) {
+        let chats = self.get_user_chat_list(user_id).unwrap();
+        let mut groups = vec![];
+        let mut friends = vec![];
+        let mut frineds_chats = vec![];
+        for chat in chats {
+            match self.get_chat_user_list(chat.0) {
+                Ok(ChatMembers::Group(_)) => match self.check_user_is_owner(user_id, chat.0) {
+                    Ok(true) => return (UserLogOffResponse::NoPermission, vec![]),
+                    Ok(false) => groups.push(chat.0),
+                    Err(_) => return (UserLogOffResponse::DatabaseError, vec![]),
+                },
+                Ok(ChatMembers::Private(pair)) => {
+                    if user_id == pair.0 {
+                        friends.push(pair.1);
+                        frineds_chats.push((pair.1, chat.0));
+                    } else {
+                        friends.push(pair.0);
+                        frineds_chats.push((pair.0, chat.0));
+                    }
+                }
+                Err(_) => return (UserLogOffResponse::DatabaseError, vec![]),
+            }
+        }
+
+        let email = match self.user_mut(user_id).email.take() {
+            Some(email) => email,
+            None => return (UserLogOffResponse::UserNotFound, vec![]),
+        };
+        if self.email_to_id.remove(&email).is_none() {
+            return (UserLogOffResponse::UserNotFound, vec![]);
+        }
+
+        for friend in friends {
+            self.unfriend(user_id, friend);
+        }
+        for group in groups {
+            self.quit_group_chat(user_id, group);
+        }
+        if let UserUpdateResponse::Success =
+            self.update_user_name(user_id, "用户已注销".to_string())
+        {
+            if self.del_user_name_id("用户已注销", user_id).is_err() {
+                return (UserLogOffResponse::DatabaseError, vec![]);
+            }
+        } else {
+            return (UserLogOffResponse::DatabaseError, vec![]);
+        }
+        self.user_mut(user_id).exist = Some(false);
+
+        return (UserLogOffResponse::Success, frineds_chats);
+    }
+
+    pub fn apply_for_token(
+        &mut self,
+        user_id: UserID,
+        device_id: &DeviceID,
+    ) -> UserApplyForTokenResponse {
+        let token = Token::generate();
+        self.user_mut(user_id)
+            .tokens
+            .insert(device_id.clone(), serde_json::to_string(&token).unwrap());
+        return UserApplyForTokenResponse::Success {
+            device_id: device_id.clone(),
+            access_token: token.access_token,
+            refresh_token: token.refresh_token,
+            timestamp: token.timestamp,
+        };
+    }
+
+    pub fn refresh_token(
+        &mut self,
+        data: &UserRefreshTokenData,
+    ) -> Result<UserRefreshTokenResponse, DatabaseError> {
+        let user_id = match self.email_to_id.get(&data.email) {
+            Some(id) => *id,
+            None => return Ok(UserRefreshTokenResponse::UserNotFound),
+        };
+        let token = match self
+            .user(user_id)
+            .and_then(|user| user.tokens.get(&data.device_id))
+        {
+            Some(token) => serde_json::from_str::<Token>(token)?,
+            None => return Ok(UserRefreshTokenResponse::TokenError),
+        };
+        if let Err(res) = token.check_refresh(&data.refresh_token) {
+            return Ok(res);
+        }
+
+        let token = Token::generate();
+        self.user_mut(user_id).tokens.insert(
+            data.device_id.clone(),
+            serde_json::to_string(&token).unwrap(),
+        );
+        return Ok(UserRefreshTokenResponse::Success {
+            device_id: data.device_id.clone(),
+            access_token: token.access_token,
+            refresh_token: token.refresh_token,
+            timestamp: token.timestamp,
+        });
+    }
+
+    pub fn revoke_token(
+        &mut self,
+        user_id: UserID,
+        device_id: &DeviceID,
+    ) -> Result<bool, DatabaseError> {
+        return Ok(self.user_mut(user_id).tokens.remove(device_id).is_some());
+    }
+
+    pub fn revoke_all_tokens(&mut self, user_id: UserID) -> Result<(), DatabaseError> {
+        self.user_mut(user_id).tokens.clear();
+        return Ok(());
+    }
+
+    pub fn check_user_exist(&self, user_id: UserID) -> Result<(), DatabaseError> {
+        match self.user(user_id).and_then(|user| user.exist) {
+            Some(true) => Ok(()),
+            _ => Err(DatabaseError::MissingKey(format!("user:{}:exist", user_id))),
+        }
+    }
+
+    pub fn set_user_last_seen(
+        &mut self,
+        user_id: UserID,
+        timestamp: Timestamp,
+    ) -> Result<(), DatabaseError> {
+        self.user_mut(user_id).last_seen = Some(timestamp);
+        return Ok(());
+    }
+
+    pub fn get_user_last_seen(&self, user_id: UserID) -> Result<Option<Timestamp>, DatabaseError> {
+        return Ok(self.user(user_id).and_then(|user| user.last_seen));
+    }
+
+    pub fn set_presence_hidden(
+        &mut self,
+        user_id: UserID,
+        hidden: bool,
+    ) -> Result<(), DatabaseError> {
+        self.user_mut(user_id).presence_hidden = hidden;
+        return Ok(());
+    }
+
+    pub fn get_presence_hidden(&self, user_id: UserID) -> Result<bool, DatabaseError> {
+        return Ok(self.user(user_id).is_some_and(|user| user.presence_hidden));
+    }
+
+    pub fn set_device_keys(
+        &mut self,
+        user_id: UserID,
+        device_id: &DeviceID,
+        serialized: &str,
+    ) -> Result<(), DatabaseError> {
+        self.user_mut(user_id)
+            .device_keys
+            .insert(device_id.clone(), serialized.to_string());
+        return Ok(());
+    }
+
+    pub fn get_device_keys(
+        &self,
+        user_id: UserID,
+        device_id: &DeviceID,
+    ) -> Result<Option<String>, DatabaseError> {
+        return Ok(self
+            .user(user_id)
+            .and_then(|user| user.device_keys.get(device_id).cloned()));
+    }
+
+    pub fn get_user_device_keys(&self, user_id: UserID) -> Result<Vec<String>, DatabaseError> {
+        return Ok(self
+            .user(user_id)
+            .map_or(vec![], |user| user.device_keys.values().cloned().collect()));
+    }
+}
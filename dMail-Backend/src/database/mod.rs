@@ -2,17 +2,22 @@
  * 数据库抽象库，为不同的数据库后端提供统一的异步接口。
 */
 
+mod memory;
 mod postgre;
 mod redis;
 
+mod backend;
 mod chat;
 mod common;
+mod error;
 mod file;
 mod request;
 mod user;
 
+pub use backend::{ChatStorage, FileStorage, RequestStorage, StorageBackend, UserStorage};
 pub use chat::*;
 pub use common::*;
+pub use error::DatabaseError;
 pub use file::*;
 pub use request::*;
 pub use user::*;
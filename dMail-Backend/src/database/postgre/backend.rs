@@ -0,0 +1,765 @@
+/*!
+ * PostgreSQL存储后端，将存储接口转发至本模块的各个函数
+*/
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use super::*;
+use crate::chat::*;
+use crate::config::datatype::*;
+use crate::database::backend::*;
+use crate::database::DatabaseError;
+use crate::oss::{ObjectUploadRequest, PresignUrl};
+use crate::user::*;
+
+/** `PostgreBackend` 以PostgreSQL为存储的后端，表结构由迁移脚本维护
+*/
+pub struct PostgreBackend;
+
+#[async_trait]
+impl StorageBackend for PostgreBackend {
+    async fn connect(&self) -> Result<(), DatabaseError> {
+        connect_database().await
+    }
+
+    async fn flush(&self) -> Result<(), DatabaseError> {
+        flush_database().await
+    }
+}
+
+#[async_trait]
+impl UserStorage for PostgreBackend {
+    async fn user_register(
+        &self,
+        data: UserRegisterData,
+    ) -> Result<UserRegisterResponse, DatabaseError> {
+        user_register(data).await
+    }
+
+    async fn user_login_with_password(
+        &self,
+        data: &UserLoginData,
+    ) -> Result<UserLoginResponse, DatabaseError> {
+        user_login_with_password(data).await
+    }
+
+    async fn user_login_with_token(
+        &self,
+        data: &UserLoginData,
+    ) -> Result<UserLoginResponse, DatabaseError> {
+        user_login_with_token(data).await
+    }
+
+    async fn get_user_id_by_email(&self, email: &str) -> Result<Option<UserID>, DatabaseError> {
+        get_user_id_by_email(email).await
+    }
+
+    async fn get_user_chat_list(
+        &self,
+        user_id: UserID,
+    ) -> Result<Vec<(ChatID, MessageID)>, DatabaseError> {
+        get_user_chat_list(user_id).await
+    }
+
+    async fn get_user_info(&self, user_id: UserID) -> GetUserInfoResponse {
+        get_user_info(user_id).await
+    }
+
+    async fn get_user_email(&self, user_id: UserID) -> Result<String, DatabaseError> {
+        get_user_email(user_id).await
+    }
+
+    async fn make_two_users_be_friends(
+        &self,
+        user_one_id: UserID,
+        user_two_id: UserID,
+    ) -> Result<ChatID, DatabaseError> {
+        make_two_users_be_friends(user_one_id, user_two_id).await
+    }
+
+    async fn check_make_friend_error(
+        &self,
+        user_one_id: UserID,
+        user_two_id: UserID,
+    ) -> Result<(), MakeFriendError> {
+        check_make_friend_error(user_one_id, user_two_id).await
+    }
+
+    async fn set_user_setting(&self, user_id: UserID, user_setting: String) -> SetSettingResponse {
+        set_user_setting(user_id, user_setting).await
+    }
+
+    async fn get_user_setting(&self, user_id: UserID) -> Result<Option<String>, DatabaseError> {
+        get_user_setting(user_id).await
+    }
+
+    async fn update_user_name(&self, user_id: UserID, new_name: String) -> UserUpdateResponse {
+        update_user_name(user_id, new_name).await
+    }
+
+    async fn update_user_avater(&self, user_id: UserID, new_hash: String) -> UserUpdateResponse {
+        update_user_avater(user_id, new_hash).await
+    }
+
+    async fn update_user_password(
+        &self,
+        user_id: UserID,
+        new_password: String,
+    ) -> UserUpdateResponse {
+        update_user_password(user_id, new_password).await
+    }
+
+    async fn unfriend(&self, user_id: UserID, friend_id: UserID) -> UserUnfriendResponse {
+        unfriend(user_id, friend_id).await
+    }
+
+    async fn set_user_already_read(
+        &self,
+        user_id: UserID,
+        data: UserSetAlreadyReadData,
+    ) -> SetAlreadyReadResponse {
+        set_user_already_read(user_id, data).await
+    }
+
+    async fn check_user_in_chat(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<bool, DatabaseError> {
+        check_user_in_chat(user_id, chat_id).await
+    }
+
+    async fn write_user_notice(
+        &self,
+        user_id: UserID,
+        timestamp: Timestamp,
+        serialized: &SerializedUserNotice,
+    ) -> Result<(), DatabaseError> {
+        write_user_notice(user_id, timestamp, serialized).await
+    }
+
+    async fn get_user_notice(
+        &self,
+        user_id: UserID,
+        start_timestamp: Timestamp,
+    ) -> Result<Vec<SerializedUserNotice>, DatabaseError> {
+        get_user_notice(user_id, start_timestamp).await
+    }
+
+    async fn get_chat_id_by_friends(
+        &self,
+        user_id1: UserID,
+        user_id2: UserID,
+    ) -> Result<Option<ChatID>, DatabaseError> {
+        get_chat_id_by_friends(user_id1, user_id2).await
+    }
+
+    async fn get_user_id(&self, name: &str) -> Result<Vec<UserID>, bool> {
+        get_user_id(name).await
+    }
+
+    async fn user_log_off(&self, user_id: UserID) -> (UserLogOffResponse, Vec<(UserID, ChatID)>) {
+        user_log_off(user_id).await
+    }
+
+    async fn apply_for_token(
+        &self,
+        user_id: UserID,
+        device_id: &DeviceID,
+    ) -> UserApplyForTokenResponse {
+        apply_for_token(user_id, device_id).await
+    }
+
+    async fn refresh_token(
+        &self,
+        data: &UserRefreshTokenData,
+    ) -> Result<UserRefreshTokenResponse, DatabaseError> {
+        refresh_token(data).await
+    }
+
+    async fn revoke_token(
+        &self,
+        user_id: UserID,
+        device_id: &DeviceID,
+    ) -> Result<bool, DatabaseError> {
+        revoke_token(user_id, device_id).await
+    }
+
+    async fn revoke_all_tokens(&self, user_id: UserID) -> Result<(), DatabaseError> {
+        revoke_all_tokens(user_id).await
+    }
+
+    async fn check_user_exist(&self, user_id: UserID) -> Result<(), DatabaseError> {
+        check_user_exist(user_id).await
+    }
+
+    async fn set_user_last_seen(
+        &self,
+        user_id: UserID,
+        timestamp: Timestamp,
+    ) -> Result<(), DatabaseError> {
+        set_user_last_seen(user_id, timestamp).await
+    }
+
+    async fn get_user_last_seen(
+        &self,
+        user_id: UserID,
+    ) -> Result<Option<Timestamp>, DatabaseError> {
+        get_user_last_seen(user_id).await
+    }
+
+    async fn set_presence_hidden(
+        &self,
+        user_id: UserID,
+        hidden: bool,
+    ) -> Result<(), DatabaseError> {
+        set_presence_hidden(user_id, hidden).await
+    }
+
+    async fn get_presence_hidden(&self, user_id: UserID) -> Result<bool, DatabaseError> {
+        get_presence_hidden(user_id).await
+    }
+
+    async fn set_device_keys(
+        &self,
+        user_id: UserID,
+        device_id: &DeviceID,
+        serialized: &str,
+    ) -> Result<(), DatabaseError> {
+        set_device_keys(user_id, device_id, serialized).await
+    }
+
+    async fn get_device_keys(
+        &self,
+        user_id: UserID,
+        device_id: &DeviceID,
+    ) -> Result<Option<String>, DatabaseError> {
+        get_device_keys(user_id, device_id).await
+    }
+
+    async fn get_user_device_keys(&self, user_id: UserID) -> Result<Vec<String>, DatabaseError> {
+        get_user_device_keys(user_id).await
+    }
+}
+
+#[async_trait]
+impl ChatStorage for PostgreBackend {
+    async fn create_group_chat(
+        &self,
+        creator_id: UserID,
+        data: UserCreateGroupChatData,
+    ) -> Result<ChatID, DatabaseError> {
+        create_group_chat(creator_id, data).await
+    }
+
+    async fn add_user_to_group_chat(
+        &self,
+        chat_id: ChatID,
+        user_id: UserID,
+    ) -> Result<(), DatabaseError> {
+        add_user_to_group_chat(chat_id, user_id).await
+    }
+
+    async fn write_message_to_chat(
+        &self,
+        r#type: &str,
+        serialized_content: String,
+        chat_id: ChatID,
+        sender_id: UserID,
+        forwarded_from: Option<&ForwardedFrom>,
+        reply_to: Option<MessageID>,
+    ) -> Result<(SerializedChatMessage, MessageID, Timestamp), DatabaseError> {
+        write_message_to_chat(
+            r#type,
+            serialized_content,
+            chat_id,
+            sender_id,
+            forwarded_from,
+            reply_to,
+        )
+        .await
+    }
+
+    async fn check_user_can_send_in_chat(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<ChatType, UserSendMessageResponseState> {
+        check_user_can_send_in_chat(user_id, chat_id).await
+    }
+
+    async fn get_chat_user_list(&self, chat_id: ChatID) -> Result<ChatMembers, DatabaseError> {
+        get_chat_user_list(chat_id).await
+    }
+
+    async fn get_private_chat_user_list(
+        &self,
+        chat_id: ChatID,
+    ) -> Result<Option<(UserID, UserID)>, DatabaseError> {
+        get_private_chat_user_list(chat_id).await
+    }
+
+    async fn get_messages_in_chat(
+        &self,
+        chat_id: ChatID,
+        start_msg_id: MessageID,
+        end_msg_id: Option<MessageID>,
+    ) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+        get_messages_in_chat(chat_id, start_msg_id, end_msg_id).await
+    }
+
+    async fn get_chats_last_messages(
+        &self,
+        chats: &[(ChatID, MessageID)],
+        max_messages_per_chat: u8,
+    ) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+        get_chats_last_messages(chats, max_messages_per_chat).await
+    }
+
+    async fn get_chat_info(
+        &self,
+        chat_id: ChatID,
+    ) -> Result<Option<SerializedChatInfo>, DatabaseError> {
+        get_chat_info(chat_id).await
+    }
+
+    async fn revoke_message(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        sender_id: UserID,
+        timestamp: Timestamp,
+    ) -> Result<(), DatabaseError> {
+        revoke_message(chat_id, in_chat_id, sender_id, timestamp).await
+    }
+
+    async fn edit_message(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        old_msg: &str,
+        new_msg: &str,
+    ) -> Result<bool, DatabaseError> {
+        edit_message(chat_id, in_chat_id, old_msg, new_msg).await
+    }
+
+    async fn get_edit_history(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+    ) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+        get_edit_history(chat_id, in_chat_id).await
+    }
+
+    async fn add_reaction(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        user_id: UserID,
+        emoji: &str,
+    ) -> Result<bool, DatabaseError> {
+        add_reaction(chat_id, in_chat_id, user_id, emoji).await
+    }
+
+    async fn remove_reaction(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        user_id: UserID,
+        emoji: &str,
+    ) -> Result<bool, DatabaseError> {
+        remove_reaction(chat_id, in_chat_id, user_id, emoji).await
+    }
+
+    async fn get_reaction_counts(
+        &self,
+        chat_id: ChatID,
+        start_msg_id: MessageID,
+        end_msg_id: Option<MessageID>,
+    ) -> Result<HashMap<MessageID, ReactionCounts>, DatabaseError> {
+        get_reaction_counts(chat_id, start_msg_id, end_msg_id).await
+    }
+
+    async fn get_thread_replies(
+        &self,
+        chat_id: ChatID,
+        root_id: MessageID,
+    ) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+        get_thread_replies(chat_id, root_id).await
+    }
+
+    async fn get_reply_counts(
+        &self,
+        chat_id: ChatID,
+        start_msg_id: MessageID,
+        end_msg_id: Option<MessageID>,
+    ) -> Result<HashMap<MessageID, u32>, DatabaseError> {
+        get_reply_counts(chat_id, start_msg_id, end_msg_id).await
+    }
+
+    async fn pin_message(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+    ) -> Result<bool, DatabaseError> {
+        pin_message(chat_id, in_chat_id).await
+    }
+
+    async fn unpin_message(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+    ) -> Result<bool, DatabaseError> {
+        unpin_message(chat_id, in_chat_id).await
+    }
+
+    async fn get_pinned_messages(
+        &self,
+        chat_id: ChatID,
+    ) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+        get_pinned_messages(chat_id).await
+    }
+
+    async fn get_chat_ids(&self) -> Result<Vec<ChatID>, DatabaseError> {
+        get_chat_ids().await
+    }
+
+    async fn prune_messages(
+        &self,
+        chat_id: ChatID,
+        min_timestamp: Option<Timestamp>,
+        max_count: Option<MessageID>,
+    ) -> Result<MessageID, DatabaseError> {
+        prune_messages(chat_id, min_timestamp, max_count).await
+    }
+
+    async fn get_pruned_id(&self, chat_id: ChatID) -> Result<MessageID, DatabaseError> {
+        get_pruned_id(chat_id).await
+    }
+
+    async fn set_disappearing_ttl(
+        &self,
+        chat_id: ChatID,
+        ttl: Option<u32>,
+    ) -> Result<(), DatabaseError> {
+        set_disappearing_ttl(chat_id, ttl).await
+    }
+
+    async fn get_disappearing_ttl(&self, chat_id: ChatID) -> Result<Option<u32>, DatabaseError> {
+        get_disappearing_ttl(chat_id).await
+    }
+
+    async fn get_disappearing_chats(&self) -> Result<Vec<(ChatID, u32)>, DatabaseError> {
+        get_disappearing_chats().await
+    }
+
+    async fn get_expired_id(&self, chat_id: ChatID) -> Result<MessageID, DatabaseError> {
+        get_expired_id(chat_id).await
+    }
+
+    async fn set_expired_id(
+        &self,
+        chat_id: ChatID,
+        expired_id: MessageID,
+    ) -> Result<(), DatabaseError> {
+        set_expired_id(chat_id, expired_id).await
+    }
+
+    async fn search_messages(
+        &self,
+        chat_ids: &[ChatID],
+        keyword: &str,
+        cursor: Option<SearchCursor>,
+        limit: u8,
+    ) -> Result<SearchPage, DatabaseError> {
+        search_messages(chat_ids, keyword, cursor, limit).await
+    }
+
+    async fn add_scheduled_message(
+        &self,
+        sender_id: UserID,
+        deliver_at: Timestamp,
+        message: &UserSendMessageData,
+    ) -> Result<ScheduleID, DatabaseError> {
+        add_scheduled_message(sender_id, deliver_at, message).await
+    }
+
+    async fn get_scheduled_messages(
+        &self,
+        user_id: UserID,
+    ) -> Result<Vec<ScheduledMessage>, DatabaseError> {
+        get_scheduled_messages(user_id).await
+    }
+
+    async fn cancel_scheduled_message(
+        &self,
+        user_id: UserID,
+        schedule_id: ScheduleID,
+    ) -> Result<bool, DatabaseError> {
+        cancel_scheduled_message(user_id, schedule_id).await
+    }
+
+    async fn get_due_scheduled_messages(
+        &self,
+        now: Timestamp,
+    ) -> Result<Vec<ScheduledMessage>, DatabaseError> {
+        get_due_scheduled_messages(now).await
+    }
+
+    async fn remove_scheduled_message(
+        &self,
+        schedule_id: ScheduleID,
+    ) -> Result<bool, DatabaseError> {
+        remove_scheduled_message(schedule_id).await
+    }
+
+    async fn check_group_invitation_error(
+        &self,
+        sender_id: UserID,
+        receiver_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), GroupInvitationError> {
+        check_group_invitation_error(sender_id, receiver_id, chat_id).await
+    }
+
+    async fn check_invited_join_group_error(
+        &self,
+        sender_id: UserID,
+        receiver_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), InvitedJoinGroupError> {
+        check_invited_join_group_error(sender_id, receiver_id, chat_id).await
+    }
+
+    async fn quit_group_chat(&self, user_id: UserID, chat_id: ChatID) -> UserQuitGroupChatResponse {
+        quit_group_chat(user_id, chat_id).await
+    }
+
+    async fn set_as_admin(&self, user_id: UserID, chat_id: ChatID) -> UserSetGroupAdminResponse {
+        set_as_admin(user_id, chat_id).await
+    }
+
+    async fn check_join_group_error(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), JoinGroupError> {
+        check_join_group_error(user_id, chat_id).await
+    }
+
+    async fn get_chat_admins_list(
+        &self,
+        chat_id: ChatID,
+    ) -> Result<UserRequestHandler, DatabaseError> {
+        get_chat_admins_list(chat_id).await
+    }
+
+    async fn get_chat_owner(&self, chat_id: ChatID) -> Result<UserID, DatabaseError> {
+        get_chat_owner(chat_id).await
+    }
+
+    async fn check_user_is_owner(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<bool, DatabaseError> {
+        check_user_is_owner(user_id, chat_id).await
+    }
+
+    async fn check_user_is_admin(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<bool, DatabaseError> {
+        check_user_is_admin(user_id, chat_id).await
+    }
+
+    async fn owner_transfer(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> UserGroupOwnerTransferResponse {
+        owner_transfer(user_id, chat_id).await
+    }
+
+    async fn add_group_notice(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+        client_id: ClientID,
+        notice: String,
+    ) -> UserSendGroupNoticeResponse {
+        add_group_notice(user_id, chat_id, client_id, notice).await
+    }
+
+    async fn pull_group_notice(
+        &self,
+        chat_id: ChatID,
+        last_notice_id: NoticeID,
+    ) -> UserPullGroupNoticeResponse {
+        pull_group_notice(chat_id, last_notice_id).await
+    }
+
+    async fn update_group_info(
+        &self,
+        chat_id: ChatID,
+        data: UserUpdateGroupContent,
+    ) -> UserUpdateGroupInfoResponse {
+        update_group_info(chat_id, data).await
+    }
+
+    async fn unset_admin(&self, user_id: UserID, chat_id: ChatID) -> UserUnsetGroupAdminResponse {
+        unset_admin(user_id, chat_id).await
+    }
+
+    async fn check_is_group_chat(&self, chat_id: ChatID) -> Result<bool, DatabaseError> {
+        check_is_group_chat(chat_id).await
+    }
+
+    async fn get_user_read_in_group(
+        &self,
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+    ) -> UserGetUserReadInGroupResponse {
+        get_user_read_in_group(chat_id, in_chat_id).await
+    }
+
+    async fn get_user_read_in_private(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> UserGetUserReadInPrivateResponse {
+        get_user_read_in_private(user_id, chat_id).await
+    }
+}
+
+#[async_trait]
+impl RequestStorage for PostgreBackend {
+    async fn get_user_requests(
+        &self,
+        user_id: UserID,
+        start_req_id: UserReqId,
+    ) -> Result<Vec<SerializedRequest>, DatabaseError> {
+        get_user_requests(user_id, start_req_id).await
+    }
+
+    async fn write_user_request(
+        &self,
+        sender_id: UserID,
+        data: UserSendRequestData,
+        handler: &UserRequestHandler,
+    ) -> Result<(SerializedRequest, UserRequestInfo), DatabaseError> {
+        write_user_request(sender_id, data, handler).await
+    }
+
+    async fn store_user_request(
+        &self,
+        user_id: UserID,
+        req_id: UserReqId,
+    ) -> Result<(), DatabaseError> {
+        store_user_request(user_id, req_id).await
+    }
+
+    async fn get_user_request(
+        &self,
+        req_id: UserReqId,
+    ) -> Result<Option<UserRequset>, DatabaseError> {
+        get_user_request(req_id).await
+    }
+
+    async fn set_user_request_state(
+        &self,
+        req_id: UserReqId,
+        state: UserRequestState,
+    ) -> Result<(), UserSolveRequestState> {
+        set_user_request_state(req_id, state).await
+    }
+
+    async fn write_friend_request_send(
+        &self,
+        user_one_id: UserID,
+        user_two_id: UserID,
+    ) -> Result<(), DatabaseError> {
+        write_friend_request_send(user_one_id, user_two_id).await
+    }
+
+    async fn delete_friend_request_send(
+        &self,
+        user_one_id: UserID,
+        user_two_id: UserID,
+    ) -> Result<(), DatabaseError> {
+        delete_friend_request_send(user_one_id, user_two_id).await
+    }
+
+    async fn write_join_group_request_send(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), DatabaseError> {
+        write_join_group_request_send(user_id, chat_id).await
+    }
+
+    async fn delete_join_group_request_send(
+        &self,
+        user_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), DatabaseError> {
+        delete_join_group_request_send(user_id, chat_id).await
+    }
+
+    async fn write_invite_request_send(
+        &self,
+        inviter_id: UserID,
+        receiver_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), DatabaseError> {
+        write_invite_request_send(inviter_id, receiver_id, chat_id).await
+    }
+
+    async fn delete_invite_request_send(
+        &self,
+        inviter_id: UserID,
+        receiver_id: UserID,
+        chat_id: ChatID,
+    ) -> Result<(), DatabaseError> {
+        delete_invite_request_send(inviter_id, receiver_id, chat_id).await
+    }
+}
+
+#[async_trait]
+impl FileStorage for PostgreBackend {
+    async fn get_file_public_url(
+        &self,
+        hash: &str,
+    ) -> Result<Option<(PresignUrl, SerializedFilePubUrl)>, DatabaseError> {
+        get_file_public_url(hash).await
+    }
+
+    async fn write_file_public_url(
+        &self,
+        hash: &str,
+        pub_url: &PresignUrl,
+    ) -> Result<(), DatabaseError> {
+        write_file_public_url(hash, pub_url).await
+    }
+
+    async fn write_upload_request(
+        &self,
+        req: ObjectUploadRequest,
+    ) -> Result<UploadId, DatabaseError> {
+        write_upload_request(req).await
+    }
+
+    async fn get_upload_request(
+        &self,
+        upload_id: UploadId,
+    ) -> Result<Option<ObjectUploadRequest>, DatabaseError> {
+        get_upload_request(upload_id).await
+    }
+
+    async fn get_file_url(&self, hash: &str) -> Result<SerializedFilePubUrl, DatabaseError> {
+        get_file_url(hash).await
+    }
+}
@@ -0,0 +1,1431 @@
+use std::collections::HashMap;
+
+use mobc_postgres::tokio_postgres::{GenericClient, Row};
+
+use super::check_user_in_chat;
+use super::common::*;
+use crate::chat::get_forwarded_field;
+use crate::chat::get_keyword_tokens;
+use crate::chat::get_search_tokens;
+use crate::chat::ChatInfo;
+use crate::chat::ChatMembers;
+use crate::chat::ChatMessage;
+use crate::chat::ChatType;
+use crate::chat::ForwardedFrom;
+use crate::chat::SearchCursor;
+use crate::chat::SearchPage;
+use crate::config::datatype::*;
+use crate::database::check_user_exist;
+use crate::database::DatabaseError;
+use crate::user::*;
+
+/// 私聊的两位用户，解除好友关系后不再返回
+async fn get_chat_pair<C: GenericClient>(
+    con: &C,
+    chat_id: ChatID,
+) -> Result<Option<(UserID, UserID)>, DatabaseError> {
+    let row = con
+        .query_opt(
+            "SELECT chats.user_one_id, chats.user_two_id FROM chats
+            JOIN friends USING (chat_id) WHERE chat_id = $1",
+            &[&(chat_id as i64)],
+        )
+        .await?;
+
+    return Ok(row.map(|row| {
+        (
+            row.get::<_, i64>(0) as UserID,
+            row.get::<_, i64>(1) as UserID,
+        )
+    }));
+}
+
+/// 聊天是否存在以及是否为群聊
+async fn get_chat_kind<C: GenericClient>(
+    con: &C,
+    chat_id: ChatID,
+) -> Result<Option<bool>, DatabaseError> {
+    let row = con
+        .query_opt(
+            "SELECT is_group FROM chats WHERE chat_id = $1",
+            &[&(chat_id as i64)],
+        )
+        .await?;
+
+    return Ok(row.map(|row| row.get(0)));
+}
+
+pub async fn create_group_chat(
+    creator: UserID,
+    data: UserCreateGroupChatData,
+) -> Result<ChatID, DatabaseError> {
+    let mut con = get_con().await?;
+    let creator = creator as i64;
+
+    let tx = con.transaction().await?;
+
+    let chat_id: i64 = tx
+        .query_one(
+            "INSERT INTO chats (is_group, name, avater_hash, owner_id) VALUES (TRUE, $1, $2, $3)
+            RETURNING chat_id",
+            &[&data.name, &data.avater_hash, &creator],
+        )
+        .await?
+        .get(0);
+
+    tx.execute(
+        "INSERT INTO chat_admins (chat_id, user_id) VALUES ($1, $2)",
+        &[&chat_id, &creator],
+    )
+    .await?;
+    tx.execute(
+        "INSERT INTO chat_members (chat_id, user_id) VALUES ($1, $2)",
+        &[&chat_id, &creator],
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    return Ok(chat_id as ChatID);
+}
+
+pub async fn add_user_to_group_chat(chat_id: ChatID, user_id: UserID) -> Result<(), DatabaseError> {
+    let con = get_con().await?;
+
+    con.execute(
+        "INSERT INTO chat_members (chat_id, user_id) VALUES ($1, $2)
+        ON CONFLICT (chat_id, user_id) DO UPDATE SET already_read = 0",
+        &[&(chat_id as i64), &(user_id as i64)],
+    )
+    .await?;
+
+    return Ok(());
+}
+
+pub async fn write_message_to_chat(
+    r#type: &str,
+    serialized_content: String,
+    chat_id: ChatID,
+    sender_id: UserID,
+    forwarded_from: Option<&ForwardedFrom>,
+    reply_to: Option<MessageID>,
+) -> Result<(SerializedChatMessage, MessageID, Timestamp), DatabaseError> {
+    let mut con = get_con().await?;
+
+    let timestamp = chrono::Utc::now().timestamp_millis() as Timestamp;
+
+    let tx = con.transaction().await?;
+
+    let in_chat_id: i64 = tx
+        .query_opt(
+            "UPDATE chats SET last_msg_id = last_msg_id + 1 WHERE chat_id = $1
+            RETURNING last_msg_id",
+            &[&(chat_id as i64)],
+        )
+        .await?
+        .ok_or_else(|| DatabaseError::MissingKey(format!("chat:{}", chat_id)))?
+        .get(0);
+
+    let serialized_msg = format!(
+        r#"{{"type":{}, "inChatId":{}, "chatId":{}, "senderId":{}, "serializedContent":{}, "timestamp":{}{}}}"#,
+        r#type,
+        in_chat_id,
+        chat_id,
+        sender_id,
+        serialized_content,
+        timestamp,
+        get_forwarded_field(forwarded_from)
+    );
+    let search_text = serde_json::from_str::<ChatMessage>(&serialized_msg)
+        .ok()
+        .and_then(|msg| msg.get_search_text());
+    let reply_root = reply_to.map(|root_id| root_id as i64);
+
+    tx.execute(
+        "INSERT INTO messages
+        (chat_id, in_chat_id, msg_type, sender_id, timestamp, serialized, search_text, reply_root)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        &[
+            &(chat_id as i64),
+            &in_chat_id,
+            &r#type,
+            &(sender_id as i64),
+            &(timestamp as i64),
+            &serialized_msg,
+            &search_text,
+            &reply_root,
+        ],
+    )
+    .await?;
+    if let Some(root_id) = reply_root {
+        tx.execute(
+            "UPDATE messages SET reply_count = reply_count + 1 WHERE chat_id = $1 AND in_chat_id = $2",
+            &[&(chat_id as i64), &root_id],
+        )
+        .await?;
+    }
+    if let Some(search_text) = &search_text {
+        let tokens: Vec<String> = get_search_tokens(search_text).into_iter().collect();
+        tx.execute(
+            "INSERT INTO message_tokens (token, chat_id, in_chat_id)
+            SELECT UNNEST($1::TEXT[]), $2, $3",
+            &[&tokens, &(chat_id as i64), &in_chat_id],
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    return Ok((serialized_msg, in_chat_id as MessageID, timestamp));
+}
+
+pub async fn get_chat_user_list(chat_id: ChatID) -> Result<ChatMembers, DatabaseError> {
+    let con = get_con().await?;
+
+    match get_chat_kind(&*con, chat_id).await? {
+        Some(true) => {
+            let rows = con
+                .query(
+                    "SELECT user_id FROM chat_members WHERE chat_id = $1 ORDER BY user_id",
+                    &[&(chat_id as i64)],
+                )
+                .await?;
+            return Ok(ChatMembers::Group(
+                rows.iter()
+                    .map(|row| row.get::<_, i64>(0) as UserID)
+                    .collect(),
+            ));
+        }
+        _ => {
+            let pair = get_chat_pair(&*con, chat_id)
+                .await?
+                .ok_or_else(|| DatabaseError::MissingKey(format!("chat:{}:users", chat_id)))?;
+            return Ok(ChatMembers::Private(pair));
+        }
+    }
+}
+
+pub async fn get_private_chat_user_list(
+    chat_id: ChatID,
+) -> Result<Option<(UserID, UserID)>, DatabaseError> {
+    let con = get_con().await?;
+
+    if let Some(true) = get_chat_kind(&*con, chat_id).await? {
+        return Ok(None);
+    }
+    let pair = get_chat_pair(&*con, chat_id)
+        .await?
+        .ok_or_else(|| DatabaseError::MissingKey(format!("chat:{}:users", chat_id)))?;
+    return Ok(Some(pair));
+}
+
+pub async fn check_user_can_send_in_chat(
+    user_id: UserID,
+    chat_id: ChatID,
+) -> Result<ChatType, UserSendMessageResponseState> {
+    let con = get_con()
+        .await
+        .map_err(|_| UserSendMessageResponseState::DatabaseError)?;
+
+    let is_group = match get_chat_kind(&*con, chat_id).await {
+        Ok(Some(is_group)) => is_group,
+        Ok(None) => return Err(UserSendMessageResponseState::ChatNotFound),
+        Err(_) => return Err(UserSendMessageResponseState::DatabaseError),
+    };
+
+    if is_group {
+        let row = con
+            .query_one(
+                "SELECT COUNT(*), COALESCE(BOOL_OR(user_id = $2), FALSE)
+                FROM chat_members WHERE chat_id = $1",
+                &[&(chat_id as i64), &(user_id as i64)],
+            )
+            .await
+            .map_err(|_| UserSendMessageResponseState::DatabaseError)?;
+        let (group_user_num, user_in_chat): (i64, bool) = (row.get(0), row.get(1));
+
+        if !user_in_chat && user_id != 0 {
+            return Err(UserSendMessageResponseState::UserNotInChat);
+        }
+        return Ok(ChatType::Group(group_user_num as UserID));
+    } else {
+        let pair = match get_chat_pair(&*con, chat_id).await {
+            Ok(Some(pair)) => pair,
+            Ok(None) => return Err(UserSendMessageResponseState::UserNotInChat),
+            Err(_) => return Err(UserSendMessageResponseState::DatabaseError),
+        };
+        if user_id == pair.0 || user_id == pair.1 || user_id == 0 {
+            return Ok(ChatType::Private(pair));
+        } else {
+            return Err(UserSendMessageResponseState::UserNotInChat);
+        }
+    }
+}
+
+pub async fn get_chats_last_messages(
+    chats_id: &[(ChatID, MessageID)],
+    max_messages_per_chat: u8,
+) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+    let con = get_con().await?;
+
+    let mut ret: Vec<SerializedChatMessage> = Vec::new();
+
+    for (chat_id, _) in chats_id {
+        // 与Redis实现保持一致，消息数足够时多取出一条
+        let rows = con
+            .query(
+                "SELECT serialized FROM messages WHERE chat_id = $1
+                ORDER BY in_chat_id DESC LIMIT $2",
+                &[&(*chat_id as i64), &(max_messages_per_chat as i64 + 1)],
+            )
+            .await?;
+
+        ret.extend(rows.iter().rev().map(|row| row.get::<_, String>(0)));
+    }
+
+    return Ok(ret);
+}
+
+pub async fn get_messages_in_chat(
+    chat_id: ChatID,
+    start_msg_id: MessageID,
+    end_msg_id_opt: Option<MessageID>,
+) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+    let con = get_con().await?;
+
+    let end_msg_id = match end_msg_id_opt {
+        Some(id) => id as i64,
+        None => i64::MAX,
+    };
+
+    let rows = con
+        .query(
+            "SELECT serialized FROM messages
+            WHERE chat_id = $1 AND in_chat_id BETWEEN $2 AND $3 ORDER BY in_chat_id",
+            &[&(chat_id as i64), &(start_msg_id as i64), &end_msg_id],
+        )
+        .await?;
+
+    return Ok(rows.iter().map(|row| row.get(0)).collect());
+}
+
+pub async fn get_chat_info(chat_id: ChatID) -> Result<Option<SerializedChatInfo>, DatabaseError> {
+    let con = get_con().await?;
+
+    let row = con
+        .query_opt(
+            "SELECT is_group, name, avater_hash, user_one_id, user_two_id
+            FROM chats WHERE chat_id = $1",
+            &[&(chat_id as i64)],
+        )
+        .await?;
+
+    return Ok(row.map(|row| {
+        if row.get(0) {
+            serde_json::to_string(&ChatInfo {
+                id: chat_id,
+                name: row.get(1),
+                avater_hash: row.get(2),
+            })
+            .unwrap()
+        } else {
+            format!(
+                r#"{{"id":{}, "users":[{},{}]}}"#,
+                chat_id,
+                row.get::<_, i64>(3),
+                row.get::<_, i64>(4)
+            )
+        }
+    }));
+}
+
+pub async fn revoke_message(
+    chat_id: ChatID,
+    in_chat_id: MessageID,
+    sender_id: UserID,
+    timestamp: Timestamp,
+) -> Result<(), DatabaseError> {
+    let mut con = get_con().await?;
+
+    let msg_type = ChatMessageType::Revoked.get_str();
+    let serialized_msg = format!(
+        r#"{{"type":{}, "inChatId":{}, "chatId":{}, "senderId":{}, "serializedContent":"\"\"", "timestamp":{}}}"#,
+        msg_type, in_chat_id, chat_id, sender_id, timestamp,
+    );
+
+    let tx = con.transaction().await?;
+
+    tx.execute(
+        "DELETE FROM message_tokens WHERE chat_id = $1 AND in_chat_id = $2",
+        &[&(chat_id as i64), &(in_chat_id as i64)],
+    )
+    .await?;
+    tx.execute(
+        "DELETE FROM message_edits WHERE chat_id = $1 AND in_chat_id = $2",
+        &[&(chat_id as i64), &(in_chat_id as i64)],
+    )
+    .await?;
+    tx.execute(
+        "DELETE FROM message_reactions WHERE chat_id = $1 AND in_chat_id = $2",
+        &[&(chat_id as i64), &(in_chat_id as i64)],
+    )
+    .await?;
+    tx.execute(
+        "DELETE FROM pinned_messages WHERE chat_id = $1 AND in_chat_id = $2",
+        &[&(chat_id as i64), &(in_chat_id as i64)],
+    )
+    .await?;
+    tx.execute(
+        "INSERT INTO messages (chat_id, in_chat_id, msg_type, sender_id, timestamp, serialized)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (chat_id, in_chat_id) DO UPDATE
+        SET msg_type = $3, sender_id = $4, timestamp = $5, serialized = $6, search_text = NULL",
+        &[
+            &(chat_id as i64),
+            &(in_chat_id as i64),
+            &msg_type,
+            &(sender_id as i64),
+            &(timestamp as i64),
+            &serialized_msg,
+        ],
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    return Ok(());
+}
+
+pub async fn edit_message(
+    chat_id: ChatID,
+    in_chat_id: MessageID,
+    old_msg: &str,
+    new_msg: &str,
+) -> Result<bool, DatabaseError> {
+    let mut con = get_con().await?;
+    let chat_id = chat_id as i64;
+    let in_chat_id = in_chat_id as i64;
+
+    let search_text = serde_json::from_str::<ChatMessage>(new_msg)
+        .ok()
+        .and_then(|msg| msg.get_search_text());
+
+    let tx = con.transaction().await?;
+
+    let updated = tx
+        .execute(
+            "UPDATE messages SET serialized = $4, search_text = $5
+            WHERE chat_id = $1 AND in_chat_id = $2 AND serialized = $3",
+            &[&chat_id, &in_chat_id, &old_msg, &new_msg, &search_text],
+        )
+        .await?;
+    if updated == 0 {
+        return Ok(false);
+    }
+
+    tx.execute(
+        "INSERT INTO message_edits (chat_id, in_chat_id, serialized) VALUES ($1, $2, $3)",
+        &[&chat_id, &in_chat_id, &old_msg],
+    )
+    .await?;
+    tx.execute(
+        "DELETE FROM message_tokens WHERE chat_id = $1 AND in_chat_id = $2",
+        &[&chat_id, &in_chat_id],
+    )
+    .await?;
+    if let Some(search_text) = &search_text {
+        let tokens: Vec<String> = get_search_tokens(search_text).into_iter().collect();
+        tx.execute(
+            "INSERT INTO message_tokens (token, chat_id, in_chat_id)
+            SELECT UNNEST($1::TEXT[]), $2, $3",
+            &[&tokens, &chat_id, &in_chat_id],
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    return Ok(true);
+}
+
+pub async fn get_edit_history(
+    chat_id: ChatID,
+    in_chat_id: MessageID,
+) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+    let con = get_con().await?;
+
+    let rows = con
+        .query(
+            "SELECT serialized FROM message_edits WHERE chat_id = $1 AND in_chat_id = $2
+            ORDER BY edit_id",
+            &[&(chat_id as i64), &(in_chat_id as i64)],
+        )
+        .await?;
+
+    return Ok(rows.iter().map(|row| row.get(0)).collect());
+}
+
+pub async fn add_reaction(
+    chat_id: ChatID,
+    in_chat_id: MessageID,
+    user_id: UserID,
+    emoji: &str,
+) -> Result<bool, DatabaseError> {
+    let con = get_con().await?;
+
+    let rows = con
+        .execute(
+            "INSERT INTO message_reactions (chat_id, in_chat_id, emoji, user_id)
+            VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING",
+            &[
+                &(chat_id as i64),
+                &(in_chat_id as i64),
+                &emoji,
+                &(user_id as i64),
+            ],
+        )
+        .await?;
+
+    return Ok(rows == 1);
+}
+
+pub async fn remove_reaction(
+    chat_id: ChatID,
+    in_chat_id: MessageID,
+    user_id: UserID,
+    emoji: &str,
+) -> Result<bool, DatabaseError> {
+    let con = get_con().await?;
+
+    let rows = con
+        .execute(
+            "DELETE FROM message_reactions
+            WHERE chat_id = $1 AND in_chat_id = $2 AND emoji = $3 AND user_id = $4",
+            &[
+                &(chat_id as i64),
+                &(in_chat_id as i64),
+                &emoji,
+                &(user_id as i64),
+            ],
+        )
+        .await?;
+
+    return Ok(rows == 1);
+}
+
+pub async fn get_reaction_counts(
+    chat_id: ChatID,
+    start_msg_id: MessageID,
+    end_msg_id: Option<MessageID>,
+) -> Result<HashMap<MessageID, ReactionCounts>, DatabaseError> {
+    let con = get_con().await?;
+
+    let rows = con
+        .query(
+            "SELECT in_chat_id, emoji, COUNT(*) FROM message_reactions
+            WHERE chat_id = $1 AND in_chat_id >= $2 AND ($3::BIGINT IS NULL OR in_chat_id <= $3)
+            GROUP BY in_chat_id, emoji",
+            &[
+                &(chat_id as i64),
+                &(start_msg_id as i64),
+                &end_msg_id.map(|id| id as i64),
+            ],
+        )
+        .await?;
+
+    let mut counts: HashMap<MessageID, ReactionCounts> = HashMap::new();
+    for row in rows {
+        counts
+            .entry(row.get::<_, i64>(0) as MessageID)
+            .or_default()
+            .insert(row.get(1), row.get::<_, i64>(2) as u32);
+    }
+    return Ok(counts);
+}
+
+pub async fn get_thread_replies(
+    chat_id: ChatID,
+    root_id: MessageID,
+) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+    let con = get_con().await?;
+
+    let rows = con
+        .query(
+            "SELECT serialized FROM messages WHERE chat_id = $1 AND reply_root = $2
+            ORDER BY in_chat_id",
+            &[&(chat_id as i64), &(root_id as i64)],
+        )
+        .await?;
+
+    return Ok(rows.iter().map(|row| row.get(0)).collect());
+}
+
+pub async fn get_reply_counts(
+    chat_id: ChatID,
+    start_msg_id: MessageID,
+    end_msg_id: Option<MessageID>,
+) -> Result<HashMap<MessageID, u32>, DatabaseError> {
+    let con = get_con().await?;
+
+    let rows = con
+        .query(
+            "SELECT in_chat_id, reply_count FROM messages
+            WHERE chat_id = $1 AND in_chat_id >= $2 AND ($3::BIGINT IS NULL OR in_chat_id <= $3)
+            AND reply_count > 0",
+            &[
+                &(chat_id as i64),
+                &(start_msg_id as i64),
+                &end_msg_id.map(|id| id as i64),
+            ],
+        )
+        .await?;
+
+    return Ok(rows
+        .iter()
+        .map(|row| {
+            (
+                row.get::<_, i64>(0) as MessageID,
+                row.get::<_, i32>(1) as u32,
+            )
+        })
+        .collect());
+}
+
+pub async fn pin_message(chat_id: ChatID, in_chat_id: MessageID) -> Result<bool, DatabaseError> {
+    let con = get_con().await?;
+
+    let rows = con
+        .execute(
+            "INSERT INTO pinned_messages (chat_id, in_chat_id) VALUES ($1, $2)
+            ON CONFLICT DO NOTHING",
+            &[&(chat_id as i64), &(in_chat_id as i64)],
+        )
+        .await?;
+
+    return Ok(rows == 1);
+}
+
+pub async fn unpin_message(chat_id: ChatID, in_chat_id: MessageID) -> Result<bool, DatabaseError> {
+    let con = get_con().await?;
+
+    let rows = con
+        .execute(
+            "DELETE FROM pinned_messages WHERE chat_id = $1 AND in_chat_id = $2",
+            &[&(chat_id as i64), &(in_chat_id as i64)],
+        )
+        .await?;
+
+    return Ok(rows == 1);
+}
+
+pub async fn get_pinned_messages(
+    chat_id: ChatID,
+) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+    let con = get_con().await?;
+
+    let rows = con
+        .query(
+            "SELECT m.serialized FROM pinned_messages p
+            JOIN messages m ON m.chat_id = p.chat_id AND m.in_chat_id = p.in_chat_id
+            WHERE p.chat_id = $1 ORDER BY p.in_chat_id",
+            &[&(chat_id as i64)],
+        )
+        .await?;
+
+    return Ok(rows.iter().map(|row| row.get(0)).collect());
+}
+
+pub async fn get_chat_ids() -> Result<Vec<ChatID>, DatabaseError> {
+    let con = get_con().await?;
+
+    let rows = con
+        .query("SELECT chat_id FROM chats ORDER BY chat_id", &[])
+        .await?;
+
+    return Ok(rows
+        .iter()
+        .map(|row| row.get::<_, i64>(0) as ChatID)
+        .collect());
+}
+
+pub async fn prune_messages(
+    chat_id: ChatID,
+    min_timestamp: Option<Timestamp>,
+    max_count: Option<MessageID>,
+) -> Result<MessageID, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let tx = con.transaction().await?;
+
+    let row = tx
+        .query_opt(
+            "SELECT last_msg_id, pruned_msg_id FROM chats WHERE chat_id = $1 FOR UPDATE",
+            &[&(chat_id as i64)],
+        )
+        .await?;
+    let (last_id, old_pruned_id): (i64, i64) = match row {
+        Some(row) => (row.get(0), row.get(1)),
+        None => return Ok(0),
+    };
+
+    let mut pruned_id = old_pruned_id;
+    if let Some(max_count) = max_count {
+        pruned_id = std::cmp::max(pruned_id, last_id - max_count as i64);
+    }
+    if let Some(min_timestamp) = min_timestamp {
+        // 只清理最早的连续一段消息，遇到不早于min_timestamp的消息即停止
+        let row = tx
+            .query_one(
+                "SELECT COALESCE(MIN(in_chat_id), $3::BIGINT + 1) - 1 FROM messages
+                WHERE chat_id = $1 AND in_chat_id > $2 AND timestamp >= $4",
+                &[
+                    &(chat_id as i64),
+                    &pruned_id,
+                    &last_id,
+                    &(min_timestamp as i64),
+                ],
+            )
+            .await?;
+        pruned_id = std::cmp::max(pruned_id, row.get::<_, i64>(0));
+    }
+
+    if pruned_id <= old_pruned_id {
+        return Ok(old_pruned_id as MessageID);
+    }
+
+    tx.execute(
+        "DELETE FROM messages WHERE chat_id = $1 AND in_chat_id <= $2",
+        &[&(chat_id as i64), &pruned_id],
+    )
+    .await?;
+    tx.execute(
+        "DELETE FROM message_tokens WHERE chat_id = $1 AND in_chat_id <= $2",
+        &[&(chat_id as i64), &pruned_id],
+    )
+    .await?;
+    tx.execute(
+        "DELETE FROM message_edits WHERE chat_id = $1 AND in_chat_id <= $2",
+        &[&(chat_id as i64), &pruned_id],
+    )
+    .await?;
+    tx.execute(
+        "DELETE FROM message_reactions WHERE chat_id = $1 AND in_chat_id <= $2",
+        &[&(chat_id as i64), &pruned_id],
+    )
+    .await?;
+    tx.execute(
+        "DELETE FROM pinned_messages WHERE chat_id = $1 AND in_chat_id <= $2",
+        &[&(chat_id as i64), &pruned_id],
+    )
+    .await?;
+    tx.execute(
+        "UPDATE chats SET pruned_msg_id = $2 WHERE chat_id = $1",
+        &[&(chat_id as i64), &pruned_id],
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    return Ok(pruned_id as MessageID);
+}
+
+pub async fn get_pruned_id(chat_id: ChatID) -> Result<MessageID, DatabaseError> {
+    let con = get_con().await?;
+
+    let row = con
+        .query_opt(
+            "SELECT pruned_msg_id FROM chats WHERE chat_id = $1",
+            &[&(chat_id as i64)],
+        )
+        .await?;
+
+    return Ok(row.map_or(0, |row| row.get::<_, i64>(0) as MessageID));
+}
+
+pub async fn set_disappearing_ttl(chat_id: ChatID, ttl: Option<u32>) -> Result<(), DatabaseError> {
+    let con = get_con().await?;
+
+    // 右侧读取的是更新前的值，从关闭变为开启时将过期检查的位置移到最后一条消息
+    con.execute(
+        "UPDATE chats SET
+            expired_msg_id = CASE WHEN disappearing_ttl IS NULL AND $2::INTEGER IS NOT NULL
+                THEN last_msg_id ELSE expired_msg_id END,
+            disappearing_ttl = $2
+        WHERE chat_id = $1",
+        &[&(chat_id as i64), &ttl.map(|ttl| ttl as i32)],
+    )
+    .await?;
+
+    return Ok(());
+}
+
+pub async fn get_disappearing_ttl(chat_id: ChatID) -> Result<Option<u32>, DatabaseError> {
+    let con = get_con().await?;
+
+    let row = con
+        .query_opt(
+            "SELECT disappearing_ttl FROM chats WHERE chat_id = $1",
+            &[&(chat_id as i64)],
+        )
+        .await?;
+
+    return Ok(row.and_then(|row| row.get::<_, Option<i32>>(0).map(|ttl| ttl as u32)));
+}
+
+pub async fn get_disappearing_chats() -> Result<Vec<(ChatID, u32)>, DatabaseError> {
+    let con = get_con().await?;
+
+    let rows = con
+        .query(
+            "SELECT chat_id, disappearing_ttl FROM chats
+            WHERE disappearing_ttl IS NOT NULL ORDER BY chat_id",
+            &[],
+        )
+        .await?;
+
+    return Ok(rows
+        .iter()
+        .map(|row| (row.get::<_, i64>(0) as ChatID, row.get::<_, i32>(1) as u32))
+        .collect());
+}
+
+pub async fn get_expired_id(chat_id: ChatID) -> Result<MessageID, DatabaseError> {
+    let con = get_con().await?;
+
+    let row = con
+        .query_opt(
+            "SELECT expired_msg_id FROM chats WHERE chat_id = $1",
+            &[&(chat_id as i64)],
+        )
+        .await?;
+
+    return Ok(row.map_or(0, |row| row.get::<_, i64>(0) as MessageID));
+}
+
+pub async fn set_expired_id(chat_id: ChatID, expired_id: MessageID) -> Result<(), DatabaseError> {
+    let con = get_con().await?;
+
+    con.execute(
+        "UPDATE chats SET expired_msg_id = $2 WHERE chat_id = $1",
+        &[&(chat_id as i64), &(expired_id as i64)],
+    )
+    .await?;
+
+    return Ok(());
+}
+
+pub async fn check_group_invitation_error(
+    sender_id: UserID,
+    receiver_id: UserID,
+    chat_id: ChatID,
+) -> Result<(), GroupInvitationError> {
+    if let Ok(con) = get_con().await {
+        if check_user_exist(sender_id).await.is_err()
+            || check_user_exist(receiver_id).await.is_err()
+        {
+            return Err(GroupInvitationError::UserNotFound);
+        }
+
+        let check_result = check_is_group_chat(chat_id).await;
+        if check_result.is_err() || !check_result.unwrap() {
+            return Err(GroupInvitationError::NotGroupChat);
+        }
+
+        let row = con
+            .query_one(
+                "SELECT
+                    EXISTS (SELECT 1 FROM chat_members WHERE chat_id = $3 AND user_id = $2),
+                    EXISTS (SELECT 1 FROM invitations
+                        WHERE inviter_id = $1 AND receiver_id = $2 AND chat_id = $3)",
+                &[
+                    &(sender_id as i64),
+                    &(receiver_id as i64),
+                    &(chat_id as i64),
+                ],
+            )
+            .await
+            .map_err(|_| GroupInvitationError::DatabaseError)?;
+        let (already_in_group, req_exist): (bool, bool) = (row.get(0), row.get(1));
+
+        if already_in_group {
+            return Err(GroupInvitationError::AlreadyInGroup);
+        } else if req_exist {
+            return Err(GroupInvitationError::RequestExist);
+        } else {
+            return Ok(());
+        }
+    } else {
+        return Err(GroupInvitationError::DatabaseError);
+    }
+}
+
+pub async fn check_invited_join_group_error(
+    sender_id: UserID,
+    receiver_id: UserID,
+    chat_id: ChatID,
+) -> Result<(), InvitedJoinGroupError> {
+    if check_user_exist(sender_id).await.is_err() || check_user_exist(receiver_id).await.is_err() {
+        return Err(InvitedJoinGroupError::UserNotFound);
+    }
+
+    let check_result = check_is_group_chat(chat_id).await;
+    if check_result.is_err() || !check_result.unwrap() {
+        return Err(InvitedJoinGroupError::NotGroupChat);
+    }
+
+    match check_user_in_chat(receiver_id, chat_id).await {
+        Ok(true) => return Err(InvitedJoinGroupError::AlreadyInGroup),
+        Ok(false) => return Ok(()),
+        Err(_) => return Err(InvitedJoinGroupError::DatabaseError),
+    }
+}
+
+pub async fn quit_group_chat(user_id: UserID, chat_id: ChatID) -> UserQuitGroupChatResponse {
+    if let Ok(mut con) = get_con().await {
+        let tx = match con.transaction().await {
+            Ok(tx) => tx,
+            Err(_) => return UserQuitGroupChatResponse::DatabaseError,
+        };
+        let (user_id, chat_id) = (user_id as i64, chat_id as i64);
+
+        let del_result = tx
+            .execute(
+                "DELETE FROM chat_members WHERE chat_id = $1 AND user_id = $2
+                AND chat_id IN (SELECT chat_id FROM chats WHERE is_group)",
+                &[&chat_id, &user_id],
+            )
+            .await;
+        match del_result {
+            Ok(0) => return UserQuitGroupChatResponse::UserNotInChat,
+            Ok(_) => {}
+            Err(_) => return UserQuitGroupChatResponse::DatabaseError,
+        }
+
+        let del_result = tx
+            .execute(
+                "DELETE FROM chat_admins WHERE chat_id = $1 AND user_id = $2",
+                &[&chat_id, &user_id],
+            )
+            .await;
+        if del_result.is_err() || tx.commit().await.is_err() {
+            return UserQuitGroupChatResponse::DatabaseError;
+        }
+
+        return UserQuitGroupChatResponse::Success {
+            chat_id: chat_id as ChatID,
+        };
+    } else {
+        return UserQuitGroupChatResponse::ServerError;
+    }
+}
+
+pub async fn set_as_admin(user_id: UserID, chat_id: ChatID) -> UserSetGroupAdminResponse {
+    if let Ok(con) = get_con().await {
+        let add_result = con
+            .execute(
+                "INSERT INTO chat_admins (chat_id, user_id) VALUES ($1, $2)
+                ON CONFLICT (chat_id, user_id) DO NOTHING",
+                &[&(chat_id as i64), &(user_id as i64)],
+            )
+            .await;
+        match add_result {
+            Ok(1) => return UserSetGroupAdminResponse::Success { chat_id, user_id },
+            _ => return UserSetGroupAdminResponse::DatabaseError,
+        }
+    } else {
+        return UserSetGroupAdminResponse::ServerError;
+    }
+}
+
+pub async fn check_join_group_error(
+    user_id: UserID,
+    chat_id: ChatID,
+) -> Result<(), JoinGroupError> {
+    if let Ok(con) = get_con().await {
+        if check_user_exist(user_id).await.is_err() {
+            return Err(JoinGroupError::UserNotFound);
+        }
+        let check_result = check_is_group_chat(chat_id).await;
+        if check_result.is_err() || !check_result.unwrap() {
+            return Err(JoinGroupError::NotGroupChat);
+        }
+
+        let row = con
+            .query_one(
+                "SELECT
+                    EXISTS (SELECT 1 FROM chat_members WHERE chat_id = $2 AND user_id = $1),
+                    EXISTS (SELECT 1 FROM join_requests WHERE chat_id = $2 AND user_id = $1)",
+                &[&(user_id as i64), &(chat_id as i64)],
+            )
+            .await
+            .map_err(|_| JoinGroupError::DatabaseError)?;
+        let (already_in_group, req_exist): (bool, bool) = (row.get(0), row.get(1));
+
+        if already_in_group {
+            return Err(JoinGroupError::AlreadyInGroup);
+        } else if req_exist {
+            return Err(JoinGroupError::RequestExisted);
+        } else {
+            return Ok(());
+        }
+    } else {
+        return Err(JoinGroupError::DatabaseError);
+    }
+}
+
+pub async fn get_chat_admins_list(chat_id: ChatID) -> Result<UserRequestHandler, DatabaseError> {
+    let con = get_con().await?;
+
+    if let Some(true) = get_chat_kind(&*con, chat_id).await? {
+        let rows = con
+            .query(
+                "SELECT user_id FROM chat_admins WHERE chat_id = $1 ORDER BY user_id",
+                &[&(chat_id as i64)],
+            )
+            .await?;
+        return Ok(UserRequestHandler::Group(
+            rows.iter()
+                .map(|row| row.get::<_, i64>(0) as UserID)
+                .collect(),
+        ));
+    } else {
+        return Err(DatabaseError::MissingKey(format!(
+            "chat:{}:admins",
+            chat_id
+        )));
+    }
+}
+
+pub async fn get_chat_owner(chat_id: ChatID) -> Result<UserID, DatabaseError> {
+    let con = get_con().await?;
+
+    let row = con
+        .query_opt(
+            "SELECT owner_id FROM chats WHERE chat_id = $1 AND is_group",
+            &[&(chat_id as i64)],
+        )
+        .await?
+        .ok_or_else(|| DatabaseError::MissingKey(format!("chat:{}:owner", chat_id)))?;
+
+    return row
+        .get::<_, Option<i64>>(0)
+        .map(|id| id as UserID)
+        .ok_or_else(|| DatabaseError::MissingKey(format!("chat:{}:owner", chat_id)));
+}
+
+pub async fn check_user_is_owner(user_id: UserID, chat_id: ChatID) -> Result<bool, DatabaseError> {
+    let owner = get_chat_owner(chat_id).await?;
+    return Ok(owner == user_id);
+}
+
+pub async fn check_user_is_admin(user_id: UserID, chat_id: ChatID) -> Result<bool, DatabaseError> {
+    let con = get_con().await?;
+
+    let row = con
+        .query_opt(
+            "SELECT 1 FROM chat_admins WHERE chat_id = $1 AND user_id = $2",
+            &[&(chat_id as i64), &(user_id as i64)],
+        )
+        .await?;
+
+    return Ok(row.is_some());
+}
+
+/// 在一个事务内更换群主，并保证新群主是管理员
+pub async fn owner_transfer(user_id: UserID, chat_id: ChatID) -> UserGroupOwnerTransferResponse {
+    if let Ok(mut con) = get_con().await {
+        let tx = match con.transaction().await {
+            Ok(tx) => tx,
+            Err(_) => return UserGroupOwnerTransferResponse::DatabaseError,
+        };
+
+        let set_result = tx
+            .execute(
+                "UPDATE chats SET owner_id = $2 WHERE chat_id = $1 AND is_group",
+                &[&(chat_id as i64), &(user_id as i64)],
+            )
+            .await;
+        match set_result {
+            Ok(1) => {}
+            Ok(_) => return UserGroupOwnerTransferResponse::ServerError,
+            Err(_) => return UserGroupOwnerTransferResponse::DatabaseError,
+        }
+
+        let add_result = tx
+            .execute(
+                "INSERT INTO chat_admins (chat_id, user_id) VALUES ($1, $2)
+                ON CONFLICT (chat_id, user_id) DO NOTHING",
+                &[&(chat_id as i64), &(user_id as i64)],
+            )
+            .await;
+        if add_result.is_err() || tx.commit().await.is_err() {
+            return UserGroupOwnerTransferResponse::DatabaseError;
+        }
+
+        return UserGroupOwnerTransferResponse::Success { chat_id, user_id };
+    } else {
+        return UserGroupOwnerTransferResponse::ServerError;
+    }
+}
+
+pub async fn add_group_notice(
+    user_id: UserID,
+    chat_id: ChatID,
+    client_id: ClientID,
+    notice: String,
+) -> UserSendGroupNoticeResponse {
+    if let Ok(mut con) = get_con().await {
+        let timestamp = chrono::Utc::now().timestamp_millis() as Timestamp;
+
+        let tx = match con.transaction().await {
+            Ok(tx) => tx,
+            Err(_) => return UserSendGroupNoticeResponse::DatabaseError,
+        };
+
+        let notice_id: i64 = match tx
+            .query_opt(
+                "UPDATE chats SET last_notice_id = last_notice_id + 1 WHERE chat_id = $1
+                RETURNING last_notice_id",
+                &[&(chat_id as i64)],
+            )
+            .await
+        {
+            Ok(Some(row)) => row.get(0),
+            _ => return UserSendGroupNoticeResponse::DatabaseError,
+        };
+        let notice_id = notice_id as NoticeID;
+
+        let serialized_notice = format!(
+            r#"{{"chatId":{}, "noticeId":{}, "senderId":{}, "content":{}, "timestamp":{}}}"#,
+            chat_id,
+            notice_id,
+            user_id,
+            serde_json::to_string::<String>(&notice).unwrap(),
+            timestamp
+        );
+
+        let add_result = tx
+            .execute(
+                "INSERT INTO group_notices (chat_id, notice_id, serialized) VALUES ($1, $2, $3)",
+                &[&(chat_id as i64), &(notice_id as i64), &serialized_notice],
+            )
+            .await;
+        if add_result.is_err() || tx.commit().await.is_err() {
+            return UserSendGroupNoticeResponse::DatabaseError;
+        }
+
+        return UserSendGroupNoticeResponse::Success {
+            chat_id,
+            client_id,
+            notice_id,
+            timestamp,
+        };
+    } else {
+        return UserSendGroupNoticeResponse::ServerError;
+    }
+}
+
+pub async fn pull_group_notice(
+    chat_id: ChatID,
+    last_notice_id: NoticeID,
+) -> UserPullGroupNoticeResponse {
+    if let Ok(con) = get_con().await {
+        let rows = match con
+            .query(
+                "SELECT serialized FROM group_notices WHERE chat_id = $1 AND notice_id > $2
+                ORDER BY notice_id",
+                &[&(chat_id as i64), &(last_notice_id as i64)],
+            )
+            .await
+        {
+            Ok(rows) => rows,
+            Err(_) => return UserPullGroupNoticeResponse::DatabaseError,
+        };
+
+        return UserPullGroupNoticeResponse::Success {
+            group_notice: rows.iter().map(|row| row.get(0)).collect(),
+            chat_id,
+        };
+    } else {
+        return UserPullGroupNoticeResponse::ServerError;
+    }
+}
+
+pub async fn update_group_info(
+    chat_id: ChatID,
+    data: UserUpdateGroupContent,
+) -> UserUpdateGroupInfoResponse {
+    if let Ok(con) = get_con().await {
+        let set_result = match data {
+            UserUpdateGroupContent::GroupName { new_name } => {
+                con.execute(
+                    "UPDATE chats SET name = $2 WHERE chat_id = $1 AND is_group",
+                    &[&(chat_id as i64), &new_name],
+                )
+                .await
+            }
+            UserUpdateGroupContent::Avater { new_avater } => {
+                con.execute(
+                    "UPDATE chats SET avater_hash = $2 WHERE chat_id = $1 AND is_group",
+                    &[&(chat_id as i64), &new_avater],
+                )
+                .await
+            }
+        };
+        match set_result {
+            Ok(1) => return UserUpdateGroupInfoResponse::Success,
+            _ => return UserUpdateGroupInfoResponse::DatabaseError,
+        }
+    } else {
+        return UserUpdateGroupInfoResponse::ServerError;
+    }
+}
+
+pub async fn unset_admin(user_id: UserID, chat_id: ChatID) -> UserUnsetGroupAdminResponse {
+    if let Ok(con) = get_con().await {
+        let rem_result = con
+            .execute(
+                "DELETE FROM chat_admins WHERE chat_id = $1 AND user_id = $2",
+                &[&(chat_id as i64), &(user_id as i64)],
+            )
+            .await;
+        match rem_result {
+            Ok(1) => return UserUnsetGroupAdminResponse::Success { chat_id, user_id },
+            _ => return UserUnsetGroupAdminResponse::DatabaseError,
+        }
+    } else {
+        return UserUnsetGroupAdminResponse::ServerError;
+    }
+}
+
+pub async fn check_is_group_chat(chat_id: ChatID) -> Result<bool, DatabaseError> {
+    let con = get_con().await?;
+    return Ok(get_chat_kind(&*con, chat_id).await? == Some(true));
+}
+
+pub async fn get_user_read_in_group(
+    chat_id: ChatID,
+    in_chat_id: MessageID,
+) -> UserGetUserReadInGroupResponse {
+    if let Ok(con) = get_con().await {
+        match get_chat_kind(&*con, chat_id).await {
+            Ok(Some(true)) => {}
+            Ok(_) => return UserGetUserReadInGroupResponse::NotGroupChat,
+            Err(_) => return UserGetUserReadInGroupResponse::DatabaseError,
+        }
+
+        let rows = match con
+            .query(
+                "SELECT user_id FROM chat_members WHERE chat_id = $1 AND already_read >= $2
+                ORDER BY user_id",
+                &[&(chat_id as i64), &(in_chat_id as i64)],
+            )
+            .await
+        {
+            Ok(rows) => rows,
+            Err(_) => return UserGetUserReadInGroupResponse::DatabaseError,
+        };
+
+        return UserGetUserReadInGroupResponse::Success {
+            user_ids: rows
+                .iter()
+                .map(|row| row.get::<_, i64>(0) as UserID)
+                .collect(),
+            chat_id,
+            in_chat_id,
+        };
+    } else {
+        return UserGetUserReadInGroupResponse::ServerError;
+    }
+}
+
+pub async fn get_user_read_in_private(
+    user_id: UserID,
+    chat_id: ChatID,
+) -> UserGetUserReadInPrivateResponse {
+    if let Ok(con) = get_con().await {
+        let users = match get_chat_pair(&*con, chat_id).await {
+            Ok(Some(users)) => users,
+            _ => return UserGetUserReadInPrivateResponse::DatabaseError,
+        };
+
+        let friend_id;
+        if user_id == users.0 {
+            friend_id = users.1;
+        } else if user_id == users.1 {
+            friend_id = users.0;
+        } else {
+            return UserGetUserReadInPrivateResponse::UserNotInChat;
+        }
+
+        let row = con
+            .query_opt(
+                "SELECT already_read FROM chat_members WHERE chat_id = $1 AND user_id = $2",
+                &[&(chat_id as i64), &(friend_id as i64)],
+            )
+            .await;
+        match row {
+            Ok(Some(row)) => {
+                return UserGetUserReadInPrivateResponse::Success {
+                    chat_id,
+                    in_chat_id: row.get::<_, i64>(0) as MessageID,
+                }
+            }
+            _ => return UserGetUserReadInPrivateResponse::DatabaseError,
+        }
+    } else {
+        return UserGetUserReadInPrivateResponse::ServerError;
+    }
+}
+
+pub async fn search_messages(
+    chat_ids: &[ChatID],
+    keyword: &str,
+    cursor: Option<SearchCursor>,
+    limit: u8,
+) -> Result<SearchPage, DatabaseError> {
+    let con = get_con().await?;
+
+    let tokens = get_keyword_tokens(keyword);
+    if chat_ids.is_empty() || tokens.is_empty() || limit == 0 {
+        return Ok(SearchPage {
+            messages: vec![],
+            next_cursor: None,
+        });
+    }
+    let chat_ids: Vec<i64> = chat_ids.iter().map(|chat_id| *chat_id as i64).collect();
+    let (cursor_chat_id, cursor_in_chat_id) = match cursor {
+        Some(cursor) => (Some(cursor.chat_id as i64), Some(cursor.in_chat_id as i64)),
+        None => (None, None),
+    };
+
+    // 先由倒排索引找出包含全部词项的消息，再按子串精确匹配
+    // 结果按会话升序、会话内由新到旧排列，从游标之后开始取
+    let rows = con
+        .query(
+            "SELECT chat_id, in_chat_id, serialized FROM messages
+            WHERE chat_id = ANY($1) AND STRPOS(search_text, $2) > 0
+            AND ($5::BIGINT IS NULL OR chat_id > $5 OR (chat_id = $5 AND in_chat_id < $6))
+            AND (chat_id, in_chat_id) IN (
+                SELECT chat_id, in_chat_id FROM message_tokens
+                WHERE token = ANY($3) AND chat_id = ANY($1)
+                GROUP BY chat_id, in_chat_id HAVING COUNT(*) = $4
+            )
+            ORDER BY chat_id ASC, in_chat_id DESC
+            LIMIT $7",
+            &[
+                &chat_ids,
+                &keyword,
+                &tokens,
+                &(tokens.len() as i64),
+                &cursor_chat_id,
+                &cursor_in_chat_id,
+                &(limit as i64),
+            ],
+        )
+        .await?;
+
+    let next_cursor = match rows.last() {
+        Some(row) if rows.len() == limit as usize => Some(SearchCursor {
+            chat_id: row.get::<_, i64>(0) as ChatID,
+            in_chat_id: row.get::<_, i64>(1) as MessageID,
+        }),
+        _ => None,
+    };
+    return Ok(SearchPage {
+        messages: rows.iter().map(|row| row.get(2)).collect(),
+        next_cursor,
+    });
+}
+
+pub async fn add_scheduled_message(
+    sender_id: UserID,
+    deliver_at: Timestamp,
+    message: &UserSendMessageData,
+) -> Result<ScheduleID, DatabaseError> {
+    let con = get_con().await?;
+
+    let row = con
+        .query_one(
+            "INSERT INTO scheduled_messages (sender_id, deliver_at, message)
+            VALUES ($1, $2, $3) RETURNING schedule_id",
+            &[
+                &(sender_id as i64),
+                &(deliver_at as i64),
+                &serde_json::to_string(message)?,
+            ],
+        )
+        .await?;
+
+    return Ok(row.get::<_, i64>(0) as ScheduleID);
+}
+
+fn get_scheduled_message(row: &Row) -> Result<ScheduledMessage, DatabaseError> {
+    return Ok(ScheduledMessage {
+        schedule_id: row.get::<_, i64>(0) as ScheduleID,
+        sender_id: row.get::<_, i64>(1) as UserID,
+        deliver_at: row.get::<_, i64>(2) as Timestamp,
+        message: serde_json::from_str(row.get(3))?,
+    });
+}
+
+pub async fn get_scheduled_messages(
+    user_id: UserID,
+) -> Result<Vec<ScheduledMessage>, DatabaseError> {
+    let con = get_con().await?;
+
+    let rows = con
+        .query(
+            "SELECT schedule_id, sender_id, deliver_at, message FROM scheduled_messages
+            WHERE sender_id = $1 ORDER BY deliver_at, schedule_id",
+            &[&(user_id as i64)],
+        )
+        .await?;
+
+    return rows.iter().map(get_scheduled_message).collect();
+}
+
+pub async fn cancel_scheduled_message(
+    user_id: UserID,
+    schedule_id: ScheduleID,
+) -> Result<bool, DatabaseError> {
+    let con = get_con().await?;
+
+    let rows = con
+        .execute(
+            "DELETE FROM scheduled_messages WHERE schedule_id = $1 AND sender_id = $2",
+            &[&(schedule_id as i64), &(user_id as i64)],
+        )
+        .await?;
+
+    return Ok(rows == 1);
+}
+
+pub async fn get_due_scheduled_messages(
+    now: Timestamp,
+) -> Result<Vec<ScheduledMessage>, DatabaseError> {
+    let con = get_con().await?;
+
+    let rows = con
+        .query(
+            "SELECT schedule_id, sender_id, deliver_at, message FROM scheduled_messages
+            WHERE deliver_at <= $1 ORDER BY deliver_at, schedule_id",
+            &[&(now as i64)],
+        )
+        .await?;
+
+    return rows.iter().map(get_scheduled_message).collect();
+}
+
+pub async fn remove_scheduled_message(schedule_id: ScheduleID) -> Result<bool, DatabaseError> {
+    let con = get_con().await?;
+
+    let rows = con
+        .execute(
+            "DELETE FROM scheduled_messages WHERE schedule_id = $1",
+            &[&(schedule_id as i64)],
+        )
+        .await?;
+
+    return Ok(rows == 1);
+}
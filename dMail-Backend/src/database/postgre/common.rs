@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use mobc::{Connection, Pool};
+use mobc_postgres::{tokio_postgres, PgConnectionManager};
+use once_cell::sync::OnceCell;
+use tokio_postgres::{error::SqlState, NoTls};
+
+use crate::config::Config;
+use crate::database::DatabaseError;
+
+pub type MobcPool = Pool<PgConnectionManager<NoTls>>;
+pub type MobcCon = Connection<PgConnectionManager<NoTls>>;
+
+pub static POOL: OnceCell<MobcPool> = OnceCell::new();
+
+impl From<tokio_postgres::Error> for DatabaseError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        if err.code() == Some(&SqlState::UNIQUE_VIOLATION) {
+            return DatabaseError::Conflict(err.to_string());
+        }
+        return DatabaseError::Backend(err.to_string());
+    }
+}
+
+/// 按版本号排列的数据库迁移脚本，只能在末尾追加，不能修改已发布的脚本
+const MIGRATIONS: &[(i64, &str)] = &[
+    (1, include_str!("migrations/0001_init.sql")),
+    (2, include_str!("migrations/0002_retention.sql")),
+    (3, include_str!("migrations/0003_search.sql")),
+    (4, include_str!("migrations/0004_edits.sql")),
+    (5, include_str!("migrations/0005_reactions.sql")),
+    (6, include_str!("migrations/0006_threads.sql")),
+    (7, include_str!("migrations/0007_pins.sql")),
+    (8, include_str!("migrations/0008_scheduled.sql")),
+    (9, include_str!("migrations/0009_disappearing.sql")),
+    (10, include_str!("migrations/0010_presence.sql")),
+    (11, include_str!("migrations/0011_tokens.sql")),
+    (12, include_str!("migrations/0012_device_keys.sql")),
+];
+
+/// 需要清空的数据表
+const TABLES: &str = "users, user_tokens, device_keys, chats, chat_members, chat_admins, friends, messages, \
+    message_tokens, message_edits, message_reactions, pinned_messages, scheduled_messages, group_notices, user_notices, requests, request_states, user_requests, \
+    join_requests, invitations, file_uploads, file_urls";
+
+pub async fn connect_database() -> Result<(), DatabaseError> {
+    let database_config = &Config::get().database;
+    let config = database_config.address.parse::<tokio_postgres::Config>()?;
+    let manager = PgConnectionManager::new(config, NoTls);
+
+    let pool = mobc::Pool::builder()
+        .get_timeout(Some(Duration::from_secs(
+            database_config.pool_timeout as u64,
+        )))
+        .max_open(database_config.pool_max_open as u64)
+        .max_idle(database_config.pool_max_idle as u64)
+        .max_lifetime(Some(Duration::from_secs(
+            database_config.pool_expire as u64,
+        )))
+        .build(manager);
+
+    let mut con = pool.get().await?;
+    migrate(&mut con).await?;
+
+    POOL.set(pool).ok();
+
+    return Ok(());
+}
+
+pub async fn get_con() -> Result<MobcCon, DatabaseError> {
+    return Ok(POOL.get().unwrap().get().await?);
+}
+
+/// 依次执行尚未执行的迁移脚本，每个脚本在单独的事务中执行
+async fn migrate(con: &mut MobcCon) -> Result<(), DatabaseError> {
+    con.batch_execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version    BIGINT      PRIMARY KEY,
+            applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+        )",
+    )
+    .await?;
+
+    let current: i64 = con
+        .query_one(
+            "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
+            &[],
+        )
+        .await?
+        .get(0);
+
+    for (version, sql) in MIGRATIONS.iter().filter(|(v, _)| *v > current) {
+        let tx = con.transaction().await?;
+        tx.batch_execute(sql).await?;
+        tx.execute(
+            "INSERT INTO schema_migrations (version) VALUES ($1)",
+            &[version],
+        )
+        .await?;
+        tx.commit().await?;
+    }
+
+    return Ok(());
+}
+
+/// 清空时因死锁被回滚的最大重试次数
+const FLUSH_RETRY_TIMES: usize = 3;
+
+pub async fn flush_database() -> Result<(), DatabaseError> {
+    let con = get_con().await?;
+    let sql = format!("TRUNCATE {} RESTART IDENTITY CASCADE", TABLES);
+    // 仍有未结束的写入事务时，TRUNCATE逐个加锁可能与其形成死锁，被数据库回滚后重试即可
+    for _ in 0..FLUSH_RETRY_TIMES {
+        match con.batch_execute(&sql).await {
+            Err(err) if err.code() == Some(&SqlState::T_R_DEADLOCK_DETECTED) => continue,
+            result => return Ok(result?),
+        }
+    }
+    con.batch_execute(&sql).await?;
+    return Ok(());
+}
@@ -0,0 +1,77 @@
+use super::common::*;
+use crate::database::DatabaseError;
+use crate::{
+    config::datatype::{SerializedFilePubUrl, UploadId},
+    oss::{ObjectUploadRequest, PresignUrl},
+};
+
+pub async fn get_file_public_url(
+    hash: &str,
+) -> Result<Option<(PresignUrl, SerializedFilePubUrl)>, DatabaseError> {
+    let con = get_con().await?;
+
+    let row = con
+        .query_opt("SELECT url FROM file_urls WHERE hash = $1", &[&hash])
+        .await?;
+
+    return Ok(row.map(|row| {
+        let serialized: String = row.get(0);
+        (
+            serde_json::from_str::<PresignUrl>(&serialized).unwrap(),
+            serialized,
+        )
+    }));
+}
+
+pub async fn write_file_public_url(hash: &str, pub_url: &PresignUrl) -> Result<(), DatabaseError> {
+    let serialized = serde_json::to_string(pub_url).unwrap();
+
+    let con = get_con().await?;
+    con.execute(
+        "INSERT INTO file_urls (hash, url) VALUES ($1, $2)
+        ON CONFLICT (hash) DO UPDATE SET url = $2",
+        &[&hash, &serialized],
+    )
+    .await?;
+    return Ok(());
+}
+
+pub async fn write_upload_request(req: ObjectUploadRequest) -> Result<UploadId, DatabaseError> {
+    let con = get_con().await?;
+
+    let upload_id: i64 = con
+        .query_one(
+            "INSERT INTO file_uploads (request) VALUES ($1) RETURNING upload_id",
+            &[&serde_json::to_string(&req).unwrap()],
+        )
+        .await?
+        .get(0);
+
+    return Ok(upload_id as UploadId);
+}
+
+pub async fn get_upload_request(
+    upload_id: UploadId,
+) -> Result<Option<ObjectUploadRequest>, DatabaseError> {
+    let con = get_con().await?;
+
+    let row = con
+        .query_opt(
+            "SELECT request FROM file_uploads WHERE upload_id = $1",
+            &[&(upload_id as i64)],
+        )
+        .await?;
+
+    return Ok(row.map(|row| serde_json::from_str(row.get(0)).unwrap()));
+}
+
+pub async fn get_file_url(hash: &str) -> Result<SerializedFilePubUrl, DatabaseError> {
+    let con = get_con().await?;
+
+    let row = con
+        .query_opt("SELECT url FROM file_urls WHERE hash = $1", &[&hash])
+        .await?
+        .ok_or_else(|| DatabaseError::MissingKey(format!("file:url:{}", hash)))?;
+
+    return Ok(row.get(0));
+}
@@ -0,0 +1,119 @@
+-- 用户
+CREATE TABLE users (
+    user_id     BIGSERIAL PRIMARY KEY,
+    user_name   TEXT    NOT NULL,
+    avater_hash TEXT    NOT NULL DEFAULT '',
+    password    TEXT    NOT NULL,
+    -- 注销后置为NULL
+    email       TEXT    UNIQUE,
+    setting     TEXT,
+    token       TEXT,
+    exist       BOOLEAN NOT NULL DEFAULT TRUE
+);
+
+CREATE INDEX users_user_name_idx ON users (user_name);
+
+-- 私聊与群聊共用同一个ID序列
+CREATE TABLE chats (
+    chat_id        BIGSERIAL PRIMARY KEY,
+    is_group       BOOLEAN NOT NULL,
+    name           TEXT    NOT NULL DEFAULT '',
+    avater_hash    TEXT    NOT NULL DEFAULT '',
+    owner_id       BIGINT,
+    -- 私聊的两位用户，user_one_id < user_two_id
+    user_one_id    BIGINT,
+    user_two_id    BIGINT,
+    last_msg_id    BIGINT  NOT NULL DEFAULT 0,
+    last_notice_id BIGINT  NOT NULL DEFAULT 0
+);
+
+-- 用户所在的聊天与已读到的消息
+CREATE TABLE chat_members (
+    chat_id      BIGINT NOT NULL REFERENCES chats (chat_id),
+    user_id      BIGINT NOT NULL REFERENCES users (user_id),
+    already_read BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (chat_id, user_id)
+);
+
+CREATE INDEX chat_members_user_id_idx ON chat_members (user_id);
+
+CREATE TABLE chat_admins (
+    chat_id BIGINT NOT NULL REFERENCES chats (chat_id),
+    user_id BIGINT NOT NULL,
+    PRIMARY KEY (chat_id, user_id)
+);
+
+-- chat_id为0时表示好友申请已发送
+CREATE TABLE friends (
+    user_one_id BIGINT NOT NULL,
+    user_two_id BIGINT NOT NULL,
+    chat_id     BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (user_one_id, user_two_id)
+);
+
+CREATE TABLE messages (
+    chat_id    BIGINT NOT NULL,
+    in_chat_id BIGINT NOT NULL,
+    msg_type   TEXT   NOT NULL,
+    sender_id  BIGINT NOT NULL,
+    timestamp  BIGINT NOT NULL,
+    serialized TEXT   NOT NULL,
+    PRIMARY KEY (chat_id, in_chat_id)
+);
+
+CREATE TABLE group_notices (
+    chat_id    BIGINT NOT NULL,
+    notice_id  BIGINT NOT NULL,
+    serialized TEXT   NOT NULL,
+    PRIMARY KEY (chat_id, notice_id)
+);
+
+CREATE TABLE user_notices (
+    user_id    BIGINT NOT NULL,
+    timestamp  BIGINT NOT NULL,
+    serialized TEXT   NOT NULL,
+    UNIQUE (user_id, serialized)
+);
+
+CREATE INDEX user_notices_timestamp_idx ON user_notices (user_id, timestamp);
+
+CREATE TABLE requests (
+    req_id BIGSERIAL PRIMARY KEY,
+    info   TEXT NOT NULL
+);
+
+-- 已处理的请求，approved为true时表示同意
+CREATE TABLE request_states (
+    req_id   BIGINT  PRIMARY KEY,
+    approved BOOLEAN NOT NULL
+);
+
+CREATE TABLE user_requests (
+    user_id BIGINT NOT NULL,
+    req_id  BIGINT NOT NULL,
+    PRIMARY KEY (user_id, req_id)
+);
+
+-- 用户已申请加入的群聊
+CREATE TABLE join_requests (
+    user_id BIGINT NOT NULL,
+    chat_id BIGINT NOT NULL,
+    PRIMARY KEY (user_id, chat_id)
+);
+
+CREATE TABLE invitations (
+    inviter_id  BIGINT NOT NULL,
+    receiver_id BIGINT NOT NULL,
+    chat_id     BIGINT NOT NULL,
+    PRIMARY KEY (inviter_id, receiver_id, chat_id)
+);
+
+CREATE TABLE file_uploads (
+    upload_id BIGSERIAL PRIMARY KEY,
+    request   TEXT NOT NULL
+);
+
+CREATE TABLE file_urls (
+    hash TEXT PRIMARY KEY,
+    url  TEXT NOT NULL
+);
@@ -0,0 +1,5 @@
+-- 消息保留策略：in_chat_id不超过pruned_msg_id的消息已被清理
+ALTER TABLE chats ADD COLUMN pruned_msg_id BIGINT NOT NULL DEFAULT 0;
+
+-- 按时间清理消息时使用
+CREATE INDEX messages_chat_id_timestamp_idx ON messages (chat_id, timestamp);
@@ -0,0 +1,13 @@
+-- 消息搜索：消息中可搜索文本的小写形式，不可搜索或已撤回的消息为NULL
+ALTER TABLE messages ADD COLUMN search_text TEXT;
+
+-- 消息搜索的倒排索引，token为消息文本中的单字与相邻两字
+CREATE TABLE message_tokens (
+    token      TEXT   NOT NULL,
+    chat_id    BIGINT NOT NULL,
+    in_chat_id BIGINT NOT NULL,
+    PRIMARY KEY (token, chat_id, in_chat_id)
+);
+
+-- 撤回与清理消息时按消息删除词项
+CREATE INDEX message_tokens_chat_id_idx ON message_tokens (chat_id, in_chat_id);
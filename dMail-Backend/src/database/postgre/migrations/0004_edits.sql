@@ -0,0 +1,9 @@
+-- 消息被编辑前的各个版本，按edit_id的先后排列
+CREATE TABLE message_edits (
+    edit_id    BIGSERIAL PRIMARY KEY,
+    chat_id    BIGINT NOT NULL,
+    in_chat_id BIGINT NOT NULL,
+    serialized TEXT   NOT NULL
+);
+
+CREATE INDEX message_edits_chat_id_idx ON message_edits (chat_id, in_chat_id);
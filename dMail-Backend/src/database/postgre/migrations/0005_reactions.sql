@@ -0,0 +1,8 @@
+-- 消息的表情回应，每个用户对同一条消息的同一表情只计一次
+CREATE TABLE message_reactions (
+    chat_id    BIGINT NOT NULL,
+    in_chat_id BIGINT NOT NULL,
+    emoji      TEXT   NOT NULL,
+    user_id    BIGINT NOT NULL,
+    PRIMARY KEY (chat_id, in_chat_id, emoji, user_id)
+);
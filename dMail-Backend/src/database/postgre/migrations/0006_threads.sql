@@ -0,0 +1,6 @@
+-- 回复所属的被回复消息，以及每条消息收到的回复数量
+ALTER TABLE messages ADD COLUMN reply_root BIGINT;
+ALTER TABLE messages ADD COLUMN reply_count INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX messages_reply_root_idx ON messages (chat_id, reply_root)
+    WHERE reply_root IS NOT NULL;
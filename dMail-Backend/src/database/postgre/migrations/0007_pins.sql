@@ -0,0 +1,6 @@
+-- 聊天中被置顶的消息
+CREATE TABLE pinned_messages (
+    chat_id    BIGINT NOT NULL,
+    in_chat_id BIGINT NOT NULL,
+    PRIMARY KEY (chat_id, in_chat_id)
+);
@@ -0,0 +1,10 @@
+-- 等待定时发送的消息，message为序列化的发送请求
+CREATE TABLE scheduled_messages (
+    schedule_id BIGSERIAL PRIMARY KEY,
+    sender_id   BIGINT NOT NULL,
+    deliver_at  BIGINT NOT NULL,
+    message     TEXT   NOT NULL
+);
+
+CREATE INDEX scheduled_messages_deliver_at_idx ON scheduled_messages (deliver_at);
+CREATE INDEX scheduled_messages_sender_id_idx ON scheduled_messages (sender_id);
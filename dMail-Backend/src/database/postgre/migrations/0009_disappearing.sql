@@ -0,0 +1,3 @@
+-- 阅后即焚中消息的存活时间（秒），以及已完成过期检查的最后一条消息
+ALTER TABLE chats ADD COLUMN disappearing_ttl INTEGER;
+ALTER TABLE chats ADD COLUMN expired_msg_id BIGINT NOT NULL DEFAULT 0;
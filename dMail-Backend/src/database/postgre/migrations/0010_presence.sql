@@ -0,0 +1,3 @@
+-- 用户最后在线的时间，以及是否向他人隐藏在线状态
+ALTER TABLE users ADD COLUMN last_seen BIGINT;
+ALTER TABLE users ADD COLUMN presence_hidden BOOLEAN NOT NULL DEFAULT FALSE;
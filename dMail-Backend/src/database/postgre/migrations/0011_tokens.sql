@@ -0,0 +1,11 @@
+-- 每台设备上的令牌，取代每位用户唯一的令牌
+CREATE TABLE user_tokens (
+    user_id       BIGINT NOT NULL REFERENCES users (user_id),
+    device_id     TEXT   NOT NULL,
+    access_token  TEXT   NOT NULL,
+    refresh_token TEXT   NOT NULL,
+    issued_at     BIGINT NOT NULL,
+    PRIMARY KEY (user_id, device_id)
+);
+
+ALTER TABLE users DROP COLUMN token;
@@ -0,0 +1,7 @@
+-- 端到端加密所用的设备公钥目录，每台设备一份序列化的公钥
+CREATE TABLE device_keys (
+    user_id    BIGINT NOT NULL REFERENCES users (user_id),
+    device_id  TEXT   NOT NULL,
+    serialized TEXT   NOT NULL,
+    PRIMARY KEY (user_id, device_id)
+);
@@ -1 +1,13 @@
-pub mod user;
+mod backend;
+mod chat;
+mod common;
+mod file;
+mod request;
+mod user;
+
+pub use backend::*;
+pub use chat::*;
+pub use common::*;
+pub use file::*;
+pub use request::*;
+pub use user::*;
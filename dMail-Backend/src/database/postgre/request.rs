@@ -0,0 +1,288 @@
+use super::common::*;
+use crate::config::datatype::*;
+use crate::database::DatabaseError;
+use crate::user::*;
+
+fn friend_pair(user_one_id: UserID, user_two_id: UserID) -> (i64, i64) {
+    return if user_one_id < user_two_id {
+        (user_one_id as i64, user_two_id as i64)
+    } else {
+        (user_two_id as i64, user_one_id as i64)
+    };
+}
+
+fn serialize_request(serialized_info: &str, approved: Option<bool>) -> SerializedRequest {
+    let state = match approved {
+        Some(true) => "Approved",
+        Some(false) => "Refused",
+        None => "Unsolved",
+    };
+    return format!(r#"{{"info":{},"state":"{}"}}"#, serialized_info, state);
+}
+
+pub async fn write_user_request(
+    sender_id: UserID,
+    data: UserSendRequestData,
+    handler: &UserRequestHandler,
+) -> Result<(SerializedRequest, UserRequestInfo), DatabaseError> {
+    let mut con = get_con().await?;
+
+    let tx = con.transaction().await?;
+
+    let req_id: i64 = tx
+        .query_one(
+            "SELECT nextval(pg_get_serial_sequence('requests', 'req_id'))",
+            &[],
+        )
+        .await?
+        .get(0);
+
+    let req_info = UserRequestInfo {
+        req_id: req_id as UserReqId,
+        sender_id,
+        message: data.message,
+        content: data.content,
+    };
+
+    let serialized_info = serde_json::to_string(&req_info).unwrap();
+
+    tx.execute(
+        "INSERT INTO requests (req_id, info) VALUES ($1, $2)",
+        &[&req_id, &serialized_info],
+    )
+    .await?;
+
+    let mut receivers: Vec<i64> = match handler {
+        UserRequestHandler::One(user_id) => vec![*user_id as i64],
+        UserRequestHandler::Group(ids) => ids.iter().map(|id| *id as i64).collect(),
+    };
+    receivers.push(sender_id as i64);
+
+    tx.execute(
+        "INSERT INTO user_requests (user_id, req_id) SELECT UNNEST($1::BIGINT[]), $2
+        ON CONFLICT (user_id, req_id) DO NOTHING",
+        &[&receivers, &req_id],
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    return Ok((serialize_request(&serialized_info, None), req_info));
+}
+
+pub async fn store_user_request(user_id: UserID, req_id: UserReqId) -> Result<(), DatabaseError> {
+    let con = get_con().await?;
+
+    con.execute(
+        "INSERT INTO user_requests (user_id, req_id) VALUES ($1, $2)
+        ON CONFLICT (user_id, req_id) DO NOTHING",
+        &[&(user_id as i64), &(req_id as i64)],
+    )
+    .await?;
+
+    return Ok(());
+}
+
+pub async fn get_user_request(req_id: UserReqId) -> Result<Option<UserRequset>, DatabaseError> {
+    let con = get_con().await?;
+
+    let row = con
+        .query_opt(
+            "SELECT requests.info, request_states.approved FROM requests
+            LEFT JOIN request_states USING (req_id) WHERE req_id = $1",
+            &[&(req_id as i64)],
+        )
+        .await?;
+
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+
+    let state = match row.get::<_, Option<bool>>(1) {
+        Some(state_bool) => {
+            if state_bool {
+                UserRequestState::Approved
+            } else {
+                UserRequestState::Refused
+            }
+        }
+        None => UserRequestState::Unsolved,
+    };
+
+    let info: UserRequestInfo =
+        serde_json::from_str(row.get(0)).expect("UserRqeuestInfo 反序列化失败");
+
+    return Ok(Some(UserRequset { info, state }));
+}
+
+pub async fn set_user_request_state(
+    req_id: UserReqId,
+    state: UserRequestState,
+) -> Result<(), UserSolveRequestState> {
+    let con = get_con()
+        .await
+        .map_err(|_| UserSolveRequestState::DatabaseError)?;
+
+    let state_bool = match state {
+        UserRequestState::Approved => true,
+        UserRequestState::Unsolved => {
+            let solved = con
+                .query_opt(
+                    "SELECT 1 FROM request_states WHERE req_id = $1",
+                    &[&(req_id as i64)],
+                )
+                .await
+                .map_err(|_| UserSolveRequestState::DatabaseError)?;
+            if solved.is_some() {
+                return Err(UserSolveRequestState::AlreadySolved);
+            }
+            return Err(UserSolveRequestState::AnswerUnsolved);
+        }
+        UserRequestState::Refused => false,
+    };
+
+    // 只有第一次处理能写入成功
+    let set_result = con
+        .execute(
+            "INSERT INTO request_states (req_id, approved) VALUES ($1, $2)
+            ON CONFLICT (req_id) DO NOTHING",
+            &[&(req_id as i64), &state_bool],
+        )
+        .await
+        .map_err(|_| UserSolveRequestState::DatabaseError)?;
+
+    if set_result == 0 {
+        return Err(UserSolveRequestState::AlreadySolved);
+    }
+
+    return Ok(());
+}
+
+pub async fn get_user_requests(
+    user_id: UserID,
+    start_id: UserReqId,
+) -> Result<Vec<SerializedRequest>, DatabaseError> {
+    let con = get_con().await?;
+
+    let rows = con
+        .query(
+            "SELECT requests.info, request_states.approved FROM user_requests
+            JOIN requests USING (req_id) LEFT JOIN request_states USING (req_id)
+            WHERE user_requests.user_id = $1 AND req_id >= $2 ORDER BY req_id",
+            &[&(user_id as i64), &(start_id as i64)],
+        )
+        .await?;
+
+    return Ok(rows
+        .iter()
+        .map(|row| serialize_request(row.get(0), row.get(1)))
+        .collect());
+}
+
+pub async fn write_friend_request_send(
+    user_one_id: UserID,
+    user_two_id: UserID,
+) -> Result<(), DatabaseError> {
+    let (id1, id2) = friend_pair(user_one_id, user_two_id);
+
+    let con = get_con().await?;
+
+    con.execute(
+        "INSERT INTO friends (user_one_id, user_two_id, chat_id) VALUES ($1, $2, 0)
+        ON CONFLICT (user_one_id, user_two_id) DO UPDATE SET chat_id = 0",
+        &[&id1, &id2],
+    )
+    .await?;
+
+    return Ok(());
+}
+
+pub async fn delete_friend_request_send(
+    user_one_id: UserID,
+    user_two_id: UserID,
+) -> Result<(), DatabaseError> {
+    let (id1, id2) = friend_pair(user_one_id, user_two_id);
+
+    let con = get_con().await?;
+
+    con.execute(
+        "DELETE FROM friends WHERE user_one_id = $1 AND user_two_id = $2",
+        &[&id1, &id2],
+    )
+    .await?;
+
+    return Ok(());
+}
+
+pub async fn write_join_group_request_send(
+    user_id: UserID,
+    chat_id: ChatID,
+) -> Result<(), DatabaseError> {
+    let con = get_con().await?;
+
+    con.execute(
+        "INSERT INTO join_requests (user_id, chat_id) VALUES ($1, $2)
+        ON CONFLICT (user_id, chat_id) DO NOTHING",
+        &[&(user_id as i64), &(chat_id as i64)],
+    )
+    .await?;
+
+    return Ok(());
+}
+
+pub async fn delete_join_group_request_send(
+    user_id: UserID,
+    chat_id: ChatID,
+) -> Result<(), DatabaseError> {
+    let con = get_con().await?;
+
+    con.execute(
+        "DELETE FROM join_requests WHERE user_id = $1 AND chat_id = $2",
+        &[&(user_id as i64), &(chat_id as i64)],
+    )
+    .await?;
+
+    return Ok(());
+}
+
+pub async fn write_invite_request_send(
+    inviter_id: UserID,
+    receiver_id: UserID,
+    chat_id: ChatID,
+) -> Result<(), DatabaseError> {
+    let con = get_con().await?;
+
+    con.execute(
+        "INSERT INTO invitations (inviter_id, receiver_id, chat_id) VALUES ($1, $2, $3)
+        ON CONFLICT (inviter_id, receiver_id, chat_id) DO NOTHING",
+        &[
+            &(inviter_id as i64),
+            &(receiver_id as i64),
+            &(chat_id as i64),
+        ],
+    )
+    .await?;
+
+    return Ok(());
+}
+
+pub async fn delete_invite_request_send(
+    inviter_id: UserID,
+    receiver_id: UserID,
+    chat_id: ChatID,
+) -> Result<(), DatabaseError> {
+    let con = get_con().await?;
+
+    con.execute(
+        "DELETE FROM invitations WHERE inviter_id = $1 AND receiver_id = $2 AND chat_id = $3",
+        &[
+            &(inviter_id as i64),
+            &(receiver_id as i64),
+            &(chat_id as i64),
+        ],
+    )
+    .await?;
+
+    return Ok(());
+}
@@ -1,3 +1,789 @@
-// pub async fn register_user(_data: UserRegisterData) -> UserRegisterResponse {
-//     todo!()
-// }
+use log::error;
+
+use super::common::*;
+use crate::config::datatype::*;
+use crate::database::DatabaseError;
+use crate::user::*;
+use crate::utils::password::{self, PasswordCheck};
+
+fn friend_pair(user_id1: UserID, user_id2: UserID) -> (i64, i64) {
+    return if user_id1 < user_id2 {
+        (user_id1 as i64, user_id2 as i64)
+    } else {
+        (user_id2 as i64, user_id1 as i64)
+    };
+}
+
+pub async fn user_register(data: UserRegisterData) -> Result<UserRegisterResponse, DatabaseError> {
+    let con = get_con().await?;
+
+    let row = con
+        .query_opt(
+            "INSERT INTO users (user_name, password, email) VALUES ($1, $2, $3)
+            ON CONFLICT (email) DO NOTHING RETURNING user_id",
+            &[&data.user_name, &data.password, &data.email],
+        )
+        .await?;
+
+    match row {
+        Some(row) => {
+            return Ok(UserRegisterResponse::Success {
+                user_id: row.get::<_, i64>(0) as UserID,
+            })
+        }
+        None => return Ok(UserRegisterResponse::EmailRegistered),
+    }
+}
+
+pub async fn user_login_with_password(
+    data: &UserLoginData,
+) -> Result<UserLoginResponse, DatabaseError> {
+    let con = get_con().await?;
+
+    let row = con
+        .query_opt(
+            "SELECT user_id, password FROM users WHERE email = $1",
+            &[&data.email],
+        )
+        .await?;
+
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(UserLoginResponse::UserNotFound),
+    };
+
+    let user_id: i64 = row.get(0);
+    let input = data.password.as_ref().unwrap();
+    match password::spawn_check_password(row.get(1), input).await {
+        PasswordCheck::Match => {}
+        PasswordCheck::MatchLegacy => {
+            // 旧版明文密码替换为哈希，期间密码已被修改时不替换，失败时下次登录再替换
+            if let Ok(hash) = password::spawn_hash_password(input).await {
+                if let Err(err) = con
+                    .execute(
+                        "UPDATE users SET password = $2 WHERE user_id = $1 AND password = $3",
+                        &[&user_id, &hash, &row.get::<_, &str>(1)],
+                    )
+                    .await
+                {
+                    error!("user_login_with_password: 替换旧版明文密码: {}", err);
+                }
+            }
+        }
+        PasswordCheck::Mismatch => return Ok(UserLoginResponse::PasswordError),
+    }
+
+    return Ok(UserLoginResponse::Success {
+        user_id: user_id as UserID,
+    });
+}
+
+pub async fn user_login_with_token(
+    data: &UserLoginData,
+) -> Result<UserLoginResponse, DatabaseError> {
+    let con = get_con().await?;
+
+    let row = con
+        .query_opt("SELECT user_id FROM users WHERE email = $1", &[&data.email])
+        .await?;
+
+    let user_id: i64 = match row {
+        Some(row) => row.get(0),
+        None => return Ok(UserLoginResponse::UserNotFound),
+    };
+
+    let device_id = match &data.device_id {
+        Some(device_id) => device_id,
+        None => return Ok(UserLoginResponse::TokenError),
+    };
+    let row = con
+        .query_opt(
+            "SELECT access_token, refresh_token, issued_at FROM user_tokens
+            WHERE user_id = $1 AND device_id = $2",
+            &[&user_id, device_id],
+        )
+        .await?;
+    let token = match row {
+        Some(row) => Token {
+            access_token: row.get(0),
+            refresh_token: row.get(1),
+            timestamp: row.get::<_, i64>(2) as Timestamp,
+        },
+        None => return Ok(UserLoginResponse::TokenError),
+    };
+
+    if let Err(res) = token.check_login(data) {
+        return Ok(res);
+    }
+
+    return Ok(UserLoginResponse::Success {
+        user_id: user_id as UserID,
+    });
+}
+
+pub async fn refresh_token(
+    data: &UserRefreshTokenData,
+) -> Result<UserRefreshTokenResponse, DatabaseError> {
+    let con = get_con().await?;
+
+    let row = con
+        .query_opt("SELECT user_id FROM users WHERE email = $1", &[&data.email])
+        .await?;
+    let user_id: i64 = match row {
+        Some(row) => row.get(0),
+        None => return Ok(UserRefreshTokenResponse::UserNotFound),
+    };
+
+    let row = con
+        .query_opt(
+            "SELECT access_token, refresh_token, issued_at FROM user_tokens
+            WHERE user_id = $1 AND device_id = $2",
+            &[&user_id, &data.device_id],
+        )
+        .await?;
+    let token = match row {
+        Some(row) => Token {
+            access_token: row.get(0),
+            refresh_token: row.get(1),
+            timestamp: row.get::<_, i64>(2) as Timestamp,
+        },
+        None => return Ok(UserRefreshTokenResponse::TokenError),
+    };
+    if let Err(res) = token.check_refresh(&data.refresh_token) {
+        return Ok(res);
+    }
+
+    // 以旧的刷新令牌为条件更新，同一刷新令牌并发换取时只有一次能成功
+    let new_token = Token::generate();
+    let updated = con
+        .execute(
+            "UPDATE user_tokens SET access_token = $3, refresh_token = $4, issued_at = $5
+            WHERE user_id = $1 AND device_id = $2 AND refresh_token = $6",
+            &[
+                &user_id,
+                &data.device_id,
+                &new_token.access_token,
+                &new_token.refresh_token,
+                &(new_token.timestamp as i64),
+                &token.refresh_token,
+            ],
+        )
+        .await?;
+    if updated != 1 {
+        return Ok(UserRefreshTokenResponse::TokenError);
+    }
+    return Ok(UserRefreshTokenResponse::Success {
+        device_id: data.device_id.clone(),
+        access_token: new_token.access_token,
+        refresh_token: new_token.refresh_token,
+        timestamp: new_token.timestamp,
+    });
+}
+
+pub async fn revoke_token(user_id: UserID, device_id: &DeviceID) -> Result<bool, DatabaseError> {
+    let con = get_con().await?;
+    let removed = con
+        .execute(
+            "DELETE FROM user_tokens WHERE user_id = $1 AND device_id = $2",
+            &[&(user_id as i64), device_id],
+        )
+        .await?;
+    return Ok(removed > 0);
+}
+
+pub async fn revoke_all_tokens(user_id: UserID) -> Result<(), DatabaseError> {
+    let con = get_con().await?;
+    con.execute(
+        "DELETE FROM user_tokens WHERE user_id = $1",
+        &[&(user_id as i64)],
+    )
+    .await?;
+    return Ok(());
+}
+
+pub async fn get_user_id_by_email(email: &str) -> Result<Option<UserID>, DatabaseError> {
+    let con = get_con().await?;
+
+    let row = con
+        .query_opt("SELECT user_id FROM users WHERE email = $1", &[&email])
+        .await?;
+
+    return Ok(row.map(|row| row.get::<_, i64>(0) as UserID));
+}
+
+pub async fn get_user_chat_list(
+    user_id: UserID,
+) -> Result<Vec<(ChatID, MessageID)>, DatabaseError> {
+    let con = get_con().await?;
+
+    let rows = con
+        .query(
+            "SELECT chat_id, already_read FROM chat_members WHERE user_id = $1",
+            &[&(user_id as i64)],
+        )
+        .await?;
+
+    return Ok(rows
+        .iter()
+        .map(|row| {
+            (
+                row.get::<_, i64>(0) as ChatID,
+                row.get::<_, i64>(1) as MessageID,
+            )
+        })
+        .collect());
+}
+
+pub async fn get_user_info(user_id: UserID) -> GetUserInfoResponse {
+    if let Ok(con) = get_con().await {
+        let row = con
+            .query_opt(
+                "SELECT user_name, avater_hash FROM users WHERE user_id = $1",
+                &[&(user_id as i64)],
+            )
+            .await;
+        match row {
+            Ok(Some(row)) => {
+                return GetUserInfoResponse::Success(UserInfo {
+                    user_id,
+                    user_name: row.get(0),
+                    avater_hash: row.get(1),
+                })
+            }
+            Ok(None) => return GetUserInfoResponse::UserNotFound,
+            Err(_) => return GetUserInfoResponse::ServerError,
+        }
+    } else {
+        return GetUserInfoResponse::ServerError;
+    }
+}
+
+pub async fn get_user_email(user_id: UserID) -> Result<String, DatabaseError> {
+    let con = get_con().await?;
+
+    let row = con
+        .query_opt(
+            "SELECT email FROM users WHERE user_id = $1",
+            &[&(user_id as i64)],
+        )
+        .await?
+        .ok_or_else(|| DatabaseError::MissingKey(format!("user:{}", user_id)))?;
+
+    return row
+        .get::<_, Option<String>>(0)
+        .ok_or_else(|| DatabaseError::MissingKey(format!("user:{}:email", user_id)));
+}
+
+pub async fn make_two_users_be_friends(
+    user_one_id: UserID,
+    user_two_id: UserID,
+) -> Result<ChatID, DatabaseError> {
+    let mut con = get_con().await?;
+    let (id1, id2) = friend_pair(user_one_id, user_two_id);
+
+    let tx = con.transaction().await?;
+
+    let chat_id: i64 = tx
+        .query_one(
+            "INSERT INTO chats (is_group, user_one_id, user_two_id) VALUES (FALSE, $1, $2)
+            RETURNING chat_id",
+            &[&id1, &id2],
+        )
+        .await?
+        .get(0);
+
+    tx.execute(
+        "INSERT INTO friends (user_one_id, user_two_id, chat_id) VALUES ($1, $2, $3)
+        ON CONFLICT (user_one_id, user_two_id) DO UPDATE SET chat_id = $3",
+        &[&id1, &id2, &chat_id],
+    )
+    .await?;
+
+    tx.execute(
+        "INSERT INTO chat_members (chat_id, user_id) VALUES ($1, $2), ($1, $3)",
+        &[&chat_id, &id1, &id2],
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    return Ok(chat_id as ChatID);
+}
+
+pub async fn check_make_friend_error(
+    user_one_id: UserID,
+    user_two_id: UserID,
+) -> Result<(), MakeFriendError> {
+    if check_user_exist(user_one_id).await.is_err() || check_user_exist(user_two_id).await.is_err()
+    {
+        return Err(MakeFriendError::UserNotFound);
+    }
+
+    match get_chat_id_by_friends(user_one_id, user_two_id).await {
+        Ok(Some(chat_id)) => {
+            return Err(if chat_id == 0 {
+                MakeFriendError::RequestExisted
+            } else {
+                MakeFriendError::AlreadyBeFrineds
+            })
+        }
+        Ok(None) => return Ok(()),
+        Err(_) => return Err(MakeFriendError::DatabaseError),
+    }
+}
+
+pub async fn set_user_setting(user_id: UserID, user_setting: String) -> SetSettingResponse {
+    if let Ok(con) = get_con().await {
+        let result = con
+            .execute(
+                "UPDATE users SET setting = $2 WHERE user_id = $1",
+                &[&(user_id as i64), &user_setting],
+            )
+            .await;
+        match result {
+            Ok(_) => return SetSettingResponse::Success,
+            Err(_) => return SetSettingResponse::DatabaseError,
+        }
+    } else {
+        return SetSettingResponse::ServerError;
+    }
+}
+
+pub async fn get_user_setting(user_id: UserID) -> Result<Option<String>, DatabaseError> {
+    let con = get_con().await?;
+
+    let row = con
+        .query_opt(
+            "SELECT setting FROM users WHERE user_id = $1",
+            &[&(user_id as i64)],
+        )
+        .await?;
+
+    return Ok(row.and_then(|row| row.get(0)));
+}
+
+pub async fn update_user_name(user_id: UserID, new_name: String) -> UserUpdateResponse {
+    if let Ok(con) = get_con().await {
+        let result = con
+            .execute(
+                "UPDATE users SET user_name = $2 WHERE user_id = $1",
+                &[&(user_id as i64), &new_name],
+            )
+            .await;
+        match result {
+            Ok(1) => return UserUpdateResponse::Success,
+            Ok(_) => return UserUpdateResponse::ServerError,
+            Err(_) => return UserUpdateResponse::DatabaseError,
+        }
+    } else {
+        return UserUpdateResponse::ServerError;
+    }
+}
+
+pub async fn update_user_avater(user_id: UserID, new_hash: String) -> UserUpdateResponse {
+    if let Ok(con) = get_con().await {
+        let result = con
+            .execute(
+                "UPDATE users SET avater_hash = $2 WHERE user_id = $1",
+                &[&(user_id as i64), &new_hash],
+            )
+            .await;
+        match result {
+            Ok(1) => return UserUpdateResponse::Success,
+            _ => return UserUpdateResponse::ServerError,
+        }
+    } else {
+        return UserUpdateResponse::ServerError;
+    }
+}
+
+pub async fn update_user_password(user_id: UserID, new_password: String) -> UserUpdateResponse {
+    if let Ok(mut con) = get_con().await {
+        let tx = match con.transaction().await {
+            Ok(tx) => tx,
+            Err(_) => return UserUpdateResponse::ServerError,
+        };
+        let id = user_id as i64;
+        let result = tx
+            .execute(
+                "UPDATE users SET password = $2 WHERE user_id = $1",
+                &[&id, &new_password],
+            )
+            .await;
+        if result.is_err() {
+            return UserUpdateResponse::ServerError;
+        }
+        // 修改密码后撤销全部设备上的令牌
+        let result = tx
+            .execute("DELETE FROM user_tokens WHERE user_id = $1", &[&id])
+            .await;
+        if result.is_err() {
+            return UserUpdateResponse::ServerError;
+        }
+        match tx.commit().await {
+            Ok(_) => return UserUpdateResponse::Success,
+            Err(_) => return UserUpdateResponse::ServerError,
+        }
+    } else {
+        return UserUpdateResponse::ServerError;
+    }
+}
+
+pub async fn get_chat_id_by_friends(
+    user_id1: UserID,
+    user_id2: UserID,
+) -> Result<Option<ChatID>, DatabaseError> {
+    let (id1, id2) = friend_pair(user_id1, user_id2);
+    let con = get_con().await?;
+
+    let row = con
+        .query_opt(
+            "SELECT chat_id FROM friends WHERE user_one_id = $1 AND user_two_id = $2",
+            &[&id1, &id2],
+        )
+        .await?;
+
+    return Ok(row.map(|row| row.get::<_, i64>(0) as ChatID));
+}
+
+pub async fn unfriend(user_id: UserID, friend_id: UserID) -> UserUnfriendResponse {
+    if let Ok(mut con) = get_con().await {
+        let (id1, id2) = friend_pair(user_id, friend_id);
+
+        let tx = match con.transaction().await {
+            Ok(tx) => tx,
+            Err(_) => return UserUnfriendResponse::ServerError,
+        };
+
+        let row = tx
+            .query_opt(
+                "DELETE FROM friends WHERE user_one_id = $1 AND user_two_id = $2
+                RETURNING chat_id",
+                &[&id1, &id2],
+            )
+            .await;
+        let chat_id: i64 = match row {
+            Ok(Some(row)) => row.get(0),
+            Ok(None) => return UserUnfriendResponse::NotFriend,
+            Err(_) => return UserUnfriendResponse::ServerError,
+        };
+
+        let del_result = tx
+            .execute(
+                "DELETE FROM chat_members WHERE chat_id = $1 AND user_id IN ($2, $3)",
+                &[&chat_id, &id1, &id2],
+            )
+            .await;
+        if del_result.is_err() || tx.commit().await.is_err() {
+            return UserUnfriendResponse::ServerError;
+        }
+        return UserUnfriendResponse::Success {
+            chat_id: chat_id as ChatID,
+        };
+    } else {
+        return UserUnfriendResponse::ServerError;
+    }
+}
+
+pub async fn set_user_already_read(
+    user_id: UserID,
+    data: UserSetAlreadyReadData,
+) -> SetAlreadyReadResponse {
+    if let Ok(con) = get_con().await {
+        let result = con
+            .execute(
+                "UPDATE chat_members SET already_read = $3
+                WHERE chat_id = $1 AND user_id = $2
+                AND $3 <= (SELECT last_msg_id FROM chats WHERE chat_id = $1)",
+                &[
+                    &(data.chat_id as i64),
+                    &(user_id as i64),
+                    &(data.in_chat_id as i64),
+                ],
+            )
+            .await;
+        match result {
+            Ok(1) => return SetAlreadyReadResponse::Success,
+            _ => return SetAlreadyReadResponse::ServerError,
+        }
+    } else {
+        return SetAlreadyReadResponse::ServerError;
+    }
+}
+
+pub async fn check_user_in_chat(user_id: UserID, chat_id: ChatID) -> Result<bool, DatabaseError> {
+    let con = get_con().await?;
+
+    let row = con
+        .query_opt(
+            "SELECT 1 FROM chat_members WHERE chat_id = $1 AND user_id = $2",
+            &[&(chat_id as i64), &(user_id as i64)],
+        )
+        .await?;
+
+    return Ok(row.is_some());
+}
+
+pub async fn write_user_notice(
+    user_id: UserID,
+    timestamp: Timestamp,
+    serialized: &SerializedUserNotice,
+) -> Result<(), DatabaseError> {
+    let con = get_con().await?;
+
+    con.execute(
+        "INSERT INTO user_notices (user_id, timestamp, serialized) VALUES ($1, $2, $3)
+        ON CONFLICT (user_id, serialized) DO UPDATE SET timestamp = $2",
+        &[&(user_id as i64), &(timestamp as i64), serialized],
+    )
+    .await?;
+
+    return Ok(());
+}
+
+pub async fn get_user_notice(
+    user_id: UserID,
+    start_timestamp: Timestamp,
+) -> Result<Vec<SerializedUserNotice>, DatabaseError> {
+    let con = get_con().await?;
+
+    let rows = con
+        .query(
+            "SELECT serialized FROM user_notices WHERE user_id = $1 AND timestamp >= $2
+            ORDER BY timestamp, serialized",
+            &[&(user_id as i64), &(start_timestamp as i64)],
+        )
+        .await?;
+
+    return Ok(rows.iter().map(|row| row.get(0)).collect());
+}
+
+pub async fn get_user_id(name: &str) -> Result<Vec<UserID>, bool> {
+    let con = get_con().await.map_err(|_| false)?;
+
+    let rows = con
+        .query(
+            "SELECT user_id FROM users WHERE user_name = $1 AND exist ORDER BY user_id",
+            &[&name],
+        )
+        .await
+        .map_err(|_| false)?;
+
+    if rows.is_empty() {
+        return Err(true);
+    }
+    return Ok(rows
+        .iter()
+        .map(|row| row.get::<_, i64>(0) as UserID)
+        .collect());
+}
+
+/// 在一个事务内完成注销：退出所有群聊、解除所有好友关系、释放邮箱
+pub async fn user_log_off(user_id: UserID) -> (UserLogOffResponse, Vec<(UserID, ChatID)>) {
+    let mut con = match get_con().await {
+        Ok(con) => con,
+        Err(_) => return (UserLogOffResponse::ServerError, vec![]),
+    };
+    let tx = match con.transaction().await {
+        Ok(tx) => tx,
+        Err(_) => return (UserLogOffResponse::DatabaseError, vec![]),
+    };
+    let id = user_id as i64;
+
+    let owned = tx
+        .query_opt(
+            "SELECT 1 FROM chats JOIN chat_members USING (chat_id)
+            WHERE chat_members.user_id = $1 AND chats.is_group AND chats.owner_id = $1",
+            &[&id],
+        )
+        .await;
+    match owned {
+        Ok(Some(_)) => return (UserLogOffResponse::NoPermission, vec![]),
+        Ok(None) => {}
+        Err(_) => return (UserLogOffResponse::DatabaseError, vec![]),
+    }
+
+    let email_result = tx
+        .execute(
+            "UPDATE users SET email = NULL, user_name = '用户已注销', exist = FALSE
+            WHERE user_id = $1 AND email IS NOT NULL",
+            &[&id],
+        )
+        .await;
+    match email_result {
+        Ok(0) => return (UserLogOffResponse::UserNotFound, vec![]),
+        Ok(_) => {}
+        Err(_) => return (UserLogOffResponse::DatabaseError, vec![]),
+    }
+
+    let friends = match tx
+        .query(
+            "DELETE FROM friends WHERE (user_one_id = $1 OR user_two_id = $1) AND chat_id <> 0
+            RETURNING user_one_id, user_two_id, chat_id",
+            &[&id],
+        )
+        .await
+    {
+        Ok(rows) => rows,
+        Err(_) => return (UserLogOffResponse::DatabaseError, vec![]),
+    };
+    let mut frineds_chats = vec![];
+    for row in friends {
+        let (id1, id2, chat_id): (i64, i64, i64) = (row.get(0), row.get(1), row.get(2));
+        let friend_id = if id1 == id { id2 } else { id1 };
+        frineds_chats.push((friend_id as UserID, chat_id as ChatID));
+    }
+
+    let statements = [
+        "DELETE FROM chat_admins WHERE user_id = $1",
+        "DELETE FROM chat_members WHERE user_id = $1
+        OR chat_id IN (SELECT chat_id FROM chats
+            WHERE NOT is_group AND (user_one_id = $1 OR user_two_id = $1))",
+    ];
+    for statement in statements {
+        if tx.execute(statement, &[&id]).await.is_err() {
+            return (UserLogOffResponse::DatabaseError, vec![]);
+        }
+    }
+
+    if tx.commit().await.is_err() {
+        return (UserLogOffResponse::DatabaseError, vec![]);
+    }
+    return (UserLogOffResponse::Success, frineds_chats);
+}
+
+pub async fn apply_for_token(user_id: UserID, device_id: &DeviceID) -> UserApplyForTokenResponse {
+    if let Ok(con) = get_con().await {
+        let token = Token::generate();
+        let set_result = con
+            .execute(
+                "INSERT INTO user_tokens (user_id, device_id, access_token, refresh_token, issued_at)
+                SELECT $1, $2, $3, $4, $5 FROM users WHERE user_id = $1
+                ON CONFLICT (user_id, device_id) DO UPDATE
+                SET access_token = $3, refresh_token = $4, issued_at = $5",
+                &[
+                    &(user_id as i64),
+                    device_id,
+                    &token.access_token,
+                    &token.refresh_token,
+                    &(token.timestamp as i64),
+                ],
+            )
+            .await;
+        match set_result {
+            Ok(1) => {
+                return UserApplyForTokenResponse::Success {
+                    device_id: device_id.clone(),
+                    access_token: token.access_token,
+                    refresh_token: token.refresh_token,
+                    timestamp: token.timestamp,
+                }
+            }
+            _ => return UserApplyForTokenResponse::DatabaseError,
+        }
+    } else {
+        return UserApplyForTokenResponse::ServerError;
+    }
+}
+
+pub async fn check_user_exist(user_id: UserID) -> Result<(), DatabaseError> {
+    let con = get_con().await?;
+
+    let row = con
+        .query_opt(
+            "SELECT 1 FROM users WHERE user_id = $1 AND exist",
+            &[&(user_id as i64)],
+        )
+        .await?;
+
+    return row
+        .map(|_| ())
+        .ok_or_else(|| DatabaseError::MissingKey(format!("user:{}:exist", user_id)));
+}
+
+pub async fn set_user_last_seen(
+    user_id: UserID,
+    timestamp: Timestamp,
+) -> Result<(), DatabaseError> {
+    let con = get_con().await?;
+    con.execute(
+        "UPDATE users SET last_seen = $2 WHERE user_id = $1",
+        &[&(user_id as i64), &(timestamp as i64)],
+    )
+    .await?;
+    return Ok(());
+}
+
+pub async fn get_user_last_seen(user_id: UserID) -> Result<Option<Timestamp>, DatabaseError> {
+    let con = get_con().await?;
+    let row = con
+        .query_opt(
+            "SELECT last_seen FROM users WHERE user_id = $1",
+            &[&(user_id as i64)],
+        )
+        .await?;
+    return Ok(row
+        .and_then(|row| row.get::<_, Option<i64>>(0))
+        .map(|last_seen| last_seen as Timestamp));
+}
+
+pub async fn set_presence_hidden(user_id: UserID, hidden: bool) -> Result<(), DatabaseError> {
+    let con = get_con().await?;
+    con.execute(
+        "UPDATE users SET presence_hidden = $2 WHERE user_id = $1",
+        &[&(user_id as i64), &hidden],
+    )
+    .await?;
+    return Ok(());
+}
+
+pub async fn get_presence_hidden(user_id: UserID) -> Result<bool, DatabaseError> {
+    let con = get_con().await?;
+    let row = con
+        .query_opt(
+            "SELECT presence_hidden FROM users WHERE user_id = $1",
+            &[&(user_id as i64)],
+        )
+        .await?;
+    return Ok(row.map_or(false, |row| row.get(0)));
+}
+
+pub async fn set_device_keys(
+    user_id: UserID,
+    device_id: &DeviceID,
+    serialized: &str,
+) -> Result<(), DatabaseError> {
+    let con = get_con().await?;
+    con.execute(
+        "INSERT INTO device_keys (user_id, device_id, serialized) VALUES ($1, $2, $3)
+        ON CONFLICT (user_id, device_id) DO UPDATE SET serialized = $3",
+        &[&(user_id as i64), device_id, &serialized],
+    )
+    .await?;
+    return Ok(());
+}
+
+pub async fn get_device_keys(
+    user_id: UserID,
+    device_id: &DeviceID,
+) -> Result<Option<String>, DatabaseError> {
+    let con = get_con().await?;
+    let row = con
+        .query_opt(
+            "SELECT serialized FROM device_keys WHERE user_id = $1 AND device_id = $2",
+            &[&(user_id as i64), device_id],
+        )
+        .await?;
+    return Ok(row.map(|row| row.get(0)));
+}
+
+pub async fn get_user_device_keys(user_id: UserID) -> Result<Vec<String>, DatabaseError> {
+    let con = get_con().await?;
+    let rows = con
+        .query(
+            "SELECT serialized FROM device_keys WHERE user_id = $1 ORDER BY device_id",
+            &[&(user_id as i64)],
+        )
+        .await?;
+    return Ok(rows.iter().map(|row| row.get(0)).collect());
+}
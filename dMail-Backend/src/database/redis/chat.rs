@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::ops::DerefMut;
 
 use mobc_redis::redis;
@@ -6,27 +7,40 @@ use mobc_redis::redis::AsyncCommands;
 use super::common::*;
 use super::index;
 use super::path;
+use super::script;
+use crate::chat::get_forwarded_field;
+use crate::chat::get_keyword_tokens;
+use crate::chat::get_message_search_tokens;
+use crate::chat::get_search_ranges;
 use crate::chat::ChatInfo;
 use crate::chat::ChatMembers;
+use crate::chat::ChatMessage;
 use crate::chat::ChatType;
+use crate::chat::ForwardedFrom;
+use crate::chat::SearchCollector;
+use crate::chat::SearchCursor;
+use crate::chat::SearchPage;
 use crate::config::datatype::ChatID;
 use crate::config::datatype::ClientID;
 use crate::config::datatype::MessageID;
 use crate::config::datatype::NoticeID;
+use crate::config::datatype::ReactionCounts;
+use crate::config::datatype::ScheduleID;
 use crate::config::datatype::SerializedChatInfo;
 use crate::config::datatype::SerializedChatMessage;
 use crate::config::datatype::SerializedGroupNotice;
 use crate::config::datatype::Timestamp;
 use crate::database::check_user_exist;
+use crate::database::DatabaseError;
 use crate::{config::datatype::UserID, user::*};
 
 pub async fn create_group_chat(
     creator: UserID,
     data: UserCreateGroupChatData,
-) -> Result<ChatID, ()> {
+) -> Result<ChatID, DatabaseError> {
     let mut con = get_con().await?;
 
-    let chat_id: ChatID = con.incr(path::LAST_CHAT_ID, 1).await.map_err(|_| ())?;
+    let chat_id: ChatID = con.incr(path::LAST_CHAT_ID, 1).await?;
     let serialized_chat_info = format!(
         r#"{{"id":{},"name":{},"avaterHash":{}}}"#,
         chat_id,
@@ -49,13 +63,12 @@ pub async fn create_group_chat(
         .ignore()
         .hset(index::get_user_chats_index(creator).as_str(), chat_id, 0)
         .query_async::<_, ()>(con.deref_mut())
-        .await
-        .map_err(|_| ())?;
+        .await?;
 
     return Ok(chat_id);
 }
 
-pub async fn add_user_to_group_chat(chat_id: ChatID, user_id: UserID) -> Result<(), ()> {
+pub async fn add_user_to_group_chat(chat_id: ChatID, user_id: UserID) -> Result<(), DatabaseError> {
     let mut con = get_con().await?;
 
     redis::pipe()
@@ -64,8 +77,7 @@ pub async fn add_user_to_group_chat(chat_id: ChatID, user_id: UserID) -> Result<
         .hset(index::get_user_chats_index(user_id).as_str(), chat_id, 0)
         .ignore()
         .query_async(con.deref_mut())
-        .await
-        .map_err(|_| ())?;
+        .await?;
 
     return Ok(());
 }
@@ -75,43 +87,72 @@ pub async fn write_message_to_chat(
     serialized_content: String,
     chat_id: ChatID,
     sender_id: UserID,
-) -> Result<(SerializedChatMessage, MessageID, Timestamp), ()> {
+    forwarded_from: Option<&ForwardedFrom>,
+    reply_to: Option<MessageID>,
+) -> Result<(SerializedChatMessage, MessageID, Timestamp), DatabaseError> {
     let mut con = get_con().await?;
 
-    let chat_index = index::get_chat_msgs_index(chat_id);
     let timestamp = chrono::Utc::now().timestamp_millis() as Timestamp;
 
-    let in_chat_id: MessageID = con
-        .incr(index::get_chat_last_id_index(chat_id).as_str(), 1)
-        .await
-        .map_err(|_| ())?;
-
-    let serialized_msg = format!(
-        r#"{{"type":{}, "inChatId":{}, "chatId":{}, "senderId":{}, "serializedContent":{}, "timestamp":{}}}"#,
-        r#type, in_chat_id, chat_id, sender_id, serialized_content, timestamp
+    // inChatId 在脚本中分配，分配与写入之间不会插入其他消息
+    // 消息所在的分块取决于新分配的inChatId，分块在读取后发生变化时重试
+    let msg_head = format!(r#"{{"type":{}, "inChatId":"#, r#type);
+    let msg_tail = format!(
+        r#", "chatId":{}, "senderId":{}, "serializedContent":{}, "timestamp":{}{}}}"#,
+        chat_id,
+        sender_id,
+        serialized_content,
+        timestamp,
+        get_forwarded_field(forwarded_from)
     );
-
-    // TODO : 实现消息列表分块存储
-    con.zadd(chat_index.as_str(), &serialized_msg, in_chat_id)
-        .await
-        .map_err(|_| ())?;
-
-    return Ok((serialized_msg, in_chat_id, timestamp));
+    // 词项只与消息内容有关，用占位的inChatId拼出消息即可得到
+    let tokens: Vec<String> = get_message_search_tokens(&format!("{}0{}", msg_head, msg_tail))
+        .into_iter()
+        .collect();
+
+    let last_id_index = index::get_chat_last_id_index(chat_id);
+    for _ in 0..script::MAX_RETRY_NUM {
+        let last_id: Option<MessageID> = con.get(last_id_index.as_str()).await?;
+        let bucket = index::get_msg_bucket(last_id.unwrap_or(0) + 1);
+        let mut invocation = script::WRITE_MESSAGE.prepare_invoke();
+        invocation
+            .key(last_id_index.as_str())
+            .key(index::get_chat_msgs_index(chat_id, bucket).as_str());
+        if let Some(root_id) = reply_to {
+            invocation
+                .key(index::get_chat_thread_index(chat_id, root_id).as_str())
+                .key(index::get_chat_threads_index(chat_id).as_str())
+                .key(index::get_chat_reply_counts_index(chat_id).as_str());
+        }
+        for token in &tokens {
+            invocation.key(index::get_chat_search_index(chat_id, token).as_str());
+        }
+        let (in_chat_id, serialized_msg): (MessageID, SerializedChatMessage) = invocation
+            .arg(&msg_head)
+            .arg(&msg_tail)
+            .arg(index::MSG_BUCKET_SIZE)
+            .arg(bucket)
+            .arg(reply_to.unwrap_or(0))
+            .invoke_async(con.deref_mut())
+            .await?;
+        if in_chat_id != 0 {
+            return Ok((serialized_msg, in_chat_id, timestamp));
+        }
+    }
+    return Err(DatabaseError::Conflict(last_id_index.to_string()));
 }
 
-pub async fn get_chat_user_list(chat_id: ChatID) -> Result<ChatMembers, ()> {
+pub async fn get_chat_user_list(chat_id: ChatID) -> Result<ChatMembers, DatabaseError> {
     let mut con = get_con().await?;
 
     let is_group: bool = con
         .exists(index::get_chat_owner_index(chat_id).as_str())
-        .await
-        .map_err(|_| ())?;
+        .await?;
 
     if is_group {
         let users: Vec<UserID> = con
             .smembers(index::get_chat_users_index(chat_id).as_str())
-            .await
-            .map_err(|_| ())?;
+            .await?;
         return Ok(ChatMembers::Group(users));
     } else {
         // "{id1}:{id2}"
@@ -119,20 +160,20 @@ pub async fn get_chat_user_list(chat_id: ChatID) -> Result<ChatMembers, ()> {
             .get(index::get_chat_user_index(chat_id, 0).as_str())
             .get(index::get_chat_user_index(chat_id, 1).as_str())
             .query_async(con.deref_mut())
-            .await
-            .map_err(|_| ())?;
+            .await?;
 
         return Ok(ChatMembers::Private(pair));
     }
 }
 
-pub async fn get_private_chat_user_list(chat_id: ChatID) -> Result<Option<(UserID, UserID)>, ()> {
+pub async fn get_private_chat_user_list(
+    chat_id: ChatID,
+) -> Result<Option<(UserID, UserID)>, DatabaseError> {
     let mut con = get_con().await?;
 
     let is_group: bool = con
         .exists(index::get_chat_owner_index(chat_id).as_str())
-        .await
-        .map_err(|_| ())?;
+        .await?;
 
     if is_group {
         return Ok(None);
@@ -142,8 +183,7 @@ pub async fn get_private_chat_user_list(chat_id: ChatID) -> Result<Option<(UserI
             .get(index::get_chat_user_index(chat_id, 0).as_str())
             .get(index::get_chat_user_index(chat_id, 1).as_str())
             .query_async(con.deref_mut())
-            .await
-            .map_err(|_| ())?;
+            .await?;
 
         return Ok(Some(pair));
     }
@@ -203,26 +243,29 @@ pub async fn check_user_can_send_in_chat(
 }
 
 pub async fn get_chats_last_messages(
-    chats_id: &Vec<(ChatID, MessageID)>,
+    chats_id: &[(ChatID, MessageID)],
     max_messages_per_chat: u8,
-) -> Result<Vec<SerializedChatMessage>, ()> {
+) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
     let mut con = get_con().await?;
 
     let mut ret: Vec<SerializedChatMessage> = Vec::new();
 
     for (chat_id, _) in chats_id {
-        let index = index::get_chat_msgs_index(*chat_id);
-
-        let end_msg_id: isize = con.zcard(index.as_str()).await.map_err(|_| ())?;
-
-        let mut msgs: Vec<SerializedChatMessage> = con
-            .zrange(
-                index.as_str(),
-                std::cmp::max(1, end_msg_id - max_messages_per_chat as isize) - 1,
-                end_msg_id - 1,
-            )
-            .await
-            .map_err(|_| ())?;
+        let end_msg_id: Option<MessageID> = con
+            .get(index::get_chat_last_id_index(*chat_id).as_str())
+            .await?;
+        let end_msg_id = end_msg_id.unwrap_or(0);
+
+        let mut msgs = get_messages_in_range(
+            &mut con,
+            *chat_id,
+            std::cmp::max(
+                1,
+                end_msg_id.saturating_sub(max_messages_per_chat as MessageID),
+            ),
+            end_msg_id,
+        )
+        .await?;
 
         ret.append(&mut msgs);
     }
@@ -230,39 +273,63 @@ pub async fn get_chats_last_messages(
     return Ok(ret);
 }
 
+/** `get_messages_in_range` 读取`in_chat_id`在[start_msg_id, end_msg_id]内的消息，范围可以跨越多个分块
+ */
+async fn get_messages_in_range(
+    con: &mut MobcCon,
+    chat_id: ChatID,
+    start_msg_id: MessageID,
+    end_msg_id: MessageID,
+) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+    if end_msg_id < start_msg_id {
+        return Ok(vec![]);
+    }
+
+    let mut pipeline = redis::pipe();
+    for bucket in index::get_msg_bucket(start_msg_id)..=index::get_msg_bucket(end_msg_id) {
+        pipeline.zrangebyscore(
+            index::get_chat_msgs_index(chat_id, bucket).as_str(),
+            start_msg_id,
+            end_msg_id,
+        );
+    }
+    let buckets: Vec<Vec<SerializedChatMessage>> = pipeline.query_async(con.deref_mut()).await?;
+
+    return Ok(buckets.into_iter().flatten().collect());
+}
+
 pub async fn get_messages_in_chat(
     chat_id: ChatID,
     start_msg_id: MessageID,
     end_msg_id_opt: Option<MessageID>,
-) -> Result<Vec<SerializedChatMessage>, ()> {
+) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
     let mut con = get_con().await?;
 
-    let index = index::get_chat_msgs_index(chat_id);
-
     let end_msg_id = match end_msg_id_opt {
         Some(id) => id,
-        None => con.zcard(index.as_str()).await.map_err(|_| ())?,
+        None => {
+            let last_id: Option<MessageID> = con
+                .get(index::get_chat_last_id_index(chat_id).as_str())
+                .await?;
+            last_id.unwrap_or(0)
+        }
     };
 
-    let msgs: Vec<SerializedChatMessage> = con
-        .zrange(
-            index.as_str(),
-            start_msg_id as isize - 1,
-            end_msg_id as isize - 1,
-        )
-        .await
-        .map_err(|_| ())?;
-
-    return Ok(msgs);
+    return get_messages_in_range(
+        &mut con,
+        chat_id,
+        std::cmp::max(1, start_msg_id),
+        end_msg_id,
+    )
+    .await;
 }
 
-pub async fn get_chat_info(chat_id: ChatID) -> Result<Option<SerializedChatInfo>, ()> {
+pub async fn get_chat_info(chat_id: ChatID) -> Result<Option<SerializedChatInfo>, DatabaseError> {
     let mut con = get_con().await?;
 
     let chat_info: Option<String> = con
         .get(index::get_chat_info_index(chat_id).as_str())
-        .await
-        .map_err(|_| ())?;
+        .await?;
 
     return Ok(chat_info);
 }
@@ -272,10 +339,10 @@ pub async fn revoke_message(
     in_chat_id: MessageID,
     sender_id: UserID,
     timestamp: Timestamp,
-) -> Result<(), ()> {
+) -> Result<(), DatabaseError> {
     let mut con = get_con().await?;
 
-    let chat_index = index::get_chat_msgs_index(chat_id);
+    let chat_index = index::get_chat_msgs_index(chat_id, index::get_msg_bucket(in_chat_id));
     let serialized_msg = format!(
         r#"{{"type":{}, "inChatId":{}, "chatId":{}, "senderId":{}, "serializedContent":"\"\"", "timestamp":{}}}"#,
         ChatMessageType::Revoked.get_str(),
@@ -285,18 +352,667 @@ pub async fn revoke_message(
         timestamp,
     );
 
-    redis::pipe()
+    let old_msgs: Vec<SerializedChatMessage> = con
+        .zrangebyscore(chat_index.as_str(), in_chat_id, in_chat_id)
+        .await?;
+
+    let mut pipeline = redis::pipe();
+    for old_msg in old_msgs {
+        remove_message_data(&mut pipeline, chat_id, &old_msg);
+    }
+    pipeline
         .zrembyscore(chat_index.as_str(), in_chat_id, in_chat_id)
         .ignore()
         .zadd(chat_index.as_str(), &serialized_msg, in_chat_id)
         .ignore()
+        .zrem(index::get_chat_pinned_index(chat_id).as_str(), in_chat_id)
+        .ignore()
         .query_async(con.deref_mut())
-        .await
-        .map_err(|_| ())?;
+        .await?;
+
+    clear_reactions(&mut con, chat_id, in_chat_id, in_chat_id).await?;
+
+    return Ok(());
+}
+
+/// 删除in_chat_id在`[start_msg_id, end_msg_id]`之间的消息的全部表情回应
+/// 这些消息已被撤回或清理，之后不会再有新的回应，先读出消息列表再删除不会遗漏
+async fn clear_reactions(
+    con: &mut MobcCon,
+    chat_id: ChatID,
+    start_msg_id: MessageID,
+    end_msg_id: MessageID,
+) -> Result<(), DatabaseError> {
+    let reacted_index = index::get_chat_reacted_index(chat_id);
+    let in_chat_ids: Vec<MessageID> = con
+        .zrangebyscore(reacted_index.as_str(), start_msg_id, end_msg_id)
+        .await?;
+    if in_chat_ids.is_empty() {
+        return Ok(());
+    }
+
+    let mut invocation = script::CLEAR_REACTIONS.key(reacted_index.as_str());
+    for in_chat_id in &in_chat_ids {
+        invocation
+            .key(index::get_msg_reactions_index(chat_id, *in_chat_id).as_str())
+            .key(index::get_msg_reaction_users_index(chat_id, *in_chat_id).as_str())
+            .arg(*in_chat_id);
+    }
+    invocation.invoke_async::<_, ()>(con.deref_mut()).await?;
+
+    return Ok(());
+}
+
+/// 删除in_chat_id不超过`end_msg_id`的消息收到的全部回复记录，被回复的消息已被清理，之后不会再有新的回复
+async fn clear_threads(
+    con: &mut MobcCon,
+    chat_id: ChatID,
+    end_msg_id: MessageID,
+) -> Result<(), DatabaseError> {
+    let threads_index = index::get_chat_threads_index(chat_id);
+    let root_ids: Vec<MessageID> = con
+        .zrangebyscore(threads_index.as_str(), 0, end_msg_id)
+        .await?;
+    if root_ids.is_empty() {
+        return Ok(());
+    }
+
+    let mut invocation = script::CLEAR_THREADS.key(threads_index.as_str());
+    invocation.key(index::get_chat_reply_counts_index(chat_id).as_str());
+    for root_id in &root_ids {
+        invocation
+            .key(index::get_chat_thread_index(chat_id, *root_id).as_str())
+            .arg(*root_id);
+    }
+    invocation.invoke_async::<_, ()>(con.deref_mut()).await?;
 
     return Ok(());
 }
 
+pub async fn add_reaction(
+    chat_id: ChatID,
+    in_chat_id: MessageID,
+    user_id: UserID,
+    emoji: &str,
+) -> Result<bool, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let added: bool = script::ADD_REACTION
+        .key(index::get_msg_reaction_users_index(chat_id, in_chat_id).as_str())
+        .key(index::get_msg_reactions_index(chat_id, in_chat_id).as_str())
+        .key(index::get_chat_reacted_index(chat_id).as_str())
+        .arg(index::get_msg_reaction_users_field(user_id, emoji).as_str())
+        .arg(emoji)
+        .arg(in_chat_id)
+        .invoke_async(con.deref_mut())
+        .await?;
+
+    return Ok(added);
+}
+
+pub async fn remove_reaction(
+    chat_id: ChatID,
+    in_chat_id: MessageID,
+    user_id: UserID,
+    emoji: &str,
+) -> Result<bool, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let removed: bool = script::REMOVE_REACTION
+        .key(index::get_msg_reaction_users_index(chat_id, in_chat_id).as_str())
+        .key(index::get_msg_reactions_index(chat_id, in_chat_id).as_str())
+        .key(index::get_chat_reacted_index(chat_id).as_str())
+        .arg(index::get_msg_reaction_users_field(user_id, emoji).as_str())
+        .arg(emoji)
+        .arg(in_chat_id)
+        .invoke_async(con.deref_mut())
+        .await?;
+
+    return Ok(removed);
+}
+
+pub async fn get_reaction_counts(
+    chat_id: ChatID,
+    start_msg_id: MessageID,
+    end_msg_id: Option<MessageID>,
+) -> Result<HashMap<MessageID, ReactionCounts>, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let reacted_index = index::get_chat_reacted_index(chat_id);
+    let in_chat_ids: Vec<MessageID> = match end_msg_id {
+        Some(end_msg_id) => {
+            con.zrangebyscore(reacted_index.as_str(), start_msg_id, end_msg_id)
+                .await?
+        }
+        None => {
+            con.zrangebyscore(reacted_index.as_str(), start_msg_id, "+inf")
+                .await?
+        }
+    };
+    if in_chat_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let mut pipeline = redis::pipe();
+    for in_chat_id in &in_chat_ids {
+        pipeline.hgetall(index::get_msg_reactions_index(chat_id, *in_chat_id).as_str());
+    }
+    let counts: Vec<HashMap<String, u32>> = pipeline.query_async(con.deref_mut()).await?;
+
+    return Ok(in_chat_ids
+        .into_iter()
+        .zip(counts)
+        .map(|(in_chat_id, counts)| (in_chat_id, counts.into_iter().collect()))
+        .collect());
+}
+
+pub async fn edit_message(
+    chat_id: ChatID,
+    in_chat_id: MessageID,
+    old_msg: &str,
+    new_msg: &str,
+) -> Result<bool, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let old_tokens = get_message_search_tokens(old_msg);
+    let new_tokens = get_message_search_tokens(new_msg);
+
+    let mut invocation = script::EDIT_MESSAGE.prepare_invoke();
+    invocation
+        .key(index::get_chat_msgs_index(chat_id, index::get_msg_bucket(in_chat_id)).as_str())
+        .key(index::get_chat_edits_index(chat_id, in_chat_id).as_str());
+    for token in old_tokens.iter().chain(new_tokens.iter()) {
+        invocation.key(index::get_chat_search_index(chat_id, token).as_str());
+    }
+    let edited: bool = invocation
+        .arg(in_chat_id)
+        .arg(old_msg)
+        .arg(new_msg)
+        .arg(old_tokens.len())
+        .invoke_async(con.deref_mut())
+        .await?;
+
+    return Ok(edited);
+}
+
+pub async fn get_edit_history(
+    chat_id: ChatID,
+    in_chat_id: MessageID,
+) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let history: Vec<SerializedChatMessage> = con
+        .lrange(
+            index::get_chat_edits_index(chat_id, in_chat_id).as_str(),
+            0,
+            -1,
+        )
+        .await?;
+
+    return Ok(history);
+}
+
+pub async fn get_thread_replies(
+    chat_id: ChatID,
+    root_id: MessageID,
+) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let reply_ids: Vec<MessageID> = con
+        .lrange(
+            index::get_chat_thread_index(chat_id, root_id).as_str(),
+            0,
+            -1,
+        )
+        .await?;
+
+    return get_messages_by_ids(&mut con, chat_id, &reply_ids).await;
+}
+
+/// 按给出的顺序读取一组消息，已被清理的消息不包含在内
+async fn get_messages_by_ids(
+    con: &mut MobcCon,
+    chat_id: ChatID,
+    in_chat_ids: &[MessageID],
+) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+    if in_chat_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut pipeline = redis::pipe();
+    for in_chat_id in in_chat_ids {
+        pipeline.zrangebyscore(
+            index::get_chat_msgs_index(chat_id, index::get_msg_bucket(*in_chat_id)).as_str(),
+            *in_chat_id,
+            *in_chat_id,
+        );
+    }
+    let msgs: Vec<Vec<SerializedChatMessage>> = pipeline.query_async(con.deref_mut()).await?;
+
+    return Ok(msgs.into_iter().flatten().collect());
+}
+
+pub async fn get_reply_counts(
+    chat_id: ChatID,
+    start_msg_id: MessageID,
+    end_msg_id: Option<MessageID>,
+) -> Result<HashMap<MessageID, u32>, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let threads_index = index::get_chat_threads_index(chat_id);
+    let root_ids: Vec<MessageID> = match end_msg_id {
+        Some(end_msg_id) => {
+            con.zrangebyscore(threads_index.as_str(), start_msg_id, end_msg_id)
+                .await?
+        }
+        None => {
+            con.zrangebyscore(threads_index.as_str(), start_msg_id, "+inf")
+                .await?
+        }
+    };
+    if root_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let counts: Vec<Option<u32>> = redis::cmd("HMGET")
+        .arg(index::get_chat_reply_counts_index(chat_id).as_str())
+        .arg(&root_ids)
+        .query_async(con.deref_mut())
+        .await?;
+
+    return Ok(root_ids
+        .into_iter()
+        .zip(counts)
+        .filter_map(|(root_id, count)| count.map(|count| (root_id, count)))
+        .collect());
+}
+
+pub async fn pin_message(chat_id: ChatID, in_chat_id: MessageID) -> Result<bool, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let added: bool = con
+        .zadd(
+            index::get_chat_pinned_index(chat_id).as_str(),
+            in_chat_id,
+            in_chat_id,
+        )
+        .await?;
+
+    return Ok(added);
+}
+
+pub async fn unpin_message(chat_id: ChatID, in_chat_id: MessageID) -> Result<bool, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let removed: bool = con
+        .zrem(index::get_chat_pinned_index(chat_id).as_str(), in_chat_id)
+        .await?;
+
+    return Ok(removed);
+}
+
+pub async fn get_pinned_messages(
+    chat_id: ChatID,
+) -> Result<Vec<SerializedChatMessage>, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let in_chat_ids: Vec<MessageID> = con
+        .zrange(index::get_chat_pinned_index(chat_id).as_str(), 0, -1)
+        .await?;
+
+    return get_messages_by_ids(&mut con, chat_id, &in_chat_ids).await;
+}
+
+pub async fn get_chat_ids() -> Result<Vec<ChatID>, DatabaseError> {
+    let mut con = get_con().await?;
+
+    // 私聊与群聊共用同一个ID计数器
+    let last_chat_id: Option<ChatID> = con.get(path::LAST_CHAT_ID).await?;
+
+    return Ok((1..=last_chat_id.unwrap_or(0)).collect());
+}
+
+pub async fn prune_messages(
+    chat_id: ChatID,
+    min_timestamp: Option<Timestamp>,
+    max_count: Option<MessageID>,
+) -> Result<MessageID, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let (last_id, old_pruned_id): (Option<MessageID>, Option<MessageID>) = redis::pipe()
+        .get(index::get_chat_last_id_index(chat_id).as_str())
+        .get(index::get_chat_pruned_index(chat_id).as_str())
+        .query_async(con.deref_mut())
+        .await?;
+    let last_id = last_id.unwrap_or(0);
+    let old_pruned_id = old_pruned_id.unwrap_or(0);
+
+    let mut pruned_id = old_pruned_id;
+    if let Some(max_count) = max_count {
+        pruned_id = std::cmp::max(pruned_id, last_id.saturating_sub(max_count));
+    }
+    if let Some(min_timestamp) = min_timestamp {
+        // 从最早的未清理消息开始逐个分块查找，遇到不早于min_timestamp的消息即停止
+        'buckets: for bucket in
+            index::get_msg_bucket(pruned_id + 1)..=index::get_msg_bucket(last_id)
+        {
+            let msgs: Vec<SerializedChatMessage> = con
+                .zrangebyscore(
+                    index::get_chat_msgs_index(chat_id, bucket).as_str(),
+                    pruned_id + 1,
+                    last_id,
+                )
+                .await?;
+            for serialized_msg in msgs {
+                let msg = serde_json::from_str::<ChatMessage>(&serialized_msg)?;
+                if msg.timestamp >= min_timestamp {
+                    break 'buckets;
+                }
+                pruned_id = msg.in_chat_id;
+            }
+        }
+    }
+
+    if pruned_id <= old_pruned_id {
+        return Ok(old_pruned_id);
+    }
+
+    // 分块中的消息被全部删除后，Redis会自动删除该分块
+    let mut pipeline = redis::pipe();
+    for bucket in index::get_msg_bucket(old_pruned_id + 1)..=index::get_msg_bucket(pruned_id) {
+        let chat_index = index::get_chat_msgs_index(chat_id, bucket);
+        // 被清理的消息同时删除其搜索词项与编辑历史
+        let msgs: Vec<SerializedChatMessage> = con
+            .zrangebyscore(chat_index.as_str(), old_pruned_id + 1, pruned_id)
+            .await?;
+        for serialized_msg in msgs {
+            remove_message_data(&mut pipeline, chat_id, &serialized_msg);
+        }
+        pipeline
+            .zrembyscore(chat_index.as_str(), 0, pruned_id)
+            .ignore();
+    }
+    pipeline
+        .zrembyscore(index::get_chat_pinned_index(chat_id).as_str(), 0, pruned_id)
+        .ignore()
+        .set(index::get_chat_pruned_index(chat_id).as_str(), pruned_id)
+        .ignore()
+        .query_async::<_, ()>(con.deref_mut())
+        .await?;
+    clear_reactions(&mut con, chat_id, 0, pruned_id).await?;
+    clear_threads(&mut con, chat_id, pruned_id).await?;
+
+    return Ok(pruned_id);
+}
+
+pub async fn get_pruned_id(chat_id: ChatID) -> Result<MessageID, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let pruned_id: Option<MessageID> = con
+        .get(index::get_chat_pruned_index(chat_id).as_str())
+        .await?;
+
+    return Ok(pruned_id.unwrap_or(0));
+}
+
+pub async fn set_disappearing_ttl(chat_id: ChatID, ttl: Option<u32>) -> Result<(), DatabaseError> {
+    let mut con = get_con().await?;
+
+    script::SET_DISAPPEARING_TTL
+        .key(path::DISAPPEARING_CHATS)
+        .key(index::get_chat_last_id_index(chat_id).as_str())
+        .key(index::get_chat_expired_index(chat_id).as_str())
+        .arg(chat_id)
+        .arg(ttl.unwrap_or(0))
+        .invoke_async::<_, ()>(con.deref_mut())
+        .await?;
+
+    return Ok(());
+}
+
+pub async fn get_disappearing_ttl(chat_id: ChatID) -> Result<Option<u32>, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let ttl: Option<u32> = con.hget(path::DISAPPEARING_CHATS, chat_id).await?;
+
+    return Ok(ttl);
+}
+
+pub async fn get_disappearing_chats() -> Result<Vec<(ChatID, u32)>, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let chats: HashMap<ChatID, u32> = con.hgetall(path::DISAPPEARING_CHATS).await?;
+    let mut chats: Vec<(ChatID, u32)> = chats.into_iter().collect();
+    chats.sort();
+
+    return Ok(chats);
+}
+
+pub async fn get_expired_id(chat_id: ChatID) -> Result<MessageID, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let expired_id: Option<MessageID> = con
+        .get(index::get_chat_expired_index(chat_id).as_str())
+        .await?;
+
+    return Ok(expired_id.unwrap_or(0));
+}
+
+pub async fn set_expired_id(chat_id: ChatID, expired_id: MessageID) -> Result<(), DatabaseError> {
+    let mut con = get_con().await?;
+
+    con.set(index::get_chat_expired_index(chat_id).as_str(), expired_id)
+        .await?;
+
+    return Ok(());
+}
+
+/// 在管道中加入删除消息的搜索词项与编辑历史的命令
+fn remove_message_data(pipeline: &mut redis::Pipeline, chat_id: ChatID, serialized_msg: &str) {
+    let msg = match serde_json::from_str::<ChatMessage>(serialized_msg) {
+        Ok(msg) => msg,
+        Err(_) => return,
+    };
+    let in_chat_id = msg.in_chat_id;
+    if msg.edited_at.is_some() {
+        pipeline
+            .del(index::get_chat_edits_index(chat_id, in_chat_id).as_str())
+            .ignore();
+    }
+    for token in get_message_search_tokens(serialized_msg) {
+        pipeline
+            .zrem(
+                index::get_chat_search_index(chat_id, &token).as_str(),
+                in_chat_id,
+            )
+            .ignore();
+    }
+}
+
+pub async fn search_messages(
+    chat_ids: &[ChatID],
+    keyword: &str,
+    cursor: Option<SearchCursor>,
+    limit: u8,
+) -> Result<SearchPage, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let tokens = get_keyword_tokens(keyword);
+    let mut collector = SearchCollector::new(keyword, limit);
+    if tokens.is_empty() {
+        return Ok(collector.finish());
+    }
+
+    for (chat_id, mut upper) in get_search_ranges(chat_ids, cursor) {
+        // 候选消息取自消息数最少的词项，其余词项由子串匹配保证
+        let mut pipeline = redis::pipe();
+        for token in &tokens {
+            pipeline.zcard(index::get_chat_search_index(chat_id, token).as_str());
+        }
+        let counts: Vec<u64> = pipeline.query_async(con.deref_mut()).await?;
+        let search_index = match tokens.iter().zip(counts).min_by_key(|(_, count)| *count) {
+            Some((token, count)) if count > 0 => index::get_chat_search_index(chat_id, token),
+            _ => continue,
+        };
+
+        while !collector.is_done() {
+            let batch = collector.remaining_candidates();
+            let max = if upper == MessageID::MAX {
+                "+inf".to_string()
+            } else {
+                format!("({}", upper)
+            };
+            let in_chat_ids: Vec<MessageID> = con
+                .zrevrangebyscore_limit(search_index.as_str(), max, "-inf", 0, batch as isize)
+                .await?;
+            if in_chat_ids.is_empty() {
+                break;
+            }
+
+            let mut pipeline = redis::pipe();
+            for in_chat_id in &in_chat_ids {
+                pipeline.zrangebyscore(
+                    index::get_chat_msgs_index(chat_id, index::get_msg_bucket(*in_chat_id))
+                        .as_str(),
+                    *in_chat_id,
+                    *in_chat_id,
+                );
+            }
+            let msgs: Vec<Vec<SerializedChatMessage>> =
+                pipeline.query_async(con.deref_mut()).await?;
+            for (in_chat_id, msgs) in in_chat_ids.iter().zip(msgs) {
+                if collector.is_done() {
+                    break;
+                }
+                collector.check(chat_id, *in_chat_id, msgs.into_iter().next());
+            }
+
+            if in_chat_ids.len() < batch {
+                break;
+            }
+            upper = *in_chat_ids.last().unwrap();
+        }
+        if collector.is_done() {
+            break;
+        }
+    }
+
+    return Ok(collector.finish());
+}
+
+pub async fn add_scheduled_message(
+    sender_id: UserID,
+    deliver_at: Timestamp,
+    message: &UserSendMessageData,
+) -> Result<ScheduleID, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let schedule_id: ScheduleID = con.incr(path::LAST_SCHEDULE_ID, 1).await?;
+    let scheduled = ScheduledMessage {
+        schedule_id,
+        sender_id,
+        deliver_at,
+        message: message.clone(),
+    };
+
+    script::ADD_SCHEDULED_MESSAGE
+        .key(path::SCHEDULE_QUEUE)
+        .key(path::SCHEDULE_MSGS)
+        .key(path::SCHEDULE_SENDERS)
+        .key(index::get_user_scheduled_index(sender_id).as_str())
+        .arg(schedule_id)
+        .arg(deliver_at)
+        .arg(serde_json::to_string(&scheduled)?)
+        .arg(sender_id)
+        .invoke_async::<_, ()>(con.deref_mut())
+        .await?;
+
+    return Ok(schedule_id);
+}
+
+/// 按投递时间排列定时消息，投递时间相同时按ID排列
+fn parse_scheduled_messages(
+    serialized: Vec<String>,
+) -> Result<Vec<ScheduledMessage>, DatabaseError> {
+    let mut messages = serialized
+        .iter()
+        .map(|scheduled| serde_json::from_str::<ScheduledMessage>(scheduled))
+        .collect::<Result<Vec<_>, _>>()?;
+    messages.sort_by_key(|scheduled| (scheduled.deliver_at, scheduled.schedule_id));
+    return Ok(messages);
+}
+
+pub async fn get_scheduled_messages(
+    user_id: UserID,
+) -> Result<Vec<ScheduledMessage>, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let schedule_ids: Vec<ScheduleID> = con
+        .zrange(index::get_user_scheduled_index(user_id).as_str(), 0, -1)
+        .await?;
+    if schedule_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let serialized: Vec<Option<String>> = redis::cmd("HMGET")
+        .arg(path::SCHEDULE_MSGS)
+        .arg(&schedule_ids)
+        .query_async(con.deref_mut())
+        .await?;
+
+    return parse_scheduled_messages(serialized.into_iter().flatten().collect());
+}
+
+pub async fn cancel_scheduled_message(
+    user_id: UserID,
+    schedule_id: ScheduleID,
+) -> Result<bool, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let canceled: bool = script::CANCEL_SCHEDULED_MESSAGE
+        .key(index::get_user_scheduled_index(user_id).as_str())
+        .key(path::SCHEDULE_QUEUE)
+        .key(path::SCHEDULE_MSGS)
+        .key(path::SCHEDULE_SENDERS)
+        .arg(schedule_id)
+        .invoke_async(con.deref_mut())
+        .await?;
+
+    return Ok(canceled);
+}
+
+pub async fn get_due_scheduled_messages(
+    now: Timestamp,
+) -> Result<Vec<ScheduledMessage>, DatabaseError> {
+    let mut con = get_con().await?;
+
+    let schedule_ids: Vec<ScheduleID> =
+        con.zrangebyscore(path::SCHEDULE_QUEUE, "-inf", now).await?;
+    if schedule_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let serialized: Vec<Option<String>> = redis::cmd("HMGET")
+        .arg(path::SCHEDULE_MSGS)
+        .arg(&schedule_ids)
+        .query_async(con.deref_mut())
+        .await?;
+
+    return parse_scheduled_messages(serialized.into_iter().flatten().collect());
+}
+
+pub async fn remove_scheduled_message(schedule_id: ScheduleID) -> Result<bool, DatabaseError> {
+    let mut con = get_con().await?;
+
+    // 定时消息的发送者不会改变，先读出发送者再删除
+    let sender_id: Option<UserID> = con.hget(path::SCHEDULE_SENDERS, schedule_id).await?;
+    let sender_id = match sender_id {
+        Some(sender_id) => sender_id,
+        None => return Ok(false),
+    };
+    return cancel_scheduled_message(sender_id, schedule_id).await;
+}
+
 pub async fn check_group_invitation_error(
     sender_id: UserID,
     receiver_id: UserID,
@@ -492,78 +1208,71 @@ pub async fn check_join_group_error(
     }
 }
 
-pub async fn get_chat_admins_list(chat_id: ChatID) -> Result<UserRequestHandler, ()> {
+pub async fn get_chat_admins_list(chat_id: ChatID) -> Result<UserRequestHandler, DatabaseError> {
     let mut con = get_con().await?;
 
     let is_group: bool = con
         .exists(index::get_chat_owner_index(chat_id).as_str())
-        .await
-        .map_err(|_| ())?;
+        .await?;
 
     if is_group {
         let admins: Vec<UserID> = con
             .smembers(index::get_chat_admins_index(chat_id).as_str())
-            .await
-            .map_err(|_| ())?;
+            .await?;
         return Ok(UserRequestHandler::Group(admins));
     } else {
-        return Err(());
+        return Err(DatabaseError::MissingKey(
+            index::get_chat_owner_index(chat_id).to_string(),
+        ));
     }
 }
 
-pub async fn get_chat_owner(chat_id: ChatID) -> Result<UserID, ()> {
+pub async fn get_chat_owner(chat_id: ChatID) -> Result<UserID, DatabaseError> {
     let mut con = get_con().await?;
 
     let is_group: bool = con
         .exists(index::get_chat_owner_index(chat_id).as_str())
-        .await
-        .map_err(|_| ())?;
+        .await?;
 
     if is_group {
         return con
             .get(index::get_chat_owner_index(chat_id).as_str())
             .await
-            .map_err(|_| ());
+            .map_err(DatabaseError::from);
     } else {
-        return Err(());
+        return Err(DatabaseError::MissingKey(
+            index::get_chat_owner_index(chat_id).to_string(),
+        ));
     }
 }
 
-pub async fn check_user_is_owner(user_id: UserID, chat_id: ChatID) -> Result<bool, ()> {
+pub async fn check_user_is_owner(user_id: UserID, chat_id: ChatID) -> Result<bool, DatabaseError> {
     let owner = get_chat_owner(chat_id).await?;
     return Ok(owner == user_id);
 }
 
-pub async fn check_user_is_admin(user_id: UserID, chat_id: ChatID) -> Result<bool, ()> {
+pub async fn check_user_is_admin(user_id: UserID, chat_id: ChatID) -> Result<bool, DatabaseError> {
     let mut con = get_con().await?;
     return con
         .sismember(index::get_chat_admins_index(chat_id).as_str(), user_id)
         .await
-        .map_err(|_| ());
+        .map_err(DatabaseError::from);
 }
 
 pub async fn owner_transfer(user_id: UserID, chat_id: ChatID) -> UserGroupOwnerTransferResponse {
     if let Ok(mut con) = get_con().await {
-        let set_result: Result<bool, ()> = con
-            .set(index::get_chat_owner_index(chat_id).as_str(), user_id)
+        let transfer_result: Result<i8, ()> = script::OWNER_TRANSFER
+            .key(index::get_chat_owner_index(chat_id).as_str())
+            .key(index::get_chat_admins_index(chat_id).as_str())
+            .key(index::get_chat_users_index(chat_id).as_str())
+            .arg(user_id)
+            .invoke_async(con.deref_mut())
             .await
             .map_err(|_| ());
-        if set_result.is_ok() && set_result.unwrap() {
-            if let Ok(check) = check_user_is_admin(user_id, chat_id).await {
-                if !check {
-                    if let UserSetGroupAdminResponse::Success { .. } =
-                        set_as_admin(user_id, chat_id).await
-                    {
-                    } else {
-                        return UserGroupOwnerTransferResponse::DatabaseError;
-                    }
-                }
-            } else {
-                return UserGroupOwnerTransferResponse::DatabaseError;
-            }
-            return UserGroupOwnerTransferResponse::Success { chat_id, user_id };
-        } else {
-            return UserGroupOwnerTransferResponse::ServerError;
+        match transfer_result {
+            Ok(1) => return UserGroupOwnerTransferResponse::Success { chat_id, user_id },
+            Ok(_) => return UserGroupOwnerTransferResponse::UserNotInChat,
+            Err(_) => return UserGroupOwnerTransferResponse::DatabaseError,
         }
     } else {
         return UserGroupOwnerTransferResponse::ServerError;
@@ -708,11 +1417,11 @@ pub async fn unset_admin(user_id: UserID, chat_id: ChatID) -> UserUnsetGroupAdmi
     }
 }
 
-pub async fn check_is_group_chat(chat_id: ChatID) -> Result<bool, ()> {
+pub async fn check_is_group_chat(chat_id: ChatID) -> Result<bool, DatabaseError> {
     let mut con = get_con().await?;
     con.exists(index::get_chat_owner_index(chat_id).as_str())
         .await
-        .map_err(|_| ())
+        .map_err(DatabaseError::from)
 }
 
 pub async fn get_user_read_in_group(
@@ -769,7 +1478,7 @@ pub async fn get_user_read_in_private(
                 };
                 users = users_opt.unwrap();
             }
-            Err(()) => return UserGetUserReadInPrivateResponse::DatabaseError,
+            Err(_) => return UserGetUserReadInPrivateResponse::DatabaseError,
         }
 
         let friend_id;
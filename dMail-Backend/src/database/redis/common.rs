@@ -1,20 +1,40 @@
+use std::ops::DerefMut;
 use std::time::Duration;
 
+use log::info;
 use mobc::{Connection, Pool};
 use mobc_redis::{redis, RedisConnectionManager};
 use once_cell::sync::OnceCell;
 use redis::AsyncCommands;
 
+use super::index;
+use super::path;
+use super::script;
+use crate::config::datatype::{ChatID, MessageID};
 use crate::config::Config;
+use crate::database::DatabaseError;
 
 pub type MobcPool = Pool<RedisConnectionManager>;
 pub type MobcCon = Connection<RedisConnectionManager>;
 
 pub static POOL: OnceCell<MobcPool> = OnceCell::new();
 
-pub async fn connect_database() -> Result<(), ()> {
+impl From<redis::RedisError> for DatabaseError {
+    fn from(err: redis::RedisError) -> Self {
+        // 读取不存在的键且目标类型不是Option时，Redis返回nil导致类型转换失败
+        if err.kind() == redis::ErrorKind::TypeError {
+            if err.to_string().contains("nil") {
+                return DatabaseError::MissingKey(err.to_string());
+            }
+            return DatabaseError::Deserialize(err.to_string());
+        }
+        return DatabaseError::Backend(err.to_string());
+    }
+}
+
+pub async fn connect_database() -> Result<(), DatabaseError> {
     let database_config = &Config::get().database;
-    let client = redis::Client::open(&*database_config.address).map_err(|_| ())?;
+    let client = redis::Client::open(&*database_config.address)?;
     let manager = RedisConnectionManager::new(client);
 
     let pool = mobc::Pool::builder()
@@ -32,9 +52,88 @@ pub async fn connect_database() -> Result<(), ()> {
 
     POOL.set(pool).ok();
 
+    split_legacy_messages().await?;
+
     return Ok(());
 }
 
-pub async fn get_con() -> Result<MobcCon, ()> {
-    return POOL.get().unwrap().get().await.map_err(|_| ());
+/** `split_legacy_messages` 将分块存储之前写入的消息列表迁移到各个分块中
+ * 迁移完成后写入标记，之后启动时不再扫描
+ */
+async fn split_legacy_messages() -> Result<(), DatabaseError> {
+    let mut con = get_con().await?;
+    if con.exists(path::MSG_BUCKETS_MIGRATED).await? {
+        return Ok(());
+    }
+
+    let mut legacy_indexes: Vec<String> = Vec::new();
+    let mut iter = con
+        .scan_match::<_, String>(path::LEGACY_CHAT_MSGS_PATTERN)
+        .await?;
+    while let Some(legacy_index) = iter.next_item().await {
+        legacy_indexes.push(legacy_index);
+    }
+    drop(iter);
+
+    for legacy_index in legacy_indexes {
+        let chat_id = match parse_legacy_index(&legacy_index) {
+            Some(chat_id) => chat_id,
+            None => continue,
+        };
+        // 模式同样匹配表情回应等其他键，只迁移有序集合
+        let key_type: String = redis::cmd("TYPE")
+            .arg(&legacy_index)
+            .query_async(con.deref_mut())
+            .await?;
+        if key_type != "zset" {
+            continue;
+        }
+        // 启动时尚未开始服务，读出的首尾消息在迁移前不会变化
+        let first: Vec<(String, MessageID)> = con.zrange_withscores(&legacy_index, 0, 0).await?;
+        let last: Vec<(String, MessageID)> = con.zrange_withscores(&legacy_index, -1, -1).await?;
+        let (first_bucket, last_bucket) = match (first.first(), last.first()) {
+            (Some(first), Some(last)) => (
+                index::get_msg_bucket(first.1),
+                index::get_msg_bucket(last.1),
+            ),
+            _ => continue,
+        };
+        let mut invocation = script::SPLIT_MESSAGES.key(&legacy_index);
+        for bucket in first_bucket..=last_bucket {
+            invocation.key(index::get_chat_msgs_index(chat_id, bucket).as_str());
+        }
+        let msg_num: i64 = invocation
+            .arg(index::MSG_BUCKET_SIZE)
+            .arg(first_bucket)
+            .invoke_async(con.deref_mut())
+            .await?;
+        if msg_num < 0 {
+            return Err(DatabaseError::Conflict(legacy_index));
+        }
+        info!("聊天{}的{}条消息已迁移到分块存储", chat_id, msg_num);
+    }
+
+    con.set::<_, _, ()>(path::MSG_BUCKETS_MIGRATED, 1).await?;
+    return Ok(());
+}
+
+/** `parse_legacy_index` 从`chat:{id}:msgs`中取出聊天ID，格式不符时为None
+ */
+fn parse_legacy_index(legacy_index: &str) -> Option<ChatID> {
+    match legacy_index.split(':').collect::<Vec<_>>()[..] {
+        ["chat", chat_id, "msgs"] => return chat_id.parse().ok(),
+        _ => return None,
+    }
+}
+
+pub async fn get_con() -> Result<MobcCon, DatabaseError> {
+    return Ok(POOL.get().unwrap().get().await?);
+}
+
+pub async fn flush_database() -> Result<(), DatabaseError> {
+    let mut con = get_con().await?;
+    redis::cmd("FLUSHALL")
+        .query_async::<_, ()>(con.deref_mut())
+        .await
+        .map_err(DatabaseError::from)
 }
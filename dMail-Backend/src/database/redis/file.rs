@@ -1,5 +1,6 @@
 use redis::AsyncCommands;
 
+use crate::database::DatabaseError;
 use crate::{
     config::datatype::{SerializedFilePubUrl, UploadId},
     oss::{ObjectUploadRequest, PresignUrl},
@@ -11,11 +12,11 @@ use super::{
 };
 
 pub async fn get_file_public_url(
-    hash: &String,
-) -> Result<Option<(PresignUrl, SerializedFilePubUrl)>, ()> {
+    hash: &str,
+) -> Result<Option<(PresignUrl, SerializedFilePubUrl)>, DatabaseError> {
     let mut con = get_con().await?;
 
-    let serialized_opt: Option<String> = con.hget(FILE_URL, hash).await.map_err(|_| ())?;
+    let serialized_opt: Option<String> = con.hget(FILE_URL, hash).await?;
 
     return Ok(serialized_opt.map(|serialized| {
         (
@@ -25,44 +26,40 @@ pub async fn get_file_public_url(
     }));
 }
 
-pub async fn write_file_public_url(hash: &String, pub_url: &PresignUrl) -> Result<(), ()> {
+pub async fn write_file_public_url(hash: &str, pub_url: &PresignUrl) -> Result<(), DatabaseError> {
     let serialized = serde_json::to_string(pub_url).unwrap();
 
     let mut con = get_con().await?;
-    con.hset(FILE_URL, hash, &serialized)
-        .await
-        .map_err(|_| ())?;
+    con.hset(FILE_URL, hash, &serialized).await?;
     return Ok(());
 }
 
-pub async fn write_upload_request(req: ObjectUploadRequest) -> Result<UploadId, ()> {
+pub async fn write_upload_request(req: ObjectUploadRequest) -> Result<UploadId, DatabaseError> {
     let mut con = get_con().await?;
 
-    let upload_id: UploadId = con.incr(LAST_UPLOAD_REQ_ID, 1).await.map_err(|_| ())?;
+    let upload_id: UploadId = con.incr(LAST_UPLOAD_REQ_ID, 1).await?;
 
     con.hset(
         FILE_UPLOAD_HASH,
         upload_id,
         serde_json::to_string(&req).unwrap(),
     )
-    .await
-    .map_err(|_| ())?;
+    .await?;
 
     return Ok(upload_id);
 }
 
-pub async fn get_upload_request(upload_id: UploadId) -> Result<Option<ObjectUploadRequest>, ()> {
+pub async fn get_upload_request(
+    upload_id: UploadId,
+) -> Result<Option<ObjectUploadRequest>, DatabaseError> {
     let mut con = get_con().await?;
 
-    let serialized_opt: Option<String> = con
-        .hget(FILE_UPLOAD_HASH, upload_id)
-        .await
-        .map_err(|_| ())?;
+    let serialized_opt: Option<String> = con.hget(FILE_UPLOAD_HASH, upload_id).await?;
 
     return Ok(serialized_opt.map(|serialized| serde_json::from_str(&serialized).unwrap()));
 }
 
-pub async fn get_file_url(hash: &String) -> Result<SerializedFilePubUrl, ()> {
+pub async fn get_file_url(hash: &str) -> Result<SerializedFilePubUrl, DatabaseError> {
     let mut con = get_con().await?;
-    con.hget(FILE_URL, hash).await.map_err(|_| ())
+    con.hget(FILE_URL, hash).await.map_err(DatabaseError::from)
 }
@@ -1,4 +1,5 @@
-use crate::config::datatype::{ChatID, UserID, UserReqId};
+use crate::config::datatype::{ChatID, MessageID, UserID, UserReqId};
+use sha2::{Digest, Sha256};
 use smartstring::alias::String;
 use std::fmt::Write;
 
@@ -8,6 +9,12 @@ pub fn get_user_info_index(user_id: UserID) -> String {
     return str;
 }
 
+pub fn get_user_scheduled_index(user_id: UserID) -> String {
+    let mut str: String = String::new();
+    write!(str, "schedule:user:{}", user_id).ok();
+    return str;
+}
+
 pub fn get_user_setting_index(user_id: UserID) -> String {
     let mut str: String = String::new();
     write!(str, "user:{}:setting", user_id).ok();
@@ -68,9 +75,93 @@ pub fn get_chat_users_index(chat_id: ChatID) -> String {
     return str;
 }
 
-pub fn get_chat_msgs_index(chat_id: ChatID) -> String {
+/// 每个消息分块存放的消息数量，按`in_chat_id`划分，修改后已有的分块将无法正确读取
+pub const MSG_BUCKET_SIZE: MessageID = 1024;
+
+pub fn get_msg_bucket(in_chat_id: MessageID) -> MessageID {
+    return in_chat_id.saturating_sub(1) / MSG_BUCKET_SIZE;
+}
+
+pub fn get_chat_msgs_index(chat_id: ChatID, bucket: MessageID) -> String {
+    let mut str: String = String::new();
+    write!(str, "chat:{}:msgs:{}", chat_id, bucket).ok();
+    return str;
+}
+
+pub fn get_chat_expired_index(chat_id: ChatID) -> String {
+    let mut str: String = String::new();
+    write!(str, "chat:{}:expired", chat_id).ok();
+    return str;
+}
+
+pub fn get_chat_pruned_index(chat_id: ChatID) -> String {
     let mut str: String = String::new();
-    write!(str, "chat:{}:msgs", chat_id).ok();
+    write!(str, "chat:{}:pruned", chat_id).ok();
+    return str;
+}
+
+pub fn get_chat_edits_index(chat_id: ChatID, in_chat_id: MessageID) -> String {
+    let mut str: String = String::new();
+    write!(str, "chat:{}:edits:{}", chat_id, in_chat_id).ok();
+    return str;
+}
+
+pub fn get_chat_reacted_index(chat_id: ChatID) -> String {
+    let mut str: String = String::new();
+    write!(str, "chat:{}:reacted", chat_id).ok();
+    return str;
+}
+
+pub fn get_msg_reactions_index(chat_id: ChatID, in_chat_id: MessageID) -> String {
+    let mut str: String = String::new();
+    write!(str, "chat:{}:reactions:{}", chat_id, in_chat_id).ok();
+    return str;
+}
+
+/// 消息的全部表情回应，字段为`{user_id}:{emoji}`，表情只出现在字段中而不拼入键名
+pub fn get_msg_reaction_users_index(chat_id: ChatID, in_chat_id: MessageID) -> String {
+    let mut str: String = String::new();
+    write!(str, "chat:{}:reactions:{}:users", chat_id, in_chat_id).ok();
+    return str;
+}
+
+pub fn get_msg_reaction_users_field(user_id: UserID, emoji: &str) -> String {
+    let mut str: String = String::new();
+    write!(str, "{}:{}", user_id, emoji).ok();
+    return str;
+}
+
+pub fn get_chat_threads_index(chat_id: ChatID) -> String {
+    let mut str: String = String::new();
+    write!(str, "chat:{}:threads", chat_id).ok();
+    return str;
+}
+
+pub fn get_chat_reply_counts_index(chat_id: ChatID) -> String {
+    let mut str: String = String::new();
+    write!(str, "chat:{}:reply_counts", chat_id).ok();
+    return str;
+}
+
+pub fn get_chat_thread_index(chat_id: ChatID, root_id: MessageID) -> String {
+    let mut str: String = String::new();
+    write!(str, "chat:{}:thread:{}", chat_id, root_id).ok();
+    return str;
+}
+
+pub fn get_chat_pinned_index(chat_id: ChatID) -> String {
+    let mut str: String = String::new();
+    write!(str, "chat:{}:pinned", chat_id).ok();
+    return str;
+}
+
+/// 词项来自用户输入，键名中只使用词项的SHA-256
+pub fn get_chat_search_index(chat_id: ChatID, token: &str) -> String {
+    let mut str: String = String::new();
+    write!(str, "chat:{}:search:", chat_id).ok();
+    for byte in Sha256::digest(token.as_bytes()).iter().take(16) {
+        write!(str, "{:02x}", byte).ok();
+    }
     return str;
 }
 
@@ -116,9 +207,9 @@ pub fn get_chat_last_notice_id_index(chat_id: ChatID) -> String {
     return str;
 }
 
-pub fn get_user_token_index(user_id: UserID) -> String {
+pub fn get_user_tokens_index(user_id: UserID) -> String {
     let mut str: String = String::new();
-    write!(str, "user:{}:token", user_id).ok();
+    write!(str, "user:{}:tokens", user_id).ok();
     return str;
 }
 
@@ -128,6 +219,12 @@ pub fn get_user_exist_index(user_id: UserID) -> String {
     return str;
 }
 
+pub fn get_user_device_keys_index(user_id: UserID) -> String {
+    let mut str: String = String::new();
+    write!(str, "user:{}:device_keys", user_id).ok();
+    return str;
+}
+
 pub fn get_user_pre_join_index(user_id: UserID) -> String {
     let mut str: String = String::new();
     write!(str, "user:{}:pre_join", user_id).ok();
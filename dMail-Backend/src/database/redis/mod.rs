@@ -1,11 +1,14 @@
+mod backend;
 mod chat;
 mod common;
 mod file;
 mod index;
 mod path;
 mod request;
+mod script;
 mod user;
 
+pub use backend::*;
 pub use chat::*;
 pub use common::*;
 pub use file::*;
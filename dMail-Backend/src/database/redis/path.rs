@@ -20,3 +20,30 @@ pub const FILE_URL: &str = "file:url";
 pub const NAME_ID: &str = "user:name_to_id";
 
 pub const INVITAION_MAP: &str = "Invitations";
+
+// 分块存储之前整个聊天的消息列表，启动时迁移到分块中
+// 该模式也会匹配到其他以msgs结尾的键，迁移前需要检查键的格式与类型
+pub const LEGACY_CHAT_MSGS_PATTERN: &str = "chat:*:msgs";
+
+// 消息列表已迁移到分块存储的标记，存在时启动不再扫描旧的消息列表
+pub const MSG_BUCKETS_MIGRATED: &str = "migration:msg_buckets";
+
+pub const LAST_SCHEDULE_ID: &str = "schedule:last_id";
+
+// 按投递时间排序的全部定时消息ID，有序集合
+pub const SCHEDULE_QUEUE: &str = "schedule:queue";
+
+// 定时消息ID到序列化定时消息的哈希表
+pub const SCHEDULE_MSGS: &str = "schedule:msgs";
+
+// 定时消息ID到发送者ID的哈希表
+pub const SCHEDULE_SENDERS: &str = "schedule:senders";
+
+// 开启了阅后即焚的聊天ID到消息存活时间的哈希表
+pub const DISAPPEARING_CHATS: &str = "chat:disappearing";
+
+// 用户ID到最后在线时间的哈希表
+pub const USER_LAST_SEEN: &str = "user:last_seen";
+
+// 隐藏了在线状态的用户ID集合
+pub const PRESENCE_HIDDEN_USERS: &str = "user:presence_hidden";
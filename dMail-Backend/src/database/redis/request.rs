@@ -9,16 +9,17 @@ use super::path;
 use crate::config::datatype::ChatID;
 use crate::config::datatype::SerializedRequest;
 use crate::config::datatype::UserReqId;
+use crate::database::DatabaseError;
 use crate::{config::datatype::UserID, user::*};
 
 pub async fn write_user_request(
     sender_id: UserID,
     data: UserSendRequestData,
     handler: &UserRequestHandler,
-) -> Result<(SerializedRequest, UserRequestInfo), ()> {
+) -> Result<(SerializedRequest, UserRequestInfo), DatabaseError> {
     let mut con = get_con().await?;
 
-    let req_id: UserReqId = con.incr(path::LAST_REQ_ID, 1).await.map_err(|_| ())?;
+    let req_id: UserReqId = con.incr(path::LAST_REQ_ID, 1).await?;
 
     let req_info = UserRequestInfo {
         req_id,
@@ -30,8 +31,7 @@ pub async fn write_user_request(
     let serialized_info = serde_json::to_string(&req_info).unwrap();
 
     con.set::<_, _, ()>(index::get_req_info_index(req_id).as_str(), &serialized_info)
-        .await
-        .map_err(|_| ())?;
+        .await?;
 
     let mut pipeline = redis::pipe();
 
@@ -66,10 +66,7 @@ pub async fn write_user_request(
         )
         .ignore();
 
-    pipeline
-        .query_async::<_, ()>(con.deref_mut())
-        .await
-        .map_err(|_| ())?;
+    pipeline.query_async::<_, ()>(con.deref_mut()).await?;
 
     return Ok((
         format!(r#"{{"info":{},"state":"Unsolved"}}"#, serialized_info),
@@ -77,22 +74,21 @@ pub async fn write_user_request(
     ));
 }
 
-pub async fn store_user_request(user_id: UserID, req_id: UserReqId) -> Result<(), ()> {
+pub async fn store_user_request(user_id: UserID, req_id: UserReqId) -> Result<(), DatabaseError> {
     let mut con = get_con().await?;
     con.zadd(index::get_user_reqs_index(user_id).as_str(), req_id, req_id)
         .await
-        .map_err(|_| ())
+        .map_err(DatabaseError::from)
 }
 
-pub async fn get_user_request(req_id: UserReqId) -> Result<Option<UserRequset>, ()> {
+pub async fn get_user_request(req_id: UserReqId) -> Result<Option<UserRequset>, DatabaseError> {
     let mut con = get_con().await?;
 
     let (serialized_info_opt, state_opt): (Option<String>, Option<bool>) = redis::pipe()
         .get(index::get_req_info_index(req_id).as_str())
         .get(index::get_req_state_index(req_id).as_str())
         .query_async(con.deref_mut())
-        .await
-        .map_err(|_| ())?;
+        .await?;
 
     let serialized_info = match serialized_info_opt {
         Some(info) => info,
@@ -151,7 +147,7 @@ pub async fn set_user_request_state(
 pub async fn get_user_requests(
     user_id: UserID,
     start_id: UserReqId,
-) -> Result<Vec<SerializedRequest>, ()> {
+) -> Result<Vec<SerializedRequest>, DatabaseError> {
     let mut con = get_con().await?;
 
     let reqs_id_opt: Option<Vec<UserReqId>> = con
@@ -160,8 +156,7 @@ pub async fn get_user_requests(
             start_id,
             "+inf",
         )
-        .await
-        .map_err(|_| ())?;
+        .await?;
 
     if reqs_id_opt.is_none() {
         return Ok(vec![]);
@@ -176,8 +171,7 @@ pub async fn get_user_requests(
             .get(index::get_req_info_index(req_id).as_str())
             .get(index::get_req_state_index(req_id).as_str())
             .query_async(con.deref_mut())
-            .await
-            .map_err(|_| ())?;
+            .await?;
 
         let serialized_req = match state_opt {
             Some(state_bool) => {
@@ -196,7 +190,10 @@ pub async fn get_user_requests(
     return Ok(reqs);
 }
 
-pub async fn write_friend_request_send(user_one_id: UserID, user_two_id: UserID) -> Result<(), ()> {
+pub async fn write_friend_request_send(
+    user_one_id: UserID,
+    user_two_id: UserID,
+) -> Result<(), DatabaseError> {
     let (id1, id2) = if user_one_id < user_two_id {
         (user_one_id, user_two_id)
     } else {
@@ -210,8 +207,7 @@ pub async fn write_friend_request_send(user_one_id: UserID, user_two_id: UserID)
         index::get_friend_pair_index(id1, id2).as_str(),
         0,
     )
-    .await
-    .map_err(|_| ())?;
+    .await?;
 
     return Ok(());
 }
@@ -219,7 +215,7 @@ pub async fn write_friend_request_send(user_one_id: UserID, user_two_id: UserID)
 pub async fn delete_friend_request_send(
     user_one_id: UserID,
     user_two_id: UserID,
-) -> Result<(), ()> {
+) -> Result<(), DatabaseError> {
     let (id1, id2) = if user_one_id < user_two_id {
         (user_one_id, user_two_id)
     } else {
@@ -232,26 +228,29 @@ pub async fn delete_friend_request_send(
         path::FRIEND_CHAT_MAP,
         index::get_friend_pair_index(id1, id2).as_str(),
     )
-    .await
-    .map_err(|_| ())?;
+    .await?;
 
     return Ok(());
 }
 
-pub async fn write_join_group_request_send(user_id: UserID, chat_id: ChatID) -> Result<(), ()> {
+pub async fn write_join_group_request_send(
+    user_id: UserID,
+    chat_id: ChatID,
+) -> Result<(), DatabaseError> {
     let mut con = get_con().await?;
     let _: bool = con
         .sadd(index::get_user_pre_join_index(user_id).as_str(), chat_id)
-        .await
-        .map_err(|_| ())?;
+        .await?;
     return Ok(());
 }
 
-pub async fn delete_join_group_request_send(user_id: UserID, chat_id: ChatID) -> Result<(), ()> {
+pub async fn delete_join_group_request_send(
+    user_id: UserID,
+    chat_id: ChatID,
+) -> Result<(), DatabaseError> {
     let mut con = get_con().await?;
     con.srem(index::get_user_pre_join_index(user_id).as_str(), chat_id)
-        .await
-        .map_err(|_| ())?;
+        .await?;
     return Ok(());
 }
 
@@ -259,7 +258,7 @@ pub async fn write_invite_request_send(
     inviter_id: UserID,
     receiver_id: UserID,
     chat_id: ChatID,
-) -> Result<(), ()> {
+) -> Result<(), DatabaseError> {
     let mut con = get_con().await?;
     let _: bool = con
         .hset(
@@ -267,8 +266,7 @@ pub async fn write_invite_request_send(
             format!("{}:{}:{}", inviter_id, receiver_id, chat_id),
             1,
         )
-        .await
-        .map_err(|_| ())?;
+        .await?;
     return Ok(());
 }
 
@@ -276,13 +274,12 @@ pub async fn delete_invite_request_send(
     inviter_id: UserID,
     receiver_id: UserID,
     chat_id: ChatID,
-) -> Result<(), ()> {
+) -> Result<(), DatabaseError> {
     let mut con = get_con().await?;
     con.hdel(
         path::INVITAION_MAP,
         format!("{}:{}:{}", inviter_id, receiver_id, chat_id),
     )
-    .await
-    .map_err(|_| ())?;
+    .await?;
     return Ok(());
 }
@@ -0,0 +1,412 @@
+/*!
+ * 需要原子执行的多键操作
+ * 这些操作以Lua脚本的形式在服务端执行，脚本执行期间不会穿插其他客户端的命令，因此后端需要支持EVAL
+ * 脚本用到的键全部经由KEYS传入，键名由`index`在调用处生成，脚本中不拼接键名
+ * 键名取决于脚本中才能确定的值（如新分配的ID）时，调用处按事先读到的值传入键，
+ * 脚本确认该值未变后再写入，否则不做任何写入并返回冲突，由调用处重新读取后重试
+ * 脚本均先完成全部检查再开始写入，不会留下写了一半的数据
+*/
+
+use mobc_redis::redis::Script;
+use once_cell::sync::Lazy;
+
+/** `MAX_RETRY_NUM` 脚本返回冲突时重新读取并重试的最大次数
+*/
+pub const MAX_RETRY_NUM: usize = 8;
+
+/** `WRITE_MESSAGE` 分配群内消息ID，写入消息所在的分块，并将消息加入搜索索引
+ * 消息为回复时同时记录回复，并增加被回复消息的回复数量
+ * KEYS: 聊天的last_id, 新消息所在的分块,
+ *       (仅回复时)回复root_id的消息列表, 聊天中有回复的消息, 聊天中各条消息的回复数量,
+ *       消息每个词项的搜索索引
+ * ARGV: 序列化消息在inChatId之前的部分, inChatId之后的部分, 分块大小, 传入的分块编号, root_id(不是回复时为0)
+ * 返回: {in_chat_id, 序列化后的消息}，新消息不在传入的分块中时为{0, ''}
+ */
+pub static WRITE_MESSAGE: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+local next_id = tonumber(redis.call('GET', KEYS[1]) or '0') + 1
+if math.floor((next_id - 1) / tonumber(ARGV[3])) ~= tonumber(ARGV[4]) then
+    return {0, ''}
+end
+local in_chat_id = redis.call('INCR', KEYS[1])
+local msg = ARGV[1] .. in_chat_id .. ARGV[2]
+redis.call('ZADD', KEYS[2], in_chat_id, msg)
+local first_token = 3
+if ARGV[5] ~= '0' then
+    redis.call('RPUSH', KEYS[3], in_chat_id)
+    redis.call('ZADD', KEYS[4], ARGV[5], ARGV[5])
+    redis.call('HINCRBY', KEYS[5], ARGV[5], 1)
+    first_token = 6
+end
+for i = first_token, #KEYS do
+    redis.call('ZADD', KEYS[i], in_chat_id, in_chat_id)
+end
+return {in_chat_id, msg}
+",
+    )
+});
+
+/** `EDIT_MESSAGE` 消息仍为编辑前的版本时将其替换，原消息写入编辑历史并更新搜索索引
+ * KEYS: 消息所在的分块, 消息的编辑历史, n个编辑前词项的搜索索引, 编辑后全部词项的搜索索引
+ * ARGV: in_chat_id, 编辑前的消息, 编辑后的消息, 编辑前的词项数n
+ * 返回: 1为成功，0为消息已被撤回、清理或再次编辑
+ */
+pub static EDIT_MESSAGE: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+local msgs = redis.call('ZRANGEBYSCORE', KEYS[1], ARGV[1], ARGV[1])
+if #msgs ~= 1 or msgs[1] ~= ARGV[2] then
+    return 0
+end
+redis.call('ZREMRANGEBYSCORE', KEYS[1], ARGV[1], ARGV[1])
+redis.call('ZADD', KEYS[1], ARGV[1], ARGV[3])
+redis.call('RPUSH', KEYS[2], ARGV[2])
+local old_num = tonumber(ARGV[4])
+for i = 3, 2 + old_num do
+    redis.call('ZREM', KEYS[i], ARGV[1])
+end
+for i = 3 + old_num, #KEYS do
+    redis.call('ZADD', KEYS[i], ARGV[1], ARGV[1])
+end
+return 1
+",
+    )
+});
+
+/** `ADD_REACTION` 用户添加表情回应，同时更新回应人数与有回应的消息列表
+ * KEYS: 消息的表情回应, 消息的回应人数, 聊天中有回应的消息
+ * ARGV: 表情回应的字段, 表情回应, in_chat_id
+ * 返回: 1为成功，0为已添加过
+ */
+pub static ADD_REACTION: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+if redis.call('HSETNX', KEYS[1], ARGV[1], 1) == 0 then
+    return 0
+end
+redis.call('HINCRBY', KEYS[2], ARGV[2], 1)
+redis.call('ZADD', KEYS[3], ARGV[3], ARGV[3])
+return 1
+",
+    )
+});
+
+/** `REMOVE_REACTION` 用户取消表情回应，人数归零的回应与没有回应的消息一并删除
+ * KEYS: 消息的表情回应, 消息的回应人数, 聊天中有回应的消息
+ * ARGV: 表情回应的字段, 表情回应, in_chat_id
+ * 返回: 1为成功，0为未添加过
+ */
+pub static REMOVE_REACTION: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+if redis.call('HDEL', KEYS[1], ARGV[1]) == 0 then
+    return 0
+end
+if redis.call('HINCRBY', KEYS[2], ARGV[2], -1) <= 0 then
+    redis.call('HDEL', KEYS[2], ARGV[2])
+end
+if redis.call('HLEN', KEYS[2]) == 0 then
+    redis.call('ZREM', KEYS[3], ARGV[3])
+end
+return 1
+",
+    )
+});
+
+/** `CLEAR_REACTIONS` 删除一组消息的全部表情回应
+ * KEYS: 聊天中有回应的消息, 每条消息的回应人数与表情回应
+ * ARGV: 各条消息的in_chat_id
+ * 返回: 删除了回应的消息数量
+ */
+pub static CLEAR_REACTIONS: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+for i = 2, #KEYS do
+    redis.call('DEL', KEYS[i])
+end
+for _, id in ipairs(ARGV) do
+    redis.call('ZREM', KEYS[1], id)
+end
+return #ARGV
+",
+    )
+});
+
+/** `CLEAR_THREADS` 删除一组消息收到的全部回复记录与回复数量
+ * KEYS: 聊天中有回复的消息, 聊天中各条消息的回复数量, 每条消息的回复列表
+ * ARGV: 各条消息的in_chat_id
+ * 返回: 删除了回复记录的消息数量
+ */
+pub static CLEAR_THREADS: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+for i, id in ipairs(ARGV) do
+    redis.call('DEL', KEYS[2 + i])
+    redis.call('HDEL', KEYS[2], id)
+    redis.call('ZREM', KEYS[1], id)
+end
+return #ARGV
+",
+    )
+});
+
+/** `ADD_SCHEDULED_MESSAGE` 保存一条定时消息
+ * KEYS: 定时消息队列, 定时消息, 定时消息的发送者, 用户的定时消息
+ * ARGV: schedule_id, 投递时间, 序列化的定时消息, 发送者ID
+ */
+pub static ADD_SCHEDULED_MESSAGE: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+redis.call('ZADD', KEYS[1], ARGV[2], ARGV[1])
+redis.call('HSET', KEYS[2], ARGV[1], ARGV[3])
+redis.call('HSET', KEYS[3], ARGV[1], ARGV[4])
+redis.call('ZADD', KEYS[4], ARGV[2], ARGV[1])
+return 1
+",
+    )
+});
+
+/** `CANCEL_SCHEDULED_MESSAGE` 取消用户的一条定时消息
+ * KEYS: 用户的定时消息, 定时消息队列, 定时消息, 定时消息的发送者
+ * ARGV: schedule_id
+ * 返回: 定时消息不存在或不属于该用户时为0，否则为1
+ */
+pub static CANCEL_SCHEDULED_MESSAGE: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+if redis.call('ZREM', KEYS[1], ARGV[1]) == 0 then
+    return 0
+end
+redis.call('ZREM', KEYS[2], ARGV[1])
+redis.call('HDEL', KEYS[3], ARGV[1])
+redis.call('HDEL', KEYS[4], ARGV[1])
+return 1
+",
+    )
+});
+
+/** `SET_DISAPPEARING_TTL` 设置聊天中消息的存活时间，从关闭变为开启时之前的消息不会过期
+ * KEYS: 开启了阅后即焚的聊天, 聊天的last_id, 聊天已完成过期检查的最后一条消息
+ * ARGV: chat_id, 存活时间（秒），为0时关闭
+ */
+pub static SET_DISAPPEARING_TTL: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+if ARGV[2] == '0' then
+    redis.call('HDEL', KEYS[1], ARGV[1])
+    return 0
+end
+if redis.call('HEXISTS', KEYS[1], ARGV[1]) == 0 then
+    redis.call('SET', KEYS[3], redis.call('GET', KEYS[2]) or 0)
+end
+redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+return 1
+",
+    )
+});
+
+/** `SPLIT_MESSAGES` 将分块存储之前的整个消息列表拆分到各个分块中
+ * KEYS: 旧的消息列表, 从第一个分块起连续的各个分块
+ * ARGV: 分块大小, 第一个分块的编号
+ * 返回: 迁移的消息数量，消息不在传入的分块中时为-1
+ */
+pub static SPLIT_MESSAGES: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+local msgs = redis.call('ZRANGE', KEYS[1], 0, -1, 'WITHSCORES')
+for i = 1, #msgs, 2 do
+    local bucket = math.floor((tonumber(msgs[i + 1]) - 1) / tonumber(ARGV[1]))
+    if not KEYS[2 + bucket - tonumber(ARGV[2])] then
+        return -1
+    end
+end
+for i = 1, #msgs, 2 do
+    local in_chat_id = tonumber(msgs[i + 1])
+    local bucket = math.floor((in_chat_id - 1) / tonumber(ARGV[1]))
+    redis.call('ZADD', KEYS[2 + bucket - tonumber(ARGV[2])], in_chat_id, msgs[i])
+end
+redis.call('DEL', KEYS[1])
+return #msgs / 2
+",
+    )
+});
+
+/** `USER_REGISTER` 检查邮箱未被注册，分配用户ID并写入用户数据
+ * KEYS: 邮箱到ID的映射, 最后一个用户ID, 用户名到ID的映射, 新用户的信息, 密码, 是否存在, 邮箱
+ * ARGV: 邮箱, 用户名, 密码, 用户信息在userId之前的部分, userId之后的部分, 传入的新用户ID
+ * 返回: 邮箱已被注册时为0，新用户ID与传入的不同时为-1，否则为新用户的ID
+ */
+pub static USER_REGISTER: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+if redis.call('HEXISTS', KEYS[1], ARGV[1]) == 1 then
+    return 0
+end
+if tonumber(redis.call('GET', KEYS[2]) or '0') + 1 ~= tonumber(ARGV[6]) then
+    return -1
+end
+local user_id = redis.call('INCR', KEYS[2])
+redis.call('SET', KEYS[4], ARGV[4] .. user_id .. ARGV[5])
+redis.call('SET', KEYS[5], ARGV[3])
+redis.call('SET', KEYS[6], 1)
+redis.call('SET', KEYS[7], ARGV[1])
+redis.call('HSET', KEYS[1], ARGV[1], user_id)
+local ids = {}
+local serialized_ids = redis.call('HGET', KEYS[3], ARGV[2])
+if serialized_ids then
+    ids = cjson.decode(serialized_ids)
+end
+table.insert(ids, user_id)
+redis.call('HSET', KEYS[3], ARGV[2], cjson.encode(ids))
+return user_id
+",
+    )
+});
+
+/** `USER_LOG_OFF` 注销用户：解除好友、退出群聊、抹去用户名并标记为不存在
+ * KEYS: 邮箱到ID的映射, 用户邮箱, 用户信息, 用户是否存在, 用户名到ID的映射, 好友对到聊天的映射, 用户的聊天列表,
+ *       n组(好友的聊天列表, 私聊的两个成员), m组(群主, 群成员列表, 管理员列表)
+ * ARGV: 用户ID, 注销后的用户名, 好友数n, n组(私聊ID, 好友对), 群聊数m, m个群聊ID
+ * 返回: 1为成功，0为用户不存在，-1为用户是某个群的群主，-2为用户的聊天列表在读取后发生了变化
+ */
+pub static USER_LOG_OFF: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+local user_id = ARGV[1]
+local friend_num = tonumber(ARGV[3])
+local group_start = 4 + friend_num * 2
+local group_num = tonumber(ARGV[group_start])
+local group_keys = 8 + friend_num * 3
+
+local email = redis.call('GET', KEYS[2])
+if not email or redis.call('HEXISTS', KEYS[1], email) == 0 then
+    return 0
+end
+-- 传入的好友与群聊须恰好是用户当前的全部聊天
+local chats = {}
+for i = 0, friend_num - 1 do
+    chats[ARGV[4 + i * 2]] = true
+end
+for i = 1, group_num do
+    chats[ARGV[group_start + i]] = true
+end
+local current_chats = redis.call('HKEYS', KEYS[7])
+if #current_chats ~= friend_num + group_num then
+    return -2
+end
+for _, chat_id in ipairs(current_chats) do
+    if not chats[chat_id] then
+        return -2
+    end
+end
+for i = 0, group_num - 1 do
+    if redis.call('GET', KEYS[group_keys + i * 3]) == user_id then
+        return -1
+    end
+end
+local info = cjson.decode(redis.call('GET', KEYS[3]))
+local old_name = info['userName']
+
+redis.call('HDEL', KEYS[1], email)
+redis.call('DEL', KEYS[2])
+for i = 0, friend_num - 1 do
+    local chat_id = ARGV[4 + i * 2]
+    redis.call('HDEL', KEYS[6], ARGV[5 + i * 2])
+    redis.call('HDEL', KEYS[7], chat_id)
+    redis.call('HDEL', KEYS[8 + i * 3], chat_id)
+    redis.call('DEL', KEYS[9 + i * 3], KEYS[10 + i * 3])
+end
+for i = 0, group_num - 1 do
+    redis.call('SREM', KEYS[group_keys + i * 3 + 1], user_id)
+    redis.call('SREM', KEYS[group_keys + i * 3 + 2], user_id)
+    redis.call('HDEL', KEYS[7], ARGV[group_start + 1 + i])
+end
+
+info['userName'] = ARGV[2]
+redis.call('SET', KEYS[3], cjson.encode(info))
+local serialized_ids = redis.call('HGET', KEYS[5], old_name)
+if serialized_ids then
+    local ids = {}
+    for _, id in ipairs(cjson.decode(serialized_ids)) do
+        if id ~= tonumber(user_id) then
+            table.insert(ids, id)
+        end
+    end
+    if #ids == 0 then
+        redis.call('HSET', KEYS[5], old_name, '[]')
+    else
+        redis.call('HSET', KEYS[5], old_name, cjson.encode(ids))
+    end
+end
+redis.call('SET', KEYS[4], 0)
+return 1
+",
+    )
+});
+
+/** `UPDATE_PASSWORD` 修改密码，同时撤销全部设备上的令牌
+ * KEYS: 用户的密码, 用户的令牌
+ * ARGV: 新密码
+ */
+pub static UPDATE_PASSWORD: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+redis.call('SET', KEYS[1], ARGV[1])
+redis.call('DEL', KEYS[2])
+return 1
+",
+    )
+});
+
+/** `REPLACE_TOKEN` 设备上的令牌仍为换取前的令牌时替换为新的令牌
+ * KEYS: 用户的令牌
+ * ARGV: 设备ID, 换取前的令牌, 新的令牌
+ * 返回: 1为成功，0为令牌已被换取、撤销或重新申请
+ */
+pub static REPLACE_TOKEN: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+if redis.call('HGET', KEYS[1], ARGV[1]) ~= ARGV[2] then
+    return 0
+end
+redis.call('HSET', KEYS[1], ARGV[1], ARGV[3])
+return 1
+",
+    )
+});
+
+/** `REPLACE_LEGACY_PASSWORD` 存储的密码仍为旧版明文时替换为哈希
+ * KEYS: 用户的密码
+ * ARGV: 旧版明文密码, 密码的哈希
+ * 返回: 1为成功，0为期间密码已被修改
+ */
+pub static REPLACE_LEGACY_PASSWORD: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+if redis.call('GET', KEYS[1]) ~= ARGV[1] then
+    return 0
+end
+redis.call('SET', KEYS[1], ARGV[2])
+return 1
+",
+    )
+});
+
+/** `OWNER_TRANSFER` 转让群主，新群主同时成为管理员
+ * KEYS: 群主, 管理员列表, 群成员列表
+ * ARGV: 新群主ID
+ * 返回: 1为成功，0为新群主不在群内
+ */
+pub static OWNER_TRANSFER: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+if redis.call('SISMEMBER', KEYS[3], ARGV[1]) == 0 then
+    return 0
+end
+redis.call('SET', KEYS[1], ARGV[1])
+redis.call('SADD', KEYS[2], ARGV[1])
+return 1
+",
+    )
+});
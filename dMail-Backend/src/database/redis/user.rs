@@ -1,77 +1,66 @@
 use std::ops::DerefMut;
 
-use chrono::Utc;
-use log::debug;
+use log::{debug, error};
 use mobc_redis::redis;
 use mobc_redis::redis::AsyncCommands;
-use uuid::Uuid;
 
-use super::check_user_is_owner;
 use super::common::*;
 use super::get_chat_user_list;
 use super::index;
 use super::path;
-use super::quit_group_chat;
+use super::script;
 
 use crate::chat::ChatMembers;
 use crate::config::datatype::ChatID;
+use crate::config::datatype::DeviceID;
 use crate::config::datatype::MessageID;
 use crate::config::datatype::SerializedUserNotice;
 use crate::config::datatype::Timestamp;
-use crate::config::Config;
+use crate::database::DatabaseError;
 
+use crate::utils::password::{self, PasswordCheck};
 use crate::{config::datatype::UserID, user::*};
 
-pub async fn user_register(data: UserRegisterData) -> Result<UserRegisterResponse, ()> {
+pub async fn user_register(data: UserRegisterData) -> Result<UserRegisterResponse, DatabaseError> {
     let mut con = get_con().await?;
 
-    let email_existed: bool = con
-        .hexists(path::USER_EMAIL_MAP, &data.email)
-        .await
-        .map_err(|_| ())?;
-
-    if email_existed {
-        return Ok(UserRegisterResponse::EmailRegistered);
+    // 检查邮箱与写入在同一脚本中完成，同一邮箱的并发注册只有一个能成功
+    let info_tail = format!(r#","userName":"{}","avaterHash":""}}"#, data.user_name);
+    // 新用户的键取决于新分配的ID，ID在读取后被其他注册占用时重试
+    for _ in 0..script::MAX_RETRY_NUM {
+        let last_user_id: Option<UserID> = con.get(path::LAST_USER_ID).await?;
+        let user_id = last_user_id.unwrap_or(0) + 1;
+        let result: i64 = script::USER_REGISTER
+            .key(path::USER_EMAIL_MAP)
+            .key(path::LAST_USER_ID)
+            .key(path::NAME_ID)
+            .key(index::get_user_info_index(user_id).as_str())
+            .key(index::get_user_password_index(user_id).as_str())
+            .key(index::get_user_exist_index(user_id).as_str())
+            .key(index::get_user_email_index(user_id).as_str())
+            .arg(&data.email)
+            .arg(&data.user_name)
+            .arg(&data.password)
+            .arg(r#"{"userId":"#)
+            .arg(&info_tail)
+            .arg(user_id)
+            .invoke_async(con.deref_mut())
+            .await?;
+        match result {
+            0 => return Ok(UserRegisterResponse::EmailRegistered),
+            -1 => continue,
+            _ => return Ok(UserRegisterResponse::Success { user_id }),
+        }
     }
-
-    let user_id: UserID = con.incr(path::LAST_USER_ID, 1).await.map_err(|_| ())?;
-
-    let info_serialized = format!(
-        r#"{{"userId":{},"userName":"{}","avaterHash":""}}"#,
-        user_id, data.user_name
-    );
-
-    redis::pipe()
-        .set(
-            index::get_user_info_index(user_id).as_str(),
-            info_serialized,
-        )
-        .ignore()
-        .set(
-            index::get_user_password_index(user_id).as_str(),
-            data.password,
-        )
-        .ignore()
-        .set(index::get_user_exist_index(user_id).as_str(), 1)
-        .ignore()
-        .set(index::get_user_email_index(user_id).as_str(), &data.email)
-        .ignore()
-        .hset(path::USER_EMAIL_MAP, data.email, user_id)
-        .query_async::<_, ()>(con.deref_mut())
-        .await
-        .map_err(|_| ())?;
-
-    add_user_name_id(&data.user_name, user_id).await?;
-    return Ok(UserRegisterResponse::Success { user_id });
+    return Err(DatabaseError::Conflict(path::LAST_USER_ID.to_string()));
 }
 
-pub async fn user_login_with_password(data: &UserLoginData) -> Result<UserLoginResponse, ()> {
+pub async fn user_login_with_password(
+    data: &UserLoginData,
+) -> Result<UserLoginResponse, DatabaseError> {
     let mut con = get_con().await?;
 
-    let user_id_opt: Option<UserID> = con
-        .hget(path::USER_EMAIL_MAP, &data.email)
-        .await
-        .map_err(|_| ())?;
+    let user_id_opt: Option<UserID> = con.hget(path::USER_EMAIL_MAP, &data.email).await?;
 
     let user_id = match user_id_opt {
         Some(id) => id,
@@ -80,32 +69,44 @@ pub async fn user_login_with_password(data: &UserLoginData) -> Result<UserLoginR
 
     let password: String = con
         .get(index::get_user_password_index(user_id).as_str())
-        .await
-        .map_err(|_| ())?;
-
-    if &password != data.password.as_ref().unwrap() {
-        return Ok(UserLoginResponse::PasswordError);
+        .await?;
+
+    match password::spawn_check_password(&password, data.password.as_ref().unwrap()).await {
+        PasswordCheck::Match => {}
+        PasswordCheck::MatchLegacy => {
+            // 旧版明文密码替换为哈希，期间密码已被修改时不替换，失败时下次登录再替换
+            if let Ok(hash) = password::spawn_hash_password(data.password.as_ref().unwrap()).await {
+                if let Err(err) = script::REPLACE_LEGACY_PASSWORD
+                    .key(index::get_user_password_index(user_id).as_str())
+                    .arg(&password)
+                    .arg(hash)
+                    .invoke_async::<_, i64>(con.deref_mut())
+                    .await
+                {
+                    error!("user_login_with_password: 替换旧版明文密码: {}", err);
+                }
+            }
+        }
+        PasswordCheck::Mismatch => return Ok(UserLoginResponse::PasswordError),
     }
 
     return Ok(UserLoginResponse::Success { user_id });
 }
 
-pub async fn get_user_id_by_email(email: &String) -> Result<Option<UserID>, ()> {
+pub async fn get_user_id_by_email(email: &str) -> Result<Option<UserID>, DatabaseError> {
     let mut con = get_con().await?;
 
-    Ok(con
-        .hget(path::USER_EMAIL_MAP, &email)
-        .await
-        .map_err(|_| ())?)
+    Ok(con.hget(path::USER_EMAIL_MAP, &email).await?)
 }
 
-pub async fn get_user_chat_list(user_id: UserID) -> Result<Vec<(ChatID, MessageID)>, ()> {
+pub async fn get_user_chat_list(
+    user_id: UserID,
+) -> Result<Vec<(ChatID, MessageID)>, DatabaseError> {
     let mut con = get_con().await?;
 
     Ok(con
         .hgetall(index::get_user_chats_index(user_id).as_str())
-        .await
-        .map_err(|_| ())?)
+        .await?)
 }
 
 pub async fn get_user_info(user_id: UserID) -> GetUserInfoResponse {
@@ -127,27 +128,19 @@ pub async fn get_user_info(user_id: UserID) -> GetUserInfoResponse {
     }
 }
 
-pub async fn get_user_email(user_id: UserID) -> Result<String, ()> {
-    if let Ok(mut con) = get_con().await {
-        let user_email: Result<String, ()> = con
-            .get(index::get_user_email_index(user_id).as_str())
-            .await
-            .map_err(|_| ());
-        if let Ok(user_email) = user_email {
-            debug!("get_user_email: {}", user_email);
-            return Ok(user_email);
-        } else {
-            return Err(());
-        }
-    } else {
-        return Err(());
-    }
+pub async fn get_user_email(user_id: UserID) -> Result<String, DatabaseError> {
+    let mut con = get_con().await?;
+    let user_email: String = con
+        .get(index::get_user_email_index(user_id).as_str())
+        .await?;
+    debug!("get_user_email: {}", user_email);
+    return Ok(user_email);
 }
 
 pub async fn make_two_users_be_friends(
     user_one_id: UserID,
     user_two_id: UserID,
-) -> Result<ChatID, ()> {
+) -> Result<ChatID, DatabaseError> {
     let mut con = get_con().await?;
 
     let (id1, id2) = if user_one_id < user_two_id {
@@ -156,7 +149,7 @@ pub async fn make_two_users_be_friends(
         (user_two_id, user_one_id)
     };
 
-    let chat_id: ChatID = con.incr(path::LAST_CHAT_ID, 1).await.map_err(|_| ())?;
+    let chat_id: ChatID = con.incr(path::LAST_CHAT_ID, 1).await?;
     let serialized_chat_info = format!(r#"{{"id":{}, "users":[{},{}]}}"#, chat_id, id1, id2);
 
     redis::pipe()
@@ -180,8 +173,7 @@ pub async fn make_two_users_be_friends(
         .hset(index::get_user_chats_index(id2).as_str(), chat_id, 0)
         .ignore()
         .query_async(con.deref_mut())
-        .await
-        .map_err(|_| ())?;
+        .await?;
 
     return Ok(chat_id);
 }
@@ -250,21 +242,15 @@ pub async fn set_user_setting(user_id: UserID, user_setting: String) -> SetSetti
     }
 }
 
-pub async fn get_user_setting(user_id: UserID) -> Result<Option<String>, ()> {
-    if let Ok(mut con) = get_con().await {
-        let user_setting: Result<String, ()> = con
-            .get(index::get_user_setting_index(user_id).as_str())
-            .await
-            .map_err(|_| ());
-        if let Ok(user_setting) = user_setting {
-            debug!("get_user_setting: {}", user_setting);
-            return Ok(Some(user_setting));
-        } else {
-            return Ok(None);
-        }
-    } else {
-        return Err(());
+pub async fn get_user_setting(user_id: UserID) -> Result<Option<String>, DatabaseError> {
+    let mut con = get_con().await?;
+    let user_setting: Option<String> = con
+        .get(index::get_user_setting_index(user_id).as_str())
+        .await?;
+    if let Some(user_setting) = &user_setting {
+        debug!("get_user_setting: {}", user_setting);
     }
+    return Ok(user_setting);
 }
 
 pub async fn update_user_name(user_id: UserID, new_name: String) -> UserUpdateResponse {
@@ -327,16 +313,17 @@ pub async fn update_user_avater(user_id: UserID, new_hash: String) -> UserUpdate
 
 pub async fn update_user_password(user_id: UserID, new_password: String) -> UserUpdateResponse {
     if let Ok(mut con) = get_con().await {
-        let set_result: Result<bool, ()> = con
-            .set(
-                index::get_user_password_index(user_id).as_str(),
-                new_password,
-            )
+        // 修改密码与撤销全部设备上的令牌在同一脚本中完成
+        let update_result: Result<i8, ()> = script::UPDATE_PASSWORD
+            .key(index::get_user_password_index(user_id).as_str())
+            .key(index::get_user_tokens_index(user_id).as_str())
+            .arg(new_password)
+            .invoke_async(con.deref_mut())
             .await
             .map_err(|_| ());
-        match set_result {
-            Ok(_) => UserUpdateResponse::Success,
-            Err(_) => UserUpdateResponse::ServerError,
+        match update_result {
+            Ok(_) => return UserUpdateResponse::Success,
+            Err(_) => return UserUpdateResponse::ServerError,
         }
     } else {
         return UserUpdateResponse::ServerError;
@@ -346,7 +333,7 @@ pub async fn update_user_password(user_id: UserID, new_password: String) -> User
 pub async fn get_chat_id_by_friends(
     user_id1: UserID,
     user_id2: UserID,
-) -> Result<Option<ChatID>, ()> {
+) -> Result<Option<ChatID>, DatabaseError> {
     let (id1, id2) = if user_id1 < user_id2 {
         (user_id1, user_id2)
     } else {
@@ -359,7 +346,7 @@ pub async fn get_chat_id_by_friends(
             index::get_friend_pair_index(id1, id2).as_str(),
         )
         .await
-        .map_err(|_| ());
+        .map_err(DatabaseError::from);
 }
 
 pub async fn unfriend(user_id: UserID, friend_id: UserID) -> UserUnfriendResponse {
@@ -437,28 +424,27 @@ pub async fn set_user_already_read(
     }
 }
 
-pub async fn check_user_in_chat(user_id: UserID, chat_id: ChatID) -> Result<bool, ()> {
+pub async fn check_user_in_chat(user_id: UserID, chat_id: ChatID) -> Result<bool, DatabaseError> {
     let mut con = get_con().await?;
 
     con.hexists(index::get_user_chats_index(user_id).as_str(), chat_id)
         .await
-        .map_err(|_| ())
+        .map_err(DatabaseError::from)
 }
 
 pub async fn write_user_notice(
     user_id: UserID,
     timestamp: Timestamp,
     serialized: &SerializedUserNotice,
-) -> Result<(), ()> {
-    let mut con = get_con().await.map_err(|_| ())?;
+) -> Result<(), DatabaseError> {
+    let mut con = get_con().await?;
 
     con.zadd(
         index::get_user_notice_index(user_id).as_str(),
         serialized,
         timestamp,
     )
-    .await
-    .map_err(|_| ())?;
+    .await?;
 
     return Ok(());
 }
@@ -469,8 +455,8 @@ const TIMESTAMP_INF: Timestamp = 1000000000000000000;
 pub async fn get_user_notice(
     user_id: UserID,
     start_timestamp: Timestamp,
-) -> Result<Vec<SerializedUserNotice>, ()> {
-    let mut con = get_con().await.map_err(|_| ())?;
+) -> Result<Vec<SerializedUserNotice>, DatabaseError> {
+    let mut con = get_con().await?;
 
     let res: Vec<SerializedUserNotice> = con
         .zrangebyscore(
@@ -478,8 +464,7 @@ pub async fn get_user_notice(
             start_timestamp,
             TIMESTAMP_INF,
         )
-        .await
-        .map_err(|_| ())?;
+        .await?;
 
     return Ok(res);
 }
@@ -501,7 +486,7 @@ pub async fn get_user_id(name: &str) -> Result<Vec<UserID>, bool> {
     }
 }
 
-pub async fn add_user_name_id(name: &str, id: UserID) -> Result<(), ()> {
+pub async fn add_user_name_id(name: &str, id: UserID) -> Result<(), DatabaseError> {
     let user_ids: Vec<UserID>;
     if let Ok(mut ids) = get_user_id(name).await {
         ids.push(id);
@@ -510,144 +495,129 @@ pub async fn add_user_name_id(name: &str, id: UserID) -> Result<(), ()> {
         user_ids = vec![id];
     }
     let serialized_user_ids = serde_json::to_string(&user_ids).unwrap();
-    if let Ok(mut con) = get_con().await {
-        con.hset(path::NAME_ID, &name, serialized_user_ids)
-            .await
-            .map_err(|_| ())
-    } else {
-        Err(())
-    }
+    let mut con = get_con().await?;
+    con.hset(path::NAME_ID, &name, serialized_user_ids)
+        .await
+        .map_err(DatabaseError::from)
 }
 
-pub async fn del_user_name_id(name: &str, id: UserID) -> Result<(), ()> {
+pub async fn del_user_name_id(name: &str, id: UserID) -> Result<(), DatabaseError> {
     let user_ids: Vec<UserID>;
     if let Ok(mut ids) = get_user_id(name).await {
         ids.retain(|&x| x != id);
         user_ids = ids;
     } else {
-        return Err(());
+        return Err(DatabaseError::MissingKey(format!(
+            "{}:{}",
+            path::NAME_ID,
+            name
+        )));
     }
     let serialized_user_ids = serde_json::to_string(&user_ids).unwrap();
-    if let Ok(mut con) = get_con().await {
-        con.hset(path::NAME_ID, &name, serialized_user_ids)
-            .await
-            .map_err(|_| ())
-    } else {
-        Err(())
-    }
+    let mut con = get_con().await?;
+    con.hset(path::NAME_ID, &name, serialized_user_ids)
+        .await
+        .map_err(DatabaseError::from)
 }
 
 pub async fn user_log_off(user_id: UserID) -> (UserLogOffResponse, Vec<(UserID, ChatID)>) {
     if let Ok(mut con) = get_con().await {
-        let chats = get_user_chat_list(user_id).await;
-        if chats.is_err() {
-            return (UserLogOffResponse::DatabaseError, vec![]);
-        }
-        let chats = chats.unwrap();
-        let mut groups = vec![];
-        let mut friends = vec![];
-        let mut frineds_chats = vec![];
-        for chat in chats {
-            if let Ok(members) = get_chat_user_list(chat.0).await {
-                match members {
-                    ChatMembers::Group(_) => {
-                        let check_result = check_user_is_owner(user_id, chat.0).await;
-                        if check_result.is_err() {
-                            return (UserLogOffResponse::DatabaseError, vec![]);
-                        } else if check_result.unwrap() {
-                            return (UserLogOffResponse::NoPermission, vec![]);
-                        } else {
-                            groups.push(chat.0);
-                        }
-                    }
-                    ChatMembers::Private(pair) => {
+        // 聊天列表在读取后发生变化时脚本不做任何写入，重新读取后再试
+        for _ in 0..script::MAX_RETRY_NUM {
+            let chats = match get_user_chat_list(user_id).await {
+                Ok(chats) => chats,
+                Err(_) => return (UserLogOffResponse::DatabaseError, vec![]),
+            };
+            let mut groups = vec![];
+            let mut frineds_chats = vec![];
+            for chat in chats {
+                match get_chat_user_list(chat.0).await {
+                    Ok(ChatMembers::Group(_)) => groups.push(chat.0),
+                    Ok(ChatMembers::Private(pair)) => {
                         if user_id == pair.0 {
-                            friends.push(pair.1);
                             frineds_chats.push((pair.1, chat.0));
                         } else {
-                            friends.push(pair.0);
                             frineds_chats.push((pair.0, chat.0));
                         }
                     }
+                    Err(_) => return (UserLogOffResponse::DatabaseError, vec![]),
                 }
-            } else {
-                return (UserLogOffResponse::DatabaseError, vec![]);
             }
-        }
 
-        let email = get_user_email(user_id).await.unwrap();
-        let user_email: Result<bool, ()> =
-            con.hdel(path::USER_EMAIL_MAP, email).await.map_err(|_| ());
-        if user_email.is_err() {
-            return (UserLogOffResponse::DatabaseError, vec![]);
-        } else if !user_email.unwrap() {
-            return (UserLogOffResponse::UserNotFound, vec![]);
-        }
-        let user_email: Result<bool, ()> = con
-            .del(index::get_user_email_index(user_id).as_str())
-            .await
-            .map_err(|_| ());
-        if user_email.is_err() {
-            return (UserLogOffResponse::DatabaseError, vec![]);
-        } else if !user_email.unwrap() {
-            return (UserLogOffResponse::UserNotFound, vec![]);
-        }
+            let mut invocation = script::USER_LOG_OFF.prepare_invoke();
+            invocation
+                .key(path::USER_EMAIL_MAP)
+                .key(index::get_user_email_index(user_id).as_str())
+                .key(index::get_user_info_index(user_id).as_str())
+                .key(index::get_user_exist_index(user_id).as_str())
+                .key(path::NAME_ID)
+                .key(path::FRIEND_CHAT_MAP)
+                .key(index::get_user_chats_index(user_id).as_str());
+            for (friend_id, chat_id) in &frineds_chats {
+                invocation
+                    .key(index::get_user_chats_index(*friend_id).as_str())
+                    .key(index::get_chat_user_index(*chat_id, 0).as_str())
+                    .key(index::get_chat_user_index(*chat_id, 1).as_str());
+            }
+            for chat_id in &groups {
+                invocation
+                    .key(index::get_chat_owner_index(*chat_id).as_str())
+                    .key(index::get_chat_users_index(*chat_id).as_str())
+                    .key(index::get_chat_admins_index(*chat_id).as_str());
+            }
+            invocation
+                .arg(user_id)
+                .arg("用户已注销")
+                .arg(frineds_chats.len());
+            for (friend_id, chat_id) in &frineds_chats {
+                let (id1, id2) = if user_id < *friend_id {
+                    (user_id, *friend_id)
+                } else {
+                    (*friend_id, user_id)
+                };
+                invocation
+                    .arg(*chat_id)
+                    .arg(index::get_friend_pair_index(id1, id2).as_str());
+            }
+            invocation.arg(groups.len()).arg(groups);
 
-        for friend in friends.clone() {
-            unfriend(user_id, friend).await;
-        }
-        for group in groups {
-            quit_group_chat(user_id, group).await;
-        }
-        if let UserUpdateResponse::Success =
-            update_user_name(user_id, "用户已注销".to_string()).await
-        {
-            let old_user_info: GetUserInfoResponse = get_user_info(user_id).await;
-            match old_user_info {
-                GetUserInfoResponse::Success(old_user_info) => {
-                    let del_result = del_user_name_id(&old_user_info.user_name, user_id).await;
-                    if del_result.is_err() {
-                        return (UserLogOffResponse::DatabaseError, vec![]);
-                    }
-                }
-                _ => return (UserLogOffResponse::DatabaseError, vec![]),
+            let log_off_result: Result<i8, ()> = invocation
+                .invoke_async(con.deref_mut())
+                .await
+                .map_err(|_| ());
+            match log_off_result {
+                Ok(1) => return (UserLogOffResponse::Success, frineds_chats),
+                Ok(0) => return (UserLogOffResponse::UserNotFound, vec![]),
+                Ok(-1) => return (UserLogOffResponse::NoPermission, vec![]),
+                Ok(_) => continue,
+                Err(_) => return (UserLogOffResponse::DatabaseError, vec![]),
             }
-        } else {
-            return (UserLogOffResponse::DatabaseError, vec![]);
         }
-        let set_result: Result<bool, ()> = con
-            .set(index::get_user_exist_index(user_id).as_str(), 0)
-            .await
-            .map_err(|_| ());
-        if set_result.is_err() {
-            return (UserLogOffResponse::DatabaseError, vec![]);
-        }
-
-        return (UserLogOffResponse::Success, frineds_chats);
+        return (UserLogOffResponse::DatabaseError, vec![]);
     } else {
         return (UserLogOffResponse::ServerError, vec![]);
     }
 }
 
-pub async fn apply_for_token(user_id: UserID) -> UserApplyForTokenResponse {
+pub async fn apply_for_token(user_id: UserID, device_id: &DeviceID) -> UserApplyForTokenResponse {
     if let Ok(mut con) = get_con().await {
-        let token = Token {
-            token: Uuid::new_v4().simple().to_string(),
-            timestamp: Utc::now().timestamp_millis() as u64,
-        };
+        let token = Token::generate();
         let serialized_token = serde_json::to_string(&token).unwrap();
-        let set_result: Result<bool, ()> = con
-            .set(
-                index::get_user_token_index(user_id).as_str(),
+        let set_result: Result<(), ()> = con
+            .hset(
+                index::get_user_tokens_index(user_id).as_str(),
+                device_id,
                 serialized_token,
             )
             .await
             .map_err(|_| ());
-        if set_result.is_err() || !set_result.unwrap() {
+        if set_result.is_err() {
             return UserApplyForTokenResponse::DatabaseError;
         }
         return UserApplyForTokenResponse::Success {
-            token: token.token,
+            device_id: device_id.clone(),
+            access_token: token.access_token,
+            refresh_token: token.refresh_token,
             timestamp: token.timestamp,
         };
     } else {
@@ -655,48 +625,173 @@ pub async fn apply_for_token(user_id: UserID) -> UserApplyForTokenResponse {
     }
 }
 
-pub async fn user_login_with_token(data: &UserLoginData) -> Result<UserLoginResponse, ()> {
+pub async fn user_login_with_token(
+    data: &UserLoginData,
+) -> Result<UserLoginResponse, DatabaseError> {
     let mut con = get_con().await?;
 
-    let user_id_opt: Option<UserID> = con
-        .hget(path::USER_EMAIL_MAP, &data.email)
-        .await
-        .map_err(|_| ())?;
+    let user_id_opt: Option<UserID> = con.hget(path::USER_EMAIL_MAP, &data.email).await?;
 
     let user_id = match user_id_opt {
         Some(id) => id,
         None => return Ok(UserLoginResponse::UserNotFound),
     };
 
-    let token: String = con
-        .get(index::get_user_token_index(user_id).as_str())
-        .await
-        .map_err(|_| ())?;
+    let device_id = match &data.device_id {
+        Some(device_id) => device_id,
+        None => return Ok(UserLoginResponse::TokenError),
+    };
+    let token: Option<String> = con
+        .hget(index::get_user_tokens_index(user_id).as_str(), device_id)
+        .await?;
+    let token = match token {
+        Some(token) => serde_json::from_str::<Token>(&token)?,
+        None => return Ok(UserLoginResponse::TokenError),
+    };
+
+    if let Err(res) = token.check_login(data) {
+        return Ok(res);
+    }
 
-    let token = serde_json::from_str::<Token>(&token).unwrap();
+    return Ok(UserLoginResponse::Success { user_id });
+}
+
+pub async fn refresh_token(
+    data: &UserRefreshTokenData,
+) -> Result<UserRefreshTokenResponse, DatabaseError> {
+    let mut con = get_con().await?;
 
-    if &token.token != data.token.as_ref().unwrap() {
-        return Ok(UserLoginResponse::TokenError);
+    let user_id: UserID = match con.hget(path::USER_EMAIL_MAP, &data.email).await? {
+        Some(id) => id,
+        None => return Ok(UserRefreshTokenResponse::UserNotFound),
+    };
+
+    let tokens_index = index::get_user_tokens_index(user_id);
+    let serialized_token: String = match con.hget(tokens_index.as_str(), &data.device_id).await? {
+        Some(token) => token,
+        None => return Ok(UserRefreshTokenResponse::TokenError),
+    };
+    let token = serde_json::from_str::<Token>(&serialized_token)?;
+    if let Err(res) = token.check_refresh(&data.refresh_token) {
+        return Ok(res);
     }
 
-    if chrono::Utc::now().timestamp_millis() as u64 - token.timestamp
-        > (Config::get().user.token_expire_time * 1000) as u64
-    {
-        return Ok(UserLoginResponse::TokenExpired);
+    // 同一刷新令牌并发换取时只有一次能成功
+    let token = Token::generate();
+    let replaced: bool = script::REPLACE_TOKEN
+        .key(tokens_index.as_str())
+        .arg(&data.device_id)
+        .arg(&serialized_token)
+        .arg(serde_json::to_string(&token).unwrap())
+        .invoke_async(con.deref_mut())
+        .await?;
+    if !replaced {
+        return Ok(UserRefreshTokenResponse::TokenError);
     }
+    return Ok(UserRefreshTokenResponse::Success {
+        device_id: data.device_id.clone(),
+        access_token: token.access_token,
+        refresh_token: token.refresh_token,
+        timestamp: token.timestamp,
+    });
+}
 
-    return Ok(UserLoginResponse::Success { user_id });
+pub async fn revoke_token(user_id: UserID, device_id: &DeviceID) -> Result<bool, DatabaseError> {
+    let mut con = get_con().await?;
+    let removed: u32 = con
+        .hdel(index::get_user_tokens_index(user_id).as_str(), device_id)
+        .await?;
+    return Ok(removed > 0);
 }
 
-pub async fn check_user_exist(user_id: UserID) -> Result<(), ()> {
+pub async fn revoke_all_tokens(user_id: UserID) -> Result<(), DatabaseError> {
+    let mut con = get_con().await?;
+    con.del::<_, ()>(index::get_user_tokens_index(user_id).as_str())
+        .await?;
+    return Ok(());
+}
+
+pub async fn check_user_exist(user_id: UserID) -> Result<(), DatabaseError> {
     let mut con = get_con().await?;
     let exist: u32 = con
         .get(index::get_user_exist_index(user_id).as_str())
-        .await
-        .map_err(|_| ())?;
+        .await?;
     if exist == 1 {
         Ok(())
     } else {
-        Err(())
+        Err(DatabaseError::MissingKey(
+            index::get_user_exist_index(user_id).to_string(),
+        ))
     }
 }
+
+pub async fn set_user_last_seen(
+    user_id: UserID,
+    timestamp: Timestamp,
+) -> Result<(), DatabaseError> {
+    let mut con = get_con().await?;
+    con.hset::<_, _, _, ()>(path::USER_LAST_SEEN, user_id, timestamp)
+        .await?;
+    return Ok(());
+}
+
+pub async fn get_user_last_seen(user_id: UserID) -> Result<Option<Timestamp>, DatabaseError> {
+    let mut con = get_con().await?;
+    let last_seen: Option<Timestamp> = con.hget(path::USER_LAST_SEEN, user_id).await?;
+    return Ok(last_seen);
+}
+
+pub async fn set_presence_hidden(user_id: UserID, hidden: bool) -> Result<(), DatabaseError> {
+    let mut con = get_con().await?;
+    if hidden {
+        con.sadd::<_, _, ()>(path::PRESENCE_HIDDEN_USERS, user_id)
+            .await?;
+    } else {
+        con.srem::<_, _, ()>(path::PRESENCE_HIDDEN_USERS, user_id)
+            .await?;
+    }
+    return Ok(());
+}
+
+pub async fn get_presence_hidden(user_id: UserID) -> Result<bool, DatabaseError> {
+    let mut con = get_con().await?;
+    let hidden: bool = con.sismember(path::PRESENCE_HIDDEN_USERS, user_id).await?;
+    return Ok(hidden);
+}
+
+pub async fn set_device_keys(
+    user_id: UserID,
+    device_id: &DeviceID,
+    serialized: &str,
+) -> Result<(), DatabaseError> {
+    let mut con = get_con().await?;
+    con.hset::<_, _, _, ()>(
+        index::get_user_device_keys_index(user_id).as_str(),
+        device_id,
+        serialized,
+    )
+    .await?;
+    return Ok(());
+}
+
+pub async fn get_device_keys(
+    user_id: UserID,
+    device_id: &DeviceID,
+) -> Result<Option<String>, DatabaseError> {
+    let mut con = get_con().await?;
+    let serialized: Option<String> = con
+        .hget(
+            index::get_user_device_keys_index(user_id).as_str(),
+            device_id,
+        )
+        .await?;
+    return Ok(serialized);
+}
+
+pub async fn get_user_device_keys(user_id: UserID) -> Result<Vec<String>, DatabaseError> {
+    let mut con = get_con().await?;
+    let serialized: Vec<String> = con
+        .hvals(index::get_user_device_keys_index(user_id).as_str())
+        .await?;
+    return Ok(serialized);
+}
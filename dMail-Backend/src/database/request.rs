@@ -1,15 +1,16 @@
 use crate::config::datatype::*;
+use crate::database::DatabaseError;
 
 use crate::user::*;
 
-use super::redis;
+use super::backend::backend;
 
 /// 获得用户请求
 pub async fn get_user_requests(
     user_id: UserID,
     start_req_id: UserReqId,
-) -> Result<Vec<SerializedRequest>, ()> {
-    return redis::get_user_requests(user_id, start_req_id).await;
+) -> Result<Vec<SerializedRequest>, DatabaseError> {
+    return backend().get_user_requests(user_id, start_req_id).await;
 }
 
 /// 写入用户请求并分配ID
@@ -17,18 +18,20 @@ pub async fn write_user_request(
     sender_id: UserID,
     data: UserSendRequestData,
     handler: &UserRequestHandler,
-) -> Result<(SerializedRequest, UserRequestInfo), ()> {
-    return redis::write_user_request(sender_id, data, &handler).await;
+) -> Result<(SerializedRequest, UserRequestInfo), DatabaseError> {
+    return backend()
+        .write_user_request(sender_id, data, &handler)
+        .await;
 }
 
 /// 存入UserReqID
-pub async fn store_user_request(user_id: UserID, req_id: UserReqId) -> Result<(), ()> {
-    return redis::store_user_request(user_id, req_id).await;
+pub async fn store_user_request(user_id: UserID, req_id: UserReqId) -> Result<(), DatabaseError> {
+    return backend().store_user_request(user_id, req_id).await;
 }
 
 /// 通过UserReqID获取UserRequest
-pub async fn get_user_request(req_id: UserReqId) -> Result<Option<UserRequset>, ()> {
-    return redis::get_user_request(req_id).await;
+pub async fn get_user_request(req_id: UserReqId) -> Result<Option<UserRequset>, DatabaseError> {
+    return backend().get_user_request(req_id).await;
 }
 
 /// 设置用户请求状态，只有Unsolved的请求能够被设置状态
@@ -36,7 +39,8 @@ pub async fn set_user_request_state(
     req_id: UserReqId,
     state: UserRequestState,
 ) -> Result<(), UserSolveRequestResponse> {
-    return redis::set_user_request_state(req_id, state)
+    return backend()
+        .set_user_request_state(req_id, state)
         .await
         .map_err(|state| UserSolveRequestResponse {
             state: state,
@@ -45,13 +49,15 @@ pub async fn set_user_request_state(
 }
 
 /// 获取请求的处理者
-pub async fn get_handlers_of_request(req: &UserRequsetContent) -> Result<UserRequestHandler, ()> {
+pub async fn get_handlers_of_request(
+    req: &UserRequsetContent,
+) -> Result<UserRequestHandler, DatabaseError> {
     match req {
         UserRequsetContent::MakeFriend { receiver_id } => {
             return Ok(UserRequestHandler::One(*receiver_id));
         }
         UserRequsetContent::JoinGroup { chat_id } => {
-            return redis::get_chat_admins_list(*chat_id).await;
+            return backend().get_chat_admins_list(*chat_id).await;
         }
         UserRequsetContent::GroupInvitation {
             chat_id: _,
@@ -61,32 +67,49 @@ pub async fn get_handlers_of_request(req: &UserRequsetContent) -> Result<UserReq
             inviter_id: _,
             chat_id,
         } => {
-            return redis::get_chat_admins_list(*chat_id).await;
+            return backend().get_chat_admins_list(*chat_id).await;
         }
     }
 }
 
 /// 在好友申请发送时为两个用户建立id=0的Chat
-pub async fn write_friend_request_send(user_one_id: UserID, user_two_id: UserID) -> Result<(), ()> {
-    return redis::write_friend_request_send(user_one_id, user_two_id).await;
+pub async fn write_friend_request_send(
+    user_one_id: UserID,
+    user_two_id: UserID,
+) -> Result<(), DatabaseError> {
+    return backend()
+        .write_friend_request_send(user_one_id, user_two_id)
+        .await;
 }
 
 /// 在好友申请被处理时删除两个用户间id=0的Chat
 pub async fn delete_friend_request_send(
     user_one_id: UserID,
     user_two_id: UserID,
-) -> Result<(), ()> {
-    return redis::delete_friend_request_send(user_one_id, user_two_id).await;
+) -> Result<(), DatabaseError> {
+    return backend()
+        .delete_friend_request_send(user_one_id, user_two_id)
+        .await;
 }
 
 /// 在申请加群时将群聊id加入用户的pre_join
-pub async fn write_join_group_request_send(user_id: UserID, chat_id: ChatID) -> Result<(), ()> {
-    return redis::write_join_group_request_send(user_id, chat_id).await;
+pub async fn write_join_group_request_send(
+    user_id: UserID,
+    chat_id: ChatID,
+) -> Result<(), DatabaseError> {
+    return backend()
+        .write_join_group_request_send(user_id, chat_id)
+        .await;
 }
 
 /// 在申请加群时将群聊id移出用户的pre_join
-pub async fn delete_join_group_request_send(user_id: UserID, chat_id: ChatID) -> Result<(), ()> {
-    return redis::delete_join_group_request_send(user_id, chat_id).await;
+pub async fn delete_join_group_request_send(
+    user_id: UserID,
+    chat_id: ChatID,
+) -> Result<(), DatabaseError> {
+    return backend()
+        .delete_join_group_request_send(user_id, chat_id)
+        .await;
 }
 
 /// 在加群邀请发送时增添Invitations哈希表键
@@ -94,8 +117,10 @@ pub async fn write_invite_request_send(
     inviter_id: UserID,
     receiver_id: UserID,
     chat_id: ChatID,
-) -> Result<(), ()> {
-    return redis::write_invite_request_send(inviter_id, receiver_id, chat_id).await;
+) -> Result<(), DatabaseError> {
+    return backend()
+        .write_invite_request_send(inviter_id, receiver_id, chat_id)
+        .await;
 }
 
 /// 在加群邀请被处理时删除Invitations的对应键
@@ -103,6 +128,8 @@ pub async fn delete_invite_request_send(
     inviter_id: UserID,
     receiver_id: UserID,
     chat_id: ChatID,
-) -> Result<(), ()> {
-    return redis::delete_invite_request_send(inviter_id, receiver_id, chat_id).await;
+) -> Result<(), DatabaseError> {
+    return backend()
+        .delete_invite_request_send(inviter_id, receiver_id, chat_id)
+        .await;
 }
@@ -1,22 +1,28 @@
 use crate::config::datatype::*;
+use crate::database::DatabaseError;
 
 use crate::user::*;
+use crate::utils::password;
 
-use super::redis;
+use super::backend::backend;
 
-/// 在数据库内写入一个用户。
+/// 在数据库内写入一个用户，密码以Argon2id哈希存储。
 /// 此接口仅负责检查 UserRegisterResponse::EmailRegistered
-pub async fn user_register(data: UserRegisterData) -> UserRegisterResponse {
-    match redis::user_register(data).await {
+pub async fn user_register(mut data: UserRegisterData) -> UserRegisterResponse {
+    data.password = match password::spawn_hash_password(&data.password).await {
+        Ok(hash) => hash,
+        Err(_) => return UserRegisterResponse::ServerError,
+    };
+    match backend().user_register(data).await {
         Ok(res) => res,
         Err(_) => UserRegisterResponse::ServerError,
     }
 }
 
-/// 数据库用户登录接口。
+/// 数据库用户登录接口，旧版的明文密码在登录成功后替换为哈希。
 // 此接口仅负责检查 UserRegisterResponse::PasswordError, UserRegisterResponse::UserNotFound
 pub async fn user_login_with_password(data: UserLoginData) -> UserLoginResponse {
-    match redis::user_login_with_password(&data).await {
+    match backend().user_login_with_password(&data).await {
         Ok(res) => res,
         Err(_) => UserLoginResponse::ServerError,
     }
@@ -24,30 +30,32 @@ pub async fn user_login_with_password(data: UserLoginData) -> UserLoginResponse
 
 /// 用户使用token自动登录时检查
 pub async fn user_login_with_token(data: UserLoginData) -> UserLoginResponse {
-    match redis::user_login_with_token(&data).await {
+    match backend().user_login_with_token(&data).await {
         Ok(res) => res,
         Err(_) => UserLoginResponse::ServerError,
     }
 }
 
 /// 通过邮件查找用户ID
-pub async fn get_user_id_by_email(email: &String) -> Result<Option<UserID>, ()> {
-    return redis::get_user_id_by_email(email).await;
+pub async fn get_user_id_by_email(email: &str) -> Result<Option<UserID>, DatabaseError> {
+    return backend().get_user_id_by_email(email).await;
 }
 
 /// 获得用户会话列表
-pub async fn get_user_chat_list(user_id: UserID) -> Result<Vec<(ChatID, MessageID)>, ()> {
-    return redis::get_user_chat_list(user_id).await;
+pub async fn get_user_chat_list(
+    user_id: UserID,
+) -> Result<Vec<(ChatID, MessageID)>, DatabaseError> {
+    return backend().get_user_chat_list(user_id).await;
 }
 
 /// 获得用户Info
 pub async fn get_user_info(user_id: UserID) -> GetUserInfoResponse {
-    return redis::get_user_info(user_id).await;
+    return backend().get_user_info(user_id).await;
 }
 
 /// 获得用户邮箱
-pub async fn get_user_email(user_id: UserID) -> Result<String, ()> {
-    return redis::get_user_email(user_id).await;
+pub async fn get_user_email(user_id: UserID) -> Result<String, DatabaseError> {
+    return backend().get_user_email(user_id).await;
 }
 
 /// 直接让两个用户成为好友，在用户确认好友申请后
@@ -56,8 +64,10 @@ pub async fn get_user_email(user_id: UserID) -> Result<String, ()> {
 pub async fn make_two_users_be_friends(
     user_one_id: UserID,
     user_two_id: UserID,
-) -> Result<ChatID, ()> {
-    return redis::make_two_users_be_friends(user_one_id, user_two_id).await;
+) -> Result<ChatID, DatabaseError> {
+    return backend()
+        .make_two_users_be_friends(user_one_id, user_two_id)
+        .await;
 }
 
 /// 发送好友邀请时检查错误
@@ -65,42 +75,48 @@ pub async fn check_make_friend_error(
     user_one_id: UserID,
     user_two_id: UserID,
 ) -> Result<(), MakeFriendError> {
-    return redis::check_make_friend_error(user_one_id, user_two_id).await;
+    return backend()
+        .check_make_friend_error(user_one_id, user_two_id)
+        .await;
 }
 
 /// 设置user_setting
 pub async fn set_user_setting(user_id: UserID, user_setting: String) -> SetSettingResponse {
-    return redis::set_user_setting(user_id, user_setting).await;
+    return backend().set_user_setting(user_id, user_setting).await;
 }
 
 /// 获取uesr_setting
-pub async fn get_user_setting(user_id: UserID) -> Result<Option<String>, ()> {
-    return redis::get_user_setting(user_id).await;
+pub async fn get_user_setting(user_id: UserID) -> Result<Option<String>, DatabaseError> {
+    return backend().get_user_setting(user_id).await;
 }
 
 /// 更新用户姓名
 pub async fn update_user_name(user_id: UserID, new_name: String) -> UserUpdateResponse {
-    return redis::update_user_name(user_id, new_name).await;
+    return backend().update_user_name(user_id, new_name).await;
 }
 
 /// 更新用户头像
 pub async fn update_user_avater(user_id: UserID, new_hash: String) -> UserUpdateResponse {
-    return redis::update_user_avater(user_id, new_hash).await;
+    return backend().update_user_avater(user_id, new_hash).await;
 }
 
 /// 更新密码
 pub async fn update_user_password(user_id: UserID, new_password: String) -> UserUpdateResponse {
-    return redis::update_user_password(user_id, new_password).await;
+    let new_password = match password::spawn_hash_password(&new_password).await {
+        Ok(hash) => hash,
+        Err(_) => return UserUpdateResponse::ServerError,
+    };
+    return backend().update_user_password(user_id, new_password).await;
 }
 
 // 从一对好友ID获取chat_id
-// pub async fn get_chat_id_by_friend(user_id1: UserID, user_id2: UserID) -> Result<ChatID, ()> {
-//     return redis::get_chat_id_by_friend(user_id1, user_id2).await;
+// pub async fn get_chat_id_by_friend(user_id1: UserID, user_id2: UserID) -> Result<ChatID, DatabaseError> {
+//     return backend().get_chat_id_by_friend(user_id1, user_id2).await;
 // }
 
 /// 解除好友关系
 pub async fn unfriend(user_id: UserID, friend_id: UserID) -> UserUnfriendResponse {
-    return redis::unfriend(user_id, friend_id).await;
+    return backend().unfriend(user_id, friend_id).await;
 }
 
 /// 更新已读消息
@@ -108,12 +124,12 @@ pub async fn set_user_already_read(
     user_id: UserID,
     data: UserSetAlreadyReadData,
 ) -> SetAlreadyReadResponse {
-    return redis::set_user_already_read(user_id, data).await;
+    return backend().set_user_already_read(user_id, data).await;
 }
 
 /// 检查用户是否在Chat中
-pub async fn check_user_in_chat(user_id: UserID, chat_id: ChatID) -> Result<bool, ()> {
-    return redis::check_user_in_chat(user_id, chat_id).await;
+pub async fn check_user_in_chat(user_id: UserID, chat_id: ChatID) -> Result<bool, DatabaseError> {
+    return backend().check_user_in_chat(user_id, chat_id).await;
 }
 
 /// 写入用户的notice
@@ -121,29 +137,31 @@ pub async fn write_user_notice(
     user_id: UserID,
     timestamp: Timestamp,
     serialized: &SerializedUserNotice,
-) -> Result<(), ()> {
-    return redis::write_user_notice(user_id, timestamp, serialized).await;
+) -> Result<(), DatabaseError> {
+    return backend()
+        .write_user_notice(user_id, timestamp, serialized)
+        .await;
 }
 
 /// 获取用户的notice
 pub async fn get_user_notice(
     user_id: UserID,
     start_timestamp: Timestamp,
-) -> Result<Vec<SerializedUserNotice>, ()> {
-    return redis::get_user_notice(user_id, start_timestamp).await;
+) -> Result<Vec<SerializedUserNotice>, DatabaseError> {
+    return backend().get_user_notice(user_id, start_timestamp).await;
 }
 
 /// 通过好友的UserID获取与他的ChatID
 pub async fn get_chat_id_by_friends(
     user_id1: UserID,
     user_id2: UserID,
-) -> Result<Option<ChatID>, ()> {
-    return redis::get_chat_id_by_friends(user_id1, user_id2).await;
+) -> Result<Option<ChatID>, DatabaseError> {
+    return backend().get_chat_id_by_friends(user_id1, user_id2).await;
 }
 
 /// 通过好友的名字获取UserID
 pub async fn get_user_id(name: String) -> UserGetUserIDResponse {
-    match redis::get_user_id(&name).await {
+    match backend().get_user_id(&name).await {
         Ok(user_ids) => UserGetUserIDResponse::Success { user_ids },
         Err(not_found) => {
             if not_found {
@@ -157,15 +175,80 @@ pub async fn get_user_id(name: String) -> UserGetUserIDResponse {
 
 /// 用户注销
 pub async fn user_log_off(user_id: UserID) -> (UserLogOffResponse, Vec<(UserID, ChatID)>) {
-    return redis::user_log_off(user_id).await;
+    return backend().user_log_off(user_id).await;
+}
+
+/// 客户端为当前设备申请token，替换该设备之前的token
+pub async fn apply_for_token(user_id: UserID, device_id: &DeviceID) -> UserApplyForTokenResponse {
+    return backend().apply_for_token(user_id, device_id).await;
+}
+
+/// 使用刷新令牌换取新的token，旧的token随之失效
+pub async fn refresh_token(data: &UserRefreshTokenData) -> UserRefreshTokenResponse {
+    match backend().refresh_token(data).await {
+        Ok(res) => res,
+        Err(_) => UserRefreshTokenResponse::DatabaseError,
+    }
 }
 
-/// 客户端申请token
-pub async fn apply_for_token(user_id: UserID) -> UserApplyForTokenResponse {
-    return redis::apply_for_token(user_id).await;
+/// 撤销某台设备上的token，返回该设备上是否有token
+pub async fn revoke_token(user_id: UserID, device_id: &DeviceID) -> Result<bool, DatabaseError> {
+    return backend().revoke_token(user_id, device_id).await;
+}
+
+/// 撤销用户全部设备上的token
+pub async fn revoke_all_tokens(user_id: UserID) -> Result<(), DatabaseError> {
+    return backend().revoke_all_tokens(user_id).await;
 }
 
 /// 检查用户是否存在
-pub async fn check_user_exist(user_id: UserID) -> Result<(), ()> {
-    return redis::check_user_exist(user_id).await;
+pub async fn check_user_exist(user_id: UserID) -> Result<(), DatabaseError> {
+    return backend().check_user_exist(user_id).await;
+}
+
+/// 记录用户最后在线的时间
+pub async fn set_user_last_seen(
+    user_id: UserID,
+    timestamp: Timestamp,
+) -> Result<(), DatabaseError> {
+    return backend().set_user_last_seen(user_id, timestamp).await;
+}
+
+/// 获取用户最后在线的时间，从未下线过时为None
+pub async fn get_user_last_seen(user_id: UserID) -> Result<Option<Timestamp>, DatabaseError> {
+    return backend().get_user_last_seen(user_id).await;
+}
+
+/// 设置是否向他人隐藏在线状态
+pub async fn set_presence_hidden(user_id: UserID, hidden: bool) -> Result<(), DatabaseError> {
+    return backend().set_presence_hidden(user_id, hidden).await;
+}
+
+/// 获取是否向他人隐藏在线状态
+pub async fn get_presence_hidden(user_id: UserID) -> Result<bool, DatabaseError> {
+    return backend().get_presence_hidden(user_id).await;
+}
+
+/// 写入设备的公钥，替换该设备之前的公钥
+pub async fn set_device_keys(
+    user_id: UserID,
+    device_id: &DeviceID,
+    serialized: &str,
+) -> Result<(), DatabaseError> {
+    return backend()
+        .set_device_keys(user_id, device_id, serialized)
+        .await;
+}
+
+/// 获取设备的公钥，设备没有上传过公钥时为None
+pub async fn get_device_keys(
+    user_id: UserID,
+    device_id: &DeviceID,
+) -> Result<Option<String>, DatabaseError> {
+    return backend().get_device_keys(user_id, device_id).await;
+}
+
+/// 获取用户全部设备的公钥
+pub async fn get_user_device_keys(user_id: UserID) -> Result<Vec<String>, DatabaseError> {
+    return backend().get_user_device_keys(user_id).await;
 }
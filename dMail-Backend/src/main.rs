@@ -6,7 +6,7 @@ use dMail::{
     database::{self},
     server::server_state::ServerState,
     user::{
-        user_session::{UserSessionData, UserSessionState},
+        user_session::{protocol::LEGACY_PROTOCOL_VERSION, UserSessionData, UserSessionState},
         UserCreateGroupChatData,
     },
 };
@@ -15,7 +15,7 @@ use log::debug;
 use rustls::{Certificate, PrivateKey, ServerConfig};
 use rustls_pemfile::{certs, pkcs8_private_keys};
 
-use std::{cell::RefCell, fs::File, io::BufReader, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, fs::File, io::BufReader, rc::Rc};
 
 use dMail::user;
 use dMail::user::user_session::WsUserSession;
@@ -38,6 +38,9 @@ async fn connect_web_socket(
                 client_ip: addr,
                 user_id: None,
                 user_pub_key: None,
+                device_id: None,
+                protocol_version: LEGACY_PROTOCOL_VERSION,
+                typing: HashMap::new(),
             })),
             cipher: None,
             last_receive_time: std::time::Instant::now(),
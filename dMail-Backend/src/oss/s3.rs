@@ -1,4 +1,5 @@
 use chrono::Utc;
+use log::error;
 use once_cell::sync::Lazy;
 use s3::{error::S3Error, serde_types::HeadObjectResult, Bucket, Region};
 use serde::{Deserialize, Serialize};
@@ -100,7 +101,9 @@ pub async fn create_pub_url(hash: &String, path: String, expire: u32) -> Result<
                 url,
                 expire: Utc::now().timestamp_millis() as Timestamp,
             };
-            database::write_file_public_url(&hash, &presign_url).await?;
+            database::write_file_public_url(&hash, &presign_url)
+                .await
+                .map_err(|err| error!("写入文件{}的公开url失败: {}", hash, err))?;
             return Ok(presign_url);
         }
         Err(_) => Err(()),
@@ -109,7 +112,10 @@ pub async fn create_pub_url(hash: &String, path: String, expire: u32) -> Result<
 
 /// `get_public_url_and_auto_renew`获取公共url并自动更新
 pub async fn get_public_url_and_auto_renew(hash: &String) -> Result<Option<String>, ()> {
-    if let Some((pub_url, _)) = database::get_file_public_url(hash).await? {
+    if let Some((pub_url, _)) = database::get_file_public_url(hash)
+        .await
+        .map_err(|err| error!("读取文件{}的公开url失败: {}", hash, err))?
+    {
         if pub_url.expire < Utc::now().timestamp_millis() as Timestamp {
             let new_pub_url =
                 create_pub_url(hash, pub_url.path, Config::get().s3_oss.presign_get_expire).await?;
@@ -0,0 +1,95 @@
+/*!
+ 阅后即焚的后台过期任务
+*/
+
+use std::time::Duration;
+
+use chrono::Utc;
+use log::{error, info};
+use tokio::time;
+
+use crate::chat::ChatMessage;
+use crate::config::{
+    datatype::{ChatID, Timestamp},
+    Config,
+};
+use crate::database::{self, DatabaseError};
+use crate::user::{send_notice_to_user_in_chat, ChatMessageType, UserNotice};
+
+/** `start_expiring` 按配置的间隔周期性地撤回所有聊天中到期的阅后即焚消息
+*/
+pub async fn start_expiring() {
+    let check_interval = Config::get().user.disappearing_check_interval;
+    info!("正在启动阅后即焚任务，间隔{}秒", check_interval);
+    let mut interval = time::interval(Duration::from_secs(std::cmp::max(1, check_interval as u64)));
+    loop {
+        interval.tick().await;
+        expire_all_chats().await;
+    }
+}
+
+/** `expire_all_chats` 对所有开启了阅后即焚的聊天执行一次过期检查
+*/
+pub async fn expire_all_chats() {
+    let chats = match database::get_disappearing_chats().await {
+        Ok(chats) => chats,
+        Err(err) => {
+            error!("读取阅后即焚的聊天失败: {}", err);
+            return;
+        }
+    };
+
+    let now = Utc::now().timestamp_millis() as Timestamp;
+    for (chat_id, ttl) in chats {
+        if let Err(err) = expire_messages(chat_id, ttl, now).await {
+            error!("撤回聊天{}中到期的消息失败: {}", chat_id, err);
+        }
+    }
+}
+
+/** `expire_messages` 将聊天中发送时间早于now - ttl的消息替换为Revoked，并通知聊天中的用户
+ * 只检查上次检查之后的消息，传出本次撤回的消息数量
+*/
+pub async fn expire_messages(
+    chat_id: ChatID,
+    ttl: u32,
+    now: Timestamp,
+) -> Result<usize, DatabaseError> {
+    let max_timestamp = now.saturating_sub(ttl as Timestamp * 1000);
+    let expired_id = database::get_expired_id(chat_id).await?;
+    let msgs = database::get_messages_in_chat(chat_id, expired_id + 1, None).await?;
+
+    let mut last_id = expired_id;
+    let mut in_chat_ids = vec![];
+    for serialized_msg in msgs {
+        let msg = serde_json::from_str::<ChatMessage>(&serialized_msg)?;
+        // 消息按发送顺序排列，之后的消息都还未到期
+        if msg.timestamp > max_timestamp {
+            break;
+        }
+        last_id = msg.in_chat_id;
+        if msg.r#type == ChatMessageType::Revoked {
+            continue;
+        }
+        database::revoke_message(chat_id, msg.in_chat_id, msg.sender_id, msg.timestamp).await?;
+        in_chat_ids.push(msg.in_chat_id);
+    }
+
+    if last_id != expired_id {
+        database::set_expired_id(chat_id, last_id).await?;
+    }
+    if in_chat_ids.is_empty() {
+        return Ok(0);
+    }
+
+    let expired_num = in_chat_ids.len();
+    let notice = UserNotice::Expired {
+        chat_id,
+        in_chat_ids,
+        timestamp: now,
+    };
+    let serialized_notice = serde_json::to_string(&notice).unwrap();
+    send_notice_to_user_in_chat(chat_id, serialized_notice, now).await?;
+
+    return Ok(expired_num);
+}
@@ -88,6 +88,8 @@ pub enum SendEmailCodeError {
     AddressParseFailed,
     EmailBuildFailed,
     SendFailed,
+    /// 同一IP申请验证码过于频繁
+    RateLimited,
 }
 
 /** `send_email_code` 发送验证码
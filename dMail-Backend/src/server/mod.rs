@@ -1,5 +1,11 @@
 /*!
  * 服务器状态
 */
+pub mod disappearing;
 pub mod email;
+pub mod presence;
+pub mod rate_limit;
+pub mod read_receipt;
+pub mod retention;
+pub mod schedule;
 pub mod server_state;
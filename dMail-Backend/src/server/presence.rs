@@ -0,0 +1,123 @@
+/*!
+ 用户的在线状态
+ 是否在线由用户连接决定，离开状态只保存在内存中，最后在线时间在连接断开时写入数据库
+*/
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use log::error;
+use once_cell::sync::Lazy;
+
+use crate::chat::ChatMembers;
+use crate::config::datatype::{Timestamp, UserID};
+use crate::database::{self, DatabaseError};
+use crate::server::server_state::{user_sessions, UserSessionGetter};
+use crate::user::user_session::protocol::ServerToClientMessage;
+use crate::user::{PresenceStatus, UserPresenceData};
+
+/** `away_users` 处于离开状态的在线用户
+*/
+#[allow(non_upper_case_globals)]
+pub static away_users: Lazy<Mutex<HashSet<UserID>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/** `get_status` 根据用户连接与离开状态得到用户当前的在线状态
+*/
+pub fn get_status(user_id: UserID) -> PresenceStatus {
+    if !user_sessions.is_online(user_id) {
+        return PresenceStatus::Offline;
+    }
+    if away_users.lock().unwrap().contains(&user_id) {
+        return PresenceStatus::Away;
+    }
+    return PresenceStatus::Online;
+}
+
+/** `set_away` 设置用户是否处于离开状态，返回状态是否改变
+*/
+pub fn set_away(user_id: UserID, away: bool) -> bool {
+    let mut users = away_users.lock().unwrap();
+    return if away {
+        users.insert(user_id)
+    } else {
+        users.remove(&user_id)
+    };
+}
+
+async fn get_visible_presence(
+    user_id: UserID,
+    hidden: bool,
+) -> Result<UserPresenceData, DatabaseError> {
+    if hidden {
+        return Ok(UserPresenceData {
+            user_id,
+            status: PresenceStatus::Offline,
+            last_seen: None,
+        });
+    }
+    let status = get_status(user_id);
+    let last_seen = if status == PresenceStatus::Offline {
+        database::get_user_last_seen(user_id).await?
+    } else {
+        None
+    };
+    return Ok(UserPresenceData {
+        user_id,
+        status,
+        last_seen,
+    });
+}
+
+/** `get_presence` 获取`viewer_id`看到的用户在线状态，用户总能看到自己的真实状态
+*/
+pub async fn get_presence(
+    viewer_id: UserID,
+    user_id: UserID,
+) -> Result<UserPresenceData, DatabaseError> {
+    let hidden = viewer_id != user_id && database::get_presence_hidden(user_id).await?;
+    return get_visible_presence(user_id, hidden).await;
+}
+
+/** `get_user_friends` 通过用户所在的私聊获取用户的全部好友
+*/
+pub async fn get_user_friends(user_id: UserID) -> Result<Vec<UserID>, DatabaseError> {
+    let mut friends = vec![];
+    for (chat_id, _) in database::get_user_chat_list(user_id).await? {
+        if let ChatMembers::Private(pair) = database::get_chat_user_list(chat_id).await? {
+            friends.push(if pair.0 == user_id { pair.1 } else { pair.0 });
+        }
+    }
+    return Ok(friends);
+}
+
+/** `push_presence` 向在线好友推送用户当前的在线状态
+*/
+pub async fn push_presence(user_id: UserID) -> Result<(), DatabaseError> {
+    let hidden = database::get_presence_hidden(user_id).await?;
+    let presence = get_visible_presence(user_id, hidden).await?;
+    let friends = get_user_friends(user_id).await?;
+    user_sessions.send_message_to_online(
+        &friends,
+        Arc::new(ServerToClientMessage::PresenceChange(presence)),
+    );
+    return Ok(());
+}
+
+/** `user_online` 用户登录后通知好友
+*/
+pub async fn user_online(user_id: UserID) {
+    if let Err(err) = push_presence(user_id).await {
+        error!("user_online: 推送在线状态: {}", err);
+    }
+}
+
+/** `user_offline` 连接断开后记录最后在线时间并通知好友
+*/
+pub async fn user_offline(user_id: UserID, timestamp: Timestamp) {
+    if let Err(err) = database::set_user_last_seen(user_id, timestamp).await {
+        error!("user_offline: 记录最后在线时间: {}", err);
+    }
+    if let Err(err) = push_presence(user_id).await {
+        error!("user_offline: 推送在线状态: {}", err);
+    }
+}
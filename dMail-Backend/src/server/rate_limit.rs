@@ -0,0 +1,200 @@
+/*!
+ 请求速率限制
+ 使用令牌桶，分别按IP、用户与消息类型计数，桶只保存在内存中
+*/
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::info;
+use once_cell::sync::Lazy;
+use tokio::time;
+
+use crate::config::config::RateLimitRule;
+use crate::config::datatype::UserID;
+use crate::config::Config;
+
+/** `SWEEP_INTERVAL` 清理已经补满的桶的间隔
+*/
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/** `RateLimitKey` 令牌桶的键
+*/
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum RateLimitKey {
+    Ip(IpAddr),
+    User(UserID),
+    IpCommand(IpAddr, &'static str),
+    UserCommand(UserID, &'static str),
+    EmailCode(IpAddr),
+}
+
+/** `TokenBucket` 令牌桶，令牌数在取用时按经过的时间补充
+*/
+#[derive(Debug)]
+pub struct TokenBucket {
+    rule: RateLimitRule,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rule: RateLimitRule, now: Instant) -> TokenBucket {
+        return TokenBucket {
+            rule,
+            tokens: rule.capacity as f64,
+            updated_at: now,
+        };
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * self.rule.refill_per_sec).min(self.rule.capacity as f64);
+        self.updated_at = now;
+    }
+
+    /** `wait_time` 补充令牌，令牌不足一个时返回需要等待的时间
+     */
+    pub fn wait_time(&mut self, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            return Ok(());
+        }
+        if self.rule.refill_per_sec <= 0.0 {
+            return Err(Duration::MAX);
+        }
+        let wait = (1.0 - self.tokens) / self.rule.refill_per_sec;
+        return Err(Duration::try_from_secs_f64(wait).unwrap_or(Duration::MAX));
+    }
+
+    /** `take` 取用一个令牌，调用前应由`wait_time`确认令牌充足
+     */
+    pub fn take(&mut self) {
+        self.tokens -= 1.0;
+    }
+
+    fn is_full(&self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        return self.tokens + elapsed * self.rule.refill_per_sec >= self.rule.capacity as f64;
+    }
+}
+
+/** `RateLimiter` 以`RateLimitKey`区分的一组令牌桶
+*/
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<RateLimitKey, TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> RateLimiter {
+        return RateLimiter {
+            buckets: Mutex::new(HashMap::new()),
+        };
+    }
+
+    /** `check` 各个桶都有令牌时从每个桶中取用一个
+     * 任意一个桶令牌不足时都不取用，返回需要等待的最长时间
+     */
+    pub fn check(
+        &self,
+        keys: &[(RateLimitKey, RateLimitRule)],
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let mut wait = None;
+        for (key, rule) in keys {
+            let bucket = buckets
+                .entry(*key)
+                .or_insert_with(|| TokenBucket::new(*rule, now));
+            if let Err(time) = bucket.wait_time(now) {
+                wait = Some(std::cmp::max(wait.unwrap_or(Duration::ZERO), time));
+            }
+        }
+        if let Some(wait) = wait {
+            return Err(wait);
+        }
+        for (key, _) in keys {
+            if let Some(bucket) = buckets.get_mut(key) {
+                bucket.take();
+            }
+        }
+        return Ok(());
+    }
+
+    /** `sweep` 清理已经补满的桶，补满的桶与新建的桶没有区别
+     */
+    pub fn sweep(&self, now: Instant) {
+        self.buckets
+            .lock()
+            .unwrap()
+            .retain(|_, bucket| !bucket.is_full(now));
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        return RateLimiter::new();
+    }
+}
+
+/** `rate_limiter` 服务端共用的速率限制
+*/
+#[allow(non_upper_case_globals)]
+pub static rate_limiter: Lazy<RateLimiter> = Lazy::new(RateLimiter::new);
+
+/** `start_sweeping` 周期性地清理已经补满的桶
+*/
+pub async fn start_sweeping() {
+    info!(
+        "正在启动速率限制清理任务，间隔{}秒",
+        SWEEP_INTERVAL.as_secs()
+    );
+    let mut interval = time::interval(SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        rate_limiter.sweep(Instant::now());
+    }
+}
+
+/** `check_client_message` 检查连接发来的消息是否超过速率限制
+*/
+pub fn check_client_message(
+    ip: IpAddr,
+    user_id: Option<UserID>,
+    command: &str,
+) -> Result<(), Duration> {
+    let config = &Config::get().rate_limit;
+    if !config.enable {
+        return Ok(());
+    }
+
+    let mut keys = vec![(RateLimitKey::Ip(ip), config.per_ip)];
+    if let Some(user_id) = user_id {
+        keys.push((RateLimitKey::User(user_id), config.per_user));
+    }
+    // 桶的键借用配置中的command，只有单独限制的消息才需要
+    if let Some((command, rule)) = config.per_command.get_key_value(command) {
+        let key = match user_id {
+            Some(user_id) => RateLimitKey::UserCommand(user_id, command.as_str()),
+            None => RateLimitKey::IpCommand(ip, command.as_str()),
+        };
+        keys.push((key, *rule));
+    }
+    return rate_limiter.check(&keys, Instant::now());
+}
+
+/** `check_email_code` 检查申请邮箱验证码的请求是否超过速率限制
+*/
+pub fn check_email_code(ip: IpAddr) -> Result<(), Duration> {
+    let config = &Config::get().rate_limit;
+    if !config.enable {
+        return Ok(());
+    }
+    return rate_limiter.check(
+        &[(RateLimitKey::EmailCode(ip), config.email_code)],
+        Instant::now(),
+    );
+}
@@ -0,0 +1,156 @@
+/*!
+ 群聊已读位置的合并推送
+ 成员更新已读位置时只记录最新的位置，由后台任务按配置的间隔统一推送给订阅者，避免大群中每次已读都广播
+*/
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use log::info;
+use once_cell::sync::Lazy;
+use tokio::time;
+
+use crate::config::{
+    datatype::{ChatID, MessageID, UserID},
+    Config,
+};
+use crate::server::server_state::{user_sessions, UserSessionGetter};
+use crate::user::user_session::{protocol::ServerToClientMessage, UserSessionActorMessage};
+use crate::user::{GroupReadCursorsData, ReadReceiptMode};
+
+/** `ReadReceiptHub` 已读位置的订阅与等待推送的变化
+*/
+#[derive(Default)]
+pub struct ReadReceiptHub {
+    /// 群聊中的订阅者及其订阅方式
+    subscriptions: HashMap<ChatID, HashMap<UserID, ReadReceiptMode>>,
+    /// 上次推送之后各成员最新的已读位置
+    pending: HashMap<ChatID, BTreeMap<UserID, MessageID>>,
+    /// 订阅者在群聊中最后发送的消息
+    last_sent: HashMap<(ChatID, UserID), MessageID>,
+}
+
+impl ReadReceiptHub {
+    pub fn subscribe(&mut self, chat_id: ChatID, user_id: UserID, mode: ReadReceiptMode) {
+        self.subscriptions
+            .entry(chat_id)
+            .or_default()
+            .insert(user_id, mode);
+    }
+
+    pub fn unsubscribe(&mut self, chat_id: ChatID, user_id: UserID) {
+        if let Some(subscribers) = self.subscriptions.get_mut(&chat_id) {
+            subscribers.remove(&user_id);
+            if subscribers.is_empty() {
+                self.subscriptions.remove(&chat_id);
+                self.pending.remove(&chat_id);
+            }
+        }
+        self.last_sent.remove(&(chat_id, user_id));
+    }
+
+    /// 用户断开连接时取消其全部订阅
+    pub fn unsubscribe_all(&mut self, user_id: UserID) {
+        let chat_ids: Vec<ChatID> = self
+            .subscriptions
+            .iter()
+            .filter(|(_, subscribers)| subscribers.contains_key(&user_id))
+            .map(|(chat_id, _)| *chat_id)
+            .collect();
+        for chat_id in chat_ids {
+            self.unsubscribe(chat_id, user_id);
+        }
+    }
+
+    pub fn has_subscribers(&self, chat_id: ChatID) -> bool {
+        return self.subscriptions.contains_key(&chat_id);
+    }
+
+    /// 记录成员的已读位置，群聊没有订阅者时忽略
+    pub fn record_read(&mut self, chat_id: ChatID, user_id: UserID, in_chat_id: MessageID) {
+        if !self.has_subscribers(chat_id) {
+            return;
+        }
+        let cursor = self
+            .pending
+            .entry(chat_id)
+            .or_default()
+            .entry(user_id)
+            .or_default();
+        *cursor = std::cmp::max(*cursor, in_chat_id);
+    }
+
+    /// 记录订阅者发送的消息，未订阅的用户忽略
+    pub fn record_sent(&mut self, chat_id: ChatID, user_id: UserID, in_chat_id: MessageID) {
+        let subscribed = self
+            .subscriptions
+            .get(&chat_id)
+            .is_some_and(|subscribers| subscribers.contains_key(&user_id));
+        if subscribed {
+            self.last_sent.insert((chat_id, user_id), in_chat_id);
+        }
+    }
+
+    /** `take_pushes` 取出等待推送的全部变化，按订阅方式分发给每位订阅者
+     * 订阅者不会收到自己的已读位置
+     */
+    pub fn take_pushes(&mut self) -> Vec<(UserID, GroupReadCursorsData)> {
+        let mut pushes = vec![];
+        for (chat_id, cursors) in self.pending.drain() {
+            let subscribers = match self.subscriptions.get(&chat_id) {
+                Some(subscribers) => subscribers,
+                None => continue,
+            };
+            for (subscriber, mode) in subscribers {
+                let last_sent = self.last_sent.get(&(chat_id, *subscriber));
+                let cursors: Vec<(UserID, MessageID)> = cursors
+                    .iter()
+                    .filter(|(user_id, in_chat_id)| {
+                        *user_id != subscriber
+                            && match mode {
+                                ReadReceiptMode::All => true,
+                                ReadReceiptMode::OwnMessages => {
+                                    last_sent.is_some_and(|last_sent| *in_chat_id >= last_sent)
+                                }
+                            }
+                    })
+                    .map(|(user_id, in_chat_id)| (*user_id, *in_chat_id))
+                    .collect();
+                if !cursors.is_empty() {
+                    pushes.push((*subscriber, GroupReadCursorsData { chat_id, cursors }));
+                }
+            }
+        }
+        return pushes;
+    }
+}
+
+/** `read_receipts` 全局的已读位置订阅
+*/
+#[allow(non_upper_case_globals)]
+pub static read_receipts: Lazy<Mutex<ReadReceiptHub>> =
+    Lazy::new(|| Mutex::new(ReadReceiptHub::default()));
+
+/** `start_pushing` 按配置的间隔周期性地推送合并后的已读位置
+*/
+pub async fn start_pushing() {
+    let push_interval = Config::get().protocol.read_receipt_push_interval;
+    info!("正在启动已读位置推送任务，间隔{}毫秒", push_interval);
+    let mut interval = time::interval(Duration::from_millis(std::cmp::max(
+        1,
+        push_interval as u64,
+    )));
+    loop {
+        interval.tick().await;
+        let pushes = read_receipts.lock().unwrap().take_pushes();
+        for (user_id, data) in pushes {
+            user_sessions.do_send_message_to(
+                user_id,
+                UserSessionActorMessage::SendServerMessage(
+                    ServerToClientMessage::GroupReadCursors(data),
+                ),
+            );
+        }
+    }
+}
@@ -0,0 +1,79 @@
+/*!
+ 消息保留策略的后台清理任务
+*/
+
+use std::time::Duration;
+
+use chrono::Utc;
+use log::{error, info};
+use tokio::time;
+
+use crate::config::{
+    config::RetentionPolicy,
+    datatype::{ChatID, Timestamp},
+    Config,
+};
+use crate::database;
+
+/** `get_retention_policy` 获得聊天使用的保留策略，单独设置的策略优先于全局策略
+*/
+pub fn get_retention_policy(chat_id: ChatID) -> &'static RetentionPolicy {
+    let retention_config = &Config::get().retention;
+    return retention_config
+        .groups
+        .get(&chat_id)
+        .unwrap_or(&retention_config.global);
+}
+
+/** `start_pruning` 按配置的间隔周期性地清理所有聊天中超出保留策略的消息
+*/
+pub async fn start_pruning() {
+    let retention_config = &Config::get().retention;
+    if retention_config.global.is_unlimited()
+        && retention_config
+            .groups
+            .values()
+            .all(|policy| policy.is_unlimited())
+    {
+        return;
+    }
+
+    info!(
+        "正在启动消息清理任务，间隔{}秒",
+        retention_config.prune_interval
+    );
+    let mut interval = time::interval(Duration::from_secs(std::cmp::max(
+        1,
+        retention_config.prune_interval as u64,
+    )));
+    loop {
+        interval.tick().await;
+        prune_all_chats().await;
+    }
+}
+
+/** `prune_all_chats` 对所有聊天执行一次清理
+*/
+pub async fn prune_all_chats() {
+    let chat_ids = match database::get_chat_ids().await {
+        Ok(chat_ids) => chat_ids,
+        Err(err) => {
+            error!("读取聊天列表失败: {}", err);
+            return;
+        }
+    };
+
+    let now = Utc::now().timestamp_millis() as Timestamp;
+    for chat_id in chat_ids {
+        let policy = get_retention_policy(chat_id);
+        if policy.is_unlimited() {
+            continue;
+        }
+        let min_timestamp = policy
+            .max_age
+            .map(|max_age| now.saturating_sub(max_age * 1000));
+        if let Err(err) = database::prune_messages(chat_id, min_timestamp, policy.max_count).await {
+            error!("清理聊天{}的消息失败: {}", chat_id, err);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+/*!
+ 定时消息的后台发送任务
+*/
+
+use std::time::Duration;
+
+use chrono::Utc;
+use log::{error, info};
+use tokio::time;
+
+use crate::config::{datatype::Timestamp, Config};
+use crate::database;
+use crate::server::server_state::{user_sessions, UserSessionGetter};
+use crate::user::user_session::protocol::ServerToClientMessage;
+use crate::user::user_session::{send_message, UserSessionActorMessage};
+use crate::user::{ScheduledMessage, UserSendMessageResponse, UserSendMessageResponseState};
+
+/** `start_delivering` 按配置的间隔周期性地发送到期的定时消息
+ * 定时消息保存在数据库中，服务端重启后第一次检查即会补发停机期间到期的消息
+*/
+pub async fn start_delivering() {
+    let check_interval = Config::get().user.schedule_check_interval;
+    info!("正在启动定时消息发送任务，间隔{}秒", check_interval);
+    let mut interval = time::interval(Duration::from_secs(std::cmp::max(1, check_interval as u64)));
+    loop {
+        interval.tick().await;
+        deliver_due_messages().await;
+    }
+}
+
+/** `deliver_due_messages` 发送一次所有到期的定时消息，并将发送结果推送给在线的发送者
+ * 定时消息在发送完成后才会删除，因数据库或服务端错误发送失败的定时消息留到下次检查时重新发送
+*/
+pub async fn deliver_due_messages() {
+    let now = Utc::now().timestamp_millis() as Timestamp;
+    let scheduled = match database::get_due_scheduled_messages(now).await {
+        Ok(scheduled) => scheduled,
+        Err(err) => {
+            error!("读取到期的定时消息失败: {}", err);
+            return;
+        }
+    };
+
+    for ScheduledMessage {
+        schedule_id,
+        sender_id,
+        mut message,
+        ..
+    } in scheduled
+    {
+        message.deliver_at = None;
+        let response = send_message(sender_id, message).await;
+        if is_retryable(&response) {
+            continue;
+        }
+        if let Err(err) = database::remove_scheduled_message(schedule_id).await {
+            error!("删除已发送的定时消息{}失败: {}", schedule_id, err);
+        }
+        user_sessions.do_send_message_to(
+            sender_id,
+            UserSessionActorMessage::SendServerMessage(response),
+        );
+    }
+}
+
+/** `is_retryable` 发送失败的原因是否为暂时性的错误，重新发送可能成功
+*/
+fn is_retryable(response: &ServerToClientMessage) -> bool {
+    return matches!(
+        response,
+        ServerToClientMessage::SendMessageResponse(UserSendMessageResponse {
+            state: UserSendMessageResponseState::DatabaseError
+                | UserSendMessageResponseState::ServerError,
+            ..
+        })
+    );
+}
@@ -2,25 +2,43 @@
 服务器状态有关的函数
 */
 
+use std::net::SocketAddr;
 use std::sync::Arc;
 
 use actix::Recipient;
 use chashmap::CHashMap;
 
+use ed25519_dalek::SigningKey;
 use log::info;
 use once_cell::sync::Lazy;
 
+use super::disappearing;
 use super::email::{self, test_sender, EmailCode, EmailSender};
-use crate::config::datatype::UserID;
+use super::rate_limit;
+use super::read_receipt;
+use super::retention;
+use super::schedule;
+use crate::config::datatype::{DeviceID, Timestamp, UserID};
 use crate::config::Config;
 use crate::database;
 use crate::user::user_session::protocol::ServerToClientMessage;
 use crate::user::user_session::UserSessionActorMessage;
+use crate::utils::base64;
 
 use tokio::runtime::Handle as TokioHandle;
-/** `UserSessionPool` 用户ID到用户连接的哈希表
+/** `DeviceSession` 用户在一台设备上的连接
 */
-pub type UserSessionsPool = CHashMap<UserID, Recipient<UserSessionActorMessage>>;
+#[derive(Clone)]
+pub struct DeviceSession {
+    pub device_id: DeviceID,
+    pub client_ip: SocketAddr,
+    pub login_time: Timestamp,
+    pub recipient: Recipient<UserSessionActorMessage>,
+}
+
+/** `UserSessionPool` 用户ID到用户各设备连接的哈希表
+*/
+pub type UserSessionsPool = CHashMap<UserID, Vec<DeviceSession>>;
 /** `UserEmailCodeMap` 邮箱到验证码的哈希表
 */
 pub type UserEmailCodeMap = CHashMap<String, EmailCode>;
@@ -51,6 +69,7 @@ pub static workers_handle: Lazy<TokioHandle> = Lazy::new(|| {
     let local_runtime = tokio::runtime::Builder::new_multi_thread()
         .worker_threads(num)
         .enable_io()
+        .enable_time()
         .build()
         .unwrap();
 
@@ -64,26 +83,69 @@ pub static workers_handle: Lazy<TokioHandle> = Lazy::new(|| {
 #[allow(non_upper_case_globals)]
 pub static email_sender: Lazy<EmailSender> = Lazy::new(|| email::start_sender());
 
+/** `handshake_signing_key` X25519握手中服务端签名临时公钥所用的私钥
+ * 客户端只信任预先分发的公钥，启用X25519握手而未配置私钥时无法启动
+*/
+#[allow(non_upper_case_globals)]
+pub static handshake_signing_key: Lazy<SigningKey> = Lazy::new(|| {
+    let key = Config::get()
+        .safety
+        .handshake_signing_key
+        .as_ref()
+        .expect("启用X25519握手时必须配置handshake_signing_key");
+    let key: [u8; 32] = base64::decode(key)
+        .ok()
+        .and_then(|key| key.try_into().ok())
+        .expect("handshake_signing_key应为base64编码的32字节Ed25519私钥");
+    let key = SigningKey::from_bytes(&key);
+    info!(
+        "X25519握手签名公钥为{}，需预先分发给客户端",
+        base64::encode(key.verifying_key().as_bytes())
+    );
+    key
+});
+
 pub struct ServerState;
 
 impl ServerState {
     pub async fn start() {
         Lazy::force(&workers_handle);
+        if Config::get().safety.enable_x25519_handshake {
+            Lazy::force(&handshake_signing_key);
+        }
         if Config::get().email.enable {
             Lazy::force(&email_sender);
             test_sender().await;
         }
         database::connect_database().await;
+        workers_handle.spawn(retention::start_pruning());
+        workers_handle.spawn(schedule::start_delivering());
+        workers_handle.spawn(disappearing::start_expiring());
+        workers_handle.spawn(read_receipt::start_pushing());
+        if Config::get().rate_limit.enable {
+            workers_handle.spawn(rate_limit::start_sweeping());
+        }
     }
 }
 
 /** `UserSessionGetter` 可以获取用户连接的特征
 */
 pub trait UserSessionGetter {
-    fn get_user_session(&self, user_id: UserID) -> Option<Recipient<UserSessionActorMessage>>;
+    fn get_user_devices(&self, user_id: UserID) -> Vec<DeviceSession>;
+
+    fn is_online(&self, user_id: UserID) -> bool;
 
+    /// 向用户的全部设备发送消息
     fn do_send_message_to(&self, user_id: UserID, actor_msg: UserSessionActorMessage);
 
+    /// 向用户除`origin`外的其他设备发送消息
+    fn do_send_message_to_other_devices(
+        &self,
+        user_id: UserID,
+        origin: &Recipient<UserSessionActorMessage>,
+        actor_msg: UserSessionActorMessage,
+    );
+
     fn send_message_to_online_with_exclusion(
         &self,
         ids: Vec<UserID>,
@@ -112,13 +174,115 @@ pub trait UserSessionGetter {
 }
 
 impl UserSessionGetter for UserSessionsPool {
-    fn get_user_session(&self, user_id: UserID) -> Option<Recipient<UserSessionActorMessage>> {
-        return self.get(&user_id).map(|guard| guard.clone());
+    fn get_user_devices(&self, user_id: UserID) -> Vec<DeviceSession> {
+        return self.get(&user_id).map_or(vec![], |devices| devices.clone());
+    }
+
+    fn is_online(&self, user_id: UserID) -> bool {
+        return self.contains_key(&user_id);
     }
 
     fn do_send_message_to(&self, user_id: UserID, actor_msg: UserSessionActorMessage) {
-        if let Some(session) = self.get(&user_id) {
-            session.do_send(actor_msg);
+        if let Some(devices) = self.get(&user_id) {
+            if let Some((last, others)) = devices.split_last() {
+                for device in others {
+                    device.recipient.do_send(actor_msg.clone());
+                }
+                last.recipient.do_send(actor_msg);
+            }
         }
     }
+
+    fn do_send_message_to_other_devices(
+        &self,
+        user_id: UserID,
+        origin: &Recipient<UserSessionActorMessage>,
+        actor_msg: UserSessionActorMessage,
+    ) {
+        if let Some(devices) = self.get(&user_id) {
+            for device in devices.iter().filter(|device| device.recipient != *origin) {
+                device.recipient.do_send(actor_msg.clone());
+            }
+        }
+    }
+}
+
+/** `DeviceInsertResult` 登记设备连接的结果
+*/
+pub enum DeviceInsertResult {
+    /// 用户之前没有在线的设备
+    FirstDevice,
+    /// 用户已有其他在线的设备
+    Inserted,
+    /// 替换了同一设备之前的连接
+    Replaced(DeviceSession),
+    /// 在线的设备数量已达上限，没有登记
+    TooManyDevices,
+}
+
+/** `UserSessionRegistry` 可以登记与注销用户设备连接的特征
+*/
+pub trait UserSessionRegistry {
+    /// 登记设备连接，同一设备已有连接时替换之前的连接，新设备只在设备数量未达`max_devices_num`时登记
+    fn insert_device(
+        &self,
+        user_id: UserID,
+        device: DeviceSession,
+        max_devices_num: usize,
+    ) -> DeviceInsertResult;
+
+    /// 注销设备连接，返回用户是否已没有在线的设备
+    fn remove_device(
+        &self,
+        user_id: UserID,
+        recipient: &Recipient<UserSessionActorMessage>,
+    ) -> bool;
+}
+
+impl UserSessionRegistry for UserSessionsPool {
+    fn insert_device(
+        &self,
+        user_id: UserID,
+        device: DeviceSession,
+        max_devices_num: usize,
+    ) -> DeviceInsertResult {
+        // 检查设备数量与登记在同一次修改中完成，并发登录不会超出上限
+        let mut result = DeviceInsertResult::TooManyDevices;
+        self.alter(user_id, |devices| {
+            let mut devices = devices.unwrap_or_default();
+            match devices
+                .iter()
+                .position(|old| old.device_id == device.device_id)
+            {
+                Some(index) => result = DeviceInsertResult::Replaced(devices.remove(index)),
+                None if devices.len() >= max_devices_num => {}
+                None if devices.is_empty() => result = DeviceInsertResult::FirstDevice,
+                None => result = DeviceInsertResult::Inserted,
+            }
+            if !matches!(result, DeviceInsertResult::TooManyDevices) {
+                devices.push(device);
+            }
+            return if devices.is_empty() {
+                None
+            } else {
+                Some(devices)
+            };
+        });
+        return result;
+    }
+
+    fn remove_device(
+        &self,
+        user_id: UserID,
+        recipient: &Recipient<UserSessionActorMessage>,
+    ) -> bool {
+        let mut offline = false;
+        self.alter(user_id, |devices| {
+            let mut devices = devices?;
+            devices.retain(|device| device.recipient != *recipient);
+            offline = devices.is_empty();
+            return if offline { None } else { Some(devices) };
+        });
+        return offline;
+    }
 }
@@ -1,10 +1,15 @@
+use futures::future::join_all;
+
 use crate::{
-    config::datatype::ChatID,
+    chat::ChatMessage,
+    config::datatype::{ChatID, MessageID, Timestamp},
     database,
+    server::disappearing,
     user::{
-        UserCreateGroupChatData, UserGetUserReadInGroupResponse, UserGetUserReadInPrivateResponse,
-        UserGroupOwnerTransferResponse, UserPullGroupNoticeResponse, UserQuitGroupChatResponse,
-        UserSendGroupNoticeResponse, UserSetGroupAdminResponse, UserUnsetGroupAdminResponse,
+        ChatMessageType, ReplyTextType, UserCreateGroupChatData, UserGetUserReadInGroupResponse,
+        UserGetUserReadInPrivateResponse, UserGroupOwnerTransferResponse,
+        UserPullGroupNoticeResponse, UserQuitGroupChatResponse, UserSendGroupNoticeResponse,
+        UserSendMessageData, UserSetGroupAdminResponse, UserUnsetGroupAdminResponse,
         UserUpdateGroupContent, UserUpdateGroupInfoResponse,
     },
 };
@@ -56,7 +61,7 @@ pub async fn test_for_get_messages_in_chat() -> Result<(), ()> {
 }
 
 pub async fn test_for_get_chats_last_messages() -> Result<(), ()> {
-    match database::get_chats_last_messages(&vec![(1, 1)], 1).await {
+    match database::get_chats_last_messages(&[(1, 1)], 1).await {
         Ok(_) => Ok(()),
         Err(_) => panic!("get_chats_last_messages"),
     }
@@ -88,7 +93,7 @@ pub async fn test_for_check_group_invitation_error() -> Result<(), ()> {
         name: "name".to_string(),
         avater_hash: "avater".to_string(),
     };
-    database::create_group_chat(1, data).await?;
+    database::create_group_chat(1, data).await.map_err(|_| ())?;
     match database::check_group_invitation_error(1, 2, 3).await {
         Ok(_) => Ok(()),
         Err(_) => panic!("check_group_invitation_error"),
@@ -96,7 +101,7 @@ pub async fn test_for_check_group_invitation_error() -> Result<(), ()> {
 }
 
 pub async fn test_for_set_as_admin() -> Result<(), ()> {
-    database::add_user_to_chat(3, 2).await?;
+    database::add_user_to_chat(3, 2).await.map_err(|_| ())?;
     match database::set_as_admin(2, 3).await {
         UserSetGroupAdminResponse::Success {
             chat_id: _,
@@ -218,7 +223,9 @@ pub async fn test_for_check_is_group() -> Result<(), ()> {
 }
 
 pub async fn test_for_get_user_read_in_group() -> Result<(), ()> {
-    database::write_message_to_chat("String", "Message".to_string(), 2, 1).await?;
+    database::write_message_to_chat("String", "Message".to_string(), 2, 1)
+        .await
+        .map_err(|_| ())?;
     match database::get_user_read_in_group(2, 1).await {
         UserGetUserReadInGroupResponse::Success {
             chat_id: _,
@@ -245,3 +252,550 @@ pub async fn test_for_get_private_chat_user_list() -> Result<(), ()> {
         Err(_) => panic!("get_private_chat_user_list"),
     }
 }
+
+pub async fn test_for_concurrent_write_message_to_chat() -> Result<(), ()> {
+    let writes =
+        (0..8).map(|_| database::write_message_to_chat("String", "Message".to_string(), 2, 1));
+    let mut in_chat_ids = vec![];
+    for result in join_all(writes).await {
+        match result {
+            Ok((_, in_chat_id, _)) => in_chat_ids.push(in_chat_id),
+            Err(_) => panic!("concurrent_write_message_to_chat"),
+        }
+    }
+    in_chat_ids.sort();
+    in_chat_ids.dedup();
+    match in_chat_ids.len() {
+        8 => Ok(()),
+        _ => panic!("concurrent_write_message_to_chat"),
+    }
+}
+
+pub async fn test_for_get_messages_across_buckets() -> Result<(), ()> {
+    let data = UserCreateGroupChatData {
+        name: "name".to_string(),
+        avater_hash: "avater".to_string(),
+    };
+    let chat_id = database::create_group_chat(1, data).await.map_err(|_| ())?;
+    // 消息数超过一个分块的大小
+    for _ in 0..1100 {
+        database::write_message_to_chat("String", "Message".to_string(), chat_id, 1)
+            .await
+            .map_err(|_| ())?;
+    }
+    match database::get_messages_in_chat(chat_id, 1000, Some(1050)).await {
+        Ok(msgs) if msgs.len() == 51 => {}
+        _ => panic!("get_messages_across_buckets"),
+    }
+    match database::get_messages_in_chat(chat_id, 1, None).await {
+        Ok(msgs) if msgs.len() == 1100 => {}
+        _ => panic!("get_messages_across_buckets"),
+    }
+    match database::get_chats_last_messages(&[(chat_id, 0)], 4).await {
+        Ok(msgs) if msgs.len() == 5 => Ok(()),
+        _ => panic!("get_messages_across_buckets"),
+    }
+}
+
+pub async fn test_for_prune_messages() -> Result<(), ()> {
+    let data = UserCreateGroupChatData {
+        name: "name".to_string(),
+        avater_hash: "avater".to_string(),
+    };
+    let chat_id = database::create_group_chat(1, data).await.map_err(|_| ())?;
+    for _ in 0..1100 {
+        database::write_message_to_chat(
+            ChatMessageType::Text.get_str(),
+            serde_json::to_string("Message").unwrap(),
+            chat_id,
+            1,
+        )
+        .await
+        .map_err(|_| ())?;
+    }
+    match database::prune_messages(chat_id, None, Some(50)).await {
+        Ok(1050) => {}
+        _ => panic!("prune_messages"),
+    }
+    match database::get_messages_in_chat(chat_id, 1, None).await {
+        Ok(msgs) if msgs.len() == 50 => {}
+        _ => panic!("prune_messages"),
+    }
+    let min_timestamp = chrono::Utc::now().timestamp_millis() as Timestamp + 1;
+    match database::prune_messages(chat_id, Some(min_timestamp), None).await {
+        Ok(1100) => {}
+        _ => panic!("prune_messages"),
+    }
+    match database::get_pruned_id(chat_id).await {
+        Ok(1100) => Ok(()),
+        _ => panic!("prune_messages"),
+    }
+}
+
+pub async fn test_for_search_messages() -> Result<(), ()> {
+    let data = UserCreateGroupChatData {
+        name: "name".to_string(),
+        avater_hash: "avater".to_string(),
+    };
+    let chat_id = database::create_group_chat(1, data).await.map_err(|_| ())?;
+    let mention = r#"{"userIds":[1],"text":"你好世界"}"#;
+    let msgs = [
+        (ChatMessageType::Text, "Hello World"),
+        (ChatMessageType::MentionText, mention),
+        (ChatMessageType::Image, "hello"),
+        (ChatMessageType::Text, "hello again"),
+    ];
+    let mut in_chat_ids = vec![];
+    for (r#type, content) in msgs {
+        let (_, in_chat_id, _) = database::write_message_to_chat(
+            r#type.get_str(),
+            serde_json::to_string(content).unwrap(),
+            chat_id,
+            1,
+        )
+        .await
+        .map_err(|_| ())?;
+        in_chat_ids.push(in_chat_id);
+    }
+    let chat_ids = vec![chat_id];
+    match database::search_messages(&chat_ids, "hello", None, 10).await {
+        Ok(page) if page.messages.len() == 2 && page.messages[0].contains("again") => {}
+        _ => panic!("search_messages"),
+    }
+    let next_cursor = match database::search_messages(&chat_ids, "hello", None, 1).await {
+        Ok(page) if page.messages.len() == 1 && page.messages[0].contains("again") => {
+            page.next_cursor
+        }
+        _ => panic!("search_messages"),
+    };
+    match database::search_messages(&chat_ids, "hello", next_cursor, 10).await {
+        Ok(page)
+            if page.messages.len() == 1
+                && page.messages[0].contains("World")
+                && page.next_cursor.is_none() => {}
+        _ => panic!("search_messages"),
+    }
+    match database::search_messages(&chat_ids, "界", None, 10).await {
+        Ok(page) if page.messages.len() == 1 => {}
+        _ => panic!("search_messages"),
+    }
+    match database::search_messages(&chat_ids, "world hello", None, 10).await {
+        Ok(page) if page.messages.is_empty() => {}
+        _ => panic!("search_messages"),
+    }
+    database::revoke_message(chat_id, in_chat_ids[3], 1, 0)
+        .await
+        .map_err(|_| ())?;
+    match database::search_messages(&chat_ids, "hello", None, 10).await {
+        Ok(page) if page.messages.len() == 1 => Ok(()),
+        _ => panic!("search_messages"),
+    }
+}
+
+pub async fn test_for_edit_message() -> Result<(), ()> {
+    let data = UserCreateGroupChatData {
+        name: "name".to_string(),
+        avater_hash: "avater".to_string(),
+    };
+    let chat_id = database::create_group_chat(1, data).await.map_err(|_| ())?;
+    let (old_msg, in_chat_id, _) = database::write_message_to_chat(
+        ChatMessageType::Text.get_str(),
+        serde_json::to_string("old text").unwrap(),
+        chat_id,
+        1,
+    )
+    .await
+    .map_err(|_| ())?;
+    let chat_msg = serde_json::from_str::<ChatMessage>(&old_msg).unwrap();
+    let new_msg = serde_json::to_string(&ChatMessage {
+        serialized_content: "new text".to_string(),
+        edited_at: Some(chat_msg.timestamp),
+        ..chat_msg
+    })
+    .unwrap();
+    match database::edit_message(chat_id, in_chat_id, &old_msg, &new_msg).await {
+        Ok(true) => {}
+        _ => panic!("edit_message"),
+    }
+    // 消息已不是编辑前的版本
+    match database::edit_message(chat_id, in_chat_id, &old_msg, &new_msg).await {
+        Ok(false) => {}
+        _ => panic!("edit_message"),
+    }
+    match database::get_messages_in_chat(chat_id, in_chat_id, Some(in_chat_id)).await {
+        Ok(msgs) if msgs == vec![new_msg.clone()] => {}
+        _ => panic!("edit_message"),
+    }
+    match database::search_messages(&[chat_id], "old", None, 10).await {
+        Ok(page) if page.messages.is_empty() => {}
+        _ => panic!("edit_message"),
+    }
+    match database::search_messages(&[chat_id], "new", None, 10).await {
+        Ok(page) if page.messages.len() == 1 => {}
+        _ => panic!("edit_message"),
+    }
+    match database::get_edit_history(chat_id, in_chat_id).await {
+        Ok(history) if history == vec![old_msg] => {}
+        _ => panic!("edit_message"),
+    }
+    database::revoke_message(chat_id, in_chat_id, 1, 0)
+        .await
+        .map_err(|_| ())?;
+    match database::get_edit_history(chat_id, in_chat_id).await {
+        Ok(history) if history.is_empty() => Ok(()),
+        _ => panic!("edit_message"),
+    }
+}
+
+pub async fn test_for_message_reactions() -> Result<(), ()> {
+    let data = UserCreateGroupChatData {
+        name: "name".to_string(),
+        avater_hash: "avater".to_string(),
+    };
+    let chat_id = database::create_group_chat(1, data).await.map_err(|_| ())?;
+    let (_, in_chat_id, _) = database::write_message_to_chat(
+        ChatMessageType::Text.get_str(),
+        serde_json::to_string("text").unwrap(),
+        chat_id,
+        1,
+    )
+    .await
+    .map_err(|_| ())?;
+    match database::add_reaction(chat_id, in_chat_id, 1, "👍").await {
+        Ok(true) => {}
+        _ => panic!("message_reactions"),
+    }
+    // 同一用户重复添加同一表情不计数
+    match database::add_reaction(chat_id, in_chat_id, 1, "👍").await {
+        Ok(false) => {}
+        _ => panic!("message_reactions"),
+    }
+    match database::add_reaction(chat_id, in_chat_id, 2, "👍").await {
+        Ok(true) => {}
+        _ => panic!("message_reactions"),
+    }
+    match database::get_messages_in_chat(chat_id, in_chat_id, None).await {
+        Ok(msgs) if msgs.len() == 1 => {
+            let chat_msg = serde_json::from_str::<ChatMessage>(&msgs[0]).unwrap();
+            assert_eq!(chat_msg.reactions.get("👍"), Some(&2));
+        }
+        _ => panic!("message_reactions"),
+    }
+    match database::remove_reaction(chat_id, in_chat_id, 2, "👍").await {
+        Ok(true) => {}
+        _ => panic!("message_reactions"),
+    }
+    match database::remove_reaction(chat_id, in_chat_id, 2, "👍").await {
+        Ok(false) => {}
+        _ => panic!("message_reactions"),
+    }
+    // 原始消息不带回应人数
+    match database::get_message(chat_id, in_chat_id).await {
+        Ok(Some(msg)) if !msg.contains("reactions") => {}
+        _ => panic!("message_reactions"),
+    }
+    database::revoke_message(chat_id, in_chat_id, 1, 0)
+        .await
+        .map_err(|_| ())?;
+    match database::get_messages_in_chat(chat_id, in_chat_id, None).await {
+        Ok(msgs) if msgs.len() == 1 && !msgs[0].contains("reactions") => Ok(()),
+        _ => panic!("message_reactions"),
+    }
+}
+
+pub async fn test_for_message_threads() -> Result<(), ()> {
+    let data = UserCreateGroupChatData {
+        name: "name".to_string(),
+        avater_hash: "avater".to_string(),
+    };
+    let chat_id = database::create_group_chat(1, data).await.map_err(|_| ())?;
+    let (_, root_id, _) = database::write_message_to_chat(
+        ChatMessageType::Text.get_str(),
+        serde_json::to_string("root").unwrap(),
+        chat_id,
+        1,
+    )
+    .await
+    .map_err(|_| ())?;
+    let mut reply_ids = vec![];
+    for text in ["first", "second"] {
+        let content = ReplyTextType {
+            reply_to_in_chat_id: root_id,
+            text: text.to_string(),
+        };
+        let (_, reply_id, _) = database::reply_message_to_chat(
+            ChatMessageType::ReplyText.get_str(),
+            serde_json::to_string(&serde_json::to_string(&content).unwrap()).unwrap(),
+            chat_id,
+            2,
+            root_id,
+        )
+        .await
+        .map_err(|_| ())?;
+        reply_ids.push(reply_id);
+    }
+    match database::get_thread_replies(chat_id, root_id).await {
+        Ok(replies) => {
+            let ids: Vec<MessageID> = replies
+                .iter()
+                .map(|reply| {
+                    serde_json::from_str::<ChatMessage>(reply)
+                        .unwrap()
+                        .in_chat_id
+                })
+                .collect();
+            assert_eq!(ids, reply_ids);
+        }
+        _ => panic!("message_threads"),
+    }
+    match database::get_messages_in_chat(chat_id, root_id, Some(root_id)).await {
+        Ok(msgs) if msgs.len() == 1 => {
+            let chat_msg = serde_json::from_str::<ChatMessage>(&msgs[0]).unwrap();
+            assert_eq!(chat_msg.reply_count, 2);
+        }
+        _ => panic!("message_threads"),
+    }
+    // 回复的文本可被搜索
+    match database::search_messages(&[chat_id], "second", None, 10).await {
+        Ok(page) if page.messages.len() == 1 => {}
+        _ => panic!("message_threads"),
+    }
+    match database::get_thread_replies(chat_id, reply_ids[0]).await {
+        Ok(replies) if replies.is_empty() => Ok(()),
+        _ => panic!("message_threads"),
+    }
+}
+
+pub async fn test_for_pinned_messages() -> Result<(), ()> {
+    let data = UserCreateGroupChatData {
+        name: "name".to_string(),
+        avater_hash: "avater".to_string(),
+    };
+    let chat_id = database::create_group_chat(1, data).await.map_err(|_| ())?;
+    let mut in_chat_ids = vec![];
+    for text in ["first", "second"] {
+        let (_, in_chat_id, _) = database::write_message_to_chat(
+            ChatMessageType::Text.get_str(),
+            serde_json::to_string(text).unwrap(),
+            chat_id,
+            1,
+        )
+        .await
+        .map_err(|_| ())?;
+        in_chat_ids.push(in_chat_id);
+    }
+    for in_chat_id in in_chat_ids.iter().rev() {
+        match database::pin_message(chat_id, *in_chat_id).await {
+            Ok(true) => {}
+            _ => panic!("pinned_messages"),
+        }
+    }
+    match database::pin_message(chat_id, in_chat_ids[0]).await {
+        Ok(false) => {}
+        _ => panic!("pinned_messages"),
+    }
+    // 置顶消息按in_chat_id排列
+    match database::get_pinned_messages(chat_id).await {
+        Ok(msgs) => {
+            let ids: Vec<MessageID> = msgs
+                .iter()
+                .map(|msg| serde_json::from_str::<ChatMessage>(msg).unwrap().in_chat_id)
+                .collect();
+            assert_eq!(ids, in_chat_ids);
+        }
+        _ => panic!("pinned_messages"),
+    }
+    match database::unpin_message(chat_id, in_chat_ids[0]).await {
+        Ok(true) => {}
+        _ => panic!("pinned_messages"),
+    }
+    match database::unpin_message(chat_id, in_chat_ids[0]).await {
+        Ok(false) => {}
+        _ => panic!("pinned_messages"),
+    }
+    // 撤回的消息不再置顶
+    database::revoke_message(chat_id, in_chat_ids[1], 1, 0)
+        .await
+        .map_err(|_| ())?;
+    match database::get_pinned_messages(chat_id).await {
+        Ok(msgs) if msgs.is_empty() => Ok(()),
+        _ => panic!("pinned_messages"),
+    }
+}
+
+pub async fn test_for_forward_messages() -> Result<(), ()> {
+    let mut chat_ids = vec![];
+    for _ in 0..3 {
+        let data = UserCreateGroupChatData {
+            name: "name".to_string(),
+            avater_hash: "avater".to_string(),
+        };
+        chat_ids.push(database::create_group_chat(1, data).await.map_err(|_| ())?);
+    }
+    let content = ReplyTextType {
+        reply_to_in_chat_id: 1,
+        text: "forwarded text".to_string(),
+    };
+    let (serialized_msg, _, _) = database::write_message_to_chat(
+        ChatMessageType::ReplyText.get_str(),
+        serde_json::to_string(&serde_json::to_string(&content).unwrap()).unwrap(),
+        chat_ids[0],
+        1,
+    )
+    .await
+    .map_err(|_| ())?;
+    let origin = serde_json::from_str::<ChatMessage>(&serialized_msg).unwrap();
+    let origin_from = origin.get_forwarded_from();
+
+    // 回复只转发其中的文本，经过两次转发仍保留最初的来源
+    let mut chat_msg = origin;
+    for chat_id in &chat_ids[1..] {
+        let (r#type, content) = chat_msg.get_forward_content().unwrap();
+        assert_eq!(r#type, ChatMessageType::Text);
+        let (serialized_msg, _, _) = database::forward_message_to_chat(
+            r#type.get_str(),
+            serde_json::to_string(&content).unwrap(),
+            *chat_id,
+            2,
+            &chat_msg.get_forwarded_from(),
+        )
+        .await
+        .map_err(|_| ())?;
+        chat_msg = serde_json::from_str::<ChatMessage>(&serialized_msg).unwrap();
+        assert_eq!(chat_msg.sender_id, 2);
+        assert_eq!(chat_msg.forwarded_from.as_ref(), Some(&origin_from));
+    }
+
+    match database::search_messages(&chat_ids[2..], "forwarded", None, 10).await {
+        Ok(page) if page.messages.len() == 1 => Ok(()),
+        _ => panic!("forward_messages"),
+    }
+}
+
+pub async fn test_for_scheduled_messages() -> Result<(), ()> {
+    let new_msg = |chat_id: ChatID, text: &str| UserSendMessageData {
+        r#type: ChatMessageType::Text,
+        client_id: 1,
+        chat_id,
+        timestamp: 1,
+        serialized_content: text.to_string(),
+        deliver_at: None,
+    };
+    let mut schedule_ids = vec![];
+    for (sender_id, deliver_at, text) in
+        [(1, 2000, "later"), (1, 1000, "first"), (2, 3000, "other")]
+    {
+        let schedule_id = database::add_scheduled_message(sender_id, deliver_at, &new_msg(1, text))
+            .await
+            .map_err(|_| ())?;
+        schedule_ids.push(schedule_id);
+    }
+
+    // 按投递时间排列，且只能取消自己的定时消息
+    let scheduled = database::get_scheduled_messages(1).await.map_err(|_| ())?;
+    assert_eq!(
+        scheduled.iter().map(|s| s.schedule_id).collect::<Vec<_>>(),
+        vec![schedule_ids[1], schedule_ids[0]]
+    );
+    assert_eq!(
+        database::cancel_scheduled_message(2, schedule_ids[0]).await,
+        Ok(false)
+    );
+    assert_eq!(
+        database::cancel_scheduled_message(1, schedule_ids[0]).await,
+        Ok(true)
+    );
+
+    // 到期的定时消息在删除前不会消失，且只能删除一次
+    let due = database::get_due_scheduled_messages(2500)
+        .await
+        .map_err(|_| ())?;
+    assert_eq!(due.len(), 1);
+    assert_eq!(due[0].schedule_id, schedule_ids[1]);
+    assert_eq!(due[0].message.serialized_content, "first");
+    assert_eq!(
+        database::get_due_scheduled_messages(2500)
+            .await
+            .map_err(|_| ())?
+            .len(),
+        1
+    );
+    assert_eq!(
+        database::remove_scheduled_message(schedule_ids[1]).await,
+        Ok(true)
+    );
+    assert_eq!(
+        database::remove_scheduled_message(schedule_ids[1]).await,
+        Ok(false)
+    );
+    assert!(database::get_due_scheduled_messages(2500)
+        .await
+        .map_err(|_| ())?
+        .is_empty());
+
+    match (
+        database::get_scheduled_messages(1).await,
+        database::get_scheduled_messages(2).await,
+    ) {
+        (Ok(first), Ok(second)) if first.is_empty() && second.len() == 1 => Ok(()),
+        _ => panic!("scheduled_messages"),
+    }
+}
+
+pub async fn test_for_disappearing_messages() -> Result<(), ()> {
+    let data = UserCreateGroupChatData {
+        name: "name".to_string(),
+        avater_hash: "avater".to_string(),
+    };
+    let chat_id = database::create_group_chat(1, data).await.map_err(|_| ())?;
+    let write_text = |text: &str| {
+        database::write_message_to_chat(
+            ChatMessageType::Text.get_str(),
+            serde_json::to_string(text).unwrap(),
+            chat_id,
+            1,
+        )
+    };
+    let (_, before_id, _) = write_text("before").await.map_err(|_| ())?;
+
+    // 开启前发送的消息不会过期，之后修改存活时间不影响过期检查的位置
+    database::set_disappearing_ttl(chat_id, Some(60))
+        .await
+        .map_err(|_| ())?;
+    assert_eq!(database::get_expired_id(chat_id).await, Ok(before_id));
+    let (_, after_id, timestamp) = write_text("after").await.map_err(|_| ())?;
+    database::set_disappearing_ttl(chat_id, Some(30))
+        .await
+        .map_err(|_| ())?;
+    assert_eq!(database::get_expired_id(chat_id).await, Ok(before_id));
+    assert!(database::get_disappearing_chats()
+        .await
+        .map_err(|_| ())?
+        .contains(&(chat_id, 30)));
+
+    assert_eq!(
+        disappearing::expire_messages(chat_id, 30, timestamp + 29_000).await,
+        Ok(0)
+    );
+    assert_eq!(
+        disappearing::expire_messages(chat_id, 30, timestamp + 30_000).await,
+        Ok(1)
+    );
+    assert_eq!(database::get_expired_id(chat_id).await, Ok(after_id));
+    let msgs = database::get_messages_in_chat(chat_id, before_id, None)
+        .await
+        .map_err(|_| ())?;
+    let types: Vec<ChatMessageType> = msgs
+        .iter()
+        .map(|msg| serde_json::from_str::<ChatMessage>(msg).unwrap().r#type)
+        .collect();
+    assert_eq!(types, vec![ChatMessageType::Text, ChatMessageType::Revoked]);
+
+    database::set_disappearing_ttl(chat_id, None)
+        .await
+        .map_err(|_| ())?;
+    match database::get_disappearing_ttl(chat_id).await {
+        Ok(None) => Ok(()),
+        _ => panic!("disappearing_messages"),
+    }
+}
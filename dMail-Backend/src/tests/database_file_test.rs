@@ -29,21 +29,21 @@ pub async fn test_for_write_file_public_url() -> Result<(), ()> {
         url: "url".to_string(),
         expire: 1,
     };
-    match database::write_file_public_url(&"hash".to_string(), &pub_url).await {
+    match database::write_file_public_url("hash", &pub_url).await {
         Ok(_) => Ok(()),
         Err(_) => panic!("write_file_public_url"),
     }
 }
 
 pub async fn test_for_get_file_public_url() -> Result<(), ()> {
-    match database::get_file_public_url(&"hash".to_string()).await {
+    match database::get_file_public_url("hash").await {
         Ok(_) => Ok(()),
         Err(_) => panic!("get_file_public_url"),
     }
 }
 
 pub async fn test_for_get_file_url() -> Result<(), ()> {
-    match database::get_file_url(&"hash".to_string()).await {
+    match database::get_file_url("hash").await {
         Ok(_) => Ok(()),
         Err(_) => panic!("get_file_url"),
     }
@@ -1,11 +1,16 @@
+use futures::future::join_all;
+
 use crate::database;
+use crate::server::presence;
 use crate::user::user_session::protocol::DataChecker;
 use crate::user::{
-    GetUserInfoResponse, SetAlreadyReadResponse, SetSettingResponse, UserApplyForTokenResponse,
-    UserCreateGroupChatData, UserGetUserIDResponse, UserLogOffResponse, UserLoginData,
-    UserLoginResponse, UserRegisterData, UserRegisterResponse, UserSetAlreadyReadData,
+    GetUserInfoResponse, PresenceStatus, SetAlreadyReadResponse, SetSettingResponse,
+    UserApplyForTokenResponse, UserCreateGroupChatData, UserGetUserIDResponse, UserLogOffResponse,
+    UserLoginData, UserLoginResponse, UserPresenceData, UserRefreshTokenData,
+    UserRefreshTokenResponse, UserRegisterData, UserRegisterResponse, UserSetAlreadyReadData,
     UserUnfriendResponse, UserUpdateResponse,
 };
+use crate::utils::password::{self, PasswordCheck};
 
 //register_password format
 pub fn test_for_check_password() -> Result<(), ()> {
@@ -57,6 +62,29 @@ pub fn test_for_check_username() -> Result<(), ()> {
     }
 }
 
+pub fn test_for_password_hash() {
+    let hash = password::hash_password("1234").expect("hash_password");
+    assert_ne!(hash, "1234");
+    assert_ne!(hash, password::hash_password("1234").unwrap());
+    assert_eq!(
+        password::check_password(&hash, "1234"),
+        PasswordCheck::Match
+    );
+    assert_eq!(
+        password::check_password(&hash, "4321"),
+        PasswordCheck::Mismatch
+    );
+    // 旧版明文密码
+    assert_eq!(
+        password::check_password("1234", "1234"),
+        PasswordCheck::MatchLegacy
+    );
+    assert_eq!(
+        password::check_password("1234", "4321"),
+        PasswordCheck::Mismatch
+    );
+}
+
 pub async fn test_for_user_register() -> Result<(), ()> {
     let data = UserRegisterData {
         user_name: "name".to_string(),
@@ -77,6 +105,7 @@ pub async fn test_for_user_login_with_password() -> Result<(), ()> {
         token: None,
         password: Some("1234".to_string()),
         email_code: None,
+        device_id: None,
     };
     match database::user_login_with_password(data).await {
         UserLoginResponse::Success { user_id: _ } => Ok(()),
@@ -85,29 +114,149 @@ pub async fn test_for_user_login_with_password() -> Result<(), ()> {
 }
 
 pub async fn test_for_apply_for_token() -> Result<String, ()> {
-    match database::apply_for_token(1).await {
+    match database::apply_for_token(1, &"device".to_string()).await {
         UserApplyForTokenResponse::Success {
-            token,
+            device_id: _,
+            access_token,
+            refresh_token: _,
             timestamp: _,
-        } => Ok(token),
+        } => Ok(access_token),
         _ => panic!("apply_for_token"),
     }
 }
 
-pub async fn test_for_user_login_with_token(token: String) -> Result<(), ()> {
-    let data = UserLoginData {
+fn token_login_data(token: Option<String>) -> UserLoginData {
+    return UserLoginData {
         email: "a@b.com".to_string(),
         address: None,
-        token: Some(token),
+        token,
         password: None,
         email_code: None,
+        device_id: Some("device".to_string()),
     };
-    match database::user_login_with_token(data).await {
+}
+
+fn refresh_token_data(refresh_token: &str) -> UserRefreshTokenData {
+    return UserRefreshTokenData {
+        email: "a@b.com".to_string(),
+        device_id: "device".to_string(),
+        refresh_token: refresh_token.to_string(),
+    };
+}
+
+pub async fn test_for_user_login_with_token(token: String) -> Result<(), ()> {
+    match database::user_login_with_token(token_login_data(Some(token))).await {
         UserLoginResponse::Success { user_id: _ } => Ok(()),
         _ => panic!("login_with_token"),
     }
 }
 
+pub async fn test_for_revoke_token() -> Result<(), ()> {
+    let device_id = "device".to_string();
+    let (access_token, refresh_token) = match database::apply_for_token(1, &device_id).await {
+        UserApplyForTokenResponse::Success {
+            device_id: _,
+            access_token,
+            refresh_token,
+            timestamp: _,
+        } => (access_token, refresh_token),
+        _ => panic!("revoke_token: apply_for_token"),
+    };
+    // 刷新令牌不能直接用于登录
+    match database::user_login_with_token(token_login_data(Some(refresh_token.clone()))).await {
+        UserLoginResponse::TokenError => {}
+        _ => panic!("revoke_token: login_with_refresh_token"),
+    }
+    let (new_access_token, new_refresh_token) =
+        match database::refresh_token(&refresh_token_data(&refresh_token)).await {
+            UserRefreshTokenResponse::Success {
+                device_id: _,
+                access_token,
+                refresh_token,
+                timestamp: _,
+            } => (access_token, refresh_token),
+            _ => panic!("revoke_token: refresh_token"),
+        };
+    // 换取后旧的两个令牌均失效
+    match database::refresh_token(&refresh_token_data(&refresh_token)).await {
+        UserRefreshTokenResponse::TokenError => {}
+        _ => panic!("revoke_token: refresh_twice"),
+    }
+    match database::user_login_with_token(token_login_data(Some(access_token))).await {
+        UserLoginResponse::TokenError => {}
+        _ => panic!("revoke_token: login_with_old_access_token"),
+    }
+    match database::user_login_with_token(token_login_data(Some(new_access_token.clone()))).await {
+        UserLoginResponse::Success { user_id: _ } => {}
+        _ => panic!("revoke_token: login_with_new_access_token"),
+    }
+    match database::revoke_token(1, &device_id).await {
+        Ok(true) => {}
+        _ => panic!("revoke_token"),
+    }
+    match database::revoke_token(1, &device_id).await {
+        Ok(false) => {}
+        _ => panic!("revoke_token: revoked twice"),
+    }
+    match database::user_login_with_token(token_login_data(Some(new_access_token))).await {
+        UserLoginResponse::TokenError => {}
+        _ => panic!("revoke_token: login_after_revoke"),
+    }
+    match database::refresh_token(&refresh_token_data(&new_refresh_token)).await {
+        UserRefreshTokenResponse::TokenError => {}
+        _ => panic!("revoke_token: refresh_after_revoke"),
+    }
+
+    // 修改密码会撤销全部token
+    let access_token = test_for_apply_for_token().await?;
+    test_for_update_user_password().await?;
+    match database::user_login_with_token(token_login_data(Some(access_token))).await {
+        UserLoginResponse::TokenError => {}
+        _ => panic!("revoke_token: login_after_update_password"),
+    }
+
+    test_for_apply_for_token().await?;
+    match database::revoke_all_tokens(1).await {
+        Ok(_) => Ok(()),
+        Err(_) => panic!("revoke_all_tokens"),
+    }
+}
+
+pub async fn test_for_device_keys() -> Result<(), ()> {
+    let device_id = "device".to_string();
+    match database::get_device_keys(1, &device_id).await {
+        Ok(None) => {}
+        _ => panic!("device_keys: get_before_set"),
+    }
+    for serialized in ["keys_1".to_string(), "keys_2".to_string()] {
+        if database::set_device_keys(1, &device_id, &serialized)
+            .await
+            .is_err()
+        {
+            panic!("device_keys: set");
+        }
+    }
+    // 同一设备的公钥会被替换
+    match database::get_device_keys(1, &device_id).await {
+        Ok(Some(serialized)) => assert_eq!(serialized, "keys_2"),
+        _ => panic!("device_keys: get"),
+    }
+    if database::set_device_keys(1, &"other".to_string(), "keys_3")
+        .await
+        .is_err()
+    {
+        panic!("device_keys: set_other");
+    }
+    match database::get_user_device_keys(1).await {
+        Ok(mut keys) => {
+            keys.sort();
+            assert_eq!(keys, vec!["keys_2".to_string(), "keys_3".to_string()]);
+            Ok(())
+        }
+        Err(_) => panic!("device_keys: get_user_device_keys"),
+    }
+}
+
 pub async fn test_for_get_user_id_by_email() -> Result<(), ()> {
     let email = "a@b.com".to_string();
     match database::get_user_id_by_email(&email).await {
@@ -201,7 +350,9 @@ pub async fn test_for_check_user_in_chat() -> Result<(), ()> {
 }
 
 pub async fn test_for_unfriend() -> Result<(), ()> {
-    database::make_two_users_be_friends(1, 2).await?;
+    database::make_two_users_be_friends(1, 2)
+        .await
+        .map_err(|_| ())?;
     match database::unfriend(1, 2).await {
         UserUnfriendResponse::Success { chat_id: _ } => Ok(()),
         _ => panic!("unfriend"),
@@ -216,6 +367,42 @@ pub async fn test_for_get_chat_id_by_friends() -> Result<(), ()> {
     }
 }
 
+pub async fn test_for_user_presence() -> Result<(), ()> {
+    database::set_user_last_seen(1, 100).await.map_err(|_| ())?;
+    if presence::get_user_friends(1).await != Ok(vec![2]) {
+        panic!("get_user_friends");
+    }
+    let offline = UserPresenceData {
+        user_id: 1,
+        status: PresenceStatus::Offline,
+        last_seen: Some(100),
+    };
+    if presence::get_presence(2, 1).await != Ok(offline.clone()) {
+        panic!("get_presence");
+    }
+    // 隐藏后他人看不到最后在线时间，自己仍能看到
+    database::set_presence_hidden(1, true)
+        .await
+        .map_err(|_| ())?;
+    let hidden = UserPresenceData {
+        last_seen: None,
+        ..offline.clone()
+    };
+    if presence::get_presence(2, 1).await != Ok(hidden) {
+        panic!("get_presence hidden");
+    }
+    if presence::get_presence(1, 1).await != Ok(offline) {
+        panic!("get_presence self");
+    }
+    database::set_presence_hidden(1, false)
+        .await
+        .map_err(|_| ())?;
+    match database::get_presence_hidden(1).await {
+        Ok(false) => Ok(()),
+        _ => panic!("get_presence_hidden"),
+    }
+}
+
 pub async fn test_for_get_user_id() -> Result<(), ()> {
     match database::get_user_id("new_name".to_string()).await {
         UserGetUserIDResponse::Success { user_ids: _ } => Ok(()),
@@ -257,15 +444,37 @@ pub async fn test_for_user_log_off() -> Result<(), ()> {
         email: "e@f.com".to_string(),
     };
     database::user_register(data).await;
-    database::make_two_users_be_friends(1, 3).await?;
+    database::make_two_users_be_friends(1, 3)
+        .await
+        .map_err(|_| ())?;
     let data = UserCreateGroupChatData {
         name: "name".to_string(),
         avater_hash: "avater".to_string(),
     };
-    database::create_group_chat(1, data).await?;
-    database::add_user_to_chat(3, 3).await?;
+    database::create_group_chat(1, data).await.map_err(|_| ())?;
+    database::add_user_to_chat(3, 3).await.map_err(|_| ())?;
     match database::user_log_off(3).await {
         (UserLogOffResponse::Success, _) => Ok(()),
         _ => panic!("user_log_off"),
     }
 }
+
+pub async fn test_for_concurrent_user_register() -> Result<(), ()> {
+    let registers = (0..8).map(|_| {
+        database::user_register(UserRegisterData {
+            user_name: "name".to_string(),
+            password: "1234".to_string(),
+            email_code: 123456,
+            email: "g@h.com".to_string(),
+        })
+    });
+    let success_num = join_all(registers)
+        .await
+        .into_iter()
+        .filter(|response| matches!(response, UserRegisterResponse::Success { .. }))
+        .count();
+    match success_num {
+        1 => Ok(()),
+        _ => panic!("concurrent_user_register"),
+    }
+}
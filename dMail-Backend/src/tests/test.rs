@@ -16,18 +16,19 @@ use super::database_user_test;
 use super::user_session_test;
 use super::user_session_test_supplement;
 use crate::server::server_state::ServerState;
+use crate::user::user_session::protocol::LEGACY_PROTOCOL_VERSION;
 use crate::user::user_session::UserSessionData;
 use crate::user::user_session::UserSessionState;
 use crate::user::user_session::WsUserSession;
 use crate::{config::Config, database};
 use std::cell::RefCell;
-use std::process::Command;
+use std::collections::HashMap;
 use std::rc::Rc;
 use std::thread;
 
 #[test]
 pub fn test_database() -> Result<(), ()> {
-    Config::init();
+    Config::init_for_test();
     let rt = tokio::runtime::Builder::new_current_thread()
         .enable_io()
         .build()
@@ -55,6 +56,9 @@ async fn connect_web_socket(
                 client_ip: addr,
                 user_id: None,
                 user_pub_key: None,
+                device_id: None,
+                protocol_version: LEGACY_PROTOCOL_VERSION,
+                typing: HashMap::new(),
             })),
             cipher: None,
             last_receive_time: std::time::Instant::now(),
@@ -65,7 +69,7 @@ async fn connect_web_socket(
 }
 
 pub async fn test_main() {
-    Config::init();
+    Config::init_for_test();
     env_logger::init();
     ServerState::start().await;
     let server = HttpServer::new(move || App::new().service(connect_web_socket)).workers(2);
@@ -98,6 +102,12 @@ pub fn test_user_session() -> Result<(), ()> {
     let connection = connect(Url::parse("ws://127.0.0.1:8080/ws").unwrap());
     let mut socket = connection.unwrap().0;
     rt.block_on(user_session_test(&mut socket))?;
+    let connection_2 = connect(Url::parse("ws://127.0.0.1:8080/ws").unwrap());
+    let mut socket_2 = connection_2.unwrap().0;
+    user_session_test::test_for_user_session_frame_nonce(&mut socket_2);
+    let connection_3 = connect(Url::parse("ws://127.0.0.1:8080/ws").unwrap());
+    let mut socket_3 = connection_3.unwrap().0;
+    user_session_test::test_for_user_session_key_exchange(&mut socket_3);
 
     return Ok(());
 }
@@ -105,6 +115,7 @@ pub fn test_user_session() -> Result<(), ()> {
 pub async fn database_test() -> Result<(), ()> {
     database_user_test::test_for_check_password()?;
     database_user_test::test_for_check_username()?;
+    database_user_test::test_for_password_hash();
     database_user_test::test_for_user_register().await?;
     database_user_test::test_for_user_login_with_password().await?;
     let token = database_user_test::test_for_apply_for_token().await?;
@@ -120,8 +131,11 @@ pub async fn database_test() -> Result<(), ()> {
     database_user_test::test_for_update_user_name().await?;
     database_user_test::test_for_update_user_avater().await?;
     database_user_test::test_for_update_user_password().await?;
+    database_user_test::test_for_revoke_token().await?;
+    database_user_test::test_for_device_keys().await?;
     database_user_test::test_for_check_user_in_chat().await?;
     database_user_test::test_for_get_chat_id_by_friends().await?;
+    database_user_test::test_for_user_presence().await?;
     database_user_test::test_for_get_user_id().await?;
     database_user_test::test_for_write_user_notice().await?;
     database_user_test::test_for_get_user_notice().await?;
@@ -129,7 +143,6 @@ pub async fn database_test() -> Result<(), ()> {
     database_chat_test::test_for_add_user_to_chat().await?;
     database_chat_test::test_for_check_user_can_send_in_chat(1).await?;
     database_chat_test::test_for_check_user_can_send_in_chat(2).await?;
-    database_user_test::test_for_user_log_off().await?;
     database_chat_test::test_for_get_chat_info().await?;
     database_chat_test::test_for_get_chat_owner().await?;
     database_chat_test::test_for_get_chat_user_list(1).await?;
@@ -175,9 +188,21 @@ pub async fn database_test() -> Result<(), ()> {
     database_request_test::test_for_delete_invite_request_send().await?;
     database_request_test::test_for_delete_join_group_request_send().await?;
     database_user_test::test_for_unfriend().await?;
-    Command::new("redis-cli")
-        .arg("FLUSHALL")
-        .output()
+    database_user_test::test_for_user_log_off().await?;
+    database_user_test::test_for_concurrent_user_register().await?;
+    database_chat_test::test_for_concurrent_write_message_to_chat().await?;
+    database_chat_test::test_for_get_messages_across_buckets().await?;
+    database_chat_test::test_for_prune_messages().await?;
+    database_chat_test::test_for_search_messages().await?;
+    database_chat_test::test_for_edit_message().await?;
+    database_chat_test::test_for_message_reactions().await?;
+    database_chat_test::test_for_message_threads().await?;
+    database_chat_test::test_for_pinned_messages().await?;
+    database_chat_test::test_for_forward_messages().await?;
+    database_chat_test::test_for_scheduled_messages().await?;
+    database_chat_test::test_for_disappearing_messages().await?;
+    database::flush_database()
+        .await
         .expect("failed to clear database");
     return Ok(());
 }
@@ -235,24 +260,28 @@ where
     user_session_test::test_for_user_session_get_group_owner(socket).await?;
     user_session_test::test_for_user_session_media_call(socket).await?;
     user_session_test::test_for_user_session_media_call_answer_ice_candidate_stop(socket).await?;
+    user_session_test::test_for_user_session_typing(socket).await?;
+    user_session_test::test_for_user_session_presence(socket).await?;
+    user_session_test::test_for_user_session_devices(socket).await?;
     user_session_test::test_for_user_session_get_user_id(socket).await?;
     user_session_test::test_for_user_session_get_user_read_in_private(socket).await?;
     user_session_test::test_for_user_session_get_user_read_in_group(socket).await?;
     user_session_test::test_for_user_session_user_apply_for_token(socket).await?;
+    user_session_test::test_for_user_session_revoke_token(socket).await?;
+    user_session_test::test_for_user_session_device_keys(socket).await?;
     user_session_test::test_for_user_session_unfriend(socket).await?;
     user_session_test::test_for_user_session_owner_transfer(socket).await?;
     user_session_test::test_for_user_session_quit_group_chat(socket).await?;
     user_session_test::test_for_user_session_logoff(socket).await?;
-    Command::new("redis-cli")
-        .arg("FLUSHALL")
-        .output()
+    database::flush_database()
+        .await
         .expect("failed to clear database");
     return Ok(());
 }
 
 #[test]
 pub fn test_user_session_supplement() -> Result<(), ()> {
-    Config::init();
+    Config::init_for_test();
     let rt = tokio::runtime::Builder::new_current_thread()
         .enable_io()
         .build()
@@ -267,9 +296,23 @@ pub async fn user_session_supplement_test() -> Result<(), ()> {
     user_session_test_supplement::test_for_user_session_file().await?;
     user_session_test_supplement::test_for_user_session_owner_transfer().await?;
     user_session_test_supplement::test_for_user_session_remove_member().await?;
-    Command::new("redis-cli")
-        .arg("FLUSHALL")
-        .output()
+    user_session_test_supplement::test_for_user_session_reaction_format().await;
+    database::flush_database()
+        .await
         .expect("failed to clear database");
     return Ok(());
 }
+
+#[test]
+pub fn test_read_receipts() {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    rt.block_on(user_session_test_supplement::test_for_user_session_read_receipts())
+        .expect("read receipts");
+}
+
+#[test]
+pub fn test_rate_limiter() {
+    user_session_test_supplement::test_for_rate_limiter();
+}
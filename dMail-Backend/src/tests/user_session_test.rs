@@ -1,23 +1,29 @@
+use crate::server::server_state::handshake_signing_key;
 use crate::user::user_session::protocol::ClientToServerMessage;
+use crate::user::user_session::protocol::PROTOCOL_VERSION;
 use crate::user::{UserGetUserReadInGroupData, UserLoginData, UserPullData, UserRequestHandler};
+use crate::user::{UserKeyExchangeResponse, UserLoginResponse, UserNegotiateProtocolResponse};
 use crate::utils;
-use crate::utils::aes::AesGcmHelper;
+use crate::utils::aes::{frame_counter, frame_nonce, AesGcmHelper, NonceDirection};
+use crate::utils::ecdh;
 use crate::{
     database,
     user::{
         user_session::{protocol::ServerToClientMessage, send_message},
-        ChatMessageType, UserCreateGroupChatData, UserGetMessagesData, UserGroupOwnerTransferData,
-        UserMediaCallAnswer, UserMediaCallData, UserMediaCallStop, UserMediaCallStopReason,
-        UserMediaCallType, UserMediaIceCandidate, UserPullGroupNoticeData, UserRegisterData,
-        UserRemoveGroupMemberData, UserRequestState, UserRequsetContent, UserRevokeMessageData,
-        UserRevokeMethod, UserSendGroupNoticeData, UserSendMessageData, UserSendRequestData,
-        UserSetAlreadyReadData, UserSetGroupAdminData, UserSolveRequestData,
-        UserUnsetGroupAdminData, UserUpdateData, UserUpdateGroupContent, UserUpdateGroupData,
+        ChatMessageType, PresenceStatus, SignedPrekey, UserCreateGroupChatData,
+        UserGetMessagesData, UserGroupOwnerTransferData, UserMediaCallAnswer, UserMediaCallData,
+        UserMediaCallStop, UserMediaCallStopReason, UserMediaCallType, UserMediaIceCandidate,
+        UserPullGroupNoticeData, UserRefreshTokenData, UserRegisterData, UserRemoveGroupMemberData,
+        UserRequestState, UserRequsetContent, UserRevokeMessageData, UserRevokeMethod,
+        UserSendGroupNoticeData, UserSendMessageData, UserSendRequestData, UserSetAlreadyReadData,
+        UserSetGroupAdminData, UserSolveRequestData, UserUnsetGroupAdminData, UserUpdateData,
+        UserUpdateGroupContent, UserUpdateGroupData, UserUploadDeviceKeysData,
         UserUploadFileRequestData,
     },
     utils::rsa::get_private_key_from_base64_pkcs1_pem,
 };
 use aes_gcm::{aes::Aes128, Aes128Gcm, AesGcm, KeyInit};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier};
 use generic_array::typenum::{
     bit::{B0, B1},
     UInt, UTerm,
@@ -33,6 +39,10 @@ static SYM_KEY_0: OnceCell<AesGcm<Aes128, UInt<UInt<UInt<UInt<UTerm, B1>, B1>, B
 static SYM_KEY: OnceCell<AesGcm<Aes128, UInt<UInt<UInt<UInt<UTerm, B1>, B1>, B0>, B0>>> =
     OnceCell::new();
 
+const TEST_PUB_KEY: &str = "MIIBCgKCAQEAuE9wq7rtUvWmPUYVKqK+zg+HDvMIzZsZccwnoFgGF8U3xyIBSoEdEMV5qBKIPOI7DSHzChi3l061S49/eh4Xv4AovIoxjTK2WXnFBS8oFTWB8hx1yuh4E8bGFo2R7xilSZXcUJ+rs03oXlR+Gsx/e0y1l/nb+I1Sb3HDfYKC1IU4YkQ7YY/7KgqdAVnWt9SRoFbWQeM/RodlbgMBaXx+a8Wjn6H9ZA9gJulNUtvzgccnlFR6wd6yxiC5yJaOPj4K5v1uHs8iGaqRQ/XX6j3tja/z0TU8kej1wPB74d7PZTXJWj97054nNdoYZqfFRLz6GbacF7cGKgckmEg7aM7JAQIDAQAB";
+
+const TEST_PRIVATE_KEY: &str = "MIIEowIBAAKCAQEAuE9wq7rtUvWmPUYVKqK+zg+HDvMIzZsZccwnoFgGF8U3xyIBSoEdEMV5qBKIPOI7DSHzChi3l061S49/eh4Xv4AovIoxjTK2WXnFBS8oFTWB8hx1yuh4E8bGFo2R7xilSZXcUJ+rs03oXlR+Gsx/e0y1l/nb+I1Sb3HDfYKC1IU4YkQ7YY/7KgqdAVnWt9SRoFbWQeM/RodlbgMBaXx+a8Wjn6H9ZA9gJulNUtvzgccnlFR6wd6yxiC5yJaOPj4K5v1uHs8iGaqRQ/XX6j3tja/z0TU8kej1wPB74d7PZTXJWj97054nNdoYZqfFRLz6GbacF7cGKgckmEg7aM7JAQIDAQABAoIBAGfJFAEf+ZPFkB7g3/pqOld+lubsJADjXaie9ZFs/8FS5N3VYDS8D8np6V+jT+Q44Fe8zkbZNEiXoa8y1u3FFEpZuJaymsSP0e8AitkofMG0p7/WFt5zmWpJfDIm9g5VKn4NTUp5Hw6QyFCV84zTqtWblIZHxH5p1gm7XgHHBDT2+jBSzYEtxXLuho/oss6HJo/I54yELR+5fciRgg0Qud8ro7+QwcV9kK3uBWIbOfWShk9lpCzPvshf8pD2oDoJiXUyH1O6y6qPT8RI7QPIMu+0yxwkz7vAFYIz33PhRbVVWqymv9+hUpiERIUgip5UcSXfukilysu4ZW+whuDH0SECgYEA6tKF43bYnZSsaFN9pAWpL9wLZmP6BOAprpBcFNb2opMr4bdRziAulG73dFRSFIVINYSG25RZaqD6QGfUEdGQk6Zk1tZ0USaPc8/aIyKrxDON1j4OGK05/PftxpkfO8J+DtV0AkId7um3s7rf2sYgjf138fi/DFF1ML/YJ+5yo3sCgYEAyO63iFYwIgg3PNkKaCSywVZOfmysuhFNI3S3HE7pCUwov6pZYziPyQfPwCGpsnk34nRHIb3IBI0L3xg4EojpmTlpjhXW2jxWz9ibAOLzELZ6uNgiVzj7b4eEOQglkO0PaUJ3z9/2hUG1iC2pD/Y1wnHsa1pnVMrXM+Lwx5tVTrMCgYBwwUfzEkUvXY1vxu9kjCdSSNncf5M1NiItpTnh89qX9A01JB6O2JslQSdnX3nOSrWCpTFQTKqm7cdcl76YE8XVcCeplW5i7R4i4SKAjoxl+M9ZmZCRPtTCaJZvL2V0/44iN1KuJutSpj1Eey40UcCeDaDDusqZ8p9QGj6D5hZ78wKBgH3Lpi++9ed4iUyY/UDyKM+N/xp7YzAigM6/1Zvtc0wU2DYWqlvKH4rWTySUbq+D4I7wCVCAhmcC/vmvKfvAp678GK+R0K9Us2zwySom69H8zJxJBEbjL9dFWmxyQ0KWh914dZY5Oxd2afZVz9BkbofL1x3mvWaCj3S2kdQF1cStAoGBALAuKY5JpV4uv6542ono1zaHtdAzh3G+zQS5RhieP4aNVD4EgW/nAzB3uw0gF/ZrBe7VJOMbkegJ2h3KQbKU80xrTIVHuO8EB1zJk7bCF/D8juDumxpwRPMhjl7LRRfFxX6gsJf6YbLqf/90UUp39noOHIktbA5TvgoTlr064yqU";
+
 pub fn encode_0(msg: String) -> String {
     SYM_KEY_0
         .get()
@@ -69,11 +79,11 @@ pub fn test_for_user_session_set_connection_pub_key_0<Stream>(socket: &mut WebSo
 where
     Stream: std::io::Read + std::io::Write,
 {
-    let request = ClientToServerMessage::SetConnectionPubKey("MIIBCgKCAQEAuE9wq7rtUvWmPUYVKqK+zg+HDvMIzZsZccwnoFgGF8U3xyIBSoEdEMV5qBKIPOI7DSHzChi3l061S49/eh4Xv4AovIoxjTK2WXnFBS8oFTWB8hx1yuh4E8bGFo2R7xilSZXcUJ+rs03oXlR+Gsx/e0y1l/nb+I1Sb3HDfYKC1IU4YkQ7YY/7KgqdAVnWt9SRoFbWQeM/RodlbgMBaXx+a8Wjn6H9ZA9gJulNUtvzgccnlFR6wd6yxiC5yJaOPj4K5v1uHs8iGaqRQ/XX6j3tja/z0TU8kej1wPB74d7PZTXJWj97054nNdoYZqfFRLz6GbacF7cGKgckmEg7aM7JAQIDAQAB".to_string());
+    let request = ClientToServerMessage::SetConnectionPubKey(TEST_PUB_KEY.to_string());
     let _ = socket.write_message(Message::Text(serde_json::to_string(&request).unwrap()));
     let after_read = socket.read_message();
     let resp = after_read.unwrap().to_string();
-    let private_key_str = "MIIEowIBAAKCAQEAuE9wq7rtUvWmPUYVKqK+zg+HDvMIzZsZccwnoFgGF8U3xyIBSoEdEMV5qBKIPOI7DSHzChi3l061S49/eh4Xv4AovIoxjTK2WXnFBS8oFTWB8hx1yuh4E8bGFo2R7xilSZXcUJ+rs03oXlR+Gsx/e0y1l/nb+I1Sb3HDfYKC1IU4YkQ7YY/7KgqdAVnWt9SRoFbWQeM/RodlbgMBaXx+a8Wjn6H9ZA9gJulNUtvzgccnlFR6wd6yxiC5yJaOPj4K5v1uHs8iGaqRQ/XX6j3tja/z0TU8kej1wPB74d7PZTXJWj97054nNdoYZqfFRLz6GbacF7cGKgckmEg7aM7JAQIDAQABAoIBAGfJFAEf+ZPFkB7g3/pqOld+lubsJADjXaie9ZFs/8FS5N3VYDS8D8np6V+jT+Q44Fe8zkbZNEiXoa8y1u3FFEpZuJaymsSP0e8AitkofMG0p7/WFt5zmWpJfDIm9g5VKn4NTUp5Hw6QyFCV84zTqtWblIZHxH5p1gm7XgHHBDT2+jBSzYEtxXLuho/oss6HJo/I54yELR+5fciRgg0Qud8ro7+QwcV9kK3uBWIbOfWShk9lpCzPvshf8pD2oDoJiXUyH1O6y6qPT8RI7QPIMu+0yxwkz7vAFYIz33PhRbVVWqymv9+hUpiERIUgip5UcSXfukilysu4ZW+whuDH0SECgYEA6tKF43bYnZSsaFN9pAWpL9wLZmP6BOAprpBcFNb2opMr4bdRziAulG73dFRSFIVINYSG25RZaqD6QGfUEdGQk6Zk1tZ0USaPc8/aIyKrxDON1j4OGK05/PftxpkfO8J+DtV0AkId7um3s7rf2sYgjf138fi/DFF1ML/YJ+5yo3sCgYEAyO63iFYwIgg3PNkKaCSywVZOfmysuhFNI3S3HE7pCUwov6pZYziPyQfPwCGpsnk34nRHIb3IBI0L3xg4EojpmTlpjhXW2jxWz9ibAOLzELZ6uNgiVzj7b4eEOQglkO0PaUJ3z9/2hUG1iC2pD/Y1wnHsa1pnVMrXM+Lwx5tVTrMCgYBwwUfzEkUvXY1vxu9kjCdSSNncf5M1NiItpTnh89qX9A01JB6O2JslQSdnX3nOSrWCpTFQTKqm7cdcl76YE8XVcCeplW5i7R4i4SKAjoxl+M9ZmZCRPtTCaJZvL2V0/44iN1KuJutSpj1Eey40UcCeDaDDusqZ8p9QGj6D5hZ78wKBgH3Lpi++9ed4iUyY/UDyKM+N/xp7YzAigM6/1Zvtc0wU2DYWqlvKH4rWTySUbq+D4I7wCVCAhmcC/vmvKfvAp678GK+R0K9Us2zwySom69H8zJxJBEbjL9dFWmxyQ0KWh914dZY5Oxd2afZVz9BkbofL1x3mvWaCj3S2kdQF1cStAoGBALAuKY5JpV4uv6542ono1zaHtdAzh3G+zQS5RhieP4aNVD4EgW/nAzB3uw0gF/ZrBe7VJOMbkegJ2h3KQbKU80xrTIVHuO8EB1zJk7bCF/D8juDumxpwRPMhjl7LRRfFxX6gsJf6YbLqf/90UUp39noOHIktbA5TvgoTlr064yqU".to_string();
+    let private_key_str = TEST_PRIVATE_KEY.to_string();
     let private_key = get_private_key_from_base64_pkcs1_pem(private_key_str.to_string()).unwrap();
     let encoded_sym_key;
     if let Ok(ServerToClientMessage::SetConnectionSymKey(key)) =
@@ -98,11 +108,11 @@ pub fn test_for_user_session_set_connection_pub_key<Stream>(socket: &mut WebSock
 where
     Stream: std::io::Read + std::io::Write,
 {
-    let request = ClientToServerMessage::SetConnectionPubKey("MIIBCgKCAQEAuE9wq7rtUvWmPUYVKqK+zg+HDvMIzZsZccwnoFgGF8U3xyIBSoEdEMV5qBKIPOI7DSHzChi3l061S49/eh4Xv4AovIoxjTK2WXnFBS8oFTWB8hx1yuh4E8bGFo2R7xilSZXcUJ+rs03oXlR+Gsx/e0y1l/nb+I1Sb3HDfYKC1IU4YkQ7YY/7KgqdAVnWt9SRoFbWQeM/RodlbgMBaXx+a8Wjn6H9ZA9gJulNUtvzgccnlFR6wd6yxiC5yJaOPj4K5v1uHs8iGaqRQ/XX6j3tja/z0TU8kej1wPB74d7PZTXJWj97054nNdoYZqfFRLz6GbacF7cGKgckmEg7aM7JAQIDAQAB".to_string());
+    let request = ClientToServerMessage::SetConnectionPubKey(TEST_PUB_KEY.to_string());
     let _ = socket.write_message(Message::Text(serde_json::to_string(&request).unwrap()));
     let after_read = socket.read_message();
     let resp = after_read.unwrap().to_string();
-    let private_key_str = "MIIEowIBAAKCAQEAuE9wq7rtUvWmPUYVKqK+zg+HDvMIzZsZccwnoFgGF8U3xyIBSoEdEMV5qBKIPOI7DSHzChi3l061S49/eh4Xv4AovIoxjTK2WXnFBS8oFTWB8hx1yuh4E8bGFo2R7xilSZXcUJ+rs03oXlR+Gsx/e0y1l/nb+I1Sb3HDfYKC1IU4YkQ7YY/7KgqdAVnWt9SRoFbWQeM/RodlbgMBaXx+a8Wjn6H9ZA9gJulNUtvzgccnlFR6wd6yxiC5yJaOPj4K5v1uHs8iGaqRQ/XX6j3tja/z0TU8kej1wPB74d7PZTXJWj97054nNdoYZqfFRLz6GbacF7cGKgckmEg7aM7JAQIDAQABAoIBAGfJFAEf+ZPFkB7g3/pqOld+lubsJADjXaie9ZFs/8FS5N3VYDS8D8np6V+jT+Q44Fe8zkbZNEiXoa8y1u3FFEpZuJaymsSP0e8AitkofMG0p7/WFt5zmWpJfDIm9g5VKn4NTUp5Hw6QyFCV84zTqtWblIZHxH5p1gm7XgHHBDT2+jBSzYEtxXLuho/oss6HJo/I54yELR+5fciRgg0Qud8ro7+QwcV9kK3uBWIbOfWShk9lpCzPvshf8pD2oDoJiXUyH1O6y6qPT8RI7QPIMu+0yxwkz7vAFYIz33PhRbVVWqymv9+hUpiERIUgip5UcSXfukilysu4ZW+whuDH0SECgYEA6tKF43bYnZSsaFN9pAWpL9wLZmP6BOAprpBcFNb2opMr4bdRziAulG73dFRSFIVINYSG25RZaqD6QGfUEdGQk6Zk1tZ0USaPc8/aIyKrxDON1j4OGK05/PftxpkfO8J+DtV0AkId7um3s7rf2sYgjf138fi/DFF1ML/YJ+5yo3sCgYEAyO63iFYwIgg3PNkKaCSywVZOfmysuhFNI3S3HE7pCUwov6pZYziPyQfPwCGpsnk34nRHIb3IBI0L3xg4EojpmTlpjhXW2jxWz9ibAOLzELZ6uNgiVzj7b4eEOQglkO0PaUJ3z9/2hUG1iC2pD/Y1wnHsa1pnVMrXM+Lwx5tVTrMCgYBwwUfzEkUvXY1vxu9kjCdSSNncf5M1NiItpTnh89qX9A01JB6O2JslQSdnX3nOSrWCpTFQTKqm7cdcl76YE8XVcCeplW5i7R4i4SKAjoxl+M9ZmZCRPtTCaJZvL2V0/44iN1KuJutSpj1Eey40UcCeDaDDusqZ8p9QGj6D5hZ78wKBgH3Lpi++9ed4iUyY/UDyKM+N/xp7YzAigM6/1Zvtc0wU2DYWqlvKH4rWTySUbq+D4I7wCVCAhmcC/vmvKfvAp678GK+R0K9Us2zwySom69H8zJxJBEbjL9dFWmxyQ0KWh914dZY5Oxd2afZVz9BkbofL1x3mvWaCj3S2kdQF1cStAoGBALAuKY5JpV4uv6542ono1zaHtdAzh3G+zQS5RhieP4aNVD4EgW/nAzB3uw0gF/ZrBe7VJOMbkegJ2h3KQbKU80xrTIVHuO8EB1zJk7bCF/D8juDumxpwRPMhjl7LRRfFxX6gsJf6YbLqf/90UUp39noOHIktbA5TvgoTlr064yqU".to_string();
+    let private_key_str = TEST_PRIVATE_KEY.to_string();
     let private_key = get_private_key_from_base64_pkcs1_pem(private_key_str.to_string()).unwrap();
     let encoded_sym_key;
     if let Ok(ServerToClientMessage::SetConnectionSymKey(key)) =
@@ -157,6 +167,7 @@ where
         email_code: None,
         address: None,
         token: None,
+        device_id: None,
     };
     let request = ClientToServerMessage::Login(data);
     socket
@@ -192,7 +203,9 @@ where
             serde_json::to_string(&request).unwrap(),
         )))
         .map_err(|_| ())?;
-    database::make_two_users_be_friends(1, 2).await?;
+    database::make_two_users_be_friends(1, 2)
+        .await
+        .map_err(|_| ())?;
     return Ok(());
 }
 
@@ -251,6 +264,7 @@ where
         chat_id: 1,
         timestamp: 1,
         serialized_content: "content".to_string(),
+        deliver_at: None,
     };
     let request = ClientToServerMessage::SendMessage(msg);
     socket
@@ -272,7 +286,9 @@ where
         content: UserRequsetContent::MakeFriend { receiver_id: 1 },
         client_id: 1,
     };
-    database::write_user_request(2, data, &UserRequestHandler::One(1)).await?;
+    database::write_user_request(2, data, &UserRequestHandler::One(1))
+        .await
+        .map_err(|_| ())?;
     let data = UserSolveRequestData {
         req_id: 1,
         answer: UserRequestState::Approved,
@@ -350,13 +366,14 @@ pub async fn test_for_user_session_send_message_2<Stream>(
 where
     Stream: std::io::Read + std::io::Write,
 {
-    database::add_user_to_chat(2, 2).await?;
+    database::add_user_to_chat(2, 2).await.map_err(|_| ())?;
     let msg = UserSendMessageData {
         r#type: ChatMessageType::MentionText,
         client_id: 1,
         chat_id: 2,
         timestamp: 1,
         serialized_content: "content".to_string(),
+        deliver_at: None,
     };
 
     let request = ClientToServerMessage::SendMessage(msg);
@@ -531,6 +548,7 @@ where
         chat_id: 1,
         timestamp: 1,
         serialized_content: "content".to_string(),
+        deliver_at: None,
     };
     send_message(2, msg).await;
     let data = UserSetAlreadyReadData {
@@ -559,6 +577,7 @@ where
         chat_id: 2,
         timestamp: 1,
         serialized_content: "content".to_string(),
+        deliver_at: None,
     };
     send_message(2, msg).await;
     let data = UserSetAlreadyReadData {
@@ -645,7 +664,7 @@ pub async fn test_for_user_session_set_as_admin<Stream>(
 where
     Stream: std::io::Read + std::io::Write,
 {
-    database::add_user_to_chat(2, 2).await?;
+    database::add_user_to_chat(2, 2).await.map_err(|_| ())?;
     let data = UserSetGroupAdminData {
         chat_id: 2,
         user_id: 2,
@@ -692,6 +711,7 @@ where
         chat_id: 2,
         timestamp: 1,
         serialized_content: "content".to_string(),
+        deliver_at: None,
     };
     send_message(1, msg).await;
     let data = UserRevokeMessageData {
@@ -721,6 +741,7 @@ where
         chat_id: 2,
         timestamp: 1,
         serialized_content: "content".to_string(),
+        deliver_at: None,
     };
     send_message(1, msg).await;
     let data = UserRevokeMessageData {
@@ -852,7 +873,7 @@ where
         email: "e@f.com".to_string(),
     };
     database::user_register(data).await;
-    database::add_user_to_chat(2, 3).await?;
+    database::add_user_to_chat(2, 3).await.map_err(|_| ())?;
     let data = UserRemoveGroupMemberData {
         chat_id: 2,
         user_id: 3,
@@ -976,6 +997,64 @@ where
     return Ok(());
 }
 
+pub async fn test_for_user_session_typing<Stream>(socket: &mut WebSocket<Stream>) -> Result<(), ()>
+where
+    Stream: std::io::Read + std::io::Write,
+{
+    // 第二次在节流间隔内，不会再次转发
+    for request in [
+        ClientToServerMessage::StartTyping(1),
+        ClientToServerMessage::StartTyping(1),
+        ClientToServerMessage::StopTyping(1),
+    ] {
+        socket
+            .write_message(Message::Text(encode(
+                serde_json::to_string(&request).unwrap(),
+            )))
+            .map_err(|_| ())?;
+    }
+    return Ok(());
+}
+
+pub async fn test_for_user_session_presence<Stream>(
+    socket: &mut WebSocket<Stream>,
+) -> Result<(), ()>
+where
+    Stream: std::io::Read + std::io::Write,
+{
+    for request in [
+        ClientToServerMessage::SetPresence(PresenceStatus::Away),
+        ClientToServerMessage::SetPresenceHidden(true),
+        ClientToServerMessage::GetPresence(vec![1, 2]),
+        ClientToServerMessage::SetPresenceHidden(false),
+        ClientToServerMessage::SetPresence(PresenceStatus::Online),
+    ] {
+        socket
+            .write_message(Message::Text(encode(
+                serde_json::to_string(&request).unwrap(),
+            )))
+            .map_err(|_| ())?;
+    }
+    return Ok(());
+}
+
+pub async fn test_for_user_session_devices<Stream>(socket: &mut WebSocket<Stream>) -> Result<(), ()>
+where
+    Stream: std::io::Read + std::io::Write,
+{
+    for request in [
+        ClientToServerMessage::ListDevices,
+        ClientToServerMessage::KickDevice("unknown".to_string()),
+    ] {
+        socket
+            .write_message(Message::Text(encode(
+                serde_json::to_string(&request).unwrap(),
+            )))
+            .map_err(|_| ())?;
+    }
+    return Ok(());
+}
+
 pub async fn test_for_user_session_get_user_id<Stream>(
     socket: &mut WebSocket<Stream>,
 ) -> Result<(), ()>
@@ -1040,6 +1119,65 @@ where
     return Ok(());
 }
 
+pub async fn test_for_user_session_revoke_token<Stream>(
+    socket: &mut WebSocket<Stream>,
+) -> Result<(), ()>
+where
+    Stream: std::io::Read + std::io::Write,
+{
+    for request in [
+        ClientToServerMessage::RefreshToken(UserRefreshTokenData {
+            email: "a@b.com".to_string(),
+            device_id: "unknown".to_string(),
+            refresh_token: "unknown".to_string(),
+        }),
+        ClientToServerMessage::RevokeToken("unknown".to_string()),
+        ClientToServerMessage::RevokeAllTokens,
+    ] {
+        socket
+            .write_message(Message::Text(encode(
+                serde_json::to_string(&request).unwrap(),
+            )))
+            .map_err(|_| ())?;
+    }
+    return Ok(());
+}
+
+pub async fn test_for_user_session_device_keys<Stream>(
+    socket: &mut WebSocket<Stream>,
+) -> Result<(), ()>
+where
+    Stream: std::io::Read + std::io::Write,
+{
+    let identity_key = SigningKey::generate(&mut rand::rngs::OsRng);
+    let signed_prekey = |key_id: u32| {
+        let prekey = x25519_dalek::PublicKey::from(
+            &x25519_dalek::EphemeralSecret::random_from_rng(rand::rngs::OsRng),
+        );
+        return SignedPrekey {
+            key_id,
+            public_key: utils::base64::encode(prekey.as_bytes()),
+            signature: utils::base64::encode(identity_key.sign(prekey.as_bytes()).to_bytes()),
+        };
+    };
+    for request in [
+        ClientToServerMessage::UploadDeviceKeys(UserUploadDeviceKeysData {
+            identity_key: utils::base64::encode(identity_key.verifying_key().as_bytes()),
+            signed_prekey: signed_prekey(1),
+        }),
+        ClientToServerMessage::RotateSignedPrekey(signed_prekey(2)),
+        ClientToServerMessage::GetDeviceKeys(1),
+        ClientToServerMessage::GetDeviceKeys(2),
+    ] {
+        socket
+            .write_message(Message::Text(encode(
+                serde_json::to_string(&request).unwrap(),
+            )))
+            .map_err(|_| ())?;
+    }
+    return Ok(());
+}
+
 pub async fn test_for_user_session_logoff<Stream>(socket: &mut WebSocket<Stream>) -> Result<(), ()>
 where
     Stream: std::io::Read + std::io::Write,
@@ -1069,3 +1207,128 @@ where
         .map_err(|_| ())?;
     return Ok(());
 }
+
+pub fn test_for_user_session_frame_nonce<Stream>(socket: &mut WebSocket<Stream>)
+where
+    Stream: std::io::Read + std::io::Write,
+{
+    // 客户端支持的版本高于服务端时，协商为服务端的最高版本
+    let request = ClientToServerMessage::NegotiateProtocol(PROTOCOL_VERSION + 1);
+    let _ = socket.write_message(Message::Text(serde_json::to_string(&request).unwrap()));
+    let resp = socket.read_message().unwrap().to_string();
+    match serde_json::from_str::<ServerToClientMessage>(&resp) {
+        Ok(ServerToClientMessage::NegotiateProtocolResponse(
+            UserNegotiateProtocolResponse::Success { version },
+        )) => assert_eq!(version, PROTOCOL_VERSION),
+        _ => panic!("negotiate_protocol"),
+    }
+
+    let request = ClientToServerMessage::SetConnectionPubKey(TEST_PUB_KEY.to_string());
+    let _ = socket.write_message(Message::Text(serde_json::to_string(&request).unwrap()));
+    let resp = socket.read_message().unwrap().to_string();
+    let encoded_sym_key = match serde_json::from_str::<ServerToClientMessage>(&resp) {
+        Ok(ServerToClientMessage::SetConnectionSymKey(key)) => utils::base64::decode(&key).unwrap(),
+        _ => panic!("set_sym_key"),
+    };
+    let private_key = get_private_key_from_base64_pkcs1_pem(TEST_PRIVATE_KEY.to_string()).unwrap();
+    let decoded_sym_key = private_key
+        .decrypt(Pkcs1v15Encrypt, &encoded_sym_key)
+        .unwrap();
+    let sym_key = Aes128Gcm::new_from_slice(
+        &utils::base64::decode((std::str::from_utf8(&decoded_sym_key)).unwrap()).unwrap(),
+    )
+    .unwrap();
+
+    // 未登录时的请求会收到NeedLogin，服务端每帧的nonce依次递增
+    let request =
+        serde_json::to_string(&ClientToServerMessage::GetUserID("name".to_string())).unwrap();
+    let mut frames = vec![];
+    for counter in 0..2 {
+        let nonce = frame_nonce(NonceDirection::ClientToServer, counter);
+        let frame = sym_key
+            .encrypt_with_nonce_to_base64(&nonce, &request)
+            .unwrap();
+        socket.write_message(Message::Text(frame.clone())).unwrap();
+        let resp = socket.read_message().unwrap().to_string();
+        let (nonce, resp) = sym_key.decrypt_with_nonce_from_base64(&resp).unwrap();
+        assert_eq!(
+            frame_counter(&nonce, NonceDirection::ServerToClient),
+            Some(counter)
+        );
+        match serde_json::from_str::<ServerToClientMessage>(&resp) {
+            Ok(ServerToClientMessage::LoginResponse(UserLoginResponse::NeedLogin)) => {}
+            _ => panic!("frame_nonce: need_login"),
+        }
+        frames.push(frame);
+    }
+
+    // 重放的帧会使服务端关闭连接
+    socket
+        .write_message(Message::Text(frames[0].clone()))
+        .unwrap();
+    match socket.read_message() {
+        Ok(Message::Close(_)) | Err(_) => {}
+        Ok(msg) => panic!("frame_nonce: replayed frame accepted: {}", msg),
+    }
+}
+
+pub fn test_for_user_session_key_exchange<Stream>(socket: &mut WebSocket<Stream>)
+where
+    Stream: std::io::Read + std::io::Write,
+{
+    let secret = x25519_dalek::EphemeralSecret::random_from_rng(rand::rngs::OsRng);
+    let client_public = x25519_dalek::PublicKey::from(&secret).to_bytes();
+    let request = ClientToServerMessage::KeyExchange(utils::base64::encode(client_public));
+    let _ = socket.write_message(Message::Text(serde_json::to_string(&request).unwrap()));
+    let resp = socket.read_message().unwrap().to_string();
+    let (server_public, signature) = match serde_json::from_str::<ServerToClientMessage>(&resp) {
+        Ok(ServerToClientMessage::KeyExchangeResponse(UserKeyExchangeResponse::Success {
+            public_key,
+            signature,
+        })) => (public_key, signature),
+        _ => panic!("key_exchange"),
+    };
+    let server_public: [u8; 32] = utils::base64::decode(&server_public)
+        .unwrap()
+        .try_into()
+        .unwrap();
+
+    // 服务端的临时公钥需有服务端的签名，客户端使用预先知道的服务端公钥校验
+    let verifying_key = handshake_signing_key.verifying_key();
+    let signature: [u8; 64] = utils::base64::decode(&signature)
+        .unwrap()
+        .try_into()
+        .unwrap();
+    verifying_key
+        .verify(
+            &ecdh::transcript(PROTOCOL_VERSION, &client_public, &server_public),
+            &Signature::from_bytes(&signature),
+        )
+        .expect("key_exchange: signature");
+
+    let shared_secret = secret.diffie_hellman(&x25519_dalek::PublicKey::from(server_public));
+    let keys = ecdh::derive_session_keys(
+        PROTOCOL_VERSION,
+        shared_secret.as_bytes(),
+        &client_public,
+        &server_public,
+    );
+    let send_key = Aes128Gcm::new_from_slice(&keys.client_to_server).unwrap();
+    let recv_key = Aes128Gcm::new_from_slice(&keys.server_to_client).unwrap();
+
+    let request =
+        serde_json::to_string(&ClientToServerMessage::GetUserID("name".to_string())).unwrap();
+    let nonce = frame_nonce(NonceDirection::ClientToServer, 0);
+    let frame = send_key
+        .encrypt_with_nonce_to_base64(&nonce, &request)
+        .unwrap();
+    socket.write_message(Message::Text(frame)).unwrap();
+    let resp = socket.read_message().unwrap().to_string();
+    // 两个方向使用不同的密钥
+    assert!(send_key.decrypt_with_nonce_from_base64(&resp).is_err());
+    let (_, resp) = recv_key.decrypt_with_nonce_from_base64(&resp).unwrap();
+    match serde_json::from_str::<ServerToClientMessage>(&resp) {
+        Ok(ServerToClientMessage::LoginResponse(UserLoginResponse::NeedLogin)) => {}
+        _ => panic!("key_exchange: need_login"),
+    }
+}
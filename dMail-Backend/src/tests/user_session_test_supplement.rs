@@ -1,13 +1,24 @@
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
 use futures::executor::block_on;
 
 use crate::{
+    config::config::RateLimitRule,
     database,
+    server::{
+        rate_limit::{RateLimitKey, RateLimiter},
+        read_receipt::{read_receipts, ReadReceiptHub},
+    },
     user::{
         user_session::client_message_handler::{
-            file_uploaded, owner_transfer, quit_group_chat, remove_member, upload_file_req,
+            add_reaction, file_uploaded, owner_transfer, quit_group_chat, remove_member,
+            upload_file_req,
         },
-        UserCreateGroupChatData, UserGroupOwnerTransferData, UserRegisterData,
-        UserRemoveGroupMemberData, UserUploadFileRequestData,
+        user_session::protocol::{ClientToServerMessage, ServerToClientMessage},
+        GroupReadCursorsData, ReadReceiptMode, UserCreateGroupChatData, UserGroupOwnerTransferData,
+        UserReactionData, UserReactionResponse, UserRegisterData, UserRemoveGroupMemberData,
+        UserUploadFileRequestData,
     },
 };
 
@@ -23,7 +34,7 @@ pub async fn test_for_user_session_quit_group_chat() -> Result<(), ()> {
         name: "name".to_string(),
         avater_hash: "avater_hash".to_string(),
     };
-    database::create_group_chat(1, data).await?;
+    database::create_group_chat(1, data).await.map_err(|_| ())?;
     quit_group_chat(2, 1).await;
     let data = UserRegisterData {
         user_name: "name".to_string(),
@@ -32,9 +43,15 @@ pub async fn test_for_user_session_quit_group_chat() -> Result<(), ()> {
         email: "e@f.com".to_string(),
     };
     database::user_register(data).await;
-    database::add_user_to_chat(1, 2).await?;
+    database::add_user_to_chat(1, 2).await.map_err(|_| ())?;
+    // 退出群聊后取消已读位置的订阅
+    read_receipts
+        .lock()
+        .unwrap()
+        .subscribe(1, 2, ReadReceiptMode::All);
     quit_group_chat(2, 1).await;
-    database::add_user_to_chat(1, 2).await?;
+    assert!(!read_receipts.lock().unwrap().has_subscribers(1));
+    database::add_user_to_chat(1, 2).await.map_err(|_| ())?;
     return Ok(());
 }
 
@@ -66,3 +83,130 @@ pub async fn test_for_user_session_remove_member() -> Result<(), ()> {
     remove_member(2, data).await;
     return Ok(());
 }
+
+pub async fn test_for_user_session_read_receipts() -> Result<(), ()> {
+    let mut hub = ReadReceiptHub::default();
+    // 没有订阅者时不记录
+    hub.record_read(1, 2, 1);
+    assert!(hub.take_pushes().is_empty());
+
+    hub.subscribe(1, 1, ReadReceiptMode::All);
+    hub.subscribe(1, 2, ReadReceiptMode::OwnMessages);
+    hub.record_sent(1, 2, 5);
+    // 推送间隔内的多次已读只保留最新的位置
+    hub.record_read(1, 3, 4);
+    hub.record_read(1, 3, 6);
+    hub.record_read(1, 3, 5);
+    hub.record_read(1, 4, 3);
+    hub.record_read(1, 1, 6);
+
+    let mut pushes = hub.take_pushes();
+    pushes.sort_by_key(|(user_id, _)| *user_id);
+    assert_eq!(
+        pushes,
+        vec![
+            (
+                1,
+                GroupReadCursorsData {
+                    chat_id: 1,
+                    cursors: vec![(3, 6), (4, 3)],
+                },
+            ),
+            (
+                2,
+                GroupReadCursorsData {
+                    chat_id: 1,
+                    cursors: vec![(1, 6), (3, 6)],
+                },
+            ),
+        ]
+    );
+    assert!(hub.take_pushes().is_empty());
+
+    hub.unsubscribe_all(1);
+    hub.unsubscribe(1, 2);
+    hub.record_read(1, 3, 7);
+    assert!(hub.take_pushes().is_empty());
+    return Ok(());
+}
+
+pub async fn test_for_user_session_reaction_format() {
+    // 表情回应会写入Redis的字段，不能包含分隔符与通配符
+    for emoji in ["", "msgs", "a:msgs", "*", "?", "[x]", " "] {
+        let data = UserReactionData {
+            chat_id: 1,
+            in_chat_id: 1,
+            emoji: emoji.to_string(),
+        };
+        match add_reaction(1, data).await {
+            ServerToClientMessage::AddReactionResponse(
+                UserReactionResponse::ReactionFormatError,
+            ) => {
+                assert_ne!(emoji, "msgs")
+            }
+            ServerToClientMessage::AddReactionResponse(_) => assert_eq!(emoji, "msgs"),
+            _ => panic!("reaction_format"),
+        }
+    }
+}
+
+pub fn test_for_rate_limiter() {
+    let limiter = RateLimiter::new();
+    let rule = RateLimitRule {
+        capacity: 2,
+        refill_per_sec: 10.0,
+    };
+    let ip = IpAddr::from([127, 0, 0, 1]);
+    let now = Instant::now();
+    let keys = [(RateLimitKey::IpCommand(ip, "SendMessage"), rule)];
+
+    // 桶满时可以连续请求`capacity`次
+    assert!(limiter.check(&keys, now).is_ok());
+    assert!(limiter.check(&keys, now).is_ok());
+    match limiter.check(&keys, now) {
+        Err(retry_after) => assert_eq!(retry_after, Duration::from_millis(100)),
+        Ok(_) => panic!("rate_limiter: over capacity"),
+    }
+    // 不同的键使用不同的桶
+    let other = [(RateLimitKey::IpCommand(ip, "SendRequest"), rule)];
+    assert!(limiter.check(&other, now).is_ok());
+    // 等待补充令牌后恢复
+    assert!(limiter
+        .check(&keys, now + Duration::from_millis(100))
+        .is_ok());
+    assert!(limiter
+        .check(&keys, now + Duration::from_millis(100))
+        .is_err());
+
+    // 任意一个桶令牌不足都会被限制
+    let user_keys = [
+        (RateLimitKey::User(1), rule),
+        (
+            RateLimitKey::UserCommand(1, "SendMessage"),
+            RateLimitRule {
+                capacity: 1,
+                refill_per_sec: 0.0,
+            },
+        ),
+    ];
+    assert!(limiter.check(&user_keys, now).is_ok());
+    assert_eq!(limiter.check(&user_keys, now), Err(Duration::MAX));
+    // 被限制时其他桶的令牌不会被取用
+    let user_only = [(RateLimitKey::User(1), rule)];
+    assert!(limiter.check(&user_only, now).is_ok());
+    assert!(limiter.check(&user_only, now).is_err());
+
+    // 清理时保留未补满的桶
+    limiter.sweep(now + Duration::from_secs(1));
+    assert_eq!(
+        limiter.check(&user_keys, now + Duration::from_secs(1)),
+        Err(Duration::MAX)
+    );
+
+    // 限制所用的command与序列化后的command字段相同
+    assert_eq!(ClientToServerMessage::Ping.get_command(), "Ping");
+    assert_eq!(
+        ClientToServerMessage::GetChatInfo(1).get_command(),
+        "GetChatInfo"
+    );
+}
@@ -2,10 +2,11 @@
  负责向用户发送验证码
 */
 
-use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use actix_web::{http::header, post, web, HttpRequest, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
 
-use crate::server::email::send_random_code;
+use crate::server::email::{send_random_code, SendEmailCodeError};
+use crate::server::rate_limit;
 
 /** `ApplyForEmail` 申请验证码时所用的数据类型
 */
@@ -19,9 +20,15 @@ pub struct ApplyForEmail {
 #[post("/email/code")]
 pub async fn apply_for_email_code(
     json: web::Json<ApplyForEmail>,
-    _request: HttpRequest,
+    request: HttpRequest,
 ) -> impl Responder {
-    // TODO : 请求速率限制
+    if let Some(addr) = request.peer_addr() {
+        if let Err(retry_after) = rate_limit::check_email_code(addr.ip()) {
+            return HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, retry_after.as_secs_f64().ceil() as u64))
+                .json(SendEmailCodeError::RateLimited);
+        }
+    }
 
     if let Err(err) = send_random_code(None, &json.email).await {
         // TODO : HTTP状态码规范
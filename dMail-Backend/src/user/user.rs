@@ -14,7 +14,7 @@ use crate::{
 
 use super::{
     user_session::{protocol::ServerToClientMessage, UserSessionActorMessage},
-    UserPullData, UserPullResponse, UserRequestHandler,
+    UserPullData, UserPullResponse, UserRequestHandler, UserTypingData,
 };
 
 /** `send_msg_to_online_user_in_private_chat` 向私聊中的两个用户发送消息
@@ -78,6 +78,44 @@ pub async fn send_msg_to_online_users_in_chat(
     }
 }
 
+/** `send_typing_to_online_users_in_chat` 向聊天中的其他在线用户转发输入状态
+ * 输入状态不写入数据库，用户不在聊天中时不转发
+*/
+pub async fn send_typing_to_online_users_in_chat(user_id: UserID, chat_id: ChatID, typing: bool) {
+    let members = match database::get_chat_user_list(chat_id).await {
+        Ok(mem) => mem,
+        Err(_) => {
+            return;
+        }
+    };
+
+    let msg = ServerToClientMessage::Typing(UserTypingData {
+        chat_id,
+        user_id,
+        typing,
+    });
+
+    match members {
+        chat::ChatMembers::Private(ids) => {
+            let receiver = if ids.0 == user_id {
+                ids.1
+            } else if ids.1 == user_id {
+                ids.0
+            } else {
+                return;
+            };
+            user_sessions
+                .do_send_message_to(receiver, UserSessionActorMessage::SendServerMessage(msg));
+        }
+        chat::ChatMembers::Group(users_vec) => {
+            if !users_vec.contains(&user_id) {
+                return;
+            }
+            user_sessions.send_message_to_online_with_exclusion(users_vec, Arc::new(msg), user_id);
+        }
+    }
+}
+
 /** `send_msg_to_online_handlers` 向请求的处理者发送消息
 */
 pub async fn send_msg_to_online_handlers(msg: ServerToClientMessage, handlers: UserRequestHandler) {
@@ -138,6 +176,19 @@ pub async fn user_pull(
         }
     };
 
+    let mut pinned_messages: Vec<SerializedChatMessage> = vec![];
+    for (chat_id, _) in &chats {
+        match database::get_pinned_messages(*chat_id).await {
+            Ok(msgs) => pinned_messages.extend(msgs),
+            Err(_) => {
+                receiver.do_send(UserSessionActorMessage::SendServerMessage(
+                    ServerToClientMessage::PullResponse(UserPullResponse::DatabaseError),
+                ));
+                return;
+            }
+        }
+    }
+
     receiver.do_send(UserSessionActorMessage::SendServerMessage(
         ServerToClientMessage::ReadCursors(chats),
     ));
@@ -146,6 +197,10 @@ pub async fn user_pull(
         ServerToClientMessage::Messages(messages),
     ));
 
+    receiver.do_send(UserSessionActorMessage::SendServerMessage(
+        ServerToClientMessage::PinnedMessages(pinned_messages),
+    ));
+
     let requests: Vec<SerializedRequest> =
         match database::get_user_requests(user_id, pull_data.last_request_id).await {
             Ok(reqs) => reqs,
@@ -1,6 +1,11 @@
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 use crate::config::datatype::{Timestamp, UserID};
+use crate::config::Config;
+
+use super::{UserLoginData, UserLoginResponse, UserRefreshTokenResponse};
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 /** `UserInfo` 用户信息的数据类型
@@ -11,11 +16,66 @@ pub struct UserInfo {
     pub avater_hash: String,
 }
 
-/** `Token` 与客户端连接时令牌的数据类型
+/** `Token` 某台设备上的令牌，访问令牌用于登录
+ * 访问令牌过期后，可以在刷新令牌的有效期内用刷新令牌换取新的一对令牌
 */
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Token {
-    pub token: String,
+    pub access_token: String,
+    pub refresh_token: String,
     pub timestamp: Timestamp,
 }
+
+impl Token {
+    pub fn generate() -> Token {
+        return Token {
+            access_token: Uuid::new_v4().simple().to_string(),
+            refresh_token: Uuid::new_v4().simple().to_string(),
+            timestamp: Utc::now().timestamp_millis() as Timestamp,
+        };
+    }
+
+    /** `check_login` 检查登录数据中的访问令牌
+     */
+    pub fn check_login(&self, data: &UserLoginData) -> Result<(), UserLoginResponse> {
+        let input = match &data.token {
+            Some(input) => input,
+            None => return Err(UserLoginResponse::TokenError),
+        };
+        let expire_time = Config::get().user.access_token_expire_time;
+        match self.check(&self.access_token, input, expire_time) {
+            Ok(_) => return Ok(()),
+            Err(TokenInvalid::Mismatch) => return Err(UserLoginResponse::TokenError),
+            Err(TokenInvalid::Expired) => return Err(UserLoginResponse::TokenExpired),
+        }
+    }
+
+    /** `check_refresh` 检查换取新令牌时所用的刷新令牌
+     */
+    pub fn check_refresh(&self, input: &str) -> Result<(), UserRefreshTokenResponse> {
+        let expire_time = Config::get().user.token_expire_time;
+        match self.check(&self.refresh_token, input, expire_time) {
+            Ok(_) => return Ok(()),
+            Err(TokenInvalid::Mismatch) => return Err(UserRefreshTokenResponse::TokenError),
+            Err(TokenInvalid::Expired) => return Err(UserRefreshTokenResponse::TokenExpired),
+        }
+    }
+
+    fn check(&self, token: &str, input: &str, expire_time: u32) -> Result<(), TokenInvalid> {
+        if token != input {
+            return Err(TokenInvalid::Mismatch);
+        }
+        // 先转为u64再乘，避免有效期较长时溢出
+        let elapsed = (Utc::now().timestamp_millis() as u64).saturating_sub(self.timestamp);
+        if elapsed > expire_time as u64 * 1000 {
+            return Err(TokenInvalid::Expired);
+        }
+        return Ok(());
+    }
+}
+
+enum TokenInvalid {
+    Mismatch,
+    Expired,
+}
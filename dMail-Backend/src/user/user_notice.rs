@@ -4,13 +4,13 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     config::datatype::{ChatID, MessageID, SerializedUserNotice, Timestamp, UserID},
-    database,
+    database::{self, DatabaseError},
     server::server_state::{user_sessions, UserSessionGetter},
 };
 
 use super::user_session::{protocol::ServerToClientMessage, UserSessionActorMessage};
 
-/** `UserNotice` 在有消息撤回和被提及的时候发送notice
+/** `UserNotice` 在有消息撤回、编辑和被提及的时候发送notice
 */
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(tag = "state")]
@@ -27,6 +27,40 @@ pub enum UserNotice {
         in_chat_id: MessageID,
         timestamp: Timestamp,
     },
+    #[serde(rename_all = "camelCase")]
+    Edited {
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        timestamp: Timestamp,
+    },
+    #[serde(rename_all = "camelCase")]
+    Pinned {
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        user_id: UserID,
+        timestamp: Timestamp,
+    },
+    #[serde(rename_all = "camelCase")]
+    Unpinned {
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        user_id: UserID,
+        timestamp: Timestamp,
+    },
+    #[serde(rename_all = "camelCase")]
+    DisappearingChanged {
+        chat_id: ChatID,
+        user_id: UserID,
+        ttl: Option<u32>,
+        timestamp: Timestamp,
+    },
+    /// 阅后即焚的消息到期，已被替换为Revoked
+    #[serde(rename_all = "camelCase")]
+    Expired {
+        chat_id: ChatID,
+        in_chat_ids: Vec<MessageID>,
+        timestamp: Timestamp,
+    },
 }
 /** `send_notice_to_user_in_chat` 向聊天中的所有用户发送notice
 */
@@ -34,13 +68,8 @@ pub async fn send_notice_to_user_in_chat(
     chat_id: ChatID,
     serialized: SerializedUserNotice,
     timestamp: Timestamp,
-) -> Result<(), ()> {
-    let members = match database::get_chat_user_list(chat_id).await {
-        Ok(mem) => mem,
-        Err(_) => {
-            return Err(());
-        }
-    };
+) -> Result<(), DatabaseError> {
+    let members = database::get_chat_user_list(chat_id).await?;
 
     let arc_msg = Arc::new(ServerToClientMessage::Notice(serialized.clone()));
 
@@ -74,7 +103,7 @@ pub async fn send_notice(
     users: Vec<UserID>,
     serialized: SerializedUserNotice,
     timestamp: Timestamp,
-) -> Result<(), ()> {
+) -> Result<(), DatabaseError> {
     let arc_msg = Arc::new(ServerToClientMessage::Notice(serialized.clone()));
     user_sessions.send_message_to_online(&users, arc_msg);
     for id in users {
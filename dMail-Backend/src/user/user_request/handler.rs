@@ -15,6 +15,8 @@ use crate::{
     },
 };
 
+use log::error;
+
 use super::{UserRequestInfo, UserRequsetContent};
 
 /** `on_request_send` 请求发送时的处理函数
@@ -83,7 +85,9 @@ pub async fn on_request_refused(info: &UserRequestInfo) -> Result<(), ()> {
 }
 
 async fn on_make_friend_approved(sender_id: UserID, receiver_id: UserID) -> Result<(), ()> {
-    let chat_id = database::make_two_users_be_friends(sender_id, receiver_id).await?;
+    let chat_id = database::make_two_users_be_friends(sender_id, receiver_id)
+        .await
+        .map_err(|err| error!("用户{}与{}成为好友失败: {}", sender_id, receiver_id, err))?;
 
     // let arc = Arc::new(ServerToClientMessage::Chat(info));
     // user_sessions.send_message_to_online(vec![sender_id, receiver_id], arc);
@@ -93,11 +97,15 @@ async fn on_make_friend_approved(sender_id: UserID, receiver_id: UserID) -> Resu
 }
 
 async fn on_make_friend_refused(sender_id: UserID, receiver_id: UserID) -> Result<(), ()> {
-    return database::delete_friend_request_send(sender_id, receiver_id).await;
+    return database::delete_friend_request_send(sender_id, receiver_id)
+        .await
+        .map_err(|err| error!("删除好友申请记录失败: {}", err));
 }
 
 async fn on_join_group_refused(sender_id: UserID, chat_id: ChatID) -> Result<(), ()> {
-    return database::delete_join_group_request_send(sender_id, chat_id).await;
+    return database::delete_join_group_request_send(sender_id, chat_id)
+        .await
+        .map_err(|err| error!("删除入群申请记录失败: {}", err));
 }
 
 async fn on_group_invitation_refused(
@@ -105,15 +113,21 @@ async fn on_group_invitation_refused(
     receiver_id: UserID,
     chat_id: ChatID,
 ) -> Result<(), ()> {
-    return database::delete_invite_request_send(inviter_id, receiver_id, chat_id).await;
+    return database::delete_invite_request_send(inviter_id, receiver_id, chat_id)
+        .await
+        .map_err(|err| error!("删除入群邀请记录失败: {}", err));
 }
 
 async fn on_make_friend_send(sender_id: UserID, receiver_id: UserID) -> Result<(), ()> {
-    return database::write_friend_request_send(sender_id, receiver_id).await;
+    return database::write_friend_request_send(sender_id, receiver_id)
+        .await
+        .map_err(|err| error!("写入好友申请记录失败: {}", err));
 }
 
 async fn on_join_group_send(user_id: UserID, chat_id: ChatID) -> Result<(), ()> {
-    return database::write_join_group_request_send(user_id, chat_id).await;
+    return database::write_join_group_request_send(user_id, chat_id)
+        .await
+        .map_err(|err| error!("写入入群申请记录失败: {}", err));
 }
 
 async fn on_group_invitation_send(
@@ -121,11 +135,15 @@ async fn on_group_invitation_send(
     receiver_id: UserID,
     chat_id: ChatID,
 ) -> Result<(), ()> {
-    return database::write_invite_request_send(inviter_id, receiver_id, chat_id).await;
+    return database::write_invite_request_send(inviter_id, receiver_id, chat_id)
+        .await
+        .map_err(|err| error!("写入入群邀请记录失败: {}", err));
 }
 
 async fn on_join_group_approved(chat_id: ChatID, user_id: UserID) -> Result<(), ()> {
-    database::delete_join_group_request_send(user_id, chat_id).await?;
+    database::delete_join_group_request_send(user_id, chat_id)
+        .await
+        .map_err(|err| error!("删除入群申请记录失败: {}", err))?;
     match database::add_user_to_chat(chat_id, user_id).await {
         Ok(()) => {
             let user_name = match database::get_user_info(user_id).await {
@@ -153,7 +171,10 @@ async fn on_join_group_approved(chat_id: ChatID, user_id: UserID) -> Result<(),
             }
             return Ok(());
         }
-        Err(()) => return Err(()),
+        Err(err) => {
+            error!("用户{}加入群聊{}失败: {}", user_id, chat_id, err);
+            return Err(());
+        }
     }
 }
 
@@ -163,7 +184,9 @@ async fn on_group_invitation_approved(
     chat_id: ChatID,
 ) -> Result<(), ()> {
     if let Ok(check) = check_user_is_admin(inviter_id, chat_id).await {
-        database::delete_invite_request_send(inviter_id, receiver_id, chat_id).await?;
+        database::delete_invite_request_send(inviter_id, receiver_id, chat_id)
+            .await
+            .map_err(|err| error!("删除入群邀请记录失败: {}", err))?;
         if check {
             return on_invited_join_group_approved(inviter_id, receiver_id, chat_id).await;
         } else {
@@ -272,6 +295,9 @@ async fn on_invited_join_group_approved(
             }
             return Ok(());
         }
-        Err(()) => return Err(()),
+        Err(err) => {
+            error!("用户{}加入群聊{}失败: {}", user_id, chat_id, err);
+            return Err(());
+        }
     }
 }
@@ -2,10 +2,11 @@
 actor的消息处理函数
 */
 
-use actix::{dev::ToEnvelope, Actor, Handler, MessageResult};
+use actix::{dev::ToEnvelope, Actor, ActorContext, Handler, MessageResult};
 
 use super::{
-    protocol::ServerMessageSender, UserSession, UserSessionActorMessage, UserSessionActorResponse,
+    protocol::{ServerMessageSender, ServerToClientMessage},
+    UserSession, UserSessionActorMessage, UserSessionActorResponse,
 };
 
 pub trait ActorMessageHandler
@@ -33,6 +34,11 @@ where
                 ctx.send_server_message(&arc_msg, cipher);
                 UserSessionActorResponse::Ok
             }
+            UserSessionActorMessage::Kick => {
+                ctx.send_server_message(&ServerToClientMessage::DeviceKicked, cipher);
+                ctx.stop();
+                UserSessionActorResponse::Ok
+            }
         };
 
         return MessageResult(res);
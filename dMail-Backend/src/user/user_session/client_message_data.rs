@@ -7,7 +7,11 @@ use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
 
 use crate::{
-    config::datatype::{ChatID, ClientID, EmailCodeValue, MessageID, Timestamp, UserID, UserReqId},
+    chat::SearchCursor,
+    config::datatype::{
+        ChatID, ClientID, DeviceID, EmailCodeValue, MessageID, ProtocolVersion, ScheduleID,
+        SerializedChatMessage, Timestamp, UserID, UserReqId,
+    },
     config::{
         config::PWD_PATTERN,
         datatype::{NoticeID, SerializedGroupNotice, UploadId},
@@ -24,6 +28,42 @@ pub enum UserSetPubKeyResponse {
     NeedSetPubKey,
     PubKeyError,
     HasApproved,
+    /// 未协商协议版本的旧版客户端低于服务端允许的最低版本
+    ProtocolVersionUnsupported,
+    HandshakeDisabled,
+}
+
+/** `UserKeyExchangeResponse` X25519握手的结果
+ * 成功时包含服务端的临时公钥，以及服务端对握手记录的Ed25519签名，均为base64编码
+ * 签名的公钥不随握手发送，客户端须用预先分发的服务端公钥校验签名，校验失败时应断开连接
+ */
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserKeyExchangeResponse {
+    #[serde(rename_all = "camelCase")]
+    Success {
+        public_key: String,
+        signature: String,
+    },
+    PublicKeyError,
+    HasApproved,
+    HandshakeDisabled,
+}
+
+/** `UserNegotiateProtocolResponse` 协商协议版本的结果，成功时为双方都支持的最高版本
+*/
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserNegotiateProtocolResponse {
+    Success {
+        version: ProtocolVersion,
+    },
+    #[serde(rename_all = "camelCase")]
+    VersionUnsupported {
+        min_version: ProtocolVersion,
+        max_version: ProtocolVersion,
+    },
+    HasApproved,
 }
 
 // Register
@@ -130,6 +170,9 @@ pub struct UserLoginData {
     pub email_code: Option<EmailCodeValue>,
     pub address: Option<SocketAddr>,
     pub token: Option<String>,
+    /// 登录的设备ID，缺省时由服务端生成，使用令牌登录时必须提供
+    #[serde(default)]
+    pub device_id: Option<DeviceID>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -149,6 +192,8 @@ pub enum UserLoginResponse {
     NeedLogin,
     TokenError,
     TokenExpired,
+    TooManyDevices,
+    DeviceIdInvalid,
 }
 
 impl DataChecker<UserLoginResponse> for UserLoginData {
@@ -156,6 +201,11 @@ impl DataChecker<UserLoginResponse> for UserLoginData {
         if let Err(_) = &self.email.parse::<Address>() {
             return Err(UserLoginResponse::EmailInvalid);
         }
+        if let Some(device_id) = &self.device_id {
+            if device_id.is_empty() || device_id.len() > 64 {
+                return Err(UserLoginResponse::DeviceIdInvalid);
+            }
+        }
         return Ok(());
     }
 }
@@ -169,6 +219,9 @@ pub struct UserSendMessageData {
     pub chat_id: ChatID,
     pub timestamp: Timestamp,
     pub serialized_content: String,
+    /// 定时发送的投递时间，缺省或已经过去时立即发送
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deliver_at: Option<Timestamp>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
@@ -181,6 +234,8 @@ pub enum ChatMessageType {
     Revoked,
     ReplyText,
     MentionText,
+    /// 端到端加密的消息，服务端只存储与转发，不解析其内容
+    Encrypted,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -190,6 +245,15 @@ pub struct MentionTextType {
     pub text: String,
 }
 
+/** `ReplyTextType` 回复消息的内容，被回复的消息须在同一聊天中且未被撤回
+ */
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplyTextType {
+    pub reply_to_in_chat_id: MessageID,
+    pub text: String,
+}
+
 impl ChatMessageType {
     pub fn get_str(&self) -> &'static str {
         match *self {
@@ -201,6 +265,7 @@ impl ChatMessageType {
             ChatMessageType::Revoked => "\"Revoked\"",
             ChatMessageType::ReplyText => "\"ReplyText\"",
             ChatMessageType::MentionText { .. } => "\"MentionText\"",
+            ChatMessageType::Encrypted => "\"Encrypted\"",
         }
     }
 }
@@ -246,6 +311,65 @@ pub enum UserSendMessageResponseState {
     FileMetaDataFormatError,
     ContentError,
     SendNoticeError,
+    // 被回复的消息不存在、已被撤回或已被清理
+    ReplyTargetNotFound,
+    // 端到端加密的消息只能在私聊中发送
+    EncryptedNotSupported,
+}
+
+/** `ScheduledMessage` 等待定时发送的消息，到期后按原样交给send_message发送
+ */
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledMessage {
+    pub schedule_id: ScheduleID,
+    pub sender_id: UserID,
+    pub deliver_at: Timestamp,
+    pub message: UserSendMessageData,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserScheduleMessageResponse {
+    #[serde(rename_all = "camelCase")]
+    Success {
+        client_id: MessageID,
+        chat_id: ChatID,
+        schedule_id: ScheduleID,
+        deliver_at: Timestamp,
+    },
+    #[serde(rename_all = "camelCase")]
+    ScheduleLimitExceeded {
+        client_id: MessageID,
+        chat_id: ChatID,
+    },
+    #[serde(rename_all = "camelCase")]
+    DatabaseError {
+        client_id: MessageID,
+        chat_id: ChatID,
+    },
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserGetScheduledMessagesResponse {
+    Success {
+        /// 尚未发送的定时消息，按投递时间排列
+        messages: Vec<ScheduledMessage>,
+    },
+    DatabaseError,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserCancelScheduledMessageResponse {
+    #[serde(rename_all = "camelCase")]
+    Success {
+        schedule_id: ScheduleID,
+    },
+    // 定时消息不存在、已经发送或不属于该用户
+    MessageNotExisted,
+    DatabaseError,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -278,6 +402,51 @@ pub struct UserGetMessagesData {
     pub end_id: Option<MessageID>,
 }
 
+/** `UserGetMessagesResponse` 读取一段聊天消息的结果
+ */
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserGetMessagesResponse {
+    #[serde(rename_all = "camelCase")]
+    Success {
+        chat_id: ChatID,
+        messages: Vec<SerializedChatMessage>,
+        /// 请求的范围中有已被保留策略清理的消息时，in_chat_id不超过该值的消息已被清理
+        truncated_before: Option<MessageID>,
+    },
+    DatabaseError,
+}
+
+/** `UserSearchMessagesData` 搜索消息的请求，chat_id为空时搜索用户所在的全部聊天
+ */
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserSearchMessagesData {
+    pub chat_id: Option<ChatID>,
+    pub keyword: String,
+    /// 结果按chat_id升序、同一聊天内由新到旧排列，为空时从头搜索，否则从上一页返回的游标之后继续
+    #[serde(default)]
+    pub cursor: Option<SearchCursor>,
+    /// 最多返回的消息数量，超过服务端限制时按服务端限制返回
+    pub limit: u8,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserSearchMessagesResponse {
+    #[serde(rename_all = "camelCase")]
+    Success {
+        chat_id: Option<ChatID>,
+        keyword: String,
+        messages: Vec<SerializedChatMessage>,
+        /// 为空时已没有更多结果，否则可作为下一页请求的cursor
+        next_cursor: Option<SearchCursor>,
+    },
+    KeywordFormatError,
+    UserNotInChat,
+    DatabaseError,
+}
+
 // SendRequest
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -654,6 +823,240 @@ pub struct UserRevokeMessageResponse {
     pub state: UserRevokeMessageResponseState,
 }
 
+/** `UserEditMessageData` 编辑消息的请求，消息类型保持不变，只替换内容
+ */
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEditMessageData {
+    pub chat_id: ChatID,
+    pub in_chat_id: MessageID,
+    pub serialized_content: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub enum UserEditMessageResponseState {
+    Success,
+    TimeLimitExceeded,
+    PermissionsDenied,
+    LenthLimitExceeded,
+    // 只有Text, ReplyText与MentionText可以编辑
+    TypeNotEditable,
+    ContentError,
+    DatabaseError,
+    MessageNotExisted,
+    // 编辑期间消息被撤回或再次编辑
+    MessageChanged,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEditMessageResponse {
+    pub chat_id: ChatID,
+    pub in_chat_id: MessageID,
+    pub state: UserEditMessageResponseState,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserGetEditHistoryData {
+    pub chat_id: ChatID,
+    pub in_chat_id: MessageID,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserGetEditHistoryResponse {
+    #[serde(rename_all = "camelCase")]
+    Success {
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        /// 消息被编辑前的各个版本，按编辑的先后排列
+        history: Vec<SerializedChatMessage>,
+    },
+    UserNotInChat,
+    DatabaseError,
+}
+
+/** `UserForwardMessagesData` 将源聊天中的一组消息按顺序转发到目标聊天
+ */
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserForwardMessagesData {
+    pub source_chat_id: ChatID,
+    pub in_chat_ids: Vec<MessageID>,
+    pub target_chat_id: ChatID,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserForwardMessagesResponse {
+    #[serde(rename_all = "camelCase")]
+    Success {
+        target_chat_id: ChatID,
+        /// 写入目标聊天的消息，与请求中的顺序一致
+        messages: Vec<SerializedChatMessage>,
+    },
+    /// 用户不能在源聊天或目标聊天中发送消息
+    #[serde(rename_all = "camelCase")]
+    CannotSend {
+        chat_id: ChatID,
+        reason: UserSendMessageResponseState,
+    },
+    #[serde(rename_all = "camelCase")]
+    MessageNotExisted {
+        in_chat_id: MessageID,
+    },
+    #[serde(rename_all = "camelCase")]
+    TypeNotForwardable {
+        in_chat_id: MessageID,
+    },
+    /// 转发的消息为空或超过单次转发的上限
+    NumberLimitExceeded,
+    DatabaseError,
+}
+
+/** `UserGetThreadData` 获取一条消息及回复它的全部消息
+ */
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserGetThreadData {
+    pub chat_id: ChatID,
+    pub in_chat_id: MessageID,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserGetThreadResponse {
+    #[serde(rename_all = "camelCase")]
+    Success {
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        /// 被回复的消息，附带回应人数与回复数量
+        root: SerializedChatMessage,
+        /// 回复，按in_chat_id排列
+        replies: Vec<SerializedChatMessage>,
+    },
+    UserNotInChat,
+    MessageNotExisted,
+    DatabaseError,
+}
+
+/** `UserPinMessageData` 置顶或取消置顶消息的请求
+ * 群聊中只有群主与管理员可以置顶，私聊中双方都可以
+ */
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserPinMessageData {
+    pub chat_id: ChatID,
+    pub in_chat_id: MessageID,
+}
+
+/** `UserSetDisappearingData` 设置阅后即焚的请求，权限与置顶消息相同
+ * ttl为消息的存活时间（秒），为空时关闭；开启前发送的消息不会过期
+ */
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserSetDisappearingData {
+    pub chat_id: ChatID,
+    #[serde(default)]
+    pub ttl: Option<u32>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserSetDisappearingResponse {
+    #[serde(rename_all = "camelCase")]
+    Success {
+        chat_id: ChatID,
+        ttl: Option<u32>,
+    },
+    UserNotInChat,
+    NoPermission,
+    TtlInvalid,
+    DatabaseError,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserGetDisappearingResponse {
+    #[serde(rename_all = "camelCase")]
+    Success {
+        chat_id: ChatID,
+        ttl: Option<u32>,
+    },
+    UserNotInChat,
+    DatabaseError,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserPinMessageResponse {
+    #[serde(rename_all = "camelCase")]
+    Success {
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+    },
+    UserNotInChat,
+    NoPermission,
+    MessageNotExisted,
+    AlreadyPinned,
+    PinLimitExceeded,
+    DatabaseError,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserUnpinMessageResponse {
+    #[serde(rename_all = "camelCase")]
+    Success {
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+    },
+    UserNotInChat,
+    NoPermission,
+    NotPinned,
+    DatabaseError,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserGetPinnedMessagesResponse {
+    #[serde(rename_all = "camelCase")]
+    Success {
+        chat_id: ChatID,
+        /// 置顶消息，按in_chat_id排列
+        messages: Vec<SerializedChatMessage>,
+    },
+    UserNotInChat,
+    DatabaseError,
+}
+
+/** `UserReactionData` 添加或取消表情回应的请求
+ */
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserReactionData {
+    pub chat_id: ChatID,
+    pub in_chat_id: MessageID,
+    pub emoji: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserReactionResponse {
+    #[serde(rename_all = "camelCase")]
+    Success {
+        chat_id: ChatID,
+        in_chat_id: MessageID,
+        /// 附带最新回应人数的消息
+        message: SerializedChatMessage,
+    },
+    UserNotInChat,
+    MessageNotExisted,
+    ReactionFormatError,
+    DatabaseError,
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(tag = "state")]
 pub enum UserGetGroupOwnerResponse {
@@ -784,6 +1187,193 @@ pub struct UserSetOppositeReadCursorData {
     pub in_chat_id: MessageID,
 }
 
+/** `UserSubscribeReadReceiptsData` 订阅群聊中其他成员已读位置推送的请求
+ * 订阅只在本次连接中有效，断开后需重新订阅
+ */
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserSubscribeReadReceiptsData {
+    pub chat_id: ChatID,
+    pub mode: ReadReceiptMode,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum ReadReceiptMode {
+    // 推送所有成员的已读位置
+    All,
+    // 只推送已读到自己最后发送的消息的成员
+    OwnMessages,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserSubscribeReadReceiptsResponse {
+    #[serde(rename_all = "camelCase")]
+    Success {
+        chat_id: ChatID,
+    },
+    NotGroupChat,
+    UserNotInChat,
+    DatabaseError,
+}
+
+/** `GroupReadCursorsData` 合并后推送的群聊已读位置，每位成员只保留推送间隔内最新的位置
+ */
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupReadCursorsData {
+    pub chat_id: ChatID,
+    pub cursors: Vec<(UserID, MessageID)>,
+}
+
+/** `UserTypingData` 转发给聊天中其他在线成员的输入状态，不会写入数据库
+ */
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UserTypingData {
+    pub chat_id: ChatID,
+    pub user_id: UserID,
+    pub typing: bool,
+}
+
+/** `PresenceStatus` 用户的在线状态，离开状态由用户自行设置
+ */
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum PresenceStatus {
+    Online,
+    Away,
+    Offline,
+}
+
+/** `UserPresenceData` 用户的在线状态，只有离线时才带有最后在线时间
+ * 隐藏了在线状态的用户对他人总是显示为离线，且没有最后在线时间
+ */
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UserPresenceData {
+    pub user_id: UserID,
+    pub status: PresenceStatus,
+    pub last_seen: Option<Timestamp>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserSetPresenceResponse {
+    Success { status: PresenceStatus },
+    StatusInvalid,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserSetPresenceHiddenResponse {
+    Success { hidden: bool },
+    DatabaseError,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserGetPresenceResponse {
+    Success { presences: Vec<UserPresenceData> },
+    TooManyUsers,
+    DatabaseError,
+}
+
+/** `DeviceInfo` 用户在线的设备
+ */
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceInfo {
+    pub device_id: DeviceID,
+    pub client_ip: SocketAddr,
+    pub login_time: Timestamp,
+    /// 是否为发起查询的设备
+    pub current: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserListDevicesResponse {
+    Success { devices: Vec<DeviceInfo> },
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserKickDeviceResponse {
+    #[serde(rename_all = "camelCase")]
+    Success {
+        device_id: DeviceID,
+    },
+    DeviceNotFound,
+    CurrentDevice,
+    DatabaseError,
+}
+
+/** `SignedPrekey` 设备的签名预共享公钥，由设备的身份私钥对公钥签名
+ */
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedPrekey {
+    /// 轮换时须大于之前的ID
+    pub key_id: u32,
+    /// base64编码的X25519公钥
+    pub public_key: String,
+    /// base64编码的Ed25519签名
+    pub signature: String,
+}
+
+/** `DeviceKeys` 公钥目录中一台设备的公钥
+ */
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceKeys {
+    pub device_id: DeviceID,
+    /// base64编码的Ed25519身份公钥
+    pub identity_key: String,
+    pub signed_prekey: SignedPrekey,
+    pub updated_at: Timestamp,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserUploadDeviceKeysData {
+    pub identity_key: String,
+    pub signed_prekey: SignedPrekey,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserSetDeviceKeysResponse {
+    Success,
+    KeyFormatError,
+    SignatureError,
+    /// 轮换前当前设备没有上传过公钥
+    KeysNotFound,
+    PrekeyIdOutdated,
+    DatabaseError,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserGetDeviceKeysResponse {
+    #[serde(rename_all = "camelCase")]
+    Success {
+        user_id: UserID,
+        devices: Vec<DeviceKeys>,
+    },
+    NotFriend,
+    DatabaseError,
+}
+
+/** `UserRateLimitedData` 消息超过速率限制时的响应，该消息不会被处理
+ */
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserRateLimitedData {
+    pub command: String,
+    /// 需要等待的时间，单位为毫秒
+    pub retry_after: u64,
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(tag = "state")]
 pub enum UserLogOffResponse {
@@ -812,11 +1402,53 @@ pub enum MemberChangeType {
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(tag = "state")]
 pub enum UserApplyForTokenResponse {
-    Success { token: String, timestamp: Timestamp },
+    #[serde(rename_all = "camelCase")]
+    Success {
+        device_id: DeviceID,
+        access_token: String,
+        refresh_token: String,
+        timestamp: Timestamp,
+    },
     DatabaseError,
     ServerError,
 }
 
+/** `UserRefreshTokenData` 访问令牌过期后，使用刷新令牌换取新的一对令牌
+ */
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserRefreshTokenData {
+    pub email: String,
+    pub device_id: DeviceID,
+    pub refresh_token: String,
+}
+
+/** `UserRefreshTokenResponse` 换取成功后旧的两个令牌均失效
+ */
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserRefreshTokenResponse {
+    #[serde(rename_all = "camelCase")]
+    Success {
+        device_id: DeviceID,
+        access_token: String,
+        refresh_token: String,
+        timestamp: Timestamp,
+    },
+    UserNotFound,
+    TokenError,
+    TokenExpired,
+    DatabaseError,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "state")]
+pub enum UserRevokeTokenResponse {
+    Success,
+    TokenNotFound,
+    DatabaseError,
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct RequestMessageResponse {
@@ -2,7 +2,10 @@
 客户端与服务端之间通信的直接处理函数
 */
 
-use std::future::Future;
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
 
 use actix::{
     dev::ToEnvelope, fut, Actor, ActorContext, ActorFutureExt, AsyncContext, Recipient, WrapFuture,
@@ -11,36 +14,68 @@ use actix::{
 use aes_gcm::{Aes128Gcm, KeyInit};
 
 use chrono::Utc;
+use log::{error, warn};
 use rand::thread_rng;
+use uuid::Uuid;
 
 use crate::{
     chat::{send_admin_message_to_group_chat, ChatMembers, ChatMessage, ChatType},
     config::{
         config::PWD_PATTERN,
-        datatype::{ChatID, EmailCodeValue, Timestamp, UploadId, UserID},
+        datatype::{
+            ChatID, DeviceID, EmailCodeValue, ProtocolVersion, ScheduleID, SerializedChatMessage,
+            SymCipher, Timestamp, UploadId, UserID,
+        },
         Config,
     },
     database::{
         self, check_user_in_chat, check_user_is_admin, check_user_is_owner, get_chat_user_list,
+        DatabaseError,
     },
     oss::{self, ObjectUploadRequest},
     server::{
         email::check_and_consume_email_code,
-        server_state::{user_sessions, workers_handle, UserSessionGetter},
+        presence, rate_limit,
+        read_receipt::read_receipts,
+        server_state::{
+            handshake_signing_key, user_sessions, workers_handle, DeviceInsertResult,
+            UserSessionGetter,
+        },
     },
     user::*,
-    utils::{self, base64, rsa::PubKeyHelper},
+    utils::{self, base64, ecdh, rsa::PubKeyHelper},
 };
 
 use super::client_message_data::*;
 use super::{
     actor_message_handler::ActorMessageHandler,
-    protocol::{ClientToServerMessage, DataChecker, ServerMessageSender, ServerToClientMessage},
-    UserSession, UserSessionActorMessage, UserSessionDataRc, UserSessionState,
+    protocol::{
+        ClientToServerMessage, DataChecker, ServerMessageSender, ServerToClientMessage,
+        PROTOCOL_VERSION,
+    },
+    TypingState, UserSession, UserSessionActorMessage, UserSessionDataRc, UserSessionState,
 };
 
 use user_request::UserRequsetContent;
 
+/** `LogDatabaseError` 记录数据库错误及其发生的位置，不改变返回值
+ * 数据不存在属于可预期的情况，只记为警告
+ */
+trait LogDatabaseError {
+    fn log_err(self, context: &str) -> Self;
+}
+
+impl<T> LogDatabaseError for Result<T, DatabaseError> {
+    fn log_err(self, context: &str) -> Self {
+        match &self {
+            Err(err @ DatabaseError::MissingKey(_)) => warn!("{}: {}", context, err),
+            Err(err) => error!("{}: {}", context, err),
+            Ok(_) => {}
+        }
+        return self;
+    }
+}
+
 // 这里原本打算用Unstable特性，trait别名，但是使用了以后发现编辑器插件傻了（）
 // 希望早日变成Stable
 pub trait ClientMessageHandler
@@ -49,15 +84,36 @@ where
     Self::Context: ServerMessageSender<Self> + ToEnvelope<Self, UserSessionActorMessage>,
 {
     fn handle_client_message(&mut self, msg: ClientToServerMessage, ctx: &mut Self::Context) {
-        // TODO : 对用户请求速度进行限制，避免被攻击
+        // 关闭连接的消息不受限制
+        if msg != ClientToServerMessage::Close {
+            let command = msg.get_command();
+            let (ip, user_id) = {
+                let data = self.get_data();
+                (data.client_ip.ip(), data.user_id)
+            };
+            if let Err(retry_after) = rate_limit::check_client_message(ip, user_id, &command) {
+                ctx.send_server_message(
+                    &ServerToClientMessage::RateLimited(UserRateLimitedData {
+                        command,
+                        retry_after: (retry_after.as_secs_f64() * 1000.0).ceil() as u64,
+                    }),
+                    self.get_cipher(),
+                );
+                return;
+            }
+        }
 
-        // 访问除了SetPubkey, Login, Register外的消息均需要登陆
+        // 访问除了SetPubkey, Login, Register, RefreshToken外的消息均需要登陆
         // 因此在编写其他接口的函数时可以假设一定已经登陆
         if msg != ClientToServerMessage::Close {
             // 为什么要加括号？
             // `Borrow Checker`仍不是很智能（）
             let cur_state = &self.get_data().state;
-            if *cur_state == UserSessionState::Started && !msg.is_set_pub_key() {
+            if *cur_state == UserSessionState::Started
+                && !msg.is_set_pub_key()
+                && !msg.is_key_exchange()
+                && !msg.is_negotiate_protocol()
+            {
                 ctx.send_server_message(
                     &ServerToClientMessage::SetConnectionPubKeyResponse(
                         UserSetPubKeyResponse::NeedSetPubKey,
@@ -68,6 +124,7 @@ where
             } else if *cur_state == UserSessionState::Approved
                 && !msg.is_login()
                 && !msg.is_register()
+                && !msg.is_refresh_token()
             {
                 ctx.send_server_message(
                     &ServerToClientMessage::LoginResponse(UserLoginResponse::NeedLogin),
@@ -87,9 +144,15 @@ where
             }
             ClientToServerMessage::Pong => {}
             ClientToServerMessage::Close => ctx.stop(),
+            ClientToServerMessage::NegotiateProtocol(version) => {
+                ctx.send_server_message(&negotiate_protocol(self, version), None);
+            }
             ClientToServerMessage::SetConnectionPubKey(key) => {
                 ctx.send_server_message(&set_connection_pub_key(self, key), None);
             }
+            ClientToServerMessage::KeyExchange(key) => {
+                ctx.send_server_message(&key_exchange(self, key), None);
+            }
             ClientToServerMessage::Register(data) => {
                 self.excute_and_send_response_wait(ctx, register(data));
             }
@@ -97,9 +160,10 @@ where
                 ctx,
                 login(self.get_data_rc(), data, ctx.address().recipient()),
             ),
-            ClientToServerMessage::SendMessage(msg) => {
-                self.excute_and_send_response(ctx, send_message(user_id.unwrap(), msg))
-            }
+            ClientToServerMessage::SendMessage(msg) => self.excute_and_send_response(
+                ctx,
+                send_message_from_device(user_id.unwrap(), msg, Some(ctx.address().recipient())),
+            ),
             ClientToServerMessage::Pull(data) => {
                 // TODO : 对Pull这种非常耗时的请求单独进行请求速度限制
                 workers_handle.spawn(user_pull(data, user_id.unwrap(), ctx.address().recipient()));
@@ -119,6 +183,9 @@ where
             ClientToServerMessage::GetMessages(data) => {
                 self.excute_and_send_response(ctx, get_messages(data))
             }
+            ClientToServerMessage::SearchMessages(data) => {
+                self.excute_and_send_response(ctx, search_messages(user_id.unwrap(), data))
+            }
             ClientToServerMessage::GetChatInfo(chat_id) => {
                 self.excute_and_send_response(ctx, get_chat_info(chat_id))
             }
@@ -131,9 +198,10 @@ where
             ClientToServerMessage::SetUserSetting(content) => {
                 self.excute_and_send_response(ctx, set_user_setting(user_id.unwrap(), content))
             }
-            ClientToServerMessage::SetAlreadyRead(data) => {
-                self.excute_and_send_response(ctx, set_user_already_read(user_id.unwrap(), data))
-            }
+            ClientToServerMessage::SetAlreadyRead(data) => self.excute_and_send_response(
+                ctx,
+                set_user_already_read(user_id.unwrap(), data, ctx.address().recipient()),
+            ),
             ClientToServerMessage::UploadFileRequest(req) => {
                 self.excute_and_send_response(ctx, upload_file_req(user_id.unwrap(), req))
             }
@@ -146,6 +214,47 @@ where
             ClientToServerMessage::RevokeMessage(data) => {
                 self.excute_and_send_response(ctx, revoke_message(user_id.unwrap(), data))
             }
+            ClientToServerMessage::EditMessage(data) => {
+                self.excute_and_send_response(ctx, edit_message(user_id.unwrap(), data))
+            }
+            ClientToServerMessage::GetEditHistory(data) => {
+                self.excute_and_send_response(ctx, get_edit_history(user_id.unwrap(), data))
+            }
+            ClientToServerMessage::AddReaction(data) => {
+                self.excute_and_send_response(ctx, add_reaction(user_id.unwrap(), data))
+            }
+            ClientToServerMessage::RemoveReaction(data) => {
+                self.excute_and_send_response(ctx, remove_reaction(user_id.unwrap(), data))
+            }
+            ClientToServerMessage::GetThread(data) => {
+                self.excute_and_send_response(ctx, get_thread(user_id.unwrap(), data))
+            }
+            ClientToServerMessage::ForwardMessages(data) => {
+                self.excute_and_send_response(ctx, forward_messages(user_id.unwrap(), data))
+            }
+            ClientToServerMessage::PinMessage(data) => {
+                self.excute_and_send_response(ctx, pin_message(user_id.unwrap(), data))
+            }
+            ClientToServerMessage::UnpinMessage(data) => {
+                self.excute_and_send_response(ctx, unpin_message(user_id.unwrap(), data))
+            }
+            ClientToServerMessage::GetPinnedMessages(chat_id) => {
+                self.excute_and_send_response(ctx, get_pinned_messages(user_id.unwrap(), chat_id))
+            }
+            ClientToServerMessage::SetDisappearing(data) => {
+                self.excute_and_send_response(ctx, set_disappearing(user_id.unwrap(), data))
+            }
+            ClientToServerMessage::GetDisappearing(chat_id) => {
+                self.excute_and_send_response(ctx, get_disappearing(user_id.unwrap(), chat_id))
+            }
+            ClientToServerMessage::GetScheduledMessages => {
+                self.excute_and_send_response(ctx, get_scheduled_messages(user_id.unwrap()))
+            }
+            ClientToServerMessage::CancelScheduledMessage(schedule_id) => self
+                .excute_and_send_response(
+                    ctx,
+                    cancel_scheduled_message(user_id.unwrap(), schedule_id),
+                ),
             ClientToServerMessage::QuitGroupChat(data) => {
                 self.excute_and_send_response(ctx, quit_group_chat(user_id.unwrap(), data))
             }
@@ -194,6 +303,37 @@ where
             ClientToServerMessage::GetUserID(name) => {
                 self.excute_and_send_response(ctx, get_user_id(name))
             }
+            ClientToServerMessage::SubscribeReadReceipts(data) => {
+                self.excute_and_send_response(ctx, subscribe_read_receipts(user_id.unwrap(), data))
+            }
+            ClientToServerMessage::UnsubscribeReadReceipts(chat_id) => self
+                .excute_and_send_response(
+                    ctx,
+                    unsubscribe_read_receipts(user_id.unwrap(), chat_id),
+                ),
+            ClientToServerMessage::StartTyping(chat_id) => {
+                self.start_typing(ctx, user_id.unwrap(), chat_id)
+            }
+            ClientToServerMessage::StopTyping(chat_id) => {
+                self.stop_typing(ctx, user_id.unwrap(), chat_id)
+            }
+            ClientToServerMessage::SetPresence(status) => {
+                self.excute_and_send_response(ctx, set_presence(user_id.unwrap(), status))
+            }
+            ClientToServerMessage::SetPresenceHidden(hidden) => {
+                self.excute_and_send_response(ctx, set_presence_hidden(user_id.unwrap(), hidden))
+            }
+            ClientToServerMessage::GetPresence(user_ids) => {
+                self.excute_and_send_response(ctx, get_presence(user_id.unwrap(), user_ids))
+            }
+            ClientToServerMessage::ListDevices => {
+                let device_id = self.get_data().device_id.clone().unwrap_or_default();
+                self.excute_and_send_response(ctx, list_devices(user_id.unwrap(), device_id))
+            }
+            ClientToServerMessage::KickDevice(target) => {
+                let device_id = self.get_data().device_id.clone().unwrap_or_default();
+                self.excute_and_send_response(ctx, kick_device(user_id.unwrap(), device_id, target))
+            }
             ClientToServerMessage::GetUserReadInGroup(data) => {
                 workers_handle.spawn(get_user_read_in_group(
                     user_id.unwrap(),
@@ -208,7 +348,37 @@ where
                 user_log_off(user_id.unwrap(), email_code, ctx.address().recipient()),
             ),
             ClientToServerMessage::ApplyForToken => {
-                self.excute_and_send_response(ctx, user_apply_for_token(user_id.unwrap()))
+                let device_id = self.get_data().device_id.clone().unwrap_or_default();
+                self.excute_and_send_response(
+                    ctx,
+                    user_apply_for_token(user_id.unwrap(), device_id),
+                )
+            }
+            ClientToServerMessage::RefreshToken(data) => {
+                self.excute_and_send_response(ctx, refresh_token(data))
+            }
+            ClientToServerMessage::RevokeToken(device_id) => {
+                self.excute_and_send_response(ctx, revoke_token(user_id.unwrap(), device_id))
+            }
+            ClientToServerMessage::RevokeAllTokens => {
+                self.excute_and_send_response(ctx, revoke_all_tokens(user_id.unwrap()))
+            }
+            ClientToServerMessage::UploadDeviceKeys(data) => {
+                let device_id = self.get_data().device_id.clone().unwrap_or_default();
+                self.excute_and_send_response(
+                    ctx,
+                    upload_device_keys(user_id.unwrap(), device_id, data),
+                )
+            }
+            ClientToServerMessage::RotateSignedPrekey(prekey) => {
+                let device_id = self.get_data().device_id.clone().unwrap_or_default();
+                self.excute_and_send_response(
+                    ctx,
+                    rotate_signed_prekey(user_id.unwrap(), device_id, prekey),
+                )
+            }
+            ClientToServerMessage::GetDeviceKeys(target_id) => {
+                self.excute_and_send_response(ctx, get_device_keys(user_id.unwrap(), target_id))
             }
         };
     }
@@ -239,6 +409,83 @@ where
     {
         ctx.spawn(fut.into_actor(self));
     }
+
+    /** `start_typing` 转发正在输入的状态，同一聊天在节流间隔内只转发一次
+     * 超过过期时间没有再次收到时自动转发停止输入
+     */
+    fn start_typing(&mut self, ctx: &mut Self::Context, user_id: UserID, chat_id: ChatID) {
+        let protocol_config = &Config::get().protocol;
+        let throttle = Duration::from_millis(protocol_config.typing_throttle_interval as u64);
+        let now = Instant::now();
+
+        let (relay, new_state) = {
+            let mut data = self.get_mut_data();
+            match data.typing.get_mut(&chat_id) {
+                Some(state) => {
+                    state.refreshed_at = now;
+                    let relay = now.duration_since(state.relayed_at) >= throttle;
+                    if relay {
+                        state.relayed_at = now;
+                    }
+                    (relay, false)
+                }
+                None => {
+                    data.typing.insert(
+                        chat_id,
+                        TypingState {
+                            relayed_at: now,
+                            refreshed_at: now,
+                        },
+                    );
+                    (true, true)
+                }
+            }
+        };
+
+        if relay {
+            self.excute(
+                ctx,
+                send_typing_to_online_users_in_chat(user_id, chat_id, true),
+            );
+        }
+        // 每段输入状态只需要一个过期检查
+        if new_state {
+            let expire_time = Duration::from_millis(protocol_config.typing_expire_time as u64);
+            ctx.run_later(expire_time, move |act, ctx| {
+                act.expire_typing(ctx, user_id, chat_id)
+            });
+        }
+    }
+
+    /** `expire_typing` 输入状态超时未刷新时转发停止输入，否则等待到新的过期时间再检查
+     */
+    fn expire_typing(&mut self, ctx: &mut Self::Context, user_id: UserID, chat_id: ChatID) {
+        let refreshed_at = match self.get_data().typing.get(&chat_id) {
+            Some(state) => state.refreshed_at,
+            None => return,
+        };
+        let expire_time = Duration::from_millis(Config::get().protocol.typing_expire_time as u64);
+        let elapsed = refreshed_at.elapsed();
+        if elapsed >= expire_time {
+            self.stop_typing(ctx, user_id, chat_id);
+        } else {
+            ctx.run_later(expire_time - elapsed, move |act, ctx| {
+                act.expire_typing(ctx, user_id, chat_id)
+            });
+        }
+    }
+
+    /** `stop_typing` 转发停止输入的状态，没有正在输入时不转发
+     */
+    fn stop_typing(&mut self, ctx: &mut Self::Context, user_id: UserID, chat_id: ChatID) {
+        if self.get_mut_data().typing.remove(&chat_id).is_none() {
+            return;
+        }
+        self.excute(
+            ctx,
+            send_typing_to_online_users_in_chat(user_id, chat_id, false),
+        );
+    }
 }
 
 pub async fn register(register_data: UserRegisterData) -> ServerToClientMessage {
@@ -263,6 +510,28 @@ pub async fn register(register_data: UserRegisterData) -> ServerToClientMessage
     return ServerToClientMessage::RegisterResponse(res);
 }
 
+fn negotiate_protocol<T: UserSession>(
+    session: &mut T,
+    version: ProtocolVersion,
+) -> ServerToClientMessage {
+    let response = ServerToClientMessage::NegotiateProtocolResponse;
+    let mut data = session.get_mut_data();
+    if data.state == UserSessionState::Approved {
+        return response(UserNegotiateProtocolResponse::HasApproved);
+    }
+
+    let min_version = Config::get().protocol.min_protocol_version;
+    let version = std::cmp::min(version, PROTOCOL_VERSION);
+    if version < min_version {
+        return response(UserNegotiateProtocolResponse::VersionUnsupported {
+            min_version,
+            max_version: PROTOCOL_VERSION,
+        });
+    }
+    data.protocol_version = version;
+    return response(UserNegotiateProtocolResponse::Success { version });
+}
+
 fn set_connection_pub_key<T: UserSession>(
     session: &mut T,
     base64_pkcs1_pub_key: String,
@@ -277,6 +546,19 @@ fn set_connection_pub_key<T: UserSession>(
             );
         }
 
+        if !Config::get().safety.enable_rsa_handshake {
+            return ServerToClientMessage::SetConnectionPubKeyResponse(
+                crate::user::UserSetPubKeyResponse::HandshakeDisabled,
+            );
+        }
+
+        // 旧版客户端不会协商版本，需明确告知而不是在之后的帧上解密失败
+        if data.protocol_version < Config::get().protocol.min_protocol_version {
+            return ServerToClientMessage::SetConnectionPubKeyResponse(
+                crate::user::UserSetPubKeyResponse::ProtocolVersionUnsupported,
+            );
+        }
+
         if let Ok(pub_key) = utils::rsa::get_pub_key_from_base64_pkcs1_pem(base64_pkcs1_pub_key) {
             data.user_pub_key = Some(pub_key);
         } else {
@@ -295,11 +577,50 @@ fn set_connection_pub_key<T: UserSession>(
         .encrypt_to_base64(base64::encode(sym_key).as_bytes())
         .unwrap();
 
-    session.on_sym_key_set_success(Aes128Gcm::new_from_slice(&sym_key).unwrap());
+    let cipher = Aes128Gcm::new_from_slice(&sym_key).unwrap();
+    let cipher = if session.get_data().protocol_version >= PROTOCOL_VERSION {
+        SymCipher::with_frame_nonce(cipher)
+    } else {
+        SymCipher::legacy(cipher)
+    };
+    session.on_sym_key_set_success(cipher);
 
     return ServerToClientMessage::SetConnectionSymKey(encoded_sym_key);
 }
 
+fn key_exchange<T: UserSession>(
+    session: &mut T,
+    base64_client_public_key: String,
+) -> ServerToClientMessage {
+    let response = ServerToClientMessage::KeyExchangeResponse;
+    if session.get_data().state == UserSessionState::Approved {
+        return response(UserKeyExchangeResponse::HasApproved);
+    }
+    if !Config::get().safety.enable_x25519_handshake {
+        return response(UserKeyExchangeResponse::HandshakeDisabled);
+    }
+
+    let share = match base64::decode(&base64_client_public_key)
+        .ok()
+        .and_then(|key| ecdh::server_key_exchange(&handshake_signing_key, PROTOCOL_VERSION, &key))
+    {
+        Some(share) => share,
+        None => return response(UserKeyExchangeResponse::PublicKeyError),
+    };
+
+    // 新的握手总是使用最新的协议版本
+    session.get_mut_data().protocol_version = PROTOCOL_VERSION;
+    session.on_sym_key_set_success(SymCipher::with_session_keys(
+        Aes128Gcm::new_from_slice(&share.keys.server_to_client).unwrap(),
+        Aes128Gcm::new_from_slice(&share.keys.client_to_server).unwrap(),
+    ));
+
+    return response(UserKeyExchangeResponse::Success {
+        public_key: base64::encode(share.public_key),
+        signature: base64::encode(share.signature.to_bytes()),
+    });
+}
+
 async fn login(
     session_data_rc: UserSessionDataRc,
     login_data: UserLoginData,
@@ -324,10 +645,17 @@ async fn login(
         }
     }
 
+    let device_id = login_data
+        .device_id
+        .clone()
+        .unwrap_or_else(|| Uuid::new_v4().simple().to_string());
+
     let res = if let Some(input_code) = login_data.email_code {
         // 使用验证码登录
         if check_and_consume_email_code(&login_data.email, input_code) {
-            let res = database::get_user_id_by_email(&login_data.email).await;
+            let res = database::get_user_id_by_email(&login_data.email)
+                .await
+                .log_err("login: 通过邮箱查找用户");
             if res.is_err() {
                 return ServerToClientMessage::LoginResponse(UserLoginResponse::ServerError);
             }
@@ -352,19 +680,39 @@ async fn login(
         _ => return ServerToClientMessage::LoginResponse(res),
     };
 
-    // 处理第二次登陆逻辑，是挤掉还是登不上？
-    if let Some(_) = user_sessions.get_user_session(user_id) {
-        return ServerToClientMessage::LoginResponse(UserLoginResponse::UserLogged);
+    // 同一设备再次登录时挤掉之前的连接，新设备登录时检查设备数量
+    let insert_result =
+        session_data_rc
+            .borrow_mut()
+            .on_login_success(user_id, device_id, self_recipient);
+    match insert_result {
+        DeviceInsertResult::FirstDevice => {
+            workers_handle.spawn(presence::user_online(user_id));
+        }
+        DeviceInsertResult::Inserted => {}
+        DeviceInsertResult::Replaced(device) => {
+            device.recipient.do_send(UserSessionActorMessage::Kick)
+        }
+        DeviceInsertResult::TooManyDevices => {
+            return ServerToClientMessage::LoginResponse(UserLoginResponse::TooManyDevices);
+        }
     }
 
-    session_data_rc
-        .borrow_mut()
-        .on_login_success(user_id, self_recipient);
-
     return ServerToClientMessage::LoginResponse(UserLoginResponse::Success { user_id: user_id });
 }
 
 pub async fn send_message(sender_id: UserID, msg: UserSendMessageData) -> ServerToClientMessage {
+    return send_message_from_device(sender_id, msg, None).await;
+}
+
+/** `send_message_from_device` 发送消息，并同步到发送者除`origin`外的其他设备
+ * `origin`为None时不同步，如定时消息与系统消息
+ */
+pub async fn send_message_from_device(
+    sender_id: UserID,
+    msg: UserSendMessageData,
+    origin: Option<Recipient<UserSessionActorMessage>>,
+) -> ServerToClientMessage {
     // TODO : 数据合法性检查
 
     // 此处一定已经登陆
@@ -385,6 +733,17 @@ pub async fn send_message(sender_id: UserID, msg: UserSendMessageData) -> Server
         }
     };
 
+    // 群聊中的成员会变化，无法约定密钥，只允许在私聊中发送端到端加密的消息
+    if msg.r#type == ChatMessageType::Encrypted && matches!(chat_type, ChatType::Group(_)) {
+        return ServerToClientMessage::SendMessageResponse(UserSendMessageResponse {
+            state: UserSendMessageResponseState::EncryptedNotSupported,
+            client_id,
+            in_chat_id: None,
+            timestamp: None,
+            chat_id,
+        });
+    }
+
     let mut user_ids = vec![];
     if ChatMessageType::MentionText == msg.r#type {
         let users_result = serde_json::from_str::<MentionTextType>(&msg.serialized_content);
@@ -400,13 +759,69 @@ pub async fn send_message(sender_id: UserID, msg: UserSendMessageData) -> Server
         user_ids = users_result.unwrap().user_ids;
     }
 
-    let (chat_msg, in_chat_id, timestamp) = match database::write_message_to_chat(
-        msg.r#type.get_str(),
-        serde_json::to_string::<String>(&msg.serialized_content).unwrap(),
-        msg.chat_id,
-        self_id,
-    )
-    .await
+    let mut reply_to = None;
+    if ChatMessageType::ReplyText == msg.r#type {
+        let content = match serde_json::from_str::<ReplyTextType>(&msg.serialized_content) {
+            Ok(content) => content,
+            Err(_) => {
+                return ServerToClientMessage::SendMessageResponse(UserSendMessageResponse {
+                    state: UserSendMessageResponseState::ContentError,
+                    client_id,
+                    chat_id,
+                    in_chat_id: None,
+                    timestamp: None,
+                });
+            }
+        };
+        let state = match database::get_message(chat_id, content.reply_to_in_chat_id)
+            .await
+            .log_err("send_message: 读取被回复的消息")
+        {
+            Ok(Some(target)) => match serde_json::from_str::<ChatMessage>(&target) {
+                Ok(target) if target.r#type != ChatMessageType::Revoked => None,
+                _ => Some(UserSendMessageResponseState::ReplyTargetNotFound),
+            },
+            Ok(None) => Some(UserSendMessageResponseState::ReplyTargetNotFound),
+            Err(_) => Some(UserSendMessageResponseState::DatabaseError),
+        };
+        if let Some(state) = state {
+            return ServerToClientMessage::SendMessageResponse(UserSendMessageResponse {
+                state,
+                client_id,
+                chat_id,
+                in_chat_id: None,
+                timestamp: None,
+            });
+        }
+        reply_to = Some(content.reply_to_in_chat_id);
+    }
+
+    // 投递时间未到时只保存定时消息，到期后由后台任务再次调用send_message
+    if let Some(deliver_at) = msg.deliver_at {
+        if deliver_at > Utc::now().timestamp_millis() as Timestamp {
+            return schedule_message(sender_id, msg, deliver_at).await;
+        }
+    }
+
+    // 回复与消息在同一次写入中记录
+    let r#type = msg.r#type.get_str();
+    let serialized_content = serde_json::to_string::<String>(&msg.serialized_content).unwrap();
+    let write_result = match reply_to {
+        Some(root_id) => {
+            database::reply_message_to_chat(
+                r#type,
+                serialized_content,
+                msg.chat_id,
+                self_id,
+                root_id,
+            )
+            .await
+        }
+        None => {
+            database::write_message_to_chat(r#type, serialized_content, msg.chat_id, self_id).await
+        }
+    };
+    let (chat_msg, in_chat_id, timestamp) = match write_result.log_err("send_message: 写入聊天消息")
     {
         Ok(data) => data,
         Err(_) => {
@@ -441,24 +856,22 @@ pub async fn send_message(sender_id: UserID, msg: UserSendMessageData) -> Server
         }
     }
 
-    match chat_type {
-        ChatType::Private(ids) => {
-            send_msg_to_online_user_in_private_chat(sender_id, chat_msg, ids);
-        }
-        ChatType::Group(num) => {
-            if num as u8
-                > Config::get()
-                    .protocol
-                    .worker_send_messages_member_num_threshold
-            {
-                workers_handle.spawn(send_msg_to_online_users_in_chat(
-                    chat_msg, sender_id, chat_id,
-                ));
-            } else {
-                send_msg_to_online_users_in_chat(chat_msg, sender_id, chat_id).await;
-            }
-        }
+    if let ChatType::Group(_) = chat_type {
+        read_receipts
+            .lock()
+            .unwrap()
+            .record_sent(chat_id, sender_id, in_chat_id);
     }
+    if let Some(origin) = origin {
+        user_sessions.do_send_message_to_other_devices(
+            sender_id,
+            &origin,
+            UserSessionActorMessage::SendServerMessage(ServerToClientMessage::Message(
+                chat_msg.clone(),
+            )),
+        );
+    }
+    send_msg_to_online_users(&chat_type, chat_msg, sender_id, chat_id).await;
 
     return ServerToClientMessage::SendMessageResponse(UserSendMessageResponse {
         state: UserSendMessageResponseState::Success,
@@ -479,7 +892,10 @@ pub async fn send_request(user_id: UserID, data: UserSendRequestData) -> ServerT
             state: UserSendRequestState::RequestError(err),
         });
     }
-    let handlers = match database::get_handlers_of_request(&data.content).await {
+    let handlers = match database::get_handlers_of_request(&data.content)
+        .await
+        .log_err("send_request: 获取请求的处理者")
+    {
         Ok(handler) => handler,
         Err(_) => {
             return ServerToClientMessage::SendRequestResponse(UserSendRequestResponse {
@@ -489,17 +905,19 @@ pub async fn send_request(user_id: UserID, data: UserSendRequestData) -> ServerT
             });
         }
     };
-    let (serialized_req, req_info) =
-        match database::write_user_request(user_id, data, &handlers).await {
-            Ok(req) => req,
-            Err(_) => {
-                return ServerToClientMessage::SendRequestResponse(UserSendRequestResponse {
-                    req_id: None,
-                    client_id,
-                    state: UserSendRequestState::DatabaseError,
-                });
-            }
-        };
+    let (serialized_req, req_info) = match database::write_user_request(user_id, data, &handlers)
+        .await
+        .log_err("send_request: 写入请求")
+    {
+        Ok(req) => req,
+        Err(_) => {
+            return ServerToClientMessage::SendRequestResponse(UserSendRequestResponse {
+                req_id: None,
+                client_id,
+                state: UserSendRequestState::DatabaseError,
+            });
+        }
+    };
     if let Err(_) = on_request_send(&req_info).await {
         return ServerToClientMessage::SendRequestResponse(UserSendRequestResponse {
             req_id: None,
@@ -517,7 +935,10 @@ pub async fn send_request(user_id: UserID, data: UserSendRequestData) -> ServerT
 }
 
 pub async fn solve_request(user_id: UserID, data: UserSolveRequestData) -> ServerToClientMessage {
-    let req = match database::get_user_request(data.req_id).await {
+    let req = match database::get_user_request(data.req_id)
+        .await
+        .log_err("solve_request: 读取请求")
+    {
         Ok(opt) => match opt {
             Some(req) => req,
             None => {
@@ -541,7 +962,10 @@ pub async fn solve_request(user_id: UserID, data: UserSolveRequestData) -> Serve
             req_id: data.req_id,
         });
     }
-    let handlers = match database::get_handlers_of_request(&req.info.content).await {
+    let handlers = match database::get_handlers_of_request(&req.info.content)
+        .await
+        .log_err("solve_request: 获取请求的处理者")
+    {
         Ok(handler) => handler,
         Err(_) => {
             return ServerToClientMessage::SolveRequestResponse(UserSolveRequestResponse {
@@ -566,6 +990,7 @@ pub async fn solve_request(user_id: UserID, data: UserSolveRequestData) -> Serve
     if let Err(_) = if data.answer == UserRequestState::Approved {
         if database::check_user_exist(req.info.sender_id)
             .await
+            .log_err("solve_request: 检查申请人是否存在")
             .is_err()
         {
             send_msg_to_online_handlers(
@@ -594,7 +1019,9 @@ pub async fn solve_request(user_id: UserID, data: UserSolveRequestData) -> Serve
                 inviter_id: _,
                 chat_id,
             } => {
-                let check = database::check_user_in_chat(req.info.sender_id, chat_id).await;
+                let check = database::check_user_in_chat(req.info.sender_id, chat_id)
+                    .await
+                    .log_err("solve_request: 检查用户是否在聊天中");
                 if check.is_err() {
                     return ServerToClientMessage::SolveRequestResponse(UserSolveRequestResponse {
                         state: UserSolveRequestState::DatabaseError,
@@ -619,7 +1046,9 @@ pub async fn solve_request(user_id: UserID, data: UserSolveRequestData) -> Serve
                 receiver_id,
                 chat_id,
             } => {
-                let check = database::check_user_in_chat(receiver_id, chat_id).await;
+                let check = database::check_user_in_chat(receiver_id, chat_id)
+                    .await
+                    .log_err("solve_request: 检查用户是否在聊天中");
                 if check.is_err() {
                     return ServerToClientMessage::SolveRequestResponse(UserSolveRequestResponse {
                         state: UserSolveRequestState::DatabaseError,
@@ -633,7 +1062,9 @@ pub async fn solve_request(user_id: UserID, data: UserSolveRequestData) -> Serve
                 }
             }
             UserRequsetContent::JoinGroup { chat_id } => {
-                let check = database::check_user_in_chat(req.info.sender_id, chat_id).await;
+                let check = database::check_user_in_chat(req.info.sender_id, chat_id)
+                    .await
+                    .log_err("solve_request: 检查用户是否在聊天中");
                 if check.is_err() {
                     return ServerToClientMessage::SolveRequestResponse(UserSolveRequestResponse {
                         state: UserSolveRequestState::DatabaseError,
@@ -659,6 +1090,7 @@ pub async fn solve_request(user_id: UserID, data: UserSolveRequestData) -> Serve
     } else {
         if database::check_user_exist(req.info.sender_id)
             .await
+            .log_err("solve_request: 检查申请人是否存在")
             .is_err()
         {
             send_msg_to_online_handlers(
@@ -726,7 +1158,10 @@ pub async fn create_group_chat(
             UserCreateGroupChatResponse::ChatNameFormatError,
         );
     }
-    match database::create_group_chat(user_id, data).await {
+    match database::create_group_chat(user_id, data)
+        .await
+        .log_err("create_group_chat: 创建群聊")
+    {
         Ok(id) => {
             send_admin_message_to_group_chat(id, "建立群聊成功".to_string()).await;
             ServerToClientMessage::CreateGroupChatResponse(UserCreateGroupChatResponse::Success {
@@ -743,19 +1178,106 @@ pub async fn get_user_info(user_id: UserID) -> ServerToClientMessage {
     return ServerToClientMessage::GetUserInfoResponse(database::get_user_info(user_id).await);
 }
 
+/** `get_messages` 读取一段聊天消息，请求的范围中有已被清理的消息时在`truncated_before`中注明
+ */
 pub async fn get_messages(data: UserGetMessagesData) -> ServerToClientMessage {
     // TODO : 判断请求id范围是否合法
 
     // TODO : 判断用户是否在聊天中
 
-    match database::get_messages_in_chat(data.chat_id, data.start_id, data.end_id).await {
-        Ok(msgs) => ServerToClientMessage::Messages(msgs),
-        Err(_) => ServerToClientMessage::DatabaseError,
+    let response = ServerToClientMessage::GetMessagesResponse;
+    let pruned_id = match database::get_pruned_id(data.chat_id)
+        .await
+        .log_err("get_messages: 读取已清理的消息范围")
+    {
+        Ok(pruned_id) => pruned_id,
+        Err(_) => return response(UserGetMessagesResponse::DatabaseError),
+    };
+
+    match database::get_messages_in_chat(data.chat_id, data.start_id, data.end_id)
+        .await
+        .log_err("get_messages: 读取聊天消息")
+    {
+        // pruned_id为0表示没有清理过消息
+        Ok(messages) => response(UserGetMessagesResponse::Success {
+            chat_id: data.chat_id,
+            messages,
+            truncated_before: Some(pruned_id)
+                .filter(|pruned_id| *pruned_id > 0 && data.start_id <= *pruned_id),
+        }),
+        Err(_) => response(UserGetMessagesResponse::DatabaseError),
+    }
+}
+
+pub async fn search_messages(
+    user_id: UserID,
+    data: UserSearchMessagesData,
+) -> ServerToClientMessage {
+    let keyword = data.keyword.trim().to_lowercase();
+    if keyword.is_empty() || keyword.chars().count() > Config::get().safety.max_msg_length as usize
+    {
+        return ServerToClientMessage::SearchMessagesResponse(
+            UserSearchMessagesResponse::KeywordFormatError,
+        );
+    }
+
+    let chat_ids = match data.chat_id {
+        Some(chat_id) => match database::check_user_in_chat(user_id, chat_id)
+            .await
+            .log_err("search_messages: 检查用户是否在聊天中")
+        {
+            Ok(true) => vec![chat_id],
+            Ok(false) => {
+                return ServerToClientMessage::SearchMessagesResponse(
+                    UserSearchMessagesResponse::UserNotInChat,
+                )
+            }
+            Err(_) => {
+                return ServerToClientMessage::SearchMessagesResponse(
+                    UserSearchMessagesResponse::DatabaseError,
+                )
+            }
+        },
+        None => match database::get_user_chat_list(user_id)
+            .await
+            .log_err("search_messages: 读取用户的聊天列表")
+        {
+            Ok(chats) => chats.into_iter().map(|(chat_id, _)| chat_id).collect(),
+            Err(_) => {
+                return ServerToClientMessage::SearchMessagesResponse(
+                    UserSearchMessagesResponse::DatabaseError,
+                )
+            }
+        },
+    };
+
+    let limit = std::cmp::min(
+        data.limit,
+        Config::get().protocol.max_messages_num_when_searching,
+    );
+    match database::search_messages(&chat_ids, &keyword, data.cursor, limit)
+        .await
+        .log_err("search_messages: 搜索聊天消息")
+    {
+        Ok(page) => {
+            ServerToClientMessage::SearchMessagesResponse(UserSearchMessagesResponse::Success {
+                chat_id: data.chat_id,
+                keyword: data.keyword,
+                messages: page.messages,
+                next_cursor: page.next_cursor,
+            })
+        }
+        Err(_) => {
+            ServerToClientMessage::SearchMessagesResponse(UserSearchMessagesResponse::DatabaseError)
+        }
     }
 }
 
 pub async fn get_chat_info(chat_id: ChatID) -> ServerToClientMessage {
-    match database::get_chat_info(chat_id).await {
+    match database::get_chat_info(chat_id)
+        .await
+        .log_err("get_chat_info: 读取聊天信息")
+    {
         Err(_) => ServerToClientMessage::DatabaseError,
         Ok(opt) => match opt {
             Some(serialized) => ServerToClientMessage::Chat(serialized),
@@ -776,7 +1298,9 @@ pub async fn update_user_info(user_id: UserID, data: UserUpdateData) -> ServerTo
             new_password,
             email_code,
         } => {
-            let email = database::get_user_email(user_id).await;
+            let email = database::get_user_email(user_id)
+                .await
+                .log_err("update_user_info: 读取用户邮箱");
             if let Ok(email) = email {
                 let check_email = check_and_consume_email_code(&email, email_code);
                 if check_email {
@@ -836,7 +1360,10 @@ pub async fn unfriend(user_id: UserID, friend_id: UserID) -> ServerToClientMessa
 }
 
 pub async fn quit_group_chat(user_id: UserID, chat_id: ChatID) -> ServerToClientMessage {
-    if let Ok(check) = database::check_user_is_owner(user_id, chat_id).await {
+    if let Ok(check) = database::check_user_is_owner(user_id, chat_id)
+        .await
+        .log_err("quit_group_chat: 检查用户是否为群主")
+    {
         if check {
             return ServerToClientMessage::QuitGroupChatResponse(
                 UserQuitGroupChatResponse::NoPermission,
@@ -850,6 +1377,8 @@ pub async fn quit_group_chat(user_id: UserID, chat_id: ChatID) -> ServerToClient
     let quit_result = database::quit_group_chat(user_id, chat_id).await;
     match quit_result {
         UserQuitGroupChatResponse::Success { chat_id } => {
+            // 退出后不再收到群内成员的已读位置
+            read_receipts.lock().unwrap().unsubscribe(chat_id, user_id);
             let user_name = match database::get_user_info(user_id).await {
                 GetUserInfoResponse::Success(user_info) => user_info.user_name,
                 _ => {
@@ -859,7 +1388,10 @@ pub async fn quit_group_chat(user_id: UserID, chat_id: ChatID) -> ServerToClient
                 }
             };
             send_admin_message_to_group_chat(chat_id, format!("{}退出群聊", user_name)).await;
-            if let Ok(users) = get_chat_user_list(chat_id).await {
+            if let Ok(users) = get_chat_user_list(chat_id)
+                .await
+                .log_err("quit_group_chat: 读取聊天成员")
+            {
                 match users {
                     ChatMembers::Group(group_users) => {
                         let handlers = UserRequestHandler::Group(group_users);
@@ -892,9 +1424,13 @@ pub async fn quit_group_chat(user_id: UserID, chat_id: ChatID) -> ServerToClient
 pub async fn set_user_already_read(
     user_id: UserID,
     data: UserSetAlreadyReadData,
+    origin: Recipient<UserSessionActorMessage>,
 ) -> ServerToClientMessage {
     if data.private {
-        let (id1, id2) = match database::get_private_chat_user_list(data.chat_id).await {
+        let (id1, id2) = match database::get_private_chat_user_list(data.chat_id)
+            .await
+            .log_err("set_user_already_read: 读取私聊成员")
+        {
             Ok(opt) => match opt {
                 Some(pair) => pair,
                 None => {
@@ -931,21 +1467,90 @@ pub async fn set_user_already_read(
         );
     }
 
-    return ServerToClientMessage::SetAlreadyReadResponse(
-        database::set_user_already_read(user_id, data).await,
-    );
+    let chat_id = data.chat_id;
+    let in_chat_id = data.in_chat_id;
+    let private = data.private;
+    let response = database::set_user_already_read(user_id, data).await;
+
+    if matches!(response, SetAlreadyReadResponse::Success) {
+        user_sessions.do_send_message_to_other_devices(
+            user_id,
+            &origin,
+            UserSessionActorMessage::SendServerMessage(ServerToClientMessage::SetSelfReadCursor(
+                UserSetOppositeReadCursorData {
+                    chat_id,
+                    in_chat_id,
+                },
+            )),
+        );
+    }
+
+    // 群聊中有成员订阅时，记录已读位置等待合并推送
+    if !private
+        && matches!(response, SetAlreadyReadResponse::Success)
+        && read_receipts.lock().unwrap().has_subscribers(chat_id)
+        && check_user_in_chat(user_id, chat_id)
+            .await
+            .log_err("set_user_already_read: 检查用户是否在聊天中")
+            == Ok(true)
+    {
+        read_receipts
+            .lock()
+            .unwrap()
+            .record_read(chat_id, user_id, in_chat_id);
+    }
+
+    return ServerToClientMessage::SetAlreadyReadResponse(response);
 }
 
-pub async fn upload_file_req(
+pub async fn subscribe_read_receipts(
     user_id: UserID,
-    req: UserUploadFileRequestData,
+    data: UserSubscribeReadReceiptsData,
 ) -> ServerToClientMessage {
-    if let Ok(url_opt) = oss::get_public_url_and_auto_renew(&req.user_hash).await {
-        if let Some(url) = url_opt {
-            return ServerToClientMessage::UploadFileRequestResponse(
-                UserUploadFileRequestResponse {
-                    user_hash: req.user_hash,
-                    state: UserUploadFileRequestResponseState::Existed,
+    let response = ServerToClientMessage::SubscribeReadReceiptsResponse;
+    let chat_id = data.chat_id;
+
+    match database::check_is_group(chat_id)
+        .await
+        .log_err("subscribe_read_receipts: 检查是否为群聊")
+    {
+        Ok(true) => {}
+        Ok(false) => return response(UserSubscribeReadReceiptsResponse::NotGroupChat),
+        Err(_) => return response(UserSubscribeReadReceiptsResponse::DatabaseError),
+    }
+    match check_user_in_chat(user_id, chat_id)
+        .await
+        .log_err("subscribe_read_receipts: 检查用户是否在聊天中")
+    {
+        Ok(true) => {}
+        Ok(false) => return response(UserSubscribeReadReceiptsResponse::UserNotInChat),
+        Err(_) => return response(UserSubscribeReadReceiptsResponse::DatabaseError),
+    }
+
+    read_receipts
+        .lock()
+        .unwrap()
+        .subscribe(chat_id, user_id, data.mode);
+    return response(UserSubscribeReadReceiptsResponse::Success { chat_id });
+}
+
+pub async fn unsubscribe_read_receipts(user_id: UserID, chat_id: ChatID) -> ServerToClientMessage {
+    read_receipts.lock().unwrap().unsubscribe(chat_id, user_id);
+    return ServerToClientMessage::UnsubscribeReadReceiptsResponse(
+        UserSubscribeReadReceiptsResponse::Success { chat_id },
+    );
+}
+
+pub async fn upload_file_req(
+    user_id: UserID,
+    req: UserUploadFileRequestData,
+) -> ServerToClientMessage {
+    if let Ok(url_opt) = oss::get_public_url_and_auto_renew(&req.user_hash).await {
+        if let Some(url) = url_opt {
+            return ServerToClientMessage::UploadFileRequestResponse(
+                UserUploadFileRequestResponse {
+                    user_hash: req.user_hash,
+                    state: UserUploadFileRequestResponseState::Existed,
                     url: Some(url),
                     upload_id: None,
                 },
@@ -982,7 +1587,10 @@ pub async fn upload_file_req(
         path: presign.path.clone(),
     };
 
-    let upload_id = match database::write_upload_request(upload_req).await {
+    let upload_id = match database::write_upload_request(upload_req)
+        .await
+        .log_err("upload_file_req: 写入上传请求")
+    {
         Ok(id) => id,
         Err(_) => {
             return ServerToClientMessage::UploadFileRequestResponse(
@@ -1005,7 +1613,10 @@ pub async fn upload_file_req(
 }
 
 pub async fn file_uploaded(user_id: UserID, upload_id: UploadId) -> ServerToClientMessage {
-    let req = match database::get_upload_request(upload_id).await {
+    let req = match database::get_upload_request(upload_id)
+        .await
+        .log_err("file_uploaded: 读取上传请求")
+    {
         Ok(req) => match req {
             Some(req) => req,
             None => {
@@ -1106,7 +1717,10 @@ pub async fn get_file_pub_url(hash: String) -> ServerToClientMessage {
 }
 
 pub async fn get_group_users(user_id: UserID, chat_id: ChatID) -> ServerToClientMessage {
-    if let Ok(check) = database::check_user_in_chat(user_id, chat_id).await {
+    if let Ok(check) = database::check_user_in_chat(user_id, chat_id)
+        .await
+        .log_err("get_group_users: 检查用户是否在聊天中")
+    {
         if !check {
             return ServerToClientMessage::GetGroupUsersResponse(
                 UserGetGroupUsersResponse::UserNotInChat,
@@ -1117,7 +1731,10 @@ pub async fn get_group_users(user_id: UserID, chat_id: ChatID) -> ServerToClient
             UserGetGroupUsersResponse::DatabaseError,
         );
     }
-    match database::get_chat_user_list(chat_id).await {
+    match database::get_chat_user_list(chat_id)
+        .await
+        .log_err("get_group_users: 读取聊天成员")
+    {
         Ok(users) => {
             let user_ids = match users {
                 ChatMembers::Group(user_ids) => user_ids,
@@ -1139,9 +1756,15 @@ pub async fn get_group_users(user_id: UserID, chat_id: ChatID) -> ServerToClient
 }
 
 pub async fn set_as_admin(user_id: UserID, data: UserSetGroupAdminData) -> ServerToClientMessage {
-    let check_owner = database::check_user_is_owner(user_id, data.chat_id).await;
-    let check_in_chat = database::check_user_in_chat(data.user_id, data.chat_id).await;
-    let check_admin = database::check_user_is_admin(data.user_id, data.chat_id).await;
+    let check_owner = database::check_user_is_owner(user_id, data.chat_id)
+        .await
+        .log_err("set_as_admin: 检查用户是否为群主");
+    let check_in_chat = database::check_user_in_chat(data.user_id, data.chat_id)
+        .await
+        .log_err("set_as_admin: 检查用户是否在聊天中");
+    let check_admin = database::check_user_is_admin(data.user_id, data.chat_id)
+        .await
+        .log_err("set_as_admin: 检查用户是否为管理员");
 
     if check_owner.is_err() || check_in_chat.is_err() || check_in_chat.is_err() {
         return ServerToClientMessage::SetGroupAdminResponse(
@@ -1175,7 +1798,10 @@ pub async fn revoke_message(user_id: UserID, data: UserRevokeMessageData) -> Ser
     let in_chat_id = data.in_chat_id;
 
     let chat_msg: ChatMessage =
-        match database::get_messages_in_chat(chat_id, in_chat_id, Some(in_chat_id)).await {
+        match database::get_messages_in_chat(chat_id, in_chat_id, Some(in_chat_id))
+            .await
+            .log_err("revoke_message: 读取聊天消息")
+        {
             Ok(msgs) => {
                 if msgs.len() != 1 {
                     return ServerToClientMessage::RevokeMessageResponse(
@@ -1209,8 +1835,12 @@ pub async fn revoke_message(user_id: UserID, data: UserRevokeMessageData) -> Ser
             }
         }
         UserRevokeMethod::GroupAdmin => {
-            let check_revoker = check_user_is_admin(user_id, chat_id).await;
-            let check_sender = check_user_is_admin(chat_msg.sender_id, chat_id).await;
+            let check_revoker = check_user_is_admin(user_id, chat_id)
+                .await
+                .log_err("revoke_message: 检查用户是否为管理员");
+            let check_sender = check_user_is_admin(chat_msg.sender_id, chat_id)
+                .await
+                .log_err("revoke_message: 检查用户是否为管理员");
             if check_revoker.is_err() || check_sender.is_err() {
                 return ServerToClientMessage::RevokeMessageResponse(UserRevokeMessageResponse {
                     chat_id,
@@ -1227,7 +1857,9 @@ pub async fn revoke_message(user_id: UserID, data: UserRevokeMessageData) -> Ser
             }
         }
         UserRevokeMethod::GroupOwner => {
-            let check_owner = check_user_is_owner(user_id, chat_id).await;
+            let check_owner = check_user_is_owner(user_id, chat_id)
+                .await
+                .log_err("revoke_message: 检查用户是否为群主");
             if let Ok(check) = check_owner {
                 if !check {
                     return ServerToClientMessage::RevokeMessageResponse(
@@ -1249,7 +1881,9 @@ pub async fn revoke_message(user_id: UserID, data: UserRevokeMessageData) -> Ser
     }
 
     if let Err(_) =
-        database::revoke_message(chat_id, in_chat_id, chat_msg.sender_id, chat_msg.timestamp).await
+        database::revoke_message(chat_id, in_chat_id, chat_msg.sender_id, chat_msg.timestamp)
+            .await
+            .log_err("revoke_message: 撤回消息")
     {
         return ServerToClientMessage::RevokeMessageResponse(UserRevokeMessageResponse {
             chat_id,
@@ -1283,12 +1917,759 @@ pub async fn revoke_message(user_id: UserID, data: UserRevokeMessageData) -> Ser
     });
 }
 
+pub async fn edit_message(user_id: UserID, data: UserEditMessageData) -> ServerToClientMessage {
+    let chat_id = data.chat_id;
+    let in_chat_id = data.in_chat_id;
+    let response = |state| {
+        ServerToClientMessage::EditMessageResponse(UserEditMessageResponse {
+            chat_id,
+            in_chat_id,
+            state,
+        })
+    };
+
+    if data.serialized_content.len() > Config::get().safety.max_msg_length as usize {
+        return response(UserEditMessageResponseState::LenthLimitExceeded);
+    }
+
+    let serialized_msg = match database::get_message(chat_id, in_chat_id)
+        .await
+        .log_err("edit_message: 读取聊天消息")
+    {
+        Ok(Some(serialized_msg)) => serialized_msg,
+        Ok(None) => return response(UserEditMessageResponseState::MessageNotExisted),
+        Err(_) => return response(UserEditMessageResponseState::DatabaseError),
+    };
+    let chat_msg = match serde_json::from_str::<ChatMessage>(&serialized_msg) {
+        Ok(chat_msg) => chat_msg,
+        Err(_) => return response(UserEditMessageResponseState::TypeNotEditable),
+    };
+
+    // 鉴权
+    if chat_msg.sender_id != user_id {
+        return response(UserEditMessageResponseState::PermissionsDenied);
+    }
+    match chat_msg.r#type {
+        ChatMessageType::Text | ChatMessageType::ReplyText | ChatMessageType::MentionText => {}
+        _ => return response(UserEditMessageResponseState::TypeNotEditable),
+    }
+    // 转发的消息须与来源保持一致
+    if chat_msg.forwarded_from.is_some() {
+        return response(UserEditMessageResponseState::TypeNotEditable);
+    }
+    let timestamp = Utc::now().timestamp_millis() as Timestamp;
+    let edit_expire = Config::get().user.sender_edit_expire as Timestamp * 1000;
+    if timestamp.saturating_sub(chat_msg.timestamp) > edit_expire {
+        return response(UserEditMessageResponseState::TimeLimitExceeded);
+    }
+
+    // 回复消息编辑后仍须回复原来的消息
+    if chat_msg.r#type == ChatMessageType::ReplyText {
+        let old_reply_to = serde_json::from_str::<ReplyTextType>(&chat_msg.serialized_content)
+            .map(|content| content.reply_to_in_chat_id);
+        match serde_json::from_str::<ReplyTextType>(&data.serialized_content) {
+            Ok(content) if old_reply_to.ok() == Some(content.reply_to_in_chat_id) => {}
+            _ => return response(UserEditMessageResponseState::ContentError),
+        }
+    }
+
+    // 编辑后新被提及的用户需要收到提及通知
+    let mut mentioned_ids = vec![];
+    if chat_msg.r#type == ChatMessageType::MentionText {
+        let new_ids = match serde_json::from_str::<MentionTextType>(&data.serialized_content) {
+            Ok(content) => content.user_ids,
+            Err(_) => return response(UserEditMessageResponseState::ContentError),
+        };
+        let old_ids = serde_json::from_str::<MentionTextType>(&chat_msg.serialized_content)
+            .map_or(vec![], |content| content.user_ids);
+        mentioned_ids = new_ids
+            .into_iter()
+            .filter(|id| !old_ids.contains(id))
+            .collect();
+    }
+
+    let edited_msg = ChatMessage {
+        serialized_content: data.serialized_content,
+        edited_at: Some(timestamp),
+        ..chat_msg
+    };
+    match database::edit_message(
+        chat_id,
+        in_chat_id,
+        &serialized_msg,
+        &serde_json::to_string(&edited_msg).unwrap(),
+    )
+    .await
+    .log_err("edit_message: 编辑消息")
+    {
+        Ok(true) => {}
+        Ok(false) => return response(UserEditMessageResponseState::MessageChanged),
+        Err(_) => return response(UserEditMessageResponseState::DatabaseError),
+    }
+
+    let notice = UserNotice::Edited {
+        chat_id,
+        in_chat_id,
+        timestamp,
+    };
+    let serialized_notice = serde_json::to_string(&notice).unwrap();
+    if user_notice::send_notice_to_user_in_chat(chat_id, serialized_notice, timestamp)
+        .await
+        .is_err()
+    {
+        return response(UserEditMessageResponseState::DatabaseError);
+    }
+
+    if !mentioned_ids.is_empty() {
+        let notice = UserNotice::Mentioned {
+            chat_id,
+            in_chat_id,
+            timestamp,
+        };
+        let serialized_notice = serde_json::to_string(&notice).unwrap();
+        if send_notice(mentioned_ids, serialized_notice, timestamp)
+            .await
+            .is_err()
+        {
+            return response(UserEditMessageResponseState::DatabaseError);
+        }
+    }
+
+    return response(UserEditMessageResponseState::Success);
+}
+
+pub async fn get_edit_history(
+    user_id: UserID,
+    data: UserGetEditHistoryData,
+) -> ServerToClientMessage {
+    match database::check_user_in_chat(user_id, data.chat_id)
+        .await
+        .log_err("get_edit_history: 检查用户是否在聊天中")
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            return ServerToClientMessage::GetEditHistoryResponse(
+                UserGetEditHistoryResponse::UserNotInChat,
+            )
+        }
+        Err(_) => {
+            return ServerToClientMessage::GetEditHistoryResponse(
+                UserGetEditHistoryResponse::DatabaseError,
+            )
+        }
+    }
+
+    match database::get_edit_history(data.chat_id, data.in_chat_id)
+        .await
+        .log_err("get_edit_history: 读取编辑历史")
+    {
+        Ok(history) => {
+            ServerToClientMessage::GetEditHistoryResponse(UserGetEditHistoryResponse::Success {
+                chat_id: data.chat_id,
+                in_chat_id: data.in_chat_id,
+                history,
+            })
+        }
+        Err(_) => {
+            ServerToClientMessage::GetEditHistoryResponse(UserGetEditHistoryResponse::DatabaseError)
+        }
+    }
+}
+
+/** `send_msg_to_online_users` 向聊天中除发送者外的在线用户推送消息，人数较多的群聊交给server_worker发送
+ */
+async fn send_msg_to_online_users(
+    chat_type: &ChatType,
+    chat_msg: SerializedChatMessage,
+    sender_id: UserID,
+    chat_id: ChatID,
+) {
+    match chat_type {
+        ChatType::Private(ids) => {
+            send_msg_to_online_user_in_private_chat(sender_id, chat_msg, *ids);
+        }
+        ChatType::Group(num) => {
+            if *num as u8
+                > Config::get()
+                    .protocol
+                    .worker_send_messages_member_num_threshold
+            {
+                workers_handle.spawn(send_msg_to_online_users_in_chat(
+                    chat_msg, sender_id, chat_id,
+                ));
+            } else {
+                send_msg_to_online_users_in_chat(chat_msg, sender_id, chat_id).await;
+            }
+        }
+    }
+}
+
+pub async fn forward_messages(
+    user_id: UserID,
+    data: UserForwardMessagesData,
+) -> ServerToClientMessage {
+    let response = ServerToClientMessage::ForwardMessagesResponse;
+
+    if data.in_chat_ids.is_empty()
+        || data.in_chat_ids.len() > Config::get().protocol.max_messages_num_when_forwarding as usize
+    {
+        return response(UserForwardMessagesResponse::NumberLimitExceeded);
+    }
+
+    if let Err(reason) = database::check_user_can_send_in_chat(user_id, data.source_chat_id).await {
+        return response(UserForwardMessagesResponse::CannotSend {
+            chat_id: data.source_chat_id,
+            reason,
+        });
+    }
+    let chat_type = match database::check_user_can_send_in_chat(user_id, data.target_chat_id).await
+    {
+        Ok(chat_type) => chat_type,
+        Err(reason) => {
+            return response(UserForwardMessagesResponse::CannotSend {
+                chat_id: data.target_chat_id,
+                reason,
+            })
+        }
+    };
+
+    // 先检查全部消息，有一条不能转发时不写入任何消息
+    let mut forwards = vec![];
+    for in_chat_id in data.in_chat_ids {
+        let serialized_msg = match database::get_message(data.source_chat_id, in_chat_id)
+            .await
+            .log_err("forward_messages: 读取聊天消息")
+        {
+            Ok(Some(serialized_msg)) => serialized_msg,
+            Ok(None) => {
+                return response(UserForwardMessagesResponse::MessageNotExisted { in_chat_id })
+            }
+            Err(_) => return response(UserForwardMessagesResponse::DatabaseError),
+        };
+        let chat_msg = match serde_json::from_str::<ChatMessage>(&serialized_msg) {
+            Ok(chat_msg) => chat_msg,
+            Err(_) => {
+                return response(UserForwardMessagesResponse::TypeNotForwardable { in_chat_id })
+            }
+        };
+        match chat_msg.get_forward_content() {
+            Some((r#type, content)) => {
+                forwards.push((r#type, content, chat_msg.get_forwarded_from()))
+            }
+            None => {
+                return response(UserForwardMessagesResponse::TypeNotForwardable { in_chat_id })
+            }
+        }
+    }
+
+    let mut messages = vec![];
+    for (r#type, content, forwarded_from) in forwards {
+        let (chat_msg, _, _) = match database::forward_message_to_chat(
+            r#type.get_str(),
+            serde_json::to_string::<String>(&content).unwrap(),
+            data.target_chat_id,
+            user_id,
+            &forwarded_from,
+        )
+        .await
+        .log_err("forward_messages: 写入聊天消息")
+        {
+            Ok(data) => data,
+            Err(_) => return response(UserForwardMessagesResponse::DatabaseError),
+        };
+        send_msg_to_online_users(&chat_type, chat_msg.clone(), user_id, data.target_chat_id).await;
+        messages.push(chat_msg);
+    }
+
+    return response(UserForwardMessagesResponse::Success {
+        target_chat_id: data.target_chat_id,
+        messages,
+    });
+}
+
+pub async fn get_thread(user_id: UserID, data: UserGetThreadData) -> ServerToClientMessage {
+    let chat_id = data.chat_id;
+    let in_chat_id = data.in_chat_id;
+
+    match database::check_user_in_chat(user_id, chat_id)
+        .await
+        .log_err("get_thread: 检查用户是否在聊天中")
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            return ServerToClientMessage::GetThreadResponse(UserGetThreadResponse::UserNotInChat)
+        }
+        Err(_) => {
+            return ServerToClientMessage::GetThreadResponse(UserGetThreadResponse::DatabaseError)
+        }
+    }
+
+    let root = match database::get_messages_in_chat(chat_id, in_chat_id, Some(in_chat_id))
+        .await
+        .log_err("get_thread: 读取聊天消息")
+    {
+        Ok(mut msgs) if !msgs.is_empty() => msgs.remove(0),
+        Ok(_) => {
+            return ServerToClientMessage::GetThreadResponse(
+                UserGetThreadResponse::MessageNotExisted,
+            )
+        }
+        Err(_) => {
+            return ServerToClientMessage::GetThreadResponse(UserGetThreadResponse::DatabaseError)
+        }
+    };
+
+    match database::get_thread_replies(chat_id, in_chat_id)
+        .await
+        .log_err("get_thread: 读取回复")
+    {
+        Ok(replies) => ServerToClientMessage::GetThreadResponse(UserGetThreadResponse::Success {
+            chat_id,
+            in_chat_id,
+            root,
+            replies,
+        }),
+        Err(_) => ServerToClientMessage::GetThreadResponse(UserGetThreadResponse::DatabaseError),
+    }
+}
+
+/** `check_user_can_pin` 检查用户能否置顶或取消置顶聊天中的消息
+ * 群聊中只有群主与管理员可以，私聊中双方都可以；用户不在聊天中时传出UserNotInChat
+ */
+async fn check_user_can_pin(
+    user_id: UserID,
+    chat_id: ChatID,
+) -> Result<bool, UserPinMessageResponse> {
+    match database::check_user_in_chat(user_id, chat_id)
+        .await
+        .log_err("check_user_can_pin: 检查用户是否在聊天中")
+    {
+        Ok(true) => {}
+        Ok(false) => return Err(UserPinMessageResponse::UserNotInChat),
+        Err(_) => return Err(UserPinMessageResponse::DatabaseError),
+    }
+    let is_group = database::check_is_group(chat_id)
+        .await
+        .log_err("check_user_can_pin: 检查是否为群聊")
+        .map_err(|_| UserPinMessageResponse::DatabaseError)?;
+    if !is_group {
+        return Ok(true);
+    }
+    // 群主同时也是管理员
+    return database::check_user_is_admin(user_id, chat_id)
+        .await
+        .log_err("check_user_can_pin: 检查用户是否为管理员")
+        .map_err(|_| UserPinMessageResponse::DatabaseError);
+}
+
+/** `send_pin_notice` 向聊天中的所有用户发送置顶或取消置顶的通知
+ */
+async fn send_pin_notice(notice: UserNotice, chat_id: ChatID, timestamp: Timestamp) -> bool {
+    let serialized_notice = serde_json::to_string(&notice).unwrap();
+    return user_notice::send_notice_to_user_in_chat(chat_id, serialized_notice, timestamp)
+        .await
+        .log_err("send_pin_notice: 发送置顶通知")
+        .is_ok();
+}
+
+pub async fn pin_message(user_id: UserID, data: UserPinMessageData) -> ServerToClientMessage {
+    let chat_id = data.chat_id;
+    let in_chat_id = data.in_chat_id;
+
+    match check_user_can_pin(user_id, chat_id).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return ServerToClientMessage::PinMessageResponse(UserPinMessageResponse::NoPermission)
+        }
+        Err(response) => return ServerToClientMessage::PinMessageResponse(response),
+    }
+
+    match database::get_message(chat_id, in_chat_id)
+        .await
+        .log_err("pin_message: 读取聊天消息")
+    {
+        Ok(Some(serialized_msg)) => match serde_json::from_str::<ChatMessage>(&serialized_msg) {
+            Ok(chat_msg) if chat_msg.r#type != ChatMessageType::Revoked => {}
+            _ => {
+                return ServerToClientMessage::PinMessageResponse(
+                    UserPinMessageResponse::MessageNotExisted,
+                )
+            }
+        },
+        Ok(None) => {
+            return ServerToClientMessage::PinMessageResponse(
+                UserPinMessageResponse::MessageNotExisted,
+            )
+        }
+        Err(_) => {
+            return ServerToClientMessage::PinMessageResponse(UserPinMessageResponse::DatabaseError)
+        }
+    }
+
+    match database::get_pinned_messages(chat_id)
+        .await
+        .log_err("pin_message: 读取置顶消息")
+    {
+        Ok(pinned) if pinned.len() >= Config::get().safety.max_pinned_messages_num as usize => {
+            return ServerToClientMessage::PinMessageResponse(
+                UserPinMessageResponse::PinLimitExceeded,
+            )
+        }
+        Ok(_) => {}
+        Err(_) => {
+            return ServerToClientMessage::PinMessageResponse(UserPinMessageResponse::DatabaseError)
+        }
+    }
+
+    match database::pin_message(chat_id, in_chat_id)
+        .await
+        .log_err("pin_message: 置顶消息")
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            return ServerToClientMessage::PinMessageResponse(UserPinMessageResponse::AlreadyPinned)
+        }
+        Err(_) => {
+            return ServerToClientMessage::PinMessageResponse(UserPinMessageResponse::DatabaseError)
+        }
+    }
+
+    let timestamp = Utc::now().timestamp_millis() as Timestamp;
+    let notice = UserNotice::Pinned {
+        chat_id,
+        in_chat_id,
+        user_id,
+        timestamp,
+    };
+    if !send_pin_notice(notice, chat_id, timestamp).await {
+        return ServerToClientMessage::PinMessageResponse(UserPinMessageResponse::DatabaseError);
+    }
+
+    return ServerToClientMessage::PinMessageResponse(UserPinMessageResponse::Success {
+        chat_id,
+        in_chat_id,
+    });
+}
+
+pub async fn unpin_message(user_id: UserID, data: UserPinMessageData) -> ServerToClientMessage {
+    let chat_id = data.chat_id;
+    let in_chat_id = data.in_chat_id;
+
+    match check_user_can_pin(user_id, chat_id).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return ServerToClientMessage::UnpinMessageResponse(
+                UserUnpinMessageResponse::NoPermission,
+            )
+        }
+        Err(UserPinMessageResponse::UserNotInChat) => {
+            return ServerToClientMessage::UnpinMessageResponse(
+                UserUnpinMessageResponse::UserNotInChat,
+            )
+        }
+        Err(_) => {
+            return ServerToClientMessage::UnpinMessageResponse(
+                UserUnpinMessageResponse::DatabaseError,
+            )
+        }
+    }
+
+    match database::unpin_message(chat_id, in_chat_id)
+        .await
+        .log_err("unpin_message: 取消置顶消息")
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            return ServerToClientMessage::UnpinMessageResponse(UserUnpinMessageResponse::NotPinned)
+        }
+        Err(_) => {
+            return ServerToClientMessage::UnpinMessageResponse(
+                UserUnpinMessageResponse::DatabaseError,
+            )
+        }
+    }
+
+    let timestamp = Utc::now().timestamp_millis() as Timestamp;
+    let notice = UserNotice::Unpinned {
+        chat_id,
+        in_chat_id,
+        user_id,
+        timestamp,
+    };
+    if !send_pin_notice(notice, chat_id, timestamp).await {
+        return ServerToClientMessage::UnpinMessageResponse(
+            UserUnpinMessageResponse::DatabaseError,
+        );
+    }
+
+    return ServerToClientMessage::UnpinMessageResponse(UserUnpinMessageResponse::Success {
+        chat_id,
+        in_chat_id,
+    });
+}
+
+pub async fn get_pinned_messages(user_id: UserID, chat_id: ChatID) -> ServerToClientMessage {
+    match database::check_user_in_chat(user_id, chat_id)
+        .await
+        .log_err("get_pinned_messages: 检查用户是否在聊天中")
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            return ServerToClientMessage::GetPinnedMessagesResponse(
+                UserGetPinnedMessagesResponse::UserNotInChat,
+            )
+        }
+        Err(_) => {
+            return ServerToClientMessage::GetPinnedMessagesResponse(
+                UserGetPinnedMessagesResponse::DatabaseError,
+            )
+        }
+    }
+
+    match database::get_pinned_messages(chat_id)
+        .await
+        .log_err("get_pinned_messages: 读取置顶消息")
+    {
+        Ok(messages) => ServerToClientMessage::GetPinnedMessagesResponse(
+            UserGetPinnedMessagesResponse::Success { chat_id, messages },
+        ),
+        Err(_) => ServerToClientMessage::GetPinnedMessagesResponse(
+            UserGetPinnedMessagesResponse::DatabaseError,
+        ),
+    }
+}
+
+pub async fn set_disappearing(
+    user_id: UserID,
+    data: UserSetDisappearingData,
+) -> ServerToClientMessage {
+    let response = ServerToClientMessage::SetDisappearingResponse;
+    let chat_id = data.chat_id;
+    let ttl = data.ttl;
+
+    if ttl == Some(0) {
+        return response(UserSetDisappearingResponse::TtlInvalid);
+    }
+    match check_user_can_pin(user_id, chat_id).await {
+        Ok(true) => {}
+        Ok(false) => return response(UserSetDisappearingResponse::NoPermission),
+        Err(UserPinMessageResponse::UserNotInChat) => {
+            return response(UserSetDisappearingResponse::UserNotInChat)
+        }
+        Err(_) => return response(UserSetDisappearingResponse::DatabaseError),
+    }
+
+    if database::set_disappearing_ttl(chat_id, ttl)
+        .await
+        .log_err("set_disappearing: 设置消息存活时间")
+        .is_err()
+    {
+        return response(UserSetDisappearingResponse::DatabaseError);
+    }
+
+    let timestamp = Utc::now().timestamp_millis() as Timestamp;
+    let notice = UserNotice::DisappearingChanged {
+        chat_id,
+        user_id,
+        ttl,
+        timestamp,
+    };
+    let serialized_notice = serde_json::to_string(&notice).unwrap();
+    if user_notice::send_notice_to_user_in_chat(chat_id, serialized_notice, timestamp)
+        .await
+        .log_err("set_disappearing: 发送阅后即焚通知")
+        .is_err()
+    {
+        return response(UserSetDisappearingResponse::DatabaseError);
+    }
+
+    return response(UserSetDisappearingResponse::Success { chat_id, ttl });
+}
+
+pub async fn get_disappearing(user_id: UserID, chat_id: ChatID) -> ServerToClientMessage {
+    let response = ServerToClientMessage::GetDisappearingResponse;
+    match database::check_user_in_chat(user_id, chat_id)
+        .await
+        .log_err("get_disappearing: 检查用户是否在聊天中")
+    {
+        Ok(true) => {}
+        Ok(false) => return response(UserGetDisappearingResponse::UserNotInChat),
+        Err(_) => return response(UserGetDisappearingResponse::DatabaseError),
+    }
+
+    match database::get_disappearing_ttl(chat_id)
+        .await
+        .log_err("get_disappearing: 读取消息存活时间")
+    {
+        Ok(ttl) => response(UserGetDisappearingResponse::Success { chat_id, ttl }),
+        Err(_) => response(UserGetDisappearingResponse::DatabaseError),
+    }
+}
+
+async fn schedule_message(
+    sender_id: UserID,
+    msg: UserSendMessageData,
+    deliver_at: Timestamp,
+) -> ServerToClientMessage {
+    let response = ServerToClientMessage::ScheduleMessageResponse;
+    let client_id = msg.client_id;
+    let chat_id = msg.chat_id;
+
+    let scheduled = match database::get_scheduled_messages(sender_id)
+        .await
+        .log_err("schedule_message: 读取定时消息")
+    {
+        Ok(scheduled) => scheduled,
+        Err(_) => {
+            return response(UserScheduleMessageResponse::DatabaseError { client_id, chat_id })
+        }
+    };
+    if scheduled.len() >= Config::get().user.max_scheduled_messages_num as usize {
+        return response(UserScheduleMessageResponse::ScheduleLimitExceeded { client_id, chat_id });
+    }
+
+    match database::add_scheduled_message(sender_id, deliver_at, &msg)
+        .await
+        .log_err("schedule_message: 保存定时消息")
+    {
+        Ok(schedule_id) => response(UserScheduleMessageResponse::Success {
+            client_id,
+            chat_id,
+            schedule_id,
+            deliver_at,
+        }),
+        Err(_) => response(UserScheduleMessageResponse::DatabaseError { client_id, chat_id }),
+    }
+}
+
+pub async fn get_scheduled_messages(user_id: UserID) -> ServerToClientMessage {
+    match database::get_scheduled_messages(user_id)
+        .await
+        .log_err("get_scheduled_messages: 读取定时消息")
+    {
+        Ok(messages) => ServerToClientMessage::GetScheduledMessagesResponse(
+            UserGetScheduledMessagesResponse::Success { messages },
+        ),
+        Err(_) => ServerToClientMessage::GetScheduledMessagesResponse(
+            UserGetScheduledMessagesResponse::DatabaseError,
+        ),
+    }
+}
+
+pub async fn cancel_scheduled_message(
+    user_id: UserID,
+    schedule_id: ScheduleID,
+) -> ServerToClientMessage {
+    let response = ServerToClientMessage::CancelScheduledMessageResponse;
+    match database::cancel_scheduled_message(user_id, schedule_id)
+        .await
+        .log_err("cancel_scheduled_message: 取消定时消息")
+    {
+        Ok(true) => response(UserCancelScheduledMessageResponse::Success { schedule_id }),
+        Ok(false) => response(UserCancelScheduledMessageResponse::MessageNotExisted),
+        Err(_) => response(UserCancelScheduledMessageResponse::DatabaseError),
+    }
+}
+
+pub async fn add_reaction(user_id: UserID, data: UserReactionData) -> ServerToClientMessage {
+    return ServerToClientMessage::AddReactionResponse(update_reaction(user_id, data, true).await);
+}
+
+pub async fn remove_reaction(user_id: UserID, data: UserReactionData) -> ServerToClientMessage {
+    return ServerToClientMessage::RemoveReactionResponse(
+        update_reaction(user_id, data, false).await,
+    );
+}
+
+/** `is_valid_reaction` 表情回应不能包含控制字符、空白，以及键名分隔符与通配符
+ */
+fn is_valid_reaction(emoji: &str) -> bool {
+    return !emoji.chars().any(|c| {
+        c.is_control() || c.is_whitespace() || matches!(c, ':' | '*' | '?' | '[' | ']' | '\\')
+    });
+}
+
+/** `update_reaction` 添加或取消表情回应，回应人数变化时向聊天中其他在线用户推送最新的消息
+ */
+async fn update_reaction(
+    user_id: UserID,
+    data: UserReactionData,
+    add: bool,
+) -> UserReactionResponse {
+    let chat_id = data.chat_id;
+    let in_chat_id = data.in_chat_id;
+
+    if data.emoji.is_empty()
+        || data.emoji.len() > Config::get().safety.max_reaction_length as usize
+        || !is_valid_reaction(&data.emoji)
+    {
+        return UserReactionResponse::ReactionFormatError;
+    }
+
+    match database::check_user_in_chat(user_id, chat_id)
+        .await
+        .log_err("update_reaction: 检查用户是否在聊天中")
+    {
+        Ok(true) => {}
+        Ok(false) => return UserReactionResponse::UserNotInChat,
+        Err(_) => return UserReactionResponse::DatabaseError,
+    }
+
+    match database::get_message(chat_id, in_chat_id)
+        .await
+        .log_err("update_reaction: 读取聊天消息")
+    {
+        Ok(Some(serialized_msg)) => match serde_json::from_str::<ChatMessage>(&serialized_msg) {
+            Ok(chat_msg) if chat_msg.r#type != ChatMessageType::Revoked => {}
+            _ => return UserReactionResponse::MessageNotExisted,
+        },
+        Ok(None) => return UserReactionResponse::MessageNotExisted,
+        Err(_) => return UserReactionResponse::DatabaseError,
+    }
+
+    let changed = if add {
+        database::add_reaction(chat_id, in_chat_id, user_id, &data.emoji)
+            .await
+            .log_err("update_reaction: 添加表情回应")
+    } else {
+        database::remove_reaction(chat_id, in_chat_id, user_id, &data.emoji)
+            .await
+            .log_err("update_reaction: 取消表情回应")
+    };
+    let changed = match changed {
+        Ok(changed) => changed,
+        Err(_) => return UserReactionResponse::DatabaseError,
+    };
+
+    let message = match database::get_messages_in_chat(chat_id, in_chat_id, Some(in_chat_id))
+        .await
+        .log_err("update_reaction: 读取聊天消息")
+    {
+        Ok(mut msgs) if !msgs.is_empty() => msgs.remove(0),
+        Ok(_) => return UserReactionResponse::MessageNotExisted,
+        Err(_) => return UserReactionResponse::DatabaseError,
+    };
+    if changed {
+        send_msg_to_online_users_in_chat(message.clone(), user_id, chat_id).await;
+    }
+
+    return UserReactionResponse::Success {
+        chat_id,
+        in_chat_id,
+        message,
+    };
+}
+
 pub async fn owner_transfer(
     user_id: UserID,
     data: UserGroupOwnerTransferData,
 ) -> ServerToClientMessage {
-    let check_owner = database::check_user_is_owner(user_id, data.chat_id).await;
-    let check_in_chat = database::check_user_in_chat(user_id, data.chat_id).await;
+    let check_owner = database::check_user_is_owner(user_id, data.chat_id)
+        .await
+        .log_err("owner_transfer: 检查用户是否为群主");
+    let check_in_chat = database::check_user_in_chat(user_id, data.chat_id)
+        .await
+        .log_err("owner_transfer: 检查用户是否在聊天中");
 
     if check_owner.is_err() || check_in_chat.is_err() {
         return ServerToClientMessage::GroupOwnerTransferResponse(
@@ -1314,7 +2695,10 @@ pub async fn owner_transfer(
 }
 
 pub async fn group_notice(user_id: UserID, data: UserSendGroupNoticeData) -> ServerToClientMessage {
-    if let Ok(check) = database::check_user_is_admin(user_id, data.chat_id).await {
+    if let Ok(check) = database::check_user_is_admin(user_id, data.chat_id)
+        .await
+        .log_err("group_notice: 检查用户是否为管理员")
+    {
         if !check {
             return ServerToClientMessage::GroupNoticeResponse(
                 UserSendGroupNoticeResponse::NoPermission,
@@ -1344,7 +2728,10 @@ pub async fn pull_group_notice(
     user_id: UserID,
     data: UserPullGroupNoticeData,
 ) -> ServerToClientMessage {
-    if let Ok(check) = database::check_user_in_chat(user_id, data.chat_id).await {
+    if let Ok(check) = database::check_user_in_chat(user_id, data.chat_id)
+        .await
+        .log_err("pull_group_notice: 检查用户是否在聊天中")
+    {
         if !check {
             return ServerToClientMessage::PullGroupNoticeResponse(
                 UserPullGroupNoticeResponse::UserNotInChat,
@@ -1377,7 +2764,10 @@ pub async fn update_group_info(
             // check_avater
         }
     }
-    if let Ok(check) = database::check_user_is_admin(user_id, data.chat_id).await {
+    if let Ok(check) = database::check_user_is_admin(user_id, data.chat_id)
+        .await
+        .log_err("update_group_info: 检查用户是否为管理员")
+    {
         if !check {
             return ServerToClientMessage::UpdateGroupInfoResponse(
                 UserUpdateGroupInfoResponse::NoPermission,
@@ -1403,10 +2793,18 @@ pub async fn remove_member(
         );
     }
 
-    let check_in_chat = check_user_in_chat(user_id, data.chat_id).await;
-    let check_owner = database::check_user_is_owner(user_id, data.chat_id).await;
-    let check_admin = database::check_user_is_admin(user_id, data.chat_id).await;
-    let check_removed = database::check_user_is_admin(data.user_id, data.chat_id).await;
+    let check_in_chat = check_user_in_chat(user_id, data.chat_id)
+        .await
+        .log_err("remove_member: 检查用户是否在聊天中");
+    let check_owner = database::check_user_is_owner(user_id, data.chat_id)
+        .await
+        .log_err("remove_member: 检查用户是否为群主");
+    let check_admin = database::check_user_is_admin(user_id, data.chat_id)
+        .await
+        .log_err("remove_member: 检查用户是否为管理员");
+    let check_removed = database::check_user_is_admin(data.user_id, data.chat_id)
+        .await
+        .log_err("remove_member: 检查用户是否为管理员");
 
     if check_owner.is_err()
         || check_admin.is_err()
@@ -1426,6 +2824,11 @@ pub async fn remove_member(
         let quit_result = database::quit_group_chat(data.user_id, data.chat_id).await;
         match quit_result {
             UserQuitGroupChatResponse::Success { chat_id } => {
+                // 被移出后不再收到群内成员的已读位置
+                read_receipts
+                    .lock()
+                    .unwrap()
+                    .unsubscribe(chat_id, data.user_id);
                 let user_name = match database::get_user_info(data.user_id).await {
                     GetUserInfoResponse::Success(user_info) => user_info.user_name,
                     _ => {
@@ -1448,7 +2851,10 @@ pub async fn remove_member(
                     format!("{}被{}移出群聊", user_name, admin_name),
                 )
                 .await;
-                if let Ok(users) = get_chat_user_list(chat_id).await {
+                if let Ok(users) = get_chat_user_list(chat_id)
+                    .await
+                    .log_err("remove_member: 读取聊天成员")
+                {
                     match users {
                         ChatMembers::Group(group_users) => {
                             let handlers = UserRequestHandler::Group(group_users);
@@ -1497,8 +2903,12 @@ pub async fn unset_group_admin(
             UserUnsetGroupAdminResponse::SameUser,
         );
     }
-    let check_owner = check_user_is_owner(user_id, data.chat_id).await;
-    let check_admin = check_user_is_admin(data.user_id, data.chat_id).await;
+    let check_owner = check_user_is_owner(user_id, data.chat_id)
+        .await
+        .log_err("unset_group_admin: 检查用户是否为群主");
+    let check_admin = check_user_is_admin(data.user_id, data.chat_id)
+        .await
+        .log_err("unset_group_admin: 检查用户是否为管理员");
 
     if check_owner.is_err() || check_admin.is_err() {
         return ServerToClientMessage::UnsetGroupAdminResponse(
@@ -1523,7 +2933,10 @@ pub async fn unset_group_admin(
 }
 
 pub async fn get_group_owner(_user_id: UserID, chat_id: ChatID) -> ServerToClientMessage {
-    match database::get_chat_owner(chat_id).await {
+    match database::get_chat_owner(chat_id)
+        .await
+        .log_err("get_group_owner: 读取群主")
+    {
         Ok(owner) => {
             return ServerToClientMessage::GetGroupOwnerResponse(
                 UserGetGroupOwnerResponse::Success {
@@ -1539,7 +2952,10 @@ pub async fn get_group_owner(_user_id: UserID, chat_id: ChatID) -> ServerToClien
 }
 
 pub async fn get_group_admin(_user_id: UserID, chat_id: ChatID) -> ServerToClientMessage {
-    match database::get_chat_admins_list(chat_id).await {
+    match database::get_chat_admins_list(chat_id)
+        .await
+        .log_err("get_group_admin: 读取管理员列表")
+    {
         Ok(users) => {
             let user_ids = match users {
                 UserRequestHandler::Group(user_ids) => user_ids,
@@ -1561,7 +2977,10 @@ pub async fn get_group_admin(_user_id: UserID, chat_id: ChatID) -> ServerToClien
 }
 
 pub async fn media_call(user_id: UserID, data: UserMediaCallData) -> ServerToClientMessage {
-    match database::get_chat_id_by_friends(user_id, data.friend_id).await {
+    match database::get_chat_id_by_friends(user_id, data.friend_id)
+        .await
+        .log_err("media_call: 读取好友私聊")
+    {
         Ok(opt) => {
             if opt.is_none() {
                 return ServerToClientMessage::MediaCallResponse(UserMediaCallResponse::NotFriend);
@@ -1633,7 +3052,10 @@ pub async fn get_user_read_in_group(
     data: UserGetUserReadInGroupData,
     receiver: Recipient<UserSessionActorMessage>,
 ) {
-    if let Ok(check) = database::check_is_group(data.chat_id).await {
+    if let Ok(check) = database::check_is_group(data.chat_id)
+        .await
+        .log_err("get_user_read_in_group: 检查是否为群聊")
+    {
         if !check {
             receiver.do_send(UserSessionActorMessage::SendServerMessage(
                 ServerToClientMessage::GetUserReadInGroupResponse(
@@ -1649,7 +3071,10 @@ pub async fn get_user_read_in_group(
         ));
     }
 
-    if let Ok(check) = database::check_user_in_chat(user_id, data.chat_id).await {
+    if let Ok(check) = database::check_user_in_chat(user_id, data.chat_id)
+        .await
+        .log_err("get_user_read_in_group: 检查用户是否在聊天中")
+    {
         if !check {
             receiver.do_send(UserSessionActorMessage::SendServerMessage(
                 ServerToClientMessage::GetUserReadInGroupResponse(
@@ -1672,7 +3097,10 @@ pub async fn get_user_read_in_group(
 }
 
 pub async fn get_user_read_in_private(user_id: UserID, chat_id: ChatID) -> ServerToClientMessage {
-    if let Ok(check) = database::check_is_group(chat_id).await {
+    if let Ok(check) = database::check_is_group(chat_id)
+        .await
+        .log_err("get_user_read_in_private: 检查是否为群聊")
+    {
         if check {
             return ServerToClientMessage::GetUserReadInPrivateResponse(
                 UserGetUserReadInPrivateResponse::NotPrivateChat,
@@ -1684,7 +3112,10 @@ pub async fn get_user_read_in_private(user_id: UserID, chat_id: ChatID) -> Serve
         );
     }
 
-    if let Ok(check) = database::check_user_in_chat(user_id, chat_id).await {
+    if let Ok(check) = database::check_user_in_chat(user_id, chat_id)
+        .await
+        .log_err("get_user_read_in_private: 检查用户是否在聊天中")
+    {
         if !check {
             return ServerToClientMessage::GetUserReadInPrivateResponse(
                 UserGetUserReadInPrivateResponse::UserNotInChat,
@@ -1706,7 +3137,10 @@ pub async fn user_log_off(
     email_code: EmailCodeValue,
     self_recipient: Recipient<UserSessionActorMessage>,
 ) -> ServerToClientMessage {
-    if let Ok(email) = database::get_user_email(user_id).await {
+    if let Ok(email) = database::get_user_email(user_id)
+        .await
+        .log_err("user_log_off: 读取用户邮箱")
+    {
         if !check_and_consume_email_code(&email, email_code) {
             return ServerToClientMessage::LogOffResponse(UserLogOffResponse::EmailCodeError);
         }
@@ -1719,9 +3153,11 @@ pub async fn user_log_off(
                 self_recipient.do_send(UserSessionActorMessage::SendServerMessage(
                     ServerToClientMessage::LogOffResponse(UserLogOffResponse::Success),
                 ));
-                self_recipient.do_send(UserSessionActorMessage::SendServerMessage(
-                    ServerToClientMessage::Close,
-                ));
+                // 关闭该用户在全部设备上的连接
+                user_sessions.do_send_message_to(
+                    user_id,
+                    UserSessionActorMessage::SendServerMessage(ServerToClientMessage::Close),
+                );
             }
             _ => {}
         }
@@ -1731,6 +3167,243 @@ pub async fn user_log_off(
     }
 }
 
-pub async fn user_apply_for_token(user_id: UserID) -> ServerToClientMessage {
-    return ServerToClientMessage::ApplyForTokenResponse(database::apply_for_token(user_id).await);
+pub async fn user_apply_for_token(user_id: UserID, device_id: DeviceID) -> ServerToClientMessage {
+    return ServerToClientMessage::ApplyForTokenResponse(
+        database::apply_for_token(user_id, &device_id).await,
+    );
+}
+
+pub async fn refresh_token(data: UserRefreshTokenData) -> ServerToClientMessage {
+    return ServerToClientMessage::RefreshTokenResponse(database::refresh_token(&data).await);
+}
+
+pub async fn revoke_token(user_id: UserID, device_id: DeviceID) -> ServerToClientMessage {
+    let response = ServerToClientMessage::RevokeTokenResponse;
+    match database::revoke_token(user_id, &device_id)
+        .await
+        .log_err("revoke_token: 撤销token")
+    {
+        Ok(true) => return response(UserRevokeTokenResponse::Success),
+        Ok(false) => return response(UserRevokeTokenResponse::TokenNotFound),
+        Err(_) => return response(UserRevokeTokenResponse::DatabaseError),
+    }
+}
+
+pub async fn revoke_all_tokens(user_id: UserID) -> ServerToClientMessage {
+    let response = ServerToClientMessage::RevokeAllTokensResponse;
+    match database::revoke_all_tokens(user_id)
+        .await
+        .log_err("revoke_all_tokens: 撤销全部token")
+    {
+        Ok(_) => return response(UserRevokeTokenResponse::Success),
+        Err(_) => return response(UserRevokeTokenResponse::DatabaseError),
+    }
+}
+
+pub async fn upload_device_keys(
+    user_id: UserID,
+    device_id: DeviceID,
+    data: UserUploadDeviceKeysData,
+) -> ServerToClientMessage {
+    let response = ServerToClientMessage::UploadDeviceKeysResponse;
+    let prekey = &data.signed_prekey;
+    match ecdh::verify_signed_prekey(&data.identity_key, &prekey.public_key, &prekey.signature) {
+        Ok(_) => {}
+        Err(ecdh::SignedPrekeyError::KeyFormatError) => {
+            return response(UserSetDeviceKeysResponse::KeyFormatError)
+        }
+        Err(ecdh::SignedPrekeyError::SignatureError) => {
+            return response(UserSetDeviceKeysResponse::SignatureError)
+        }
+    }
+
+    let keys = DeviceKeys {
+        device_id,
+        identity_key: data.identity_key,
+        signed_prekey: data.signed_prekey,
+        updated_at: Utc::now().timestamp_millis() as Timestamp,
+    };
+    return response(save_device_keys(user_id, &keys, "upload_device_keys: 写入设备公钥").await);
+}
+
+pub async fn rotate_signed_prekey(
+    user_id: UserID,
+    device_id: DeviceID,
+    prekey: SignedPrekey,
+) -> ServerToClientMessage {
+    let response = ServerToClientMessage::RotateSignedPrekeyResponse;
+    let mut keys = match database::get_device_keys(user_id, &device_id)
+        .await
+        .log_err("rotate_signed_prekey: 读取设备公钥")
+    {
+        Ok(Some(serialized)) => match serde_json::from_str::<DeviceKeys>(&serialized) {
+            Ok(keys) => keys,
+            Err(_) => return response(UserSetDeviceKeysResponse::DatabaseError),
+        },
+        Ok(None) => return response(UserSetDeviceKeysResponse::KeysNotFound),
+        Err(_) => return response(UserSetDeviceKeysResponse::DatabaseError),
+    };
+    if prekey.key_id <= keys.signed_prekey.key_id {
+        return response(UserSetDeviceKeysResponse::PrekeyIdOutdated);
+    }
+    // 新的预共享公钥须由已上传的身份公钥签名
+    match ecdh::verify_signed_prekey(&keys.identity_key, &prekey.public_key, &prekey.signature) {
+        Ok(_) => {}
+        Err(ecdh::SignedPrekeyError::KeyFormatError) => {
+            return response(UserSetDeviceKeysResponse::KeyFormatError)
+        }
+        Err(ecdh::SignedPrekeyError::SignatureError) => {
+            return response(UserSetDeviceKeysResponse::SignatureError)
+        }
+    }
+
+    keys.signed_prekey = prekey;
+    keys.updated_at = Utc::now().timestamp_millis() as Timestamp;
+    return response(save_device_keys(user_id, &keys, "rotate_signed_prekey: 写入设备公钥").await);
+}
+
+async fn save_device_keys(
+    user_id: UserID,
+    keys: &DeviceKeys,
+    err_msg: &str,
+) -> UserSetDeviceKeysResponse {
+    let serialized = serde_json::to_string(keys).unwrap();
+    match database::set_device_keys(user_id, &keys.device_id, &serialized)
+        .await
+        .log_err(err_msg)
+    {
+        Ok(_) => return UserSetDeviceKeysResponse::Success,
+        Err(_) => return UserSetDeviceKeysResponse::DatabaseError,
+    }
+}
+
+pub async fn get_device_keys(user_id: UserID, target_id: UserID) -> ServerToClientMessage {
+    let response = ServerToClientMessage::GetDeviceKeysResponse;
+    // 只有自己与好友可以获取公钥
+    if user_id != target_id {
+        match database::get_chat_id_by_friends(user_id, target_id)
+            .await
+            .log_err("get_device_keys: 读取好友私聊")
+        {
+            Ok(Some(_)) => {}
+            Ok(None) => return response(UserGetDeviceKeysResponse::NotFriend),
+            Err(_) => return response(UserGetDeviceKeysResponse::DatabaseError),
+        }
+    }
+
+    match database::get_user_device_keys(target_id)
+        .await
+        .log_err("get_device_keys: 读取设备公钥")
+    {
+        Ok(serialized) => {
+            let devices = serialized
+                .iter()
+                .filter_map(|keys| serde_json::from_str::<DeviceKeys>(keys).ok())
+                .collect();
+            return response(UserGetDeviceKeysResponse::Success {
+                user_id: target_id,
+                devices,
+            });
+        }
+        Err(_) => return response(UserGetDeviceKeysResponse::DatabaseError),
+    }
+}
+
+pub async fn set_presence(user_id: UserID, status: PresenceStatus) -> ServerToClientMessage {
+    let response = ServerToClientMessage::SetPresenceResponse;
+    // 离线状态由连接决定，不能手动设置
+    let away = match status {
+        PresenceStatus::Online => false,
+        PresenceStatus::Away => true,
+        PresenceStatus::Offline => return response(UserSetPresenceResponse::StatusInvalid),
+    };
+    if presence::set_away(user_id, away) {
+        presence::push_presence(user_id)
+            .await
+            .log_err("set_presence: 推送在线状态")
+            .ok();
+    }
+    return response(UserSetPresenceResponse::Success { status });
+}
+
+pub async fn set_presence_hidden(user_id: UserID, hidden: bool) -> ServerToClientMessage {
+    let response = ServerToClientMessage::SetPresenceHiddenResponse;
+    if database::set_presence_hidden(user_id, hidden)
+        .await
+        .log_err("set_presence_hidden: 设置是否隐藏在线状态")
+        .is_err()
+    {
+        return response(UserSetPresenceHiddenResponse::DatabaseError);
+    }
+    // 隐藏后好友看到离线，取消隐藏后看到当前状态
+    presence::push_presence(user_id)
+        .await
+        .log_err("set_presence_hidden: 推送在线状态")
+        .ok();
+    return response(UserSetPresenceHiddenResponse::Success { hidden });
+}
+
+pub async fn get_presence(user_id: UserID, user_ids: Vec<UserID>) -> ServerToClientMessage {
+    let response = ServerToClientMessage::GetPresenceResponse;
+    if user_ids.len() > Config::get().protocol.max_users_num_when_getting_presence as usize {
+        return response(UserGetPresenceResponse::TooManyUsers);
+    }
+    let mut presences = vec![];
+    for id in user_ids {
+        match presence::get_presence(user_id, id)
+            .await
+            .log_err("get_presence: 获取在线状态")
+        {
+            Ok(presence) => presences.push(presence),
+            Err(_) => return response(UserGetPresenceResponse::DatabaseError),
+        }
+    }
+    return response(UserGetPresenceResponse::Success { presences });
+}
+
+pub async fn list_devices(user_id: UserID, device_id: DeviceID) -> ServerToClientMessage {
+    let devices = user_sessions
+        .get_user_devices(user_id)
+        .into_iter()
+        .map(|device| DeviceInfo {
+            current: device.device_id == device_id,
+            device_id: device.device_id,
+            client_ip: device.client_ip,
+            login_time: device.login_time,
+        })
+        .collect();
+    return ServerToClientMessage::ListDevicesResponse(UserListDevicesResponse::Success {
+        devices,
+    });
+}
+
+pub async fn kick_device(
+    user_id: UserID,
+    device_id: DeviceID,
+    target: DeviceID,
+) -> ServerToClientMessage {
+    let response = ServerToClientMessage::KickDeviceResponse;
+    // 当前设备应直接关闭连接
+    if target == device_id {
+        return response(UserKickDeviceResponse::CurrentDevice);
+    }
+    match user_sessions
+        .get_user_devices(user_id)
+        .into_iter()
+        .find(|device| device.device_id == target)
+    {
+        Some(device) => {
+            // 撤销该设备的令牌，避免其立即使用保存的令牌重新登录
+            if database::revoke_token(user_id, &target)
+                .await
+                .log_err("kick_device: 撤销设备令牌")
+                .is_err()
+            {
+                return response(UserKickDeviceResponse::DatabaseError);
+            }
+            device.recipient.do_send(UserSessionActorMessage::Kick);
+            return response(UserKickDeviceResponse::Success { device_id: target });
+        }
+        None => return response(UserKickDeviceResponse::DeviceNotFound),
+    }
 }
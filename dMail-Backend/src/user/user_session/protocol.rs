@@ -2,19 +2,24 @@
 
 use actix::{Actor, AsyncContext};
 use actix_web_actors::ws::WebsocketContext;
-use aes_gcm::Aes128Gcm;
 use once_cell::sync::Lazy;
+use serde::ser::{Impossible, SerializeStruct};
 use serde::{Deserialize, Serialize};
 
 use super::client_message_data::*;
-use crate::{
-    config::datatype::{
-        ChatID, EmailCodeValue, MessageID, SerializedChatInfo, SerializedChatMessage,
-        SerializedRequest, SerializedUserNotice, UploadId, UserID,
-    },
-    utils::aes::AesGcmHelper,
+use crate::config::datatype::{
+    ChatID, DeviceID, EmailCodeValue, MessageID, ProtocolVersion, ScheduleID, SerializedChatInfo,
+    SerializedChatMessage, SerializedRequest, SerializedUserNotice, SymCipher, UploadId, UserID,
 };
 
+/** `LEGACY_PROTOCOL_VERSION` 未协商协议版本的连接所用的版本，每帧使用固定的nonce
+*/
+pub const LEGACY_PROTOCOL_VERSION: ProtocolVersion = 1;
+
+/** `PROTOCOL_VERSION` 服务端支持的最高协议版本，每帧随附递增的nonce并检测重放
+*/
+pub const PROTOCOL_VERSION: ProtocolVersion = 2;
+
 // TODO : Use Box to Reduce Message Size
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "command", content = "data")]
@@ -24,7 +29,9 @@ pub enum ClientToServerMessage {
     Ping,
     Pong,
     Close,
+    NegotiateProtocol(ProtocolVersion),
     SetConnectionPubKey(String),
+    KeyExchange(String),
     Register(UserRegisterData),
     UpdateUserInfo(UserUpdateData),
     UpdateGroupInfo(UserUpdateGroupData),
@@ -40,7 +47,21 @@ pub enum ClientToServerMessage {
     GetFileUrl(String),
     SolveRequest(UserSolveRequestData),
     RevokeMessage(UserRevokeMessageData),
+    EditMessage(UserEditMessageData),
+    GetEditHistory(UserGetEditHistoryData),
+    AddReaction(UserReactionData),
+    RemoveReaction(UserReactionData),
+    GetThread(UserGetThreadData),
+    ForwardMessages(UserForwardMessagesData),
+    PinMessage(UserPinMessageData),
+    UnpinMessage(UserPinMessageData),
+    GetPinnedMessages(ChatID),
+    SetDisappearing(UserSetDisappearingData),
+    GetDisappearing(ChatID),
+    GetScheduledMessages,
+    CancelScheduledMessage(ScheduleID),
     GetMessages(UserGetMessagesData),
+    SearchMessages(UserSearchMessagesData),
     CreateGroupChat(UserCreateGroupChatData),
     Unfriend(UserID),
     QuitGroupChat(ChatID),
@@ -62,6 +83,21 @@ pub enum ClientToServerMessage {
     MediaCallStop(UserMediaCallStop),
     GetUserID(String),
     GetUserReadInGroup(UserGetUserReadInGroupData),
+    SubscribeReadReceipts(UserSubscribeReadReceiptsData),
+    UnsubscribeReadReceipts(ChatID),
+    StartTyping(ChatID),
+    StopTyping(ChatID),
+    SetPresence(PresenceStatus),
+    SetPresenceHidden(bool),
+    GetPresence(Vec<UserID>),
+    ListDevices,
+    KickDevice(DeviceID),
+    RefreshToken(UserRefreshTokenData),
+    RevokeToken(DeviceID),
+    RevokeAllTokens,
+    UploadDeviceKeys(UserUploadDeviceKeysData),
+    RotateSignedPrekey(SignedPrekey),
+    GetDeviceKeys(UserID),
     GetUserReadInPrivate(ChatID),
     LogOff(EmailCodeValue),
 }
@@ -73,8 +109,10 @@ pub enum ServerToClientMessage {
     Ping,
     Pong,
     Close,
+    NegotiateProtocolResponse(UserNegotiateProtocolResponse),
     SetConnectionSymKey(String),
     SetConnectionPubKeyResponse(UserSetPubKeyResponse),
+    KeyExchangeResponse(UserKeyExchangeResponse),
     ApplyForTokenResponse(UserApplyForTokenResponse),
     LoginResponse(UserLoginResponse),
     RegisterResponse(UserRegisterResponse),
@@ -89,6 +127,20 @@ pub enum ServerToClientMessage {
     CreateGroupChatResponse(UserCreateGroupChatResponse),
     UploadFileRequestResponse(UserUploadFileRequestResponse),
     RevokeMessageResponse(UserRevokeMessageResponse),
+    EditMessageResponse(UserEditMessageResponse),
+    GetEditHistoryResponse(UserGetEditHistoryResponse),
+    AddReactionResponse(UserReactionResponse),
+    RemoveReactionResponse(UserReactionResponse),
+    GetThreadResponse(UserGetThreadResponse),
+    ForwardMessagesResponse(UserForwardMessagesResponse),
+    PinMessageResponse(UserPinMessageResponse),
+    UnpinMessageResponse(UserUnpinMessageResponse),
+    GetPinnedMessagesResponse(UserGetPinnedMessagesResponse),
+    SetDisappearingResponse(UserSetDisappearingResponse),
+    GetDisappearingResponse(UserGetDisappearingResponse),
+    ScheduleMessageResponse(UserScheduleMessageResponse),
+    GetScheduledMessagesResponse(UserGetScheduledMessagesResponse),
+    CancelScheduledMessageResponse(UserCancelScheduledMessageResponse),
     FileUploadedResponse(UserFileUploadedResponse),
     RequestStateUpdate(UserRequsetStateUpdated),
     PullResponse(UserPullResponse),
@@ -98,6 +150,9 @@ pub enum ServerToClientMessage {
     Chats(Vec<SerializedChatInfo>),
     ReadCursors(Vec<(ChatID, MessageID)>),
     Messages(Vec<SerializedChatMessage>),
+    PinnedMessages(Vec<SerializedChatMessage>),
+    GetMessagesResponse(UserGetMessagesResponse),
+    SearchMessagesResponse(UserSearchMessagesResponse),
     Message(SerializedChatMessage),
     Request(SerializedRequest),
     Requests(Vec<SerializedRequest>),
@@ -126,14 +181,39 @@ pub enum ServerToClientMessage {
     GetUserReadInGroupResponse(UserGetUserReadInGroupResponse),
     GetUserReadInPrivateResponse(UserGetUserReadInPrivateResponse),
     SetOppositeReadCursor(UserSetOppositeReadCursorData),
+    SubscribeReadReceiptsResponse(UserSubscribeReadReceiptsResponse),
+    UnsubscribeReadReceiptsResponse(UserSubscribeReadReceiptsResponse),
+    GroupReadCursors(GroupReadCursorsData),
+    Typing(UserTypingData),
+    SetPresenceResponse(UserSetPresenceResponse),
+    SetPresenceHiddenResponse(UserSetPresenceHiddenResponse),
+    GetPresenceResponse(UserGetPresenceResponse),
+    PresenceChange(UserPresenceData),
+    ListDevicesResponse(UserListDevicesResponse),
+    KickDeviceResponse(UserKickDeviceResponse),
+    DeviceKicked,
+    RevokeTokenResponse(UserRevokeTokenResponse),
+    RefreshTokenResponse(UserRefreshTokenResponse),
+    RevokeAllTokensResponse(UserRevokeTokenResponse),
+    UploadDeviceKeysResponse(UserSetDeviceKeysResponse),
+    RotateSignedPrekeyResponse(UserSetDeviceKeysResponse),
+    GetDeviceKeysResponse(UserGetDeviceKeysResponse),
+    RateLimited(UserRateLimitedData),
+    SetSelfReadCursor(UserSetOppositeReadCursorData),
     LogOffResponse(UserLogOffResponse),
     GroupMemberChange(MemberChangeData),
     RequestMessage(RequestMessageResponse),
 }
 
+static NEGOTIATE_PROTOCOL_EQ: Lazy<ClientToServerMessage> =
+    Lazy::new(|| ClientToServerMessage::NegotiateProtocol(0));
+
 static SET_PUB_KEY_EQ: Lazy<ClientToServerMessage> =
     Lazy::new(|| ClientToServerMessage::SetConnectionPubKey("".to_string()));
 
+static KEY_EXCHANGE_EQ: Lazy<ClientToServerMessage> =
+    Lazy::new(|| ClientToServerMessage::KeyExchange("".to_string()));
+
 static REGISTER_EQ: Lazy<ClientToServerMessage> = Lazy::new(|| {
     ClientToServerMessage::Register(UserRegisterData {
         user_name: "".to_string(),
@@ -150,15 +230,24 @@ static LOGIN_EQ: Lazy<ClientToServerMessage> = Lazy::new(|| {
         email_code: None,
         address: None,
         token: None,
+        device_id: None,
     })
 });
 
 // TODO : 使用宏简化代码
 impl ClientToServerMessage {
+    pub fn is_negotiate_protocol(&self) -> bool {
+        return *self == *NEGOTIATE_PROTOCOL_EQ;
+    }
+
     pub fn is_set_pub_key(&self) -> bool {
         return *self == *SET_PUB_KEY_EQ;
     }
 
+    pub fn is_key_exchange(&self) -> bool {
+        return *self == *KEY_EXCHANGE_EQ;
+    }
+
     pub fn is_register(&self) -> bool {
         return *self == *REGISTER_EQ;
     }
@@ -166,6 +255,209 @@ impl ClientToServerMessage {
     pub fn is_login(&self) -> bool {
         return *self == *LOGIN_EQ;
     }
+
+    pub fn is_refresh_token(&self) -> bool {
+        return matches!(self, ClientToServerMessage::RefreshToken(_));
+    }
+
+    /** `get_command` 消息的command，与序列化后的command字段相同
+     * 由serde按`tag = "command"`序列化得到，data字段不会被序列化
+     */
+    pub fn get_command(&self) -> String {
+        return self
+            .serialize(CommandName)
+            .expect("ClientToServerMessage应序列化为带有command字段的结构");
+    }
+}
+
+/** `CommandName` 只读取消息command字段的Serializer
+ * 消息按`tag = "command", content = "data"`序列化为结构，command字段为变体名，其余字段直接跳过
+ */
+struct CommandName;
+
+struct CommandNameStruct(Option<String>);
+
+impl serde::Serializer for CommandName {
+    type Ok = String;
+    type Error = serde::de::value::Error;
+    type SerializeSeq = Impossible<String, Self::Error>;
+    type SerializeTuple = Impossible<String, Self::Error>;
+    type SerializeTupleStruct = Impossible<String, Self::Error>;
+    type SerializeTupleVariant = Impossible<String, Self::Error>;
+    type SerializeMap = Impossible<String, Self::Error>;
+    type SerializeStruct = CommandNameStruct;
+    type SerializeStructVariant = Impossible<String, Self::Error>;
+
+    fn serialize_str(self, v: &str) -> Result<String, Self::Error> {
+        return Ok(v.to_string());
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<String, Self::Error> {
+        return Ok(variant.to_string());
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        return Ok(CommandNameStruct(None));
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<String, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<String, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<String, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<String, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<String, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<String, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<String, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<String, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<String, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<String, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<String, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_char(self, _v: char) -> Result<String, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<String, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_none(self) -> Result<String, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _value: &T) -> Result<String, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_unit(self) -> Result<String, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _value: &T,
+    ) -> Result<String, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<String, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        return Err(not_command());
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        return Err(not_command());
+    }
+}
+
+impl SerializeStruct for CommandNameStruct {
+    type Ok = String;
+    type Error = serde::de::value::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        if key == "command" {
+            self.0 = Some(value.serialize(CommandName)?);
+        }
+        return Ok(());
+    }
+
+    fn end(self) -> Result<String, Self::Error> {
+        return self.0.ok_or_else(not_command);
+    }
+}
+
+fn not_command() -> serde::de::value::Error {
+    return serde::ser::Error::custom("不是带有command字段的消息");
 }
 
 impl PartialEq for ClientToServerMessage {
@@ -180,14 +472,14 @@ where
     Self: AsyncContext<A>,
 {
     // TODO : 使用宏简化代码
-    fn send_server_message(&mut self, msg: &ServerToClientMessage, cipher: Option<&Aes128Gcm>);
+    fn send_server_message(&mut self, msg: &ServerToClientMessage, cipher: Option<&SymCipher>);
 }
 
 impl<A> ServerMessageSender<A> for WebsocketContext<A>
 where
     A: Actor<Context = WebsocketContext<A>>,
 {
-    fn send_server_message(&mut self, msg: &ServerToClientMessage, cipher: Option<&Aes128Gcm>) {
+    fn send_server_message(&mut self, msg: &ServerToClientMessage, cipher: Option<&SymCipher>) {
         match msg {
             ServerToClientMessage::Ping => self.ping(&[]),
             ServerToClientMessage::Pong => self.pong(&[]),
@@ -195,7 +487,7 @@ where
             _ => {
                 let json = serde_json::to_string::<ServerToClientMessage>(msg).unwrap();
                 if let Some(cipher) = cipher {
-                    self.text(cipher.encrypt_with_default_nouce_to_base64(&json).unwrap());
+                    self.text(cipher.encrypt_to_base64(&json).unwrap());
                 } else {
                     self.text(json);
                 }
@@ -1,18 +1,22 @@
 use std::{
     cell::{Ref, RefCell, RefMut},
+    collections::HashMap,
     net::SocketAddr,
     rc::Rc,
     sync::Arc,
+    time::Instant,
 };
 
 use actix::{Message, Recipient};
 
+use chrono::Utc;
 use log::info;
 use rsa::RsaPublicKey;
 
 use crate::{
-    config::datatype::{SymCipher, UserID},
-    server::server_state::user_sessions,
+    config::datatype::{ChatID, DeviceID, ProtocolVersion, SymCipher, Timestamp, UserID},
+    config::Config,
+    server::server_state::{user_sessions, DeviceInsertResult, DeviceSession, UserSessionRegistry},
 };
 
 use super::protocol::ServerToClientMessage;
@@ -33,18 +37,51 @@ pub struct UserSessionData {
     pub client_ip: SocketAddr,
     pub user_id: Option<UserID>,
     pub user_pub_key: Option<RsaPublicKey>,
+    /// 登录后连接所在的设备
+    pub device_id: Option<DeviceID>,
+    /// 协商得到的协议版本，未协商时为旧版
+    pub protocol_version: ProtocolVersion,
+    /// 正在输入的聊天及其状态
+    pub typing: HashMap<ChatID, TypingState>,
+}
+
+/** `TypingState` 连接在某个聊天中的输入状态
+*/
+pub struct TypingState {
+    /// 上次转发正在输入的时间
+    pub relayed_at: Instant,
+    /// 上次收到正在输入的时间
+    pub refreshed_at: Instant,
 }
 
 impl UserSessionData {
+    /** `on_login_success` 登记设备连接，登记成功后连接进入已登录状态
+     * 在线的设备数量已达上限时返回`TooManyDevices`，连接的状态不变
+     */
     pub fn on_login_success(
         &mut self,
         user_id: UserID,
+        device_id: DeviceID,
         self_recipient: Recipient<UserSessionActorMessage>,
-    ) {
+    ) -> DeviceInsertResult {
+        let result = user_sessions.insert_device(
+            user_id,
+            DeviceSession {
+                device_id: device_id.clone(),
+                client_ip: self.client_ip,
+                login_time: Utc::now().timestamp_millis() as Timestamp,
+                recipient: self_recipient,
+            },
+            Config::get().user.max_devices_num as usize,
+        );
+        if let DeviceInsertResult::TooManyDevices = result {
+            return result;
+        }
         self.state = UserSessionState::Logged;
         self.user_id = Some(user_id);
-        user_sessions.insert(user_id, self_recipient);
+        self.device_id = Some(device_id);
         info!("{} 登录成功", self.get_info());
+        return result;
     }
 }
 
@@ -83,12 +120,14 @@ pub trait UserSession {
 
 /** `UserSessionActorMessage` 用户连接从服务端其他Actor收到的消息
 */
-#[derive(Message)]
+#[derive(Message, Clone)]
 #[rtype(result = "UserSessionActorResponse")]
 pub enum UserSessionActorMessage {
     SendServerMessage(ServerToClientMessage),
     SendServerMessageArc(Arc<ServerToClientMessage>),
     SendStringSerializedServerMessage(Arc<String>),
+    /// 设备被用户下线或被同一设备的新连接替换
+    Kick,
 }
 
 /** `UserSessionActorResponse` 用户连接收到从服务端其他Actor消息时的响应
@@ -4,8 +4,16 @@ use std::{
 };
 
 use crate::{
-    config::{datatype::SymCipher, Config},
-    server::server_state::user_sessions,
+    config::{
+        datatype::{SymCipher, Timestamp},
+        Config,
+    },
+    server::{
+        presence,
+        read_receipt::read_receipts,
+        server_state::{user_sessions, workers_handle, UserSessionRegistry},
+    },
+    user::send_typing_to_online_users_in_chat,
 };
 
 use super::client_message_handler::ClientMessageHandler;
@@ -17,12 +25,13 @@ use crate::user::user_session::actor_message_handler::ActorMessageHandler;
 
 use actix::prelude::*;
 use actix_web_actors::ws::{self, WebsocketContext};
+use chrono::Utc;
 use log::debug;
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use std::time::{Duration, Instant};
 
-use crate::utils::AesGcmHelper;
+use crate::utils::aes::FrameError;
 
 /** `WsUserSession` Websocket用户连接数据类型
 */
@@ -39,6 +48,8 @@ pub struct WsUserSession {
 pub enum WsProtocolError {
     TextFormatError,
     TextDecodeFailed,
+    /// 帧的nonce没有递增，可能是重放的帧
+    FrameReplayed,
     DataParseFailed(String),
     UnsupportedWsMessage,
 }
@@ -60,11 +71,24 @@ impl Actor for WsUserSession {
         });
     }
 
-    fn stopping(&mut self, _ctx: &mut Self::Context) -> Running {
+    fn stopping(&mut self, ctx: &mut Self::Context) -> Running {
         debug!("WebSocket {} 断开", self.get_data().get_info());
 
-        if let Some(id) = self.get_mut_data().user_id {
-            user_sessions.remove(&id);
+        let user_id = self.get_data().user_id;
+        if let Some(id) = user_id {
+            // 用户的最后一台设备断开时才算下线
+            if user_sessions.remove_device(id, &ctx.address().recipient()) {
+                read_receipts.lock().unwrap().unsubscribe_all(id);
+                presence::set_away(id, false);
+                workers_handle.spawn(presence::user_offline(
+                    id,
+                    Utc::now().timestamp_millis() as Timestamp,
+                ));
+            }
+            // 连接断开时结束其全部输入状态
+            for (chat_id, _) in self.get_mut_data().typing.drain() {
+                workers_handle.spawn(send_typing_to_online_users_in_chat(id, chat_id, false));
+            }
         }
 
         Running::Stop
@@ -103,11 +127,7 @@ impl WsUserSession {
     ) -> Result<ClientToServerMessage, WsProtocolError> {
         match ws_msg {
             ws::Message::Text(bytes) => {
-                let text_str = if let Ok(str) = self.decode_string(&bytes) {
-                    str
-                } else {
-                    return Err(WsProtocolError::TextDecodeFailed);
-                };
+                let text_str = self.decode_string(&bytes)?;
 
                 match serde_json::from_str::<ClientToServerMessage>(&text_str) {
                     Ok(msg) => Ok(msg),
@@ -121,9 +141,12 @@ impl WsUserSession {
         }
     }
 
-    fn decode_string(&self, data: &str) -> Result<String, ()> {
+    fn decode_string(&self, data: &str) -> Result<String, WsProtocolError> {
         if let Some(cipher) = self.get_cipher() {
-            return cipher.decrypt_with_default_nouce_from_base64(data);
+            return cipher.decrypt_from_base64(data).map_err(|err| match err {
+                FrameError::DecryptFailed => WsProtocolError::TextDecodeFailed,
+                FrameError::Replayed => WsProtocolError::FrameReplayed,
+            });
         } else {
             return Ok(data.to_string());
         }
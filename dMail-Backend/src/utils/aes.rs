@@ -1,12 +1,51 @@
 /*! 用于aes加密的函数 */
 
+use std::cell::Cell;
+
 use aes_gcm::{aead::Aead, Aes128Gcm, Nonce};
 
 use super::base64;
 
 const IV: &[u8; 12] = b"dMailBackend";
 
-// TODO : 创建NewType，缓存类型与buffer
+/** `NONCE_LEN` 每帧随附的nonce长度，前4字节为方向，后8字节为大端序的计数器
+*/
+pub const NONCE_LEN: usize = 12;
+
+/** `NonceDirection` nonce所属的发送方向，两个方向使用同一秘钥，以此避免nonce重复
+*/
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NonceDirection {
+    ServerToClient,
+    ClientToServer,
+}
+
+impl NonceDirection {
+    fn prefix(&self) -> [u8; 4] {
+        match self {
+            NonceDirection::ServerToClient => [0, 0, 0, 0],
+            NonceDirection::ClientToServer => [0, 0, 0, 1],
+        }
+    }
+}
+
+/** `frame_nonce` 生成某一方向上第counter帧的nonce
+*/
+pub fn frame_nonce(direction: NonceDirection, counter: u64) -> [u8; NONCE_LEN] {
+    let mut nonce = [0; NONCE_LEN];
+    nonce[..4].copy_from_slice(&direction.prefix());
+    nonce[4..].copy_from_slice(&counter.to_be_bytes());
+    return nonce;
+}
+
+/** `frame_counter` 从nonce中取出计数器，方向不符时为None
+*/
+pub fn frame_counter(nonce: &[u8; NONCE_LEN], direction: NonceDirection) -> Option<u64> {
+    if nonce[..4] != direction.prefix() {
+        return None;
+    }
+    return Some(u64::from_be_bytes(nonce[4..].try_into().unwrap()));
+}
 
 /** `AesGcmHelper` 为Aes128Gcm密码封装的带base64的加密解密特征
  */
@@ -17,6 +56,19 @@ pub trait AesGcmHelper {
     /** `decrypt_with_default_nouce_from_base64` 把base64编码下的经aes加密的信息转成可读字符串
      */
     fn decrypt_with_default_nouce_from_base64(&self, str: &str) -> Result<String, ()>;
+    /** `encrypt_with_nonce_to_base64` 使用所给的nonce加密，把nonce与密文拼接后转为base64
+     */
+    fn encrypt_with_nonce_to_base64(
+        &self,
+        nonce: &[u8; NONCE_LEN],
+        str: &str,
+    ) -> Result<String, aes_gcm::Error>;
+    /** `decrypt_with_nonce_from_base64` 解密nonce与密文拼接而成的base64，返回nonce与可读字符串
+     */
+    fn decrypt_with_nonce_from_base64(
+        &self,
+        str: &str,
+    ) -> Result<([u8; NONCE_LEN], String), FrameError>;
 }
 
 impl AesGcmHelper for Aes128Gcm {
@@ -51,4 +103,118 @@ impl AesGcmHelper for Aes128Gcm {
 
         return String::from_utf8(decoded_binary).map_err(|_| ());
     }
+
+    fn encrypt_with_nonce_to_base64(
+        &self,
+        nonce: &[u8; NONCE_LEN],
+        str: &str,
+    ) -> Result<String, aes_gcm::Error> {
+        let encrypted = self.encrypt(Nonce::from_slice(nonce), str.as_bytes())?;
+        let mut binary = Vec::with_capacity(NONCE_LEN + encrypted.len());
+        binary.extend_from_slice(nonce);
+        binary.extend(encrypted);
+        return Ok(base64::encode(binary));
+    }
+
+    fn decrypt_with_nonce_from_base64(
+        &self,
+        str: &str,
+    ) -> Result<([u8; NONCE_LEN], String), FrameError> {
+        let binary = base64::decode(str).map_err(|_| FrameError::DecryptFailed)?;
+        if binary.len() < NONCE_LEN {
+            return Err(FrameError::DecryptFailed);
+        }
+        let (nonce, encrypted) = binary.split_at(NONCE_LEN);
+
+        let decoded_binary = self
+            .decrypt(Nonce::from_slice(nonce), encrypted)
+            .map_err(|_| FrameError::DecryptFailed)?;
+        let str = String::from_utf8(decoded_binary).map_err(|_| FrameError::DecryptFailed)?;
+        return Ok((nonce.try_into().unwrap(), str));
+    }
+}
+
+/** `FrameError` 解密客户端发来的帧时出现的错误
+*/
+#[derive(Debug, PartialEq)]
+pub enum FrameError {
+    DecryptFailed,
+    /// 帧的计数器不大于已收到的帧，视为重放
+    Replayed,
+}
+
+/** `SessionCipher` 连接使用的对称密码，记录两个方向上的帧计数器
+ * 旧版协议的帧使用固定nonce，不随附nonce，也不做重放检测
+ * 密钥交换得到的连接在两个方向上使用不同的密钥
+ */
+pub struct SessionCipher {
+    send_cipher: Aes128Gcm,
+    recv_cipher: Aes128Gcm,
+    frame_nonce: bool,
+    send_counter: Cell<u64>,
+    /// 下一帧允许的最小计数器
+    recv_counter: Cell<u64>,
+}
+
+impl SessionCipher {
+    pub fn legacy(cipher: Aes128Gcm) -> SessionCipher {
+        return SessionCipher {
+            send_cipher: cipher.clone(),
+            recv_cipher: cipher,
+            frame_nonce: false,
+            send_counter: Cell::new(0),
+            recv_counter: Cell::new(0),
+        };
+    }
+
+    pub fn with_frame_nonce(cipher: Aes128Gcm) -> SessionCipher {
+        return SessionCipher {
+            frame_nonce: true,
+            ..SessionCipher::legacy(cipher)
+        };
+    }
+
+    pub fn with_session_keys(send_cipher: Aes128Gcm, recv_cipher: Aes128Gcm) -> SessionCipher {
+        return SessionCipher {
+            send_cipher,
+            recv_cipher,
+            frame_nonce: true,
+            send_counter: Cell::new(0),
+            recv_counter: Cell::new(0),
+        };
+    }
+
+    /** `encrypt_to_base64` 加密发往客户端的帧，每帧使用新的nonce
+     */
+    pub fn encrypt_to_base64(&self, str: &str) -> Result<String, aes_gcm::Error> {
+        if !self.frame_nonce {
+            return self
+                .send_cipher
+                .encrypt_with_default_nouce_to_base64(str)
+                .map_err(|_| aes_gcm::Error);
+        }
+        let counter = self.send_counter.get();
+        self.send_counter.set(counter + 1);
+        let nonce = frame_nonce(NonceDirection::ServerToClient, counter);
+        return self.send_cipher.encrypt_with_nonce_to_base64(&nonce, str);
+    }
+
+    /** `decrypt_from_base64` 解密客户端发来的帧，拒绝方向不符或计数器没有递增的帧
+     */
+    pub fn decrypt_from_base64(&self, str: &str) -> Result<String, FrameError> {
+        if !self.frame_nonce {
+            return self
+                .recv_cipher
+                .decrypt_with_default_nouce_from_base64(str)
+                .map_err(|_| FrameError::DecryptFailed);
+        }
+        let (nonce, str) = self.recv_cipher.decrypt_with_nonce_from_base64(str)?;
+        match frame_counter(&nonce, NonceDirection::ClientToServer) {
+            Some(counter) if counter >= self.recv_counter.get() && counter < u64::MAX => {
+                self.recv_counter.set(counter + 1);
+                return Ok(str);
+            }
+            _ => return Err(FrameError::Replayed),
+        }
+    }
 }
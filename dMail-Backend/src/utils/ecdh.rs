@@ -0,0 +1,133 @@
+/*! 用于X25519密钥交换的函数 */
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+use super::base64;
+use crate::config::datatype::ProtocolVersion;
+
+/** `KEY_EXCHANGE_CONTEXT` 签名与密钥派生时放在双方公钥之前的上下文，避免签名被挪作他用
+*/
+const KEY_EXCHANGE_CONTEXT: &[u8] = b"dMail key exchange v1";
+
+/** `SessionKeys` 由共享秘密派生出的两个方向上的Aes128密钥
+*/
+pub struct SessionKeys {
+    pub client_to_server: [u8; 16],
+    pub server_to_client: [u8; 16],
+}
+
+/** `ServerKeyShare` 服务端在一次密钥交换中的临时公钥、对其的签名与派生出的密钥
+*/
+pub struct ServerKeyShare {
+    pub public_key: [u8; 32],
+    pub signature: Signature,
+    pub keys: SessionKeys,
+}
+
+/** `transcript` 密钥交换的记录，服务端对其签名，同时作为密钥派生的盐
+ * 记录中包含连接将使用的协议版本，版本被篡改时签名校验失败
+*/
+pub fn transcript(
+    version: ProtocolVersion,
+    client_public: &[u8; 32],
+    server_public: &[u8; 32],
+) -> Vec<u8> {
+    let mut transcript = Vec::with_capacity(KEY_EXCHANGE_CONTEXT.len() + 68);
+    transcript.extend_from_slice(KEY_EXCHANGE_CONTEXT);
+    transcript.extend_from_slice(&version.to_be_bytes());
+    transcript.extend_from_slice(client_public);
+    transcript.extend_from_slice(server_public);
+    return transcript;
+}
+
+/** `derive_session_keys` 使用HKDF-SHA256从共享秘密派生两个方向上的密钥
+*/
+pub fn derive_session_keys(
+    version: ProtocolVersion,
+    shared_secret: &[u8; 32],
+    client_public: &[u8; 32],
+    server_public: &[u8; 32],
+) -> SessionKeys {
+    let hkdf = Hkdf::<Sha256>::new(
+        Some(&transcript(version, client_public, server_public)),
+        shared_secret,
+    );
+    let mut keys = SessionKeys {
+        client_to_server: [0; 16],
+        server_to_client: [0; 16],
+    };
+    // 输出长度远小于上限，不会失败
+    hkdf.expand(b"client to server", &mut keys.client_to_server)
+        .unwrap();
+    hkdf.expand(b"server to client", &mut keys.server_to_client)
+        .unwrap();
+    return keys;
+}
+
+/** `server_key_exchange` 生成服务端的临时密钥并完成交换，客户端公钥无效时为None
+*/
+pub fn server_key_exchange(
+    signing_key: &SigningKey,
+    version: ProtocolVersion,
+    client_public: &[u8],
+) -> Option<ServerKeyShare> {
+    let client_public: [u8; 32] = client_public.try_into().ok()?;
+
+    let secret = EphemeralSecret::random_from_rng(OsRng);
+    let server_public = PublicKey::from(&secret).to_bytes();
+    let shared_secret = secret.diffie_hellman(&PublicKey::from(client_public));
+    // 小阶点会得到全零的共享秘密
+    if !shared_secret.was_contributory() {
+        return None;
+    }
+
+    return Some(ServerKeyShare {
+        public_key: server_public,
+        signature: signing_key.sign(&transcript(version, &client_public, &server_public)),
+        keys: derive_session_keys(
+            version,
+            shared_secret.as_bytes(),
+            &client_public,
+            &server_public,
+        ),
+    });
+}
+
+/** `SignedPrekeyError` 校验设备的签名预共享公钥时出现的错误
+*/
+#[derive(Debug, PartialEq)]
+pub enum SignedPrekeyError {
+    KeyFormatError,
+    SignatureError,
+}
+
+/** `verify_signed_prekey` 校验base64编码的预共享公钥及身份公钥对它的签名
+*/
+pub fn verify_signed_prekey(
+    identity_key: &str,
+    prekey: &str,
+    signature: &str,
+) -> Result<(), SignedPrekeyError> {
+    let identity_key: [u8; 32] = base64::decode(identity_key)
+        .ok()
+        .and_then(|key| key.try_into().ok())
+        .ok_or(SignedPrekeyError::KeyFormatError)?;
+    let identity_key =
+        VerifyingKey::from_bytes(&identity_key).map_err(|_| SignedPrekeyError::KeyFormatError)?;
+    let prekey: [u8; 32] = base64::decode(prekey)
+        .ok()
+        .and_then(|key| key.try_into().ok())
+        .ok_or(SignedPrekeyError::KeyFormatError)?;
+    let signature: [u8; 64] = base64::decode(signature)
+        .ok()
+        .and_then(|signature| signature.try_into().ok())
+        .ok_or(SignedPrekeyError::SignatureError)?;
+
+    return identity_key
+        .verify(&prekey, &Signature::from_bytes(&signature))
+        .map_err(|_| SignedPrekeyError::SignatureError);
+}
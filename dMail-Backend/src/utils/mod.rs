@@ -4,6 +4,8 @@
 
 pub mod aes;
 pub mod base64;
+pub mod ecdh;
+pub mod password;
 pub mod rsa;
 
 pub use aes::AesGcmHelper;
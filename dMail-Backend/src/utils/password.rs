@@ -0,0 +1,66 @@
+/*! 用于存储与校验用户密码的函数 */
+
+use argon2::{
+    password_hash::{self, rand_core::OsRng, SaltString},
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+};
+
+/** `PasswordCheck` 将登录时输入的密码与存储的密码比较的结果
+*/
+#[derive(Debug, PartialEq)]
+pub enum PasswordCheck {
+    Match,
+    /// 密码正确，但存储的是旧版的明文密码，需要替换为哈希
+    MatchLegacy,
+    Mismatch,
+}
+
+/** `hash_password` 使用随机盐生成Argon2id哈希，结果为PHC格式的字符串
+*/
+pub fn hash_password(password: &str) -> Result<String, password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    return Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string());
+}
+
+/** `check_password` 校验输入的密码，存储的不是PHC格式的哈希时按旧版明文比较
+*/
+pub fn check_password(stored: &str, password: &str) -> PasswordCheck {
+    match PasswordHash::new(stored) {
+        Ok(hash) => {
+            if Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+            {
+                return PasswordCheck::Match;
+            }
+            return PasswordCheck::Mismatch;
+        }
+        Err(_) => {
+            if stored == password {
+                return PasswordCheck::MatchLegacy;
+            }
+            return PasswordCheck::Mismatch;
+        }
+    }
+}
+
+/** `spawn_hash_password` 在阻塞线程池中执行`hash_password`，Argon2的计算不会占用异步运行时的工作线程
+*/
+pub async fn spawn_hash_password(password: &str) -> Result<String, password_hash::Error> {
+    let password = password.to_string();
+    return tokio::task::spawn_blocking(move || hash_password(&password))
+        .await
+        .unwrap_or(Err(password_hash::Error::Crypto));
+}
+
+/** `spawn_check_password` 在阻塞线程池中执行`check_password`
+*/
+pub async fn spawn_check_password(stored: &str, password: &str) -> PasswordCheck {
+    let stored = stored.to_string();
+    let password = password.to_string();
+    return tokio::task::spawn_blocking(move || check_password(&stored, &password))
+        .await
+        .unwrap_or(PasswordCheck::Mismatch);
+}
@@ -9,11 +9,15 @@ use std::sync::Once;
 
 use super::errors;
 use dMail::config::config::{
-    DatabaseConfig, EmailConfig, ProtocolConfig, S3Config, SafetyConfig, TlsConfig, UserConfig,
+    DatabaseBackendType, DatabaseConfig, EmailConfig, ProtocolConfig, RateLimitConfig,
+    RetentionConfig, S3Config, SafetyConfig, TlsConfig, UserConfig,
 };
 use dMail::config::Config;
 use errors as ERRORS;
 
+/// 测试服务端固定使用的握手签名私钥，测试客户端预先知道对应的公钥
+const TEST_HANDSHAKE_SIGNING_KEY: &str = "BwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwc=";
+
 lazy_static! {
     pub static ref EXE_PATH: PathBuf = build("dMail");
 }
@@ -52,11 +56,23 @@ pub fn build_config() {
         safety: SafetyConfig {
             max_msg_length: 500,
             max_notice_length: 500,
+            max_reaction_length: 32,
+            max_pinned_messages_num: 50,
+            enable_rsa_handshake: true,
+            enable_x25519_handshake: true,
+            handshake_signing_key: Some(TEST_HANDSHAKE_SIGNING_KEY.to_string()),
         },
         protocol: ProtocolConfig {
             max_messages_num_in_one_chat_when_pulling: 20,
             max_messages_num_in_one_chat_when_getting: 30,
+            max_messages_num_when_searching: 30,
+            max_messages_num_when_forwarding: 100,
             worker_send_messages_member_num_threshold: 5,
+            read_receipt_push_interval: 1000,
+            typing_throttle_interval: 2000,
+            typing_expire_time: 5000,
+            max_users_num_when_getting_presence: 100,
+            min_protocol_version: 1,
         },
         email: EmailConfig {
             enable: false,
@@ -75,10 +91,17 @@ pub fn build_config() {
             heart_beat_time: 5,
             password_check: "^[a-fA-F0-9]{64}$".to_string(),
             token_expire_time: 604800,
+            access_token_expire_time: 3600,
             sender_revoke_expire: 180,
+            sender_edit_expire: 900,
+            max_scheduled_messages_num: 100,
+            schedule_check_interval: 1,
+            disappearing_check_interval: 5,
+            max_devices_num: 5,
         },
         database: DatabaseConfig {
-            address: "redis://localhost:6379/".to_string(),
+            backend: DatabaseBackendType::Memory,
+            address: String::new(),
             pool_max_open: 16,
             pool_max_idle: 8,
             pool_timeout: 1,
@@ -100,6 +123,8 @@ pub fn build_config() {
             presign_put_image_expire: 120,
             presign_get_expire: 3600 * 24 * 7,
         },
+        retention: RetentionConfig::default(),
+        rate_limit: RateLimitConfig::default(),
     };
     let json = serde_json::to_string_pretty(&config).unwrap();
     let mut path = std::env::current_dir().expect("");